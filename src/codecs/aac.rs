@@ -0,0 +1,497 @@
+//!
+//! Parses the "A_AAC" "CodecPrivate" (the MPEG-4 `AudioSpecificConfig`) and reads and writes ADTS framing, as defined in ISO/IEC 14496-3.
+//!
+//! Matroska stores raw AAC frames, with the decoder configuration in "CodecPrivate".  Raw `.aac` files instead prefix every frame with an ADTS header holding the same configuration.  [`AdtsWriter`] adds these headers to export a track, and [`AdtsHeader`] reads them to build a "CodecPrivate" when importing.
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use crate::errors::CodecError;
+use super::bits::{BitReader, BitWriter};
+
+const SAMPLING_FREQUENCIES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+
+const SBR_SYNC_EXTENSION: u64 = 0x2b7;
+const PS_SYNC_EXTENSION: u64 = 0x548;
+
+///
+/// Looks up the sampling frequency index used for a sampling frequency in AAC headers, if it has one.
+///
+pub fn sampling_frequency_index(frequency: u32) -> Option<u8> {
+    SAMPLING_FREQUENCIES.iter().position(|f| *f == frequency).map(|index| index as u8)
+}
+
+///
+/// The MPEG-4 audio decoder configuration stored in the "CodecPrivate" of "A_AAC" tracks.
+///
+/// For HE-AAC, `object_type` and `sampling_frequency` describe the AAC core, while `sbr` and `extension_sampling_frequency` describe the spectral band replication layer that doubles the output rate.  Both implicit signalling (object type 5 or 29) and backward compatible explicit signalling (a sync extension after the core config) are understood.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::aac::AudioSpecificConfig;
+///
+/// // AAC LC, 44.1kHz, stereo
+/// let config = AudioSpecificConfig::try_from(&[0x12, 0x10][..]).unwrap();
+/// assert_eq!(2, config.object_type);
+/// assert_eq!(44100, config.sampling_frequency);
+/// assert_eq!(2, config.channel_configuration);
+/// assert!(!config.sbr);
+/// assert_eq!(vec![0x12, 0x10], config.to_bytes());
+/// ```
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioSpecificConfig {
+    /// The audio object type of the core codec (2 = AAC LC).
+    pub object_type: u8,
+
+    pub sampling_frequency: u32,
+
+    /// The channel configuration (1-7 give a fixed speaker layout, 0 means a program config element describes the channels).
+    pub channel_configuration: u8,
+
+    /// The number of samples in each frame, 1024 or 960.
+    pub frame_length: u16,
+
+    /// Whether spectral band replication (HE-AAC) is used.
+    pub sbr: bool,
+
+    /// Whether parametric stereo (HE-AAC v2) is used.
+    pub ps: bool,
+
+    /// The output sampling frequency when SBR is used.
+    pub extension_sampling_frequency: Option<u32>,
+}
+
+impl AudioSpecificConfig {
+    ///
+    /// The sampling frequency of the decoded output, taking SBR into account.
+    ///
+    pub fn output_sampling_frequency(&self) -> u32 {
+        self.extension_sampling_frequency.unwrap_or(self.sampling_frequency)
+    }
+
+    ///
+    /// Writes the configuration in the form stored in "CodecPrivate".
+    ///
+    /// SBR and PS are written using backward compatible explicit signalling, so decoders without HE-AAC support can still play the AAC core.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = BitWriter::new();
+        write_object_type(&mut writer, self.object_type);
+        write_sampling_frequency(&mut writer, self.sampling_frequency);
+        writer.write_bits(self.channel_configuration as u64, 4);
+
+        // GASpecificConfig: frameLengthFlag, dependsOnCoreCoder, extensionFlag
+        writer.write_bits((self.frame_length == 960) as u64, 1);
+        writer.write_bits(0, 2);
+
+        if self.sbr {
+            writer.write_bits(SBR_SYNC_EXTENSION, 11);
+            write_object_type(&mut writer, 5);
+            writer.write_bits(1, 1);
+            write_sampling_frequency(&mut writer, self.output_sampling_frequency());
+            if self.ps {
+                writer.write_bits(PS_SYNC_EXTENSION, 11);
+                writer.write_bits(1, 1);
+            }
+        }
+        writer.into_bytes()
+    }
+}
+
+impl TryFrom<&[u8]> for AudioSpecificConfig {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let mut reader = BitReader::new(data);
+        let mut object_type = read_object_type(&mut reader)?;
+        let sampling_frequency = read_sampling_frequency(&mut reader)?;
+        let channel_configuration = reader.read_bits(4)? as u8;
+
+        let mut sbr = false;
+        let mut ps = false;
+        let mut extension_sampling_frequency = None;
+        if object_type == 5 || object_type == 29 {
+            sbr = true;
+            ps = object_type == 29;
+            extension_sampling_frequency = Some(read_sampling_frequency(&mut reader)?);
+            object_type = read_object_type(&mut reader)?;
+        }
+
+        let mut frame_length = 1024;
+        if matches!(object_type, 1..=4 | 6 | 7) {
+            if reader.read_bit()? {
+                frame_length = 960;
+            }
+            if reader.read_bit()? {
+                reader.skip_bits(14)?; // coreCoderDelay
+            }
+            let extension_flag = reader.read_bit()?;
+
+            // Explicit SBR/PS signalling follows the core config, but can only be found if the channel layout didn't need a program config element
+            if channel_configuration != 0 && !extension_flag && !sbr && reader.bits_remaining() >= 16 && reader.read_bits(11)? == SBR_SYNC_EXTENSION
+                && read_object_type(&mut reader)? == 5 {
+                sbr = reader.read_bit()?;
+                if sbr {
+                    extension_sampling_frequency = Some(read_sampling_frequency(&mut reader)?);
+                    if reader.bits_remaining() >= 12 && reader.read_bits(11)? == PS_SYNC_EXTENSION {
+                        ps = reader.read_bit()?;
+                    }
+                }
+            }
+        }
+
+        Ok(AudioSpecificConfig {
+            object_type,
+            sampling_frequency,
+            channel_configuration,
+            frame_length,
+            sbr,
+            ps,
+            extension_sampling_frequency,
+        })
+    }
+}
+
+fn read_object_type(reader: &mut BitReader) -> Result<u8, CodecError> {
+    let object_type = reader.read_bits(5)? as u8;
+    if object_type == 31 {
+        Ok(32 + reader.read_bits(6)? as u8)
+    } else {
+        Ok(object_type)
+    }
+}
+
+fn write_object_type(writer: &mut BitWriter, object_type: u8) {
+    if object_type >= 31 {
+        writer.write_bits(31, 5);
+        writer.write_bits((object_type - 32) as u64, 6);
+    } else {
+        writer.write_bits(object_type as u64, 5);
+    }
+}
+
+fn read_sampling_frequency(reader: &mut BitReader) -> Result<u32, CodecError> {
+    let index = reader.read_bits(4)? as usize;
+    if index == 15 {
+        Ok(reader.read_bits(24)? as u32)
+    } else {
+        SAMPLING_FREQUENCIES.get(index).copied()
+            .ok_or_else(|| CodecError::InvalidData(format!("AAC sampling frequency index {index} is reserved")))
+    }
+}
+
+fn write_sampling_frequency(writer: &mut BitWriter, frequency: u32) {
+    match sampling_frequency_index(frequency) {
+        Some(index) => writer.write_bits(index as u64, 4),
+        None => {
+            writer.write_bits(15, 4);
+            writer.write_bits(frequency as u64, 24);
+        },
+    }
+}
+
+///
+/// The header at the start of each frame in an ADTS stream (a raw `.aac` file).
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdtsHeader {
+    /// Whether the stream is signalled as MPEG-2 AAC rather than MPEG-4.
+    pub mpeg2: bool,
+
+    /// Whether the header has no CRC.  Headers with a CRC are 2 bytes longer.
+    pub protection_absent: bool,
+
+    /// The audio object type (ADTS can only signal types 1-4).
+    pub object_type: u8,
+
+    pub sampling_frequency_index: u8,
+    pub channel_configuration: u8,
+
+    /// The length of the frame in bytes, including this header.
+    pub frame_length: usize,
+
+    pub buffer_fullness: u16,
+
+    /// The number of raw AAC frames in this ADTS frame.
+    pub raw_data_blocks: u8,
+}
+
+impl AdtsHeader {
+    ///
+    /// The length of the header in bytes, including the CRC if there is one.
+    ///
+    pub fn header_length(&self) -> usize {
+        if self.protection_absent { 7 } else { 9 }
+    }
+
+    ///
+    /// Builds the `AudioSpecificConfig` for "CodecPrivate" that describes this stream.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the sampling frequency index is reserved.
+    ///
+    pub fn audio_specific_config(&self) -> Result<AudioSpecificConfig, CodecError> {
+        let sampling_frequency = *SAMPLING_FREQUENCIES.get(self.sampling_frequency_index as usize)
+            .ok_or_else(|| CodecError::InvalidData(format!("AAC sampling frequency index {} is reserved", self.sampling_frequency_index)))?;
+        Ok(AudioSpecificConfig {
+            object_type: self.object_type,
+            sampling_frequency,
+            channel_configuration: self.channel_configuration,
+            frame_length: 1024,
+            sbr: false,
+            ps: false,
+            extension_sampling_frequency: None,
+        })
+    }
+
+    ///
+    /// Writes the header (without a CRC).
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if a field is out of range for its ADTS bits, such as an object type or raw data block count outside 1-4.
+    ///
+    pub fn to_bytes(&self) -> Result<[u8; 7], CodecError> {
+        if !(1..=4).contains(&self.object_type) {
+            return Err(CodecError::InvalidData(format!("AAC object type {} cannot be stored in ADTS", self.object_type)));
+        }
+        if !(1..=4).contains(&self.raw_data_blocks) {
+            return Err(CodecError::InvalidData(format!("ADTS frames hold 1-4 raw data blocks, not {}", self.raw_data_blocks)));
+        }
+        let fields = [
+            ("sampling frequency index", self.sampling_frequency_index as usize, 4),
+            ("channel configuration", self.channel_configuration as usize, 3),
+            ("frame length", self.frame_length, 13),
+            ("buffer fullness", self.buffer_fullness as usize, 11),
+        ];
+        if let Some((name, value, bits)) = fields.iter().find(|(_, value, bits)| *value >= 1 << bits) {
+            return Err(CodecError::InvalidData(format!("ADTS {name} {value} doesn't fit in {bits} bits")));
+        }
+
+        let mut writer = BitWriter::new();
+        writer.write_bits(0xfff, 12);
+        writer.write_bits(self.mpeg2 as u64, 1);
+        writer.write_bits(0, 2); // layer
+        writer.write_bits(1, 1); // protection_absent
+        writer.write_bits((self.object_type - 1) as u64, 2);
+        writer.write_bits(self.sampling_frequency_index as u64, 4);
+        writer.write_bits(0, 1); // private_bit
+        writer.write_bits(self.channel_configuration as u64, 3);
+        writer.write_bits(0, 4); // original_copy, home, copyright_identification_bit, copyright_identification_start
+        writer.write_bits(self.frame_length as u64, 13);
+        writer.write_bits(self.buffer_fullness as u64, 11);
+        writer.write_bits((self.raw_data_blocks - 1) as u64, 2);
+
+        let mut header = [0u8; 7];
+        header.copy_from_slice(&writer.into_bytes());
+        Ok(header)
+    }
+}
+
+impl TryFrom<&[u8]> for AdtsHeader {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 7 {
+            return Err(CodecError::TruncatedData(String::from("ADTS header is shorter than 7 bytes")));
+        }
+
+        let mut reader = BitReader::new(data);
+        if reader.read_bits(12)? != 0xfff {
+            return Err(CodecError::InvalidData(String::from("ADTS header is missing its sync word")));
+        }
+        let mpeg2 = reader.read_bit()?;
+        if reader.read_bits(2)? != 0 {
+            return Err(CodecError::InvalidData(String::from("ADTS header has a non-zero layer")));
+        }
+        let protection_absent = reader.read_bit()?;
+        let object_type = reader.read_bits(2)? as u8 + 1;
+        let sampling_frequency_index = reader.read_bits(4)? as u8;
+        reader.skip_bits(1)?;
+        let channel_configuration = reader.read_bits(3)? as u8;
+        reader.skip_bits(4)?;
+        let frame_length = reader.read_bits(13)? as usize;
+        let buffer_fullness = reader.read_bits(11)? as u16;
+        let raw_data_blocks = reader.read_bits(2)? as u8 + 1;
+
+        let header = AdtsHeader {
+            mpeg2,
+            protection_absent,
+            object_type,
+            sampling_frequency_index,
+            channel_configuration,
+            frame_length,
+            buffer_fullness,
+            raw_data_blocks,
+        };
+        if frame_length < header.header_length() {
+            return Err(CodecError::InvalidData(format!("ADTS frame length {frame_length} is shorter than its header")));
+        }
+        Ok(header)
+    }
+}
+
+///
+/// Splits an ADTS stream into its frames, returning each header with the raw AAC data that follows it.
+///
+/// # Errors
+///
+/// This function returns an error if a frame header is invalid or a frame is longer than the remaining data.
+///
+pub fn read_adts_frames(data: &[u8]) -> Result<Vec<(AdtsHeader, &[u8])>, CodecError> {
+    let mut frames = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let header = AdtsHeader::try_from(&data[position..])?;
+        if data.len() - position < header.frame_length {
+            return Err(CodecError::TruncatedData(format!("ADTS frame of {} bytes is longer than the {} bytes remaining", header.frame_length, data.len() - position)));
+        }
+        frames.push((header, &data[position + header.header_length()..position + header.frame_length]));
+        position += header.frame_length;
+    }
+    Ok(frames)
+}
+
+///
+/// Writes raw AAC frames as an ADTS stream, which can be saved as a playable `.aac` file.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::aac::{AdtsWriter, AudioSpecificConfig};
+///
+/// let config = AudioSpecificConfig::try_from(&[0x12, 0x10][..]).unwrap();
+/// let mut writer = AdtsWriter::new(Vec::new(), &config).unwrap();
+/// writer.write_frame(&[0x21, 0x00, 0x49]).unwrap();
+///
+/// let data = writer.into_inner();
+/// assert_eq!(&[0xff, 0xf1, 0x50, 0x80, 0x01, 0x5f, 0xfc, 0x21, 0x00, 0x49], data.as_slice());
+/// ```
+///
+pub struct AdtsWriter<W: Write> {
+    writer: W,
+    header: AdtsHeader,
+}
+
+impl<W: Write> AdtsWriter<W> {
+    ///
+    /// Creates a new writer for frames described by the given configuration (usually parsed from "CodecPrivate").
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the configuration can't be signalled in ADTS: only object types 1-4 with a standard sampling frequency and a channel configuration from 1 to 7 are allowed.  HE-AAC streams are written with their AAC core configuration, which decoders use to detect SBR implicitly.
+    ///
+    pub fn new(writer: W, config: &AudioSpecificConfig) -> Result<Self, CodecError> {
+        if !(1..=4).contains(&config.object_type) {
+            return Err(CodecError::InvalidData(format!("AAC object type {} cannot be stored in ADTS", config.object_type)));
+        }
+        if !(1..=7).contains(&config.channel_configuration) {
+            return Err(CodecError::InvalidData(format!("AAC channel configuration {} cannot be stored in ADTS", config.channel_configuration)));
+        }
+        let sampling_frequency_index = sampling_frequency_index(config.sampling_frequency)
+            .ok_or_else(|| CodecError::InvalidData(format!("AAC sampling frequency {} cannot be stored in ADTS", config.sampling_frequency)))?;
+
+        Ok(AdtsWriter {
+            writer,
+            header: AdtsHeader {
+                mpeg2: false,
+                protection_absent: true,
+                object_type: config.object_type,
+                sampling_frequency_index,
+                channel_configuration: config.channel_configuration,
+                frame_length: 0,
+                buffer_fullness: 0x7ff,
+                raw_data_blocks: 1,
+            },
+        })
+    }
+
+    ///
+    /// Writes a raw AAC frame (such as the data of a block) with an ADTS header.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the frame is too long for an ADTS header or if there is a problem writing to the destination.
+    ///
+    pub fn write_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        let frame_length = frame.len() + 7;
+        if frame_length >= 1 << 13 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("AAC frame of {} bytes is too long for an ADTS header", frame.len())));
+        }
+
+        self.header.frame_length = frame_length;
+        let header = self.header.to_bytes()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        self.writer.write_all(&header)?;
+        self.writer.write_all(frame)
+    }
+
+    ///
+    /// Returns a mutable reference to the underlying writer.
+    ///
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    ///
+    /// Consumes the writer, returning the underlying destination.
+    ///
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{read_adts_frames, AdtsHeader, AdtsWriter, AudioSpecificConfig};
+
+    #[test]
+    fn parses_he_aac_signalling() {
+        // Implicit: object type 29 (PS), 24kHz core, stereo, 48kHz extension, AAC LC core
+        let implicit = AudioSpecificConfig::try_from(&[0xeb, 0x11, 0x88, 0x00][..]).unwrap();
+        assert_eq!(2, implicit.object_type);
+        assert_eq!(24000, implicit.sampling_frequency);
+        assert_eq!(48000, implicit.output_sampling_frequency());
+        assert!(implicit.sbr && implicit.ps);
+
+        // Explicit signalling round trips through to_bytes
+        let explicit = AudioSpecificConfig { sbr: true, ps: false, extension_sampling_frequency: Some(44100), ..implicit };
+        let explicit = AudioSpecificConfig { sampling_frequency: 22050, ..explicit };
+        let parsed = AudioSpecificConfig::try_from(explicit.to_bytes().as_slice()).unwrap();
+        assert_eq!(explicit, parsed);
+
+        assert!(AudioSpecificConfig::try_from(&[0x17, 0x90][..]).is_err());
+    }
+
+    #[test]
+    fn round_trips_adts_frames() {
+        let config = AudioSpecificConfig::try_from(&[0x11, 0x90][..]).unwrap();
+        let mut writer = AdtsWriter::new(Vec::new(), &config).unwrap();
+        writer.write_frame(&[0x01; 20]).unwrap();
+        writer.write_frame(&[0x02; 300]).unwrap();
+        let data = writer.into_inner();
+
+        let frames = read_adts_frames(&data).unwrap();
+        assert_eq!(2, frames.len());
+        assert_eq!(&[0x01; 20][..], frames[0].1);
+        assert_eq!(&[0x02; 300][..], frames[1].1);
+        assert_eq!(config.to_bytes(), frames[0].0.audio_specific_config().unwrap().to_bytes());
+
+        assert!(read_adts_frames(&data[..data.len() - 1]).is_err());
+        assert!(AdtsWriter::new(Vec::new(), &AudioSpecificConfig { channel_configuration: 0, ..config }).is_err());
+
+        let header = frames[0].0;
+        assert_eq!(&data[..7], header.to_bytes().unwrap());
+        assert!(AdtsHeader { object_type: 0, ..header }.to_bytes().is_err());
+        assert!(AdtsHeader { raw_data_blocks: 0, ..header }.to_bytes().is_err());
+        assert!(AdtsHeader { frame_length: 1 << 13, ..header }.to_bytes().is_err());
+    }
+}
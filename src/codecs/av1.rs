@@ -0,0 +1,724 @@
+//!
+//! Parses AV1 OBUs and the "V_AV1" "CodecPrivate" (the AV1 codec configuration record, or `av1C`) as defined in the [AV1 bitstream specification](https://aomediacodec.github.io/av1-spec/) and the [AV1 in Matroska mapping](https://github.com/ietf-wg-cellar/matroska-specification/blob/master/codec/av1.md).
+//!
+//! Each "V_AV1" block holds one temporal unit - a sequence of OBUs (open bitstream units) that all have a size field.  Keyframe blocks must start with a sequence header OBU, which is also stored in the track "CodecPrivate".
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use super::bits::BitReader;
+
+///
+/// The type of an AV1 OBU.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObuType {
+    SequenceHeader,
+    TemporalDelimiter,
+    FrameHeader,
+    TileGroup,
+    Metadata,
+    Frame,
+    RedundantFrameHeader,
+    TileList,
+    Padding,
+    Reserved(u8),
+}
+
+impl From<u8> for ObuType {
+    fn from(val: u8) -> Self {
+        match val {
+            1 => ObuType::SequenceHeader,
+            2 => ObuType::TemporalDelimiter,
+            3 => ObuType::FrameHeader,
+            4 => ObuType::TileGroup,
+            5 => ObuType::Metadata,
+            6 => ObuType::Frame,
+            7 => ObuType::RedundantFrameHeader,
+            8 => ObuType::TileList,
+            15 => ObuType::Padding,
+            other => ObuType::Reserved(other),
+        }
+    }
+}
+
+impl From<ObuType> for u8 {
+    fn from(val: ObuType) -> Self {
+        match val {
+            ObuType::SequenceHeader => 1,
+            ObuType::TemporalDelimiter => 2,
+            ObuType::FrameHeader => 3,
+            ObuType::TileGroup => 4,
+            ObuType::Metadata => 5,
+            ObuType::Frame => 6,
+            ObuType::RedundantFrameHeader => 7,
+            ObuType::TileList => 8,
+            ObuType::Padding => 15,
+            ObuType::Reserved(other) => other,
+        }
+    }
+}
+
+///
+/// A single OBU read from a block frame or "CodecPrivate".
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Obu<'a> {
+    pub obu_type: ObuType,
+
+    /// The temporal and spatial layer ids, if the OBU has an extension header.
+    pub extension: Option<(u8, u8)>,
+
+    /// The OBU payload, not including the OBU header or size field.
+    pub payload: &'a [u8],
+}
+
+impl Obu<'_> {
+    ///
+    /// Writes this OBU with its header and a size field, which is the form required in Matroska blocks and the av1C.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.payload.len() + 10);
+        let header = (u8::from(self.obu_type) & 0x0f) << 3 | (self.extension.is_some() as u8) << 2 | 0x02;
+        data.push(header);
+        if let Some((temporal_id, spatial_id)) = self.extension {
+            data.push((temporal_id & 0x07) << 5 | (spatial_id & 0x03) << 3);
+        }
+        write_leb128(&mut data, self.payload.len() as u64);
+        data.extend_from_slice(self.payload);
+        data
+    }
+}
+
+///
+/// Splits block frame data into OBUs.
+///
+/// An OBU without a size field is assumed to run to the end of the data, as allowed by the AV1 spec for the last OBU in a temporal unit.
+///
+/// # Errors
+///
+/// This function returns an error if an OBU header is invalid or an OBU is larger than the remaining data.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::av1::{read_obus, ObuType};
+///
+/// // A temporal delimiter followed by a padding OBU with a 2 byte payload
+/// let obus = read_obus(&[0x12, 0x00, 0x7a, 0x02, 0xff, 0xff]).unwrap();
+/// assert_eq!(ObuType::TemporalDelimiter, obus[0].obu_type);
+/// assert_eq!(ObuType::Padding, obus[1].obu_type);
+/// assert_eq!(&[0xff, 0xff], obus[1].payload);
+/// ```
+///
+pub fn read_obus(data: &[u8]) -> Result<Vec<Obu<'_>>, CodecError> {
+    let mut obus = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let header = data[position];
+        if header & 0x80 != 0 {
+            return Err(CodecError::InvalidData(format!("AV1 OBU header at offset {position} has its forbidden bit set")));
+        }
+        let obu_type = ObuType::from((header >> 3) & 0x0f);
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+        position += 1;
+
+        let extension = if has_extension {
+            let extension = *data.get(position)
+                .ok_or_else(|| CodecError::TruncatedData(String::from("AV1 OBU ended before its extension header")))?;
+            position += 1;
+            Some((extension >> 5, (extension >> 3) & 0x03))
+        } else {
+            None
+        };
+
+        let size = if has_size {
+            let (size, length) = read_leb128(&data[position..])?;
+            position += length;
+            usize::try_from(size).map_err(|_| CodecError::InvalidData(format!("AV1 OBU size {size} is too large")))?
+        } else {
+            data.len() - position
+        };
+
+        if size > data.len() - position {
+            return Err(CodecError::TruncatedData(format!("AV1 OBU of {size} bytes is larger than the {} bytes remaining", data.len() - position)));
+        }
+
+        obus.push(Obu { obu_type, extension, payload: &data[position..position + size] });
+        position += size;
+    }
+    Ok(obus)
+}
+
+///
+/// Checks whether block frame data is a keyframe.
+///
+/// Per the Matroska mapping, a keyframe block starts with a sequence header OBU followed by a shown frame with a frame type of KEY_FRAME.  Blocks without a sequence header are never reported as keyframes.
+///
+/// # Errors
+///
+/// This function returns an error if the OBUs or sequence header can't be parsed.
+///
+pub fn is_keyframe(data: &[u8]) -> Result<bool, CodecError> {
+    let mut sequence_header = None;
+    for obu in read_obus(data)? {
+        match obu.obu_type {
+            ObuType::SequenceHeader => sequence_header = Some(SequenceHeader::try_from(obu.payload)?),
+            ObuType::Frame | ObuType::FrameHeader => {
+                return match sequence_header {
+                    Some(sequence_header) => {
+                        let frame_header = FrameHeader::read(obu.payload, &sequence_header)?;
+                        Ok(frame_header.frame_type == Some(FrameType::Key) && frame_header.show_frame)
+                    },
+                    None => Ok(false),
+                };
+            },
+            _ => {},
+        }
+    }
+    Ok(false)
+}
+
+///
+/// The AV1 frame types.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameType {
+    Key,
+    Inter,
+    IntraOnly,
+    Switch,
+}
+
+///
+/// The start of an AV1 uncompressed frame header, read from a frame header or frame OBU.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// If set, this frame re-shows the reference frame at the given index instead of coding a new frame.
+    pub show_existing_frame: Option<u8>,
+
+    /// The frame type.  This is `None` when `show_existing_frame` is set, since the type then comes from the reference frame.
+    pub frame_type: Option<FrameType>,
+
+    pub show_frame: bool,
+}
+
+impl FrameHeader {
+    ///
+    /// Reads the start of a frame header OBU payload.  The sequence header is needed to know whether the header is in the reduced still picture form.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the payload is too short.
+    ///
+    pub fn read(payload: &[u8], sequence_header: &SequenceHeader) -> Result<Self, CodecError> {
+        if sequence_header.reduced_still_picture_header {
+            return Ok(FrameHeader { show_existing_frame: None, frame_type: Some(FrameType::Key), show_frame: true });
+        }
+
+        let mut reader = BitReader::new(payload);
+        if reader.read_bit()? {
+            return Ok(FrameHeader { show_existing_frame: Some(reader.read_bits(3)? as u8), frame_type: None, show_frame: true });
+        }
+
+        let frame_type = match reader.read_bits(2)? {
+            0 => FrameType::Key,
+            1 => FrameType::Inter,
+            2 => FrameType::IntraOnly,
+            _ => FrameType::Switch,
+        };
+        Ok(FrameHeader { show_existing_frame: None, frame_type: Some(frame_type), show_frame: reader.read_bit()? })
+    }
+}
+
+///
+/// The color configuration from an AV1 sequence header.
+///
+/// `color_primaries`, `transfer_characteristics`, and `matrix_coefficients` use the ISO/IEC 23091-4/ITU-T H.273 values, which are also used by Matroska.  They are 2 (unspecified) when the sequence header doesn't describe them.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorConfig {
+    pub bit_depth: u8,
+    pub mono_chrome: bool,
+    pub color_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+
+    /// Whether the full range of values is used (as opposed to studio swing).
+    pub full_range: bool,
+
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+
+    /// The chroma sample position for 4:2:0 content (0 = unknown, 1 = vertical, 2 = colocated).
+    pub chroma_sample_position: u8,
+}
+
+impl ColorConfig {
+    ///
+    /// Builds the children of a Matroska "Colour" element describing this color configuration.
+    ///
+    pub fn colour_children(&self) -> Vec<MatroskaSpec> {
+        let mut children = vec![
+            MatroskaSpec::MatrixCoefficients(self.matrix_coefficients as u64),
+            MatroskaSpec::BitsPerChannel(self.bit_depth as u64),
+            MatroskaSpec::ChromaSubsamplingHorz(self.subsampling_x as u64),
+            MatroskaSpec::ChromaSubsamplingVert(self.subsampling_y as u64),
+        ];
+        match self.chroma_sample_position {
+            1 => children.extend([MatroskaSpec::ChromaSitingHorz(1), MatroskaSpec::ChromaSitingVert(2)]),
+            2 => children.extend([MatroskaSpec::ChromaSitingHorz(1), MatroskaSpec::ChromaSitingVert(1)]),
+            _ => {},
+        }
+        children.extend([
+            MatroskaSpec::Range(if self.full_range { 2 } else { 1 }),
+            MatroskaSpec::TransferCharacteristics(self.transfer_characteristics as u64),
+            MatroskaSpec::Primaries(self.color_primaries as u64),
+        ]);
+        children
+    }
+
+    ///
+    /// Builds a Matroska "Colour" element describing this color configuration.  See [`Self::colour_children()`].
+    ///
+    pub fn to_colour(&self) -> MatroskaSpec {
+        MatroskaSpec::Colour(Master::Full(self.colour_children()))
+    }
+}
+
+///
+/// The parameters of an AV1 sequence header OBU.
+///
+/// Only the fields needed to describe a track are kept.  The level and tier are those of the first operating point, which is what the av1C records.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SequenceHeader {
+    pub profile: u8,
+    pub still_picture: bool,
+    pub reduced_still_picture_header: bool,
+
+    /// The `seq_level_idx` of the first operating point.
+    pub level: u8,
+
+    /// The `seq_tier` of the first operating point (0 = main, 1 = high).
+    pub tier: u8,
+
+    /// The `initial_display_delay_minus_1` of the first operating point, if present.
+    pub initial_display_delay_minus_1: Option<u8>,
+
+    pub max_frame_width: u32,
+    pub max_frame_height: u32,
+    pub color_config: ColorConfig,
+    pub film_grain_params_present: bool,
+}
+
+impl TryFrom<&[u8]> for SequenceHeader {
+    type Error = CodecError;
+
+    ///
+    /// Parses a sequence header OBU payload (not including the OBU header).
+    ///
+    fn try_from(payload: &[u8]) -> Result<Self, Self::Error> {
+        let mut reader = BitReader::new(payload);
+        let profile = reader.read_bits(3)? as u8;
+        if profile > 2 {
+            return Err(CodecError::InvalidData(format!("AV1 sequence header has unknown profile {profile}")));
+        }
+        let still_picture = reader.read_bit()?;
+        let reduced_still_picture_header = reader.read_bit()?;
+
+        let mut level = 0;
+        let mut tier = 0;
+        let mut initial_display_delay_minus_1 = None;
+        if reduced_still_picture_header {
+            level = reader.read_bits(5)? as u8;
+        } else {
+            let mut buffer_delay_length = 0;
+            let decoder_model_info_present = if reader.read_bit()? {
+                reader.skip_bits(64)?; // num_units_in_display_tick, time_scale
+                if reader.read_bit()? {
+                    read_uvlc(&mut reader)?; // num_ticks_per_picture_minus_1
+                }
+                let present = reader.read_bit()?;
+                if present {
+                    buffer_delay_length = reader.read_bits(5)? as usize + 1;
+                    reader.skip_bits(32 + 5 + 5)?; // num_units_in_decoding_tick, buffer_removal_time_length_minus_1, frame_presentation_time_length_minus_1
+                }
+                present
+            } else {
+                false
+            };
+
+            let initial_display_delay_present = reader.read_bit()?;
+            let operating_points = reader.read_bits(5)? + 1;
+            for index in 0..operating_points {
+                reader.skip_bits(12)?; // operating_point_idc
+                let op_level = reader.read_bits(5)? as u8;
+                let op_tier = if op_level > 7 { reader.read_bit()? as u8 } else { 0 };
+                if decoder_model_info_present && reader.read_bit()? {
+                    reader.skip_bits(buffer_delay_length * 2 + 1)?;
+                }
+                let op_delay = if initial_display_delay_present && reader.read_bit()? {
+                    Some(reader.read_bits(4)? as u8)
+                } else {
+                    None
+                };
+
+                if index == 0 {
+                    level = op_level;
+                    tier = op_tier;
+                    initial_display_delay_minus_1 = op_delay;
+                }
+            }
+        }
+
+        let width_bits = reader.read_bits(4)? as usize + 1;
+        let height_bits = reader.read_bits(4)? as usize + 1;
+        let max_frame_width = reader.read_bits(width_bits)? as u32 + 1;
+        let max_frame_height = reader.read_bits(height_bits)? as u32 + 1;
+
+        if !reduced_still_picture_header && reader.read_bit()? {
+            reader.skip_bits(4 + 3)?; // delta_frame_id_length_minus_2, additional_frame_id_length_minus_1
+        }
+        reader.skip_bits(3)?; // use_128x128_superblock, enable_filter_intra, enable_intra_edge_filter
+
+        if !reduced_still_picture_header {
+            reader.skip_bits(4)?; // enable_interintra_compound, enable_masked_compound, enable_warped_motion, enable_dual_filter
+            let enable_order_hint = reader.read_bit()?;
+            if enable_order_hint {
+                reader.skip_bits(2)?; // enable_jnt_comp, enable_ref_frame_mvs
+            }
+            let force_screen_content_tools = if reader.read_bit()? { 2 } else { reader.read_bits(1)? };
+            if force_screen_content_tools > 0 && !reader.read_bit()? {
+                reader.skip_bits(1)?; // seq_force_integer_mv
+            }
+            if enable_order_hint {
+                reader.skip_bits(3)?; // order_hint_bits_minus_1
+            }
+        }
+
+        reader.skip_bits(3)?; // enable_superres, enable_cdef, enable_restoration
+        let color_config = read_color_config(&mut reader, profile)?;
+        let film_grain_params_present = reader.read_bit()?;
+
+        Ok(SequenceHeader {
+            profile,
+            still_picture,
+            reduced_still_picture_header,
+            level,
+            tier,
+            initial_display_delay_minus_1,
+            max_frame_width,
+            max_frame_height,
+            color_config,
+            film_grain_params_present,
+        })
+    }
+}
+
+fn read_color_config(reader: &mut BitReader, profile: u8) -> Result<ColorConfig, CodecError> {
+    let high_bitdepth = reader.read_bit()?;
+    let bit_depth = if profile == 2 && high_bitdepth {
+        if reader.read_bit()? { 12 } else { 10 }
+    } else if high_bitdepth {
+        10
+    } else {
+        8
+    };
+
+    let mono_chrome = if profile == 1 { false } else { reader.read_bit()? };
+    let (color_primaries, transfer_characteristics, matrix_coefficients) = if reader.read_bit()? {
+        (reader.read_bits(8)? as u8, reader.read_bits(8)? as u8, reader.read_bits(8)? as u8)
+    } else {
+        (2, 2, 2)
+    };
+
+    let mut config = ColorConfig {
+        bit_depth,
+        mono_chrome,
+        color_primaries,
+        transfer_characteristics,
+        matrix_coefficients,
+        full_range: false,
+        subsampling_x: true,
+        subsampling_y: true,
+        chroma_sample_position: 0,
+    };
+
+    if mono_chrome {
+        config.full_range = reader.read_bit()?;
+        return Ok(config);
+    }
+
+    if color_primaries == 1 && transfer_characteristics == 13 && matrix_coefficients == 0 {
+        // sRGB is always full range 4:4:4
+        config.full_range = true;
+        config.subsampling_x = false;
+        config.subsampling_y = false;
+    } else {
+        config.full_range = reader.read_bit()?;
+        match profile {
+            0 => {},
+            1 => {
+                config.subsampling_x = false;
+                config.subsampling_y = false;
+            },
+            _ => {
+                if bit_depth == 12 {
+                    config.subsampling_x = reader.read_bit()?;
+                    config.subsampling_y = config.subsampling_x && reader.read_bit()?;
+                } else {
+                    config.subsampling_y = false;
+                }
+            },
+        }
+        if config.subsampling_x && config.subsampling_y {
+            config.chroma_sample_position = reader.read_bits(2)? as u8;
+        }
+    }
+    reader.skip_bits(1)?; // separate_uv_delta_q
+
+    Ok(config)
+}
+
+///
+/// The AV1 codec configuration record (`av1C`) stored in the "CodecPrivate" of "V_AV1" tracks.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::av1::CodecConfiguration;
+///
+/// let data = [0x81, 0x08, 0x0c, 0x00];
+/// let config = CodecConfiguration::try_from(&data[..]).unwrap();
+/// assert_eq!(0, config.profile);
+/// assert_eq!(8, config.level);
+/// assert!(config.subsampling_x && config.subsampling_y);
+/// assert_eq!(data.to_vec(), config.to_bytes());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodecConfiguration {
+    pub profile: u8,
+    pub level: u8,
+    pub tier: u8,
+    pub high_bitdepth: bool,
+    pub twelve_bit: bool,
+    pub mono_chrome: bool,
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+    pub chroma_sample_position: u8,
+    pub initial_presentation_delay_minus_1: Option<u8>,
+
+    /// The configuration OBUs (normally the sequence header OBU), stored with their headers and size fields.
+    pub config_obus: Vec<u8>,
+}
+
+impl CodecConfiguration {
+    ///
+    /// Builds a configuration record from a sequence header OBU, such as one found at the start of a keyframe block.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the OBU isn't a valid sequence header.
+    ///
+    pub fn from_sequence_header(obu: &Obu) -> Result<Self, CodecError> {
+        if obu.obu_type != ObuType::SequenceHeader {
+            return Err(CodecError::InvalidData(format!("Expected an AV1 sequence header OBU, found {:?}", obu.obu_type)));
+        }
+
+        let header = SequenceHeader::try_from(obu.payload)?;
+        let color = header.color_config;
+        Ok(CodecConfiguration {
+            profile: header.profile,
+            level: header.level,
+            tier: header.tier,
+            high_bitdepth: color.bit_depth > 8,
+            twelve_bit: color.bit_depth == 12,
+            mono_chrome: color.mono_chrome,
+            subsampling_x: color.subsampling_x,
+            subsampling_y: color.subsampling_y,
+            chroma_sample_position: color.chroma_sample_position,
+            initial_presentation_delay_minus_1: header.initial_display_delay_minus_1,
+            config_obus: obu.to_bytes(),
+        })
+    }
+
+    ///
+    /// Builds a configuration record from the first sequence header OBU in block frame data.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the data doesn't contain a valid sequence header.
+    ///
+    pub fn from_frame(data: &[u8]) -> Result<Self, CodecError> {
+        let obus = read_obus(data)?;
+        let sequence_header = obus.iter().find(|obu| obu.obu_type == ObuType::SequenceHeader)
+            .ok_or_else(|| CodecError::InvalidData(String::from("AV1 frame does not contain a sequence header")))?;
+        Self::from_sequence_header(sequence_header)
+    }
+
+    ///
+    /// Parses the sequence header stored in the configuration OBUs, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the configuration OBUs can't be parsed.
+    ///
+    pub fn sequence_header(&self) -> Result<Option<SequenceHeader>, CodecError> {
+        read_obus(&self.config_obus)?.iter()
+            .find(|obu| obu.obu_type == ObuType::SequenceHeader)
+            .map(|obu| SequenceHeader::try_from(obu.payload))
+            .transpose()
+    }
+
+    ///
+    /// Writes the configuration record in the form stored in "CodecPrivate".
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![
+            0x81,
+            (self.profile & 0x07) << 5 | (self.level & 0x1f),
+            (self.tier & 0x01) << 7
+                | (self.high_bitdepth as u8) << 6
+                | (self.twelve_bit as u8) << 5
+                | (self.mono_chrome as u8) << 4
+                | (self.subsampling_x as u8) << 3
+                | (self.subsampling_y as u8) << 2
+                | (self.chroma_sample_position & 0x03),
+            match self.initial_presentation_delay_minus_1 {
+                Some(delay) => 0x10 | (delay & 0x0f),
+                None => 0,
+            },
+        ];
+        data.extend_from_slice(&self.config_obus);
+        data
+    }
+}
+
+impl TryFrom<&[u8]> for CodecConfiguration {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 4 {
+            return Err(CodecError::TruncatedData(String::from("AV1 codec configuration record is shorter than 4 bytes")));
+        }
+        if data[0] != 0x81 {
+            return Err(CodecError::InvalidData(format!("AV1 codec configuration record has unknown marker/version byte {:#04x}", data[0])));
+        }
+
+        Ok(CodecConfiguration {
+            profile: data[1] >> 5,
+            level: data[1] & 0x1f,
+            tier: data[2] >> 7,
+            high_bitdepth: data[2] & 0x40 != 0,
+            twelve_bit: data[2] & 0x20 != 0,
+            mono_chrome: data[2] & 0x10 != 0,
+            subsampling_x: data[2] & 0x08 != 0,
+            subsampling_y: data[2] & 0x04 != 0,
+            chroma_sample_position: data[2] & 0x03,
+            initial_presentation_delay_minus_1: if data[3] & 0x10 != 0 { Some(data[3] & 0x0f) } else { None },
+            config_obus: data[4..].to_vec(),
+        })
+    }
+}
+
+fn read_leb128(data: &[u8]) -> Result<(u64, usize), CodecError> {
+    let mut value = 0u64;
+    for (index, byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as u64) << (index * 7);
+        if byte & 0x80 == 0 {
+            return Ok((value, index + 1));
+        }
+    }
+    Err(CodecError::TruncatedData(String::from("AV1 OBU size field is truncated or longer than 8 bytes")))
+}
+
+fn write_leb128(data: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+fn read_uvlc(reader: &mut BitReader) -> Result<u64, CodecError> {
+    let mut leading_zeros = 0;
+    while !reader.read_bit()? {
+        leading_zeros += 1;
+        if leading_zeros >= 32 {
+            return Ok(u32::MAX as u64);
+        }
+    }
+    Ok(reader.read_bits(leading_zeros)? + (1 << leading_zeros) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{is_keyframe, read_obus, CodecConfiguration, ObuType, SequenceHeader};
+
+    // A 1920x1080 profile 0, level 4.0 (8), 8 bit 4:2:0 BT.709 sequence header OBU
+    const SEQUENCE_HEADER: [u8; 16] = [0x0a, 0x0e, 0x00, 0x00, 0x00, 0x42, 0xab, 0xbf, 0xc3, 0x73, 0xff, 0xe6, 0x40, 0x40, 0x40, 0x41];
+
+    // Frame OBUs starting with show_existing_frame 0, a frame_type of KEY_FRAME or INTER_FRAME, and show_frame 1
+    const KEY_FRAME_OBU: [u8; 3] = [0x32, 0x01, 0x10];
+    const INTER_FRAME_OBU: [u8; 3] = [0x32, 0x01, 0x30];
+
+    #[test]
+    fn parses_sequence_header() {
+        let obus = read_obus(&SEQUENCE_HEADER).unwrap();
+        assert_eq!(1, obus.len());
+        assert_eq!(ObuType::SequenceHeader, obus[0].obu_type);
+
+        let header = SequenceHeader::try_from(obus[0].payload).unwrap();
+        assert_eq!(0, header.profile);
+        assert_eq!(8, header.level);
+        assert_eq!(0, header.tier);
+        assert_eq!((1920, 1080), (header.max_frame_width, header.max_frame_height));
+        assert_eq!(8, header.color_config.bit_depth);
+        assert_eq!((1, 1, 1), (header.color_config.color_primaries, header.color_config.transfer_characteristics, header.color_config.matrix_coefficients));
+        assert!(header.color_config.subsampling_x && header.color_config.subsampling_y);
+        assert!(!header.color_config.full_range);
+    }
+
+    #[test]
+    fn builds_codec_configuration() {
+        let mut frame = vec![0x12, 0x00];
+        frame.extend_from_slice(&SEQUENCE_HEADER);
+        frame.extend_from_slice(&KEY_FRAME_OBU);
+
+        let config = CodecConfiguration::from_frame(&frame).unwrap();
+        let bytes = config.to_bytes();
+        assert_eq!(&[0x81, 0x08, 0x0c, 0x00], &bytes[..4]);
+        assert_eq!(&SEQUENCE_HEADER, &bytes[4..]);
+
+        let parsed = CodecConfiguration::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(config, parsed);
+        assert_eq!(1920, parsed.sequence_header().unwrap().unwrap().max_frame_width);
+    }
+
+    #[test]
+    fn detects_keyframes() {
+        let mut keyframe = SEQUENCE_HEADER.to_vec();
+        keyframe.extend_from_slice(&KEY_FRAME_OBU);
+        assert!(is_keyframe(&keyframe).unwrap());
+
+        let mut inter_frame = SEQUENCE_HEADER.to_vec();
+        inter_frame.extend_from_slice(&INTER_FRAME_OBU);
+        assert!(!is_keyframe(&inter_frame).unwrap());
+
+        assert!(!is_keyframe(&KEY_FRAME_OBU).unwrap());
+        assert!(read_obus(&[0x0a, 0x05, 0x00]).is_err());
+    }
+}
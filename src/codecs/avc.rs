@@ -0,0 +1,570 @@
+//!
+//! Parses the "V_MPEG4/ISO/AVC" "CodecPrivate" (the AVC decoder configuration record, or `avcC`) and H.264 sequence parameter sets as defined in ISO/IEC 14496-15 and [ITU-T H.264](https://www.itu.int/rec/T-REC-H.264).
+//!
+//! Matroska blocks store H.264 NAL units with length prefixes, and keep the sequence and picture parameter sets (SPS and PPS) in "CodecPrivate" instead of in the stream.  [`to_annex_b()`] and [`from_annex_b()`] convert block frames to and from the Annex-B byte stream format used by raw `.h264` files and many hardware decoders.
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use super::bits::BitReader;
+use super::nal::{check_length_size, split_annex_b, split_length_prefixed, unescape_rbsp, write_annex_b, write_length_prefixed};
+
+///
+/// The NAL unit type of a sequence parameter set.
+///
+pub const NAL_TYPE_SPS: u8 = 7;
+
+///
+/// The NAL unit type of a picture parameter set.
+///
+pub const NAL_TYPE_PPS: u8 = 8;
+
+///
+/// The NAL unit type of an IDR (keyframe) slice.
+///
+pub const NAL_TYPE_IDR: u8 = 5;
+
+///
+/// Returns the type of an H.264 NAL unit.
+///
+pub fn nal_type(unit: &[u8]) -> Option<u8> {
+    unit.first().map(|header| header & 0x1f)
+}
+
+///
+/// The extra fields stored in an `avcC` for the High profiles.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HighProfileExtension {
+    pub chroma_format: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub sps_ext: Vec<Vec<u8>>,
+}
+
+///
+/// The AVC decoder configuration record stored in the "CodecPrivate" of "V_MPEG4/ISO/AVC" tracks.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::avc::AvcConfiguration;
+///
+/// let data = [0x01, 0x42, 0xc0, 0x1e, 0xff, 0xe1, 0x00, 0x03, 0x67, 0x42, 0xc0, 0x01, 0x00, 0x02, 0x68, 0xce];
+/// let config = AvcConfiguration::try_from(&data[..]).unwrap();
+/// assert_eq!(66, config.profile);
+/// assert_eq!(30, config.level);
+/// assert_eq!(4, config.nal_length_size);
+/// assert_eq!(vec![vec![0x67, 0x42, 0xc0]], config.sps);
+/// assert_eq!(vec![vec![0x68, 0xce]], config.pps);
+/// assert_eq!(data.to_vec(), config.to_bytes().unwrap());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AvcConfiguration {
+    pub version: u8,
+    pub profile: u8,
+    pub profile_compatibility: u8,
+    pub level: u8,
+
+    /// The size in bytes of the length prefix before each NAL unit in blocks.
+    pub nal_length_size: u8,
+
+    pub sps: Vec<Vec<u8>>,
+    pub pps: Vec<Vec<u8>>,
+    pub high_profile_extension: Option<HighProfileExtension>,
+}
+
+impl AvcConfiguration {
+    ///
+    /// Builds a configuration record from parameter set NAL units, such as those found at the start of an Annex-B stream.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if there is no SPS, the first SPS is too short to read the profile and level, or `nal_length_size` isn't 1, 2, or 4.
+    ///
+    pub fn from_parameter_sets(sps: Vec<Vec<u8>>, pps: Vec<Vec<u8>>, nal_length_size: u8) -> Result<Self, CodecError> {
+        check_length_size(nal_length_size)?;
+        let first = sps.first().ok_or_else(|| CodecError::InvalidData(String::from("An AVC configuration needs at least one SPS")))?;
+        if first.len() < 4 {
+            return Err(CodecError::TruncatedData(String::from("SPS is too short to contain a profile and level")));
+        }
+
+        let high_profile_extension = if matches!(first[1], 100 | 110 | 122 | 144) {
+            let parsed = Sps::try_from(first.as_slice())?;
+            Some(HighProfileExtension {
+                chroma_format: parsed.chroma_format_idc,
+                bit_depth_luma_minus8: parsed.bit_depth_luma - 8,
+                bit_depth_chroma_minus8: parsed.bit_depth_chroma - 8,
+                sps_ext: Vec::new(),
+            })
+        } else {
+            None
+        };
+
+        Ok(AvcConfiguration {
+            version: 1,
+            profile: first[1],
+            profile_compatibility: first[2],
+            level: first[3],
+            nal_length_size,
+            sps,
+            pps,
+            high_profile_extension,
+        })
+    }
+
+    ///
+    /// Writes the configuration record in the form stored in "CodecPrivate".
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `nal_length_size` isn't 1, 2, or 4.
+    ///
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CodecError> {
+        check_length_size(self.nal_length_size)?;
+        let mut data = vec![
+            self.version,
+            self.profile,
+            self.profile_compatibility,
+            self.level,
+            0xfc | ((self.nal_length_size - 1) & 0x03),
+            0xe0 | (self.sps.len() as u8 & 0x1f),
+        ];
+        write_parameter_sets(&mut data, &self.sps);
+        data.push(self.pps.len() as u8);
+        write_parameter_sets(&mut data, &self.pps);
+
+        if let Some(extension) = &self.high_profile_extension {
+            data.push(0xfc | (extension.chroma_format & 0x03));
+            data.push(0xf8 | (extension.bit_depth_luma_minus8 & 0x07));
+            data.push(0xf8 | (extension.bit_depth_chroma_minus8 & 0x07));
+            data.push(extension.sps_ext.len() as u8);
+            write_parameter_sets(&mut data, &extension.sps_ext);
+        }
+        Ok(data)
+    }
+}
+
+impl TryFrom<&[u8]> for AvcConfiguration {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 7 {
+            return Err(CodecError::TruncatedData(String::from("AVC configuration record is shorter than 7 bytes")));
+        }
+        if data[0] != 1 {
+            return Err(CodecError::InvalidData(format!("AVC configuration record version {} is not supported", data[0])));
+        }
+
+        let nal_length_size = (data[4] & 0x03) + 1;
+        if nal_length_size == 3 {
+            return Err(CodecError::InvalidData(String::from("AVC configuration record has an invalid NAL length size of 3")));
+        }
+
+        let mut position = 6;
+        let sps = read_parameter_sets(data, &mut position, (data[5] & 0x1f) as usize)?;
+        let pps_count = *data.get(position).ok_or_else(|| CodecError::TruncatedData(String::from("AVC configuration record ended before its PPS count")))?;
+        position += 1;
+        let pps = read_parameter_sets(data, &mut position, pps_count as usize)?;
+
+        let high_profile_extension = if matches!(data[1], 100 | 110 | 122 | 144) && data.len() >= position + 4 {
+            let chroma_format = data[position] & 0x03;
+            let bit_depth_luma_minus8 = data[position + 1] & 0x07;
+            let bit_depth_chroma_minus8 = data[position + 2] & 0x07;
+            let sps_ext_count = data[position + 3] as usize;
+            position += 4;
+            Some(HighProfileExtension {
+                chroma_format,
+                bit_depth_luma_minus8,
+                bit_depth_chroma_minus8,
+                sps_ext: read_parameter_sets(data, &mut position, sps_ext_count)?,
+            })
+        } else {
+            None
+        };
+
+        Ok(AvcConfiguration {
+            version: data[0],
+            profile: data[1],
+            profile_compatibility: data[2],
+            level: data[3],
+            nal_length_size,
+            sps,
+            pps,
+            high_profile_extension,
+        })
+    }
+}
+
+fn read_parameter_sets(data: &[u8], position: &mut usize, count: usize) -> Result<Vec<Vec<u8>>, CodecError> {
+    let mut sets = Vec::with_capacity(count);
+    for _ in 0..count {
+        if data.len() < *position + 2 {
+            return Err(CodecError::TruncatedData(String::from("Configuration record ended before a parameter set length")));
+        }
+        let length = u16::from_be_bytes([data[*position], data[*position + 1]]) as usize;
+        *position += 2;
+        if data.len() < *position + length {
+            return Err(CodecError::TruncatedData(format!("Parameter set of {length} bytes is larger than the remaining configuration record")));
+        }
+        sets.push(data[*position..*position + length].to_vec());
+        *position += length;
+    }
+    Ok(sets)
+}
+
+fn write_parameter_sets(data: &mut Vec<u8>, sets: &[Vec<u8>]) {
+    for set in sets {
+        data.extend_from_slice(&(set.len() as u16).to_be_bytes());
+        data.extend_from_slice(set);
+    }
+}
+
+///
+/// Converts a block frame of length-prefixed NAL units into an Annex-B byte stream.
+///
+/// For keyframes, the SPS and PPS from the configuration record are inserted before the frame's NAL units unless the frame already starts with its own SPS, so that decoders can start decoding at any keyframe.
+///
+/// # Errors
+///
+/// This function returns an error if the frame can't be split using the configuration's NAL length size.
+///
+pub fn to_annex_b(frame: &[u8], config: &AvcConfiguration, keyframe: bool) -> Result<Vec<u8>, CodecError> {
+    let units = split_length_prefixed(frame, config.nal_length_size)?;
+    let has_sps = units.iter().any(|unit| nal_type(unit) == Some(NAL_TYPE_SPS));
+
+    let mut all_units: Vec<&[u8]> = Vec::with_capacity(units.len() + config.sps.len() + config.pps.len());
+    if keyframe && !has_sps {
+        all_units.extend(config.sps.iter().map(|sps| sps.as_slice()));
+        all_units.extend(config.pps.iter().map(|pps| pps.as_slice()));
+    }
+    all_units.extend(units);
+    Ok(write_annex_b(&all_units))
+}
+
+///
+/// Converts an Annex-B access unit into a block frame of length-prefixed NAL units.
+///
+/// # Errors
+///
+/// This function returns an error if a NAL unit is too large for `nal_length_size`.
+///
+pub fn from_annex_b(data: &[u8], nal_length_size: u8) -> Result<Vec<u8>, CodecError> {
+    write_length_prefixed(&split_annex_b(data), nal_length_size)
+}
+
+///
+/// The color information from the VUI parameters of an SPS.
+///
+/// `color_primaries`, `transfer_characteristics`, and `matrix_coefficients` use the ISO/IEC 23091-4/ITU-T H.273 values, which are also used by Matroska.  They are 2 (unspecified) when the VUI doesn't describe them.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorInfo {
+    pub full_range: bool,
+    pub color_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+}
+
+impl ColorInfo {
+    ///
+    /// Builds the children of a Matroska "Colour" element describing this color information.
+    ///
+    pub fn colour_children(&self) -> Vec<MatroskaSpec> {
+        vec![
+            MatroskaSpec::MatrixCoefficients(self.matrix_coefficients as u64),
+            MatroskaSpec::Range(if self.full_range { 2 } else { 1 }),
+            MatroskaSpec::TransferCharacteristics(self.transfer_characteristics as u64),
+            MatroskaSpec::Primaries(self.color_primaries as u64),
+        ]
+    }
+
+    ///
+    /// Builds a Matroska "Colour" element describing this color information.  See [`Self::colour_children()`].
+    ///
+    pub fn to_colour(&self) -> MatroskaSpec {
+        MatroskaSpec::Colour(Master::Full(self.colour_children()))
+    }
+}
+
+///
+/// The parts of an H.264 sequence parameter set that describe the video.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sps {
+    pub profile: u8,
+    pub constraint_flags: u8,
+    pub level: u8,
+    pub id: u32,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+
+    /// The display width in pixels, after cropping.
+    pub width: u32,
+
+    /// The display height in pixels, after cropping.
+    pub height: u32,
+
+    /// The sample aspect ratio from the VUI, if present.
+    pub sample_aspect_ratio: Option<(u16, u16)>,
+
+    /// The color information from the VUI, if a video signal type is present.
+    pub color: Option<ColorInfo>,
+}
+
+impl TryFrom<&[u8]> for Sps {
+    type Error = CodecError;
+
+    ///
+    /// Parses an SPS NAL unit, including its one byte NAL header.
+    ///
+    fn try_from(unit: &[u8]) -> Result<Self, Self::Error> {
+        if nal_type(unit) != Some(NAL_TYPE_SPS) {
+            return Err(CodecError::InvalidData(String::from("NAL unit is not an SPS")));
+        }
+
+        let rbsp = unescape_rbsp(&unit[1..]);
+        let mut reader = BitReader::new(&rbsp);
+        let profile = reader.read_bits(8)? as u8;
+        let constraint_flags = reader.read_bits(8)? as u8;
+        let level = reader.read_bits(8)? as u8;
+        let id = reader.read_ue()? as u32;
+
+        let mut chroma_format_idc = 1;
+        let mut separate_colour_plane = false;
+        let mut bit_depth_luma = 8;
+        let mut bit_depth_chroma = 8;
+        if matches!(profile, 100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135) {
+            chroma_format_idc = match reader.read_ue()? {
+                value @ 0..=3 => value as u8,
+                value => return Err(CodecError::InvalidData(format!("SPS chroma_format_idc {value} is out of range"))),
+            };
+            if chroma_format_idc == 3 {
+                separate_colour_plane = reader.read_bit()?;
+            }
+            bit_depth_luma = read_bit_depth(&mut reader)?;
+            bit_depth_chroma = read_bit_depth(&mut reader)?;
+            reader.skip_bits(1)?; // qpprime_y_zero_transform_bypass_flag
+            if reader.read_bit()? {
+                let list_count = if chroma_format_idc == 3 { 12 } else { 8 };
+                for index in 0..list_count {
+                    if reader.read_bit()? {
+                        skip_scaling_list(&mut reader, if index < 6 { 16 } else { 64 })?;
+                    }
+                }
+            }
+        }
+
+        reader.read_ue()?; // log2_max_frame_num_minus4
+        match reader.read_ue()? {
+            0 => {
+                reader.read_ue()?; // log2_max_pic_order_cnt_lsb_minus4
+            },
+            1 => {
+                reader.skip_bits(1)?; // delta_pic_order_always_zero_flag
+                reader.read_se()?; // offset_for_non_ref_pic
+                reader.read_se()?; // offset_for_top_to_bottom_field
+                for _ in 0..reader.read_ue()? {
+                    reader.read_se()?; // offset_for_ref_frame
+                }
+            },
+            _ => {},
+        }
+        reader.read_ue()?; // max_num_ref_frames
+        reader.skip_bits(1)?; // gaps_in_frame_num_value_allowed_flag
+
+        let width_in_mbs = reader.read_ue()? + 1;
+        let height_in_map_units = reader.read_ue()? + 1;
+        let frame_mbs_only = reader.read_bit()?;
+        if !frame_mbs_only {
+            reader.skip_bits(1)?; // mb_adaptive_frame_field_flag
+        }
+        reader.skip_bits(1)?; // direct_8x8_inference_flag
+
+        let (mut crop_left, mut crop_right, mut crop_top, mut crop_bottom) = (0, 0, 0, 0);
+        if reader.read_bit()? {
+            crop_left = reader.read_ue()?;
+            crop_right = reader.read_ue()?;
+            crop_top = reader.read_ue()?;
+            crop_bottom = reader.read_ue()?;
+        }
+
+        let field_factor = if frame_mbs_only { 1 } else { 2 };
+        let (crop_unit_x, crop_unit_y) = match (separate_colour_plane, chroma_format_idc) {
+            (true, _) | (false, 0) => (1, field_factor),
+            (false, 1) => (2, 2 * field_factor),
+            (false, 2) => (2, field_factor),
+            _ => (1, field_factor),
+        };
+        let width = (width_in_mbs * 16).saturating_sub(crop_unit_x * (crop_left + crop_right)) as u32;
+        let height = (field_factor * height_in_map_units * 16).saturating_sub(crop_unit_y * (crop_top + crop_bottom)) as u32;
+
+        let mut sample_aspect_ratio = None;
+        let mut color = None;
+        if reader.read_bit()? {
+            if reader.read_bit()? {
+                let aspect_ratio_idc = reader.read_bits(8)? as usize;
+                sample_aspect_ratio = if aspect_ratio_idc == 255 {
+                    Some((reader.read_bits(16)? as u16, reader.read_bits(16)? as u16))
+                } else {
+                    SAMPLE_ASPECT_RATIOS.get(aspect_ratio_idc).copied().filter(|sar| sar.0 != 0)
+                };
+            }
+            if reader.read_bit()? {
+                reader.skip_bits(1)?; // overscan_appropriate_flag
+            }
+            if reader.read_bit()? {
+                reader.skip_bits(3)?; // video_format
+                let full_range = reader.read_bit()?;
+                let (color_primaries, transfer_characteristics, matrix_coefficients) = if reader.read_bit()? {
+                    (reader.read_bits(8)? as u8, reader.read_bits(8)? as u8, reader.read_bits(8)? as u8)
+                } else {
+                    (2, 2, 2)
+                };
+                color = Some(ColorInfo { full_range, color_primaries, transfer_characteristics, matrix_coefficients });
+            }
+        }
+
+        Ok(Sps {
+            profile,
+            constraint_flags,
+            level,
+            id,
+            chroma_format_idc,
+            bit_depth_luma,
+            bit_depth_chroma,
+            width,
+            height,
+            sample_aspect_ratio,
+            color,
+        })
+    }
+}
+
+const SAMPLE_ASPECT_RATIOS: [(u16, u16); 17] = [
+    (0, 0), (1, 1), (12, 11), (10, 11), (16, 11), (40, 33), (24, 11), (20, 11), (32, 11),
+    (80, 33), (18, 11), (15, 11), (64, 33), (160, 99), (4, 3), (3, 2), (2, 1),
+];
+
+///
+/// Reads a `bit_depth_*_minus8` field, which the spec limits to 0-6.
+///
+fn read_bit_depth(reader: &mut BitReader) -> Result<u8, CodecError> {
+    match reader.read_ue()? {
+        value @ 0..=6 => Ok(value as u8 + 8),
+        value => Err(CodecError::InvalidData(format!("SPS bit_depth_minus8 {value} is out of range"))),
+    }
+}
+
+fn skip_scaling_list(reader: &mut BitReader, size: usize) -> Result<(), CodecError> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = reader.read_se()?;
+            next_scale = (last_scale + delta_scale + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{from_annex_b, to_annex_b, AvcConfiguration, Sps};
+
+    fn write_ue(bits: &mut String, value: u64) {
+        let code = format!("{:b}", value + 1);
+        bits.push_str(&"0".repeat(code.len() - 1));
+        bits.push_str(&code);
+    }
+
+    fn to_bytes(mut bits: String) -> Vec<u8> {
+        bits.push('1'); // rbsp_stop_one_bit
+        while !bits.len().is_multiple_of(8) {
+            bits.push('0');
+        }
+        bits.as_bytes().chunks(8).map(|c| u8::from_str_radix(std::str::from_utf8(c).unwrap(), 2).unwrap()).collect()
+    }
+
+    // A High profile 1920x1080 SPS with BT.709 color in the VUI
+    fn high_profile_sps() -> Vec<u8> {
+        let mut bits = format!("{:08b}{:08b}{:08b}", 100, 0, 40);
+        write_ue(&mut bits, 0); // seq_parameter_set_id
+        write_ue(&mut bits, 1); // chroma_format_idc
+        write_ue(&mut bits, 0); // bit_depth_luma_minus8
+        write_ue(&mut bits, 0); // bit_depth_chroma_minus8
+        bits.push_str("00"); // qpprime_y_zero_transform_bypass_flag, seq_scaling_matrix_present_flag
+        write_ue(&mut bits, 0); // log2_max_frame_num_minus4
+        write_ue(&mut bits, 2); // pic_order_cnt_type
+        write_ue(&mut bits, 4); // max_num_ref_frames
+        bits.push('0');
+        write_ue(&mut bits, 119); // pic_width_in_mbs_minus1
+        write_ue(&mut bits, 67); // pic_height_in_map_units_minus1
+        bits.push_str("11"); // frame_mbs_only_flag, direct_8x8_inference_flag
+        bits.push('1'); // frame_cropping_flag
+        write_ue(&mut bits, 0);
+        write_ue(&mut bits, 0);
+        write_ue(&mut bits, 0);
+        write_ue(&mut bits, 4); // 8 pixels cropped from the bottom
+        bits.push('1'); // vui_parameters_present_flag
+        bits.push_str(&format!("1{:08b}", 1)); // 1:1 sample aspect ratio
+        bits.push('0'); // overscan_info_present_flag
+        bits.push_str(&format!("1{:03b}01{:08b}{:08b}{:08b}", 5, 1, 1, 1));
+
+        let mut sps = vec![0x67];
+        sps.extend(to_bytes(bits));
+        sps
+    }
+
+    #[test]
+    fn parses_sps() {
+        let sps = Sps::try_from(high_profile_sps().as_slice()).unwrap();
+        assert_eq!(100, sps.profile);
+        assert_eq!(40, sps.level);
+        assert_eq!((1920, 1080), (sps.width, sps.height));
+        assert_eq!(Some((1, 1)), sps.sample_aspect_ratio);
+        let color = sps.color.unwrap();
+        assert!(!color.full_range);
+        assert_eq!((1, 1, 1), (color.color_primaries, color.transfer_characteristics, color.matrix_coefficients));
+
+        assert!(Sps::try_from(&[0x68, 0xce][..]).is_err());
+
+        let mut bits = format!("{:08b}{:08b}{:08b}", 100, 0, 40);
+        write_ue(&mut bits, 0); // seq_parameter_set_id
+        write_ue(&mut bits, 1); // chroma_format_idc
+        write_ue(&mut bits, 300); // bit_depth_luma_minus8
+        let mut sps = vec![0x67];
+        sps.extend(to_bytes(bits));
+        assert!(Sps::try_from(sps.as_slice()).is_err());
+    }
+
+    #[test]
+    fn converts_frames_to_and_from_annex_b() {
+        let config = AvcConfiguration::from_parameter_sets(vec![high_profile_sps()], vec![vec![0x68, 0xeb, 0xe3, 0xcb]], 4).unwrap();
+        assert_eq!(100, config.profile);
+        assert_eq!(0, config.high_profile_extension.as_ref().unwrap().bit_depth_luma_minus8);
+        assert_eq!(config, AvcConfiguration::try_from(config.to_bytes().unwrap().as_slice()).unwrap());
+        assert!(AvcConfiguration { nal_length_size: 0, ..config.clone() }.to_bytes().is_err());
+        assert!(AvcConfiguration::from_parameter_sets(vec![high_profile_sps()], Vec::new(), 0).is_err());
+
+        let frame = [0x00, 0x00, 0x00, 0x03, 0x65, 0x88, 0x84];
+        let annex_b = to_annex_b(&frame, &config, true).unwrap();
+        let mut expected = vec![0x00, 0x00, 0x00, 0x01];
+        expected.extend(high_profile_sps());
+        expected.extend([0x00, 0x00, 0x00, 0x01, 0x68, 0xeb, 0xe3, 0xcb, 0x00, 0x00, 0x00, 0x01, 0x65, 0x88, 0x84]);
+        assert_eq!(expected, annex_b);
+
+        assert_eq!(vec![0x00, 0x00, 0x00, 0x01, 0x65, 0x88, 0x84], to_annex_b(&frame, &config, false).unwrap());
+        assert_eq!(frame.to_vec(), from_annex_b(&[0x00, 0x00, 0x01, 0x65, 0x88, 0x84], 4).unwrap());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::errors::CodecError;
+
+///
+/// Reads big-endian bit fields from a byte slice, as used by most video bitstream headers.
+///
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0 }
+    }
+
+    pub(crate) fn bits_remaining(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.position)
+    }
+
+    pub(crate) fn read_bit(&mut self) -> Result<bool, CodecError> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    ///
+    /// Reads up to 64 bits as an unsigned value.
+    ///
+    pub(crate) fn read_bits(&mut self, count: usize) -> Result<u64, CodecError> {
+        debug_assert!(count <= 64);
+        if count > self.bits_remaining() {
+            return Err(CodecError::TruncatedData(format!("Bitstream ended after {} bits while reading {} more", self.position, count)));
+        }
+
+        let mut value: u64 = 0;
+        for _ in 0..count {
+            let byte = self.data[self.position / 8];
+            let bit = (byte >> (7 - (self.position % 8))) & 0x01;
+            value = (value << 1) | bit as u64;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn skip_bits(&mut self, count: usize) -> Result<(), CodecError> {
+        if count > self.bits_remaining() {
+            return Err(CodecError::TruncatedData(format!("Bitstream ended after {} bits while skipping {} more", self.position, count)));
+        }
+        self.position += count;
+        Ok(())
+    }
+
+    ///
+    /// Reads an unsigned Exp-Golomb code (`ue(v)` in the H.264 and HEVC specs).
+    ///
+    pub(crate) fn read_ue(&mut self) -> Result<u64, CodecError> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 63 {
+                return Err(CodecError::InvalidData(String::from("Exp-Golomb code is too long")));
+            }
+        }
+        Ok((1u64 << leading_zeros) - 1 + self.read_bits(leading_zeros)?)
+    }
+
+    ///
+    /// Reads a signed Exp-Golomb code (`se(v)` in the H.264 and HEVC specs).
+    ///
+    pub(crate) fn read_se(&mut self) -> Result<i64, CodecError> {
+        let value = self.read_ue()?;
+        if value % 2 == 1 {
+            Ok(value.div_ceil(2) as i64)
+        } else {
+            Ok(-((value / 2) as i64))
+        }
+    }
+}
+
+///
+/// Writes big-endian bit fields into a byte vector.  The final byte is padded with zero bits.
+///
+pub(crate) struct BitWriter {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl BitWriter {
+    pub(crate) fn new() -> Self {
+        BitWriter { data: Vec::new(), position: 0 }
+    }
+
+    ///
+    /// Writes the low `count` bits of `value`, most significant bit first.
+    ///
+    pub(crate) fn write_bits(&mut self, value: u64, count: usize) {
+        debug_assert!(count <= 64);
+        for bit in (0..count).rev() {
+            if self.position.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            if (value >> bit) & 0x01 == 1 {
+                let last = self.data.len() - 1;
+                self.data[last] |= 0x80 >> (self.position % 8);
+            }
+            self.position += 1;
+        }
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitReader, BitWriter};
+
+    #[test]
+    fn reads_bits() {
+        let mut reader = BitReader::new(&[0b1011_0100, 0b1100_1001]);
+        assert_eq!(0b101, reader.read_bits(3).unwrap());
+        assert!(reader.read_bit().unwrap());
+        reader.skip_bits(4).unwrap();
+        assert_eq!(0b110_0100, reader.read_bits(7).unwrap());
+        assert_eq!(1, reader.bits_remaining());
+        assert!(reader.read_bits(2).is_err());
+        assert!(reader.skip_bits(2).is_err());
+    }
+
+    #[test]
+    fn reads_exp_golomb_codes() {
+        // 1 | 010 | 011 | 00100 | 010 | 0 (truncated code)
+        let mut reader = BitReader::new(&[0b1010_0110, 0b0100_0100]);
+        assert_eq!(0, reader.read_ue().unwrap());
+        assert_eq!(1, reader.read_ue().unwrap());
+        assert_eq!(-1, reader.read_se().unwrap());
+        assert_eq!(3, reader.read_ue().unwrap());
+        assert_eq!(1, reader.read_se().unwrap());
+        assert!(reader.read_ue().is_err());
+    }
+
+    #[test]
+    fn writes_bits() {
+        let mut writer = BitWriter::new();
+        writer.write_bits(0b101, 3);
+        writer.write_bits(0x1ff, 9);
+        writer.write_bits(0, 1);
+        assert_eq!(vec![0b1011_1111, 0b1111_0000], writer.into_bytes());
+    }
+}
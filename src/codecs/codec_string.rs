@@ -0,0 +1,349 @@
+//!
+//! Builds the `codecs` parameter strings used in MIME types, as defined in [RFC 6381](https://www.rfc-editor.org/rfc/rfc6381).
+//!
+//! Players pass these strings to APIs like `MediaSource.isTypeSupported()`, for example `video/webm; codecs="vp09.02.10.10.01.09.16.09.01"`.  Browsers use the profile, level, and bit depth they contain to decide whether a track can be decoded, so the values are read from the track's "CodecPrivate" and bitstream rather than guessed.
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use super::{aac, av1, avc, hevc, vp9};
+
+// (level, max luma sample rate, max luma picture size) from Annex A of the VP9 bitstream specification
+const VP9_LEVELS: [(u8, u64, u64); 14] = [
+    (10, 829_440, 36_864),
+    (11, 2_764_800, 73_728),
+    (20, 4_608_000, 122_880),
+    (21, 9_216_000, 245_760),
+    (30, 20_736_000, 552_960),
+    (31, 36_864_000, 983_040),
+    (40, 83_558_400, 2_228_224),
+    (41, 160_432_128, 2_228_224),
+    (50, 311_951_360, 8_912_896),
+    (51, 588_251_136, 8_912_896),
+    (52, 1_176_502_272, 8_912_896),
+    (60, 1_176_502_272, 35_651_584),
+    (61, 2_353_004_544, 35_651_584),
+    (62, 4_706_009_088, 35_651_584),
+];
+
+///
+/// Builds the codecs parameter string for a track from the children of its "TrackEntry".
+///
+/// Some codecs need more than the track entry provides.  For "V_VP9" tracks without codec feature metadata in "CodecPrivate", the profile, bit depth, and color details come from `keyframe`, and the level is derived from the frame size and "DefaultDuration".  For "V_AV1" tracks whose "CodecPrivate" has no sequence header, the sequence header is read from `keyframe`.  Other codecs ignore `keyframe`.
+///
+/// The supported codecs are "V_VP8", "V_VP9", "V_AV1", "V_MPEG4/ISO/AVC", "V_MPEGH/ISO/HEVC", "A_OPUS", "A_VORBIS", "A_FLAC", and "A_AAC" (including the legacy "A_AAC/..." IDs).
+///
+/// # Errors
+///
+/// This function returns an error if the codec isn't supported, if "CodecPrivate" is invalid, or if a detail needed for the string can't be determined (for example, the bit depth of a VP9 profile 2 track with no keyframe).
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::codec_string;
+/// use webm_iterable::matroska_spec::MatroskaSpec;
+///
+/// let track = vec![
+///     MatroskaSpec::CodecID(String::from("V_MPEG4/ISO/AVC")),
+///     MatroskaSpec::CodecPrivate(vec![0x01, 0x64, 0x00, 0x1f, 0xff, 0xe0, 0x00]),
+/// ];
+/// assert_eq!("avc1.64001F", codec_string::from_track_entry(&track, None).unwrap());
+/// ```
+///
+pub fn from_track_entry(children: &[MatroskaSpec], keyframe: Option<&[u8]>) -> Result<String, CodecError> {
+    let codec_id = children.iter().find_map(|c| match c {
+        MatroskaSpec::CodecID(val) => Some(val.as_str()),
+        _ => None,
+    }).ok_or_else(|| CodecError::InvalidData(String::from("TrackEntry has no CodecID")))?;
+    let codec_private = children.iter().find_map(|c| match c {
+        MatroskaSpec::CodecPrivate(data) => Some(data.as_slice()),
+        _ => None,
+    });
+
+    match codec_id {
+        "V_VP8" => Ok(String::from("vp8")),
+        "V_VP9" => vp9_string(children, codec_private, keyframe),
+        "V_AV1" => av1_string(codec_private, keyframe),
+        "V_MPEG4/ISO/AVC" => {
+            let config = avc::AvcConfiguration::try_from(required_codec_private(codec_id, codec_private)?)?;
+            Ok(format!("avc1.{:02X}{:02X}{:02X}", config.profile, config.profile_compatibility, config.level))
+        },
+        "V_MPEGH/ISO/HEVC" => hevc_string(required_codec_private(codec_id, codec_private)?),
+        "A_OPUS" => Ok(String::from("opus")),
+        "A_VORBIS" => Ok(String::from("vorbis")),
+        "A_FLAC" => Ok(String::from("flac")),
+        "A_AAC" => {
+            let config = aac::AudioSpecificConfig::try_from(required_codec_private(codec_id, codec_private)?)?;
+            let object_type = if config.ps { 29 } else if config.sbr { 5 } else { config.object_type };
+            Ok(format!("mp4a.40.{object_type}"))
+        },
+        _ if codec_id.starts_with("A_AAC/") => {
+            let object_type = if codec_id.ends_with("/SBR") {
+                5
+            } else if codec_id.contains("/MAIN") {
+                1
+            } else if codec_id.contains("/LC") {
+                2
+            } else if codec_id.contains("/SSR") {
+                3
+            } else if codec_id.contains("/LTP") {
+                4
+            } else {
+                return Err(CodecError::InvalidData(format!("Unknown AAC CodecID {codec_id}")));
+            };
+            Ok(format!("mp4a.40.{object_type}"))
+        },
+        _ => Err(CodecError::InvalidData(format!("No codecs parameter is defined for CodecID {codec_id}"))),
+    }
+}
+
+fn required_codec_private<'a>(codec_id: &str, codec_private: Option<&'a [u8]>) -> Result<&'a [u8], CodecError> {
+    codec_private.ok_or_else(|| CodecError::InvalidData(format!("{codec_id} track has no CodecPrivate")))
+}
+
+fn vp9_string(children: &[MatroskaSpec], codec_private: Option<&[u8]>, keyframe: Option<&[u8]>) -> Result<String, CodecError> {
+    let features = codec_private.map(vp9::CodecFeatures::try_from).transpose()?.unwrap_or_default();
+    let headers: Vec<vp9::FrameHeader> = keyframe
+        .map(|data| vp9::split_superframe(data).unwrap_or_default())
+        .unwrap_or_default().into_iter()
+        .filter_map(|data| vp9::FrameHeader::try_from(data).ok())
+        .collect();
+    let header = headers.iter().find(|h| h.color_config.is_some()).or_else(|| headers.first());
+
+    let profile = features.profile.or_else(|| header.map(|h| h.profile))
+        .ok_or_else(|| CodecError::InvalidData(String::from("VP9 profile is unknown without codec features in CodecPrivate or a keyframe")))?;
+
+    let video = children.iter().find_map(|c| match c {
+        MatroskaSpec::Video(Master::Full(children)) => Some(children.as_slice()),
+        _ => None,
+    }).unwrap_or(&[]);
+    let mut colour: Vec<MatroskaSpec> = video.iter().find_map(|c| match c {
+        MatroskaSpec::Colour(Master::Full(children)) => Some(children.clone()),
+        _ => None,
+    }).unwrap_or_default();
+    if let Some(color_config) = header.and_then(|h| h.color_config) {
+        colour.extend(color_config.colour_children());
+    }
+    let colour_value = |value: fn(&MatroskaSpec) -> Option<u64>| colour.iter().find_map(value);
+
+    let level = match features.level {
+        Some(level) => level,
+        None => vp9_level(video, header.and_then(|h| h.dimensions), children)?,
+    };
+
+    let bit_depth = features.bit_depth
+        .or_else(|| colour_value(|c| match c {
+            MatroskaSpec::BitsPerChannel(val) if *val > 0 => Some(*val),
+            _ => None,
+        }).map(|val| val as u8))
+        .or(if profile < 2 { Some(8) } else { None })
+        .ok_or_else(|| CodecError::InvalidData(format!("VP9 profile {profile} bit depth is unknown without codec features in CodecPrivate or a keyframe")))?;
+
+    let subsampling = (
+        colour_value(|c| match c { MatroskaSpec::ChromaSubsamplingHorz(val) => Some(*val), _ => None }),
+        colour_value(|c| match c { MatroskaSpec::ChromaSubsamplingVert(val) => Some(*val), _ => None }),
+    );
+    let chroma_subsampling = match (features.chroma_subsampling, subsampling) {
+        (Some(chroma_subsampling), _) => chroma_subsampling,
+        (None, (Some(1), Some(1))) | (None, (None, None)) if profile % 2 == 0 => {
+            // 4:2:0 is sited with luma unless the track says the chroma is vertically between luma rows
+            if colour_value(|c| match c { MatroskaSpec::ChromaSitingVert(val) => Some(*val), _ => None }) == Some(2) { 0 } else { 1 }
+        },
+        (None, (Some(1), Some(0))) => 2,
+        (None, (Some(0), Some(0))) => 3,
+        _ => return Err(CodecError::InvalidData(format!("VP9 profile {profile} chroma subsampling is unknown or unsupported"))),
+    };
+
+    let primaries = colour_value(|c| match c { MatroskaSpec::Primaries(val) => Some(*val), _ => None }).unwrap_or(1);
+    let transfer = colour_value(|c| match c { MatroskaSpec::TransferCharacteristics(val) => Some(*val), _ => None }).unwrap_or(1);
+    let matrix = colour_value(|c| match c { MatroskaSpec::MatrixCoefficients(val) => Some(*val), _ => None }).unwrap_or(1);
+    let full_range = colour_value(|c| match c { MatroskaSpec::Range(val) => Some(*val), _ => None }) == Some(2);
+
+    Ok(format!(
+        "vp09.{profile:02}.{level:02}.{bit_depth:02}.{chroma_subsampling:02}.{primaries:02}.{transfer:02}.{matrix:02}.{:02}",
+        full_range as u8
+    ))
+}
+
+///
+/// Finds the lowest VP9 level that allows the track's frame size and frame rate.
+///
+fn vp9_level(video: &[MatroskaSpec], dimensions: Option<vp9::Dimensions>, children: &[MatroskaSpec]) -> Result<u8, CodecError> {
+    let width = video.iter().find_map(|c| match c {
+        MatroskaSpec::PixelWidth(val) => Some(*val),
+        _ => None,
+    }).or_else(|| dimensions.map(|d| d.width as u64));
+    let height = video.iter().find_map(|c| match c {
+        MatroskaSpec::PixelHeight(val) => Some(*val),
+        _ => None,
+    }).or_else(|| dimensions.map(|d| d.height as u64));
+    let picture_size = match (width, height) {
+        (Some(width), Some(height)) => width * height,
+        _ => return Err(CodecError::InvalidData(String::from("VP9 level is unknown without codec features in CodecPrivate or the frame size"))),
+    };
+
+    let frame_rate = children.iter().find_map(|c| match c {
+        MatroskaSpec::DefaultDuration(val) if *val > 0 => Some(1_000_000_000.0 / *val as f64),
+        _ => None,
+    });
+    let sample_rate = frame_rate.map(|rate| (picture_size as f64 * rate) as u64).unwrap_or(0);
+
+    VP9_LEVELS.iter()
+        .find(|(_, max_sample_rate, max_picture_size)| picture_size <= *max_picture_size && sample_rate <= *max_sample_rate)
+        .map(|(level, _, _)| *level)
+        .ok_or_else(|| CodecError::InvalidData(format!("VP9 frame size of {picture_size} samples exceeds every level")))
+}
+
+fn av1_string(codec_private: Option<&[u8]>, keyframe: Option<&[u8]>) -> Result<String, CodecError> {
+    let config = codec_private.map(av1::CodecConfiguration::try_from).transpose()?;
+    let sequence_header = match config.as_ref().map(|config| config.sequence_header()).transpose()?.flatten() {
+        Some(sequence_header) => Some(sequence_header),
+        None => match keyframe {
+            Some(keyframe) => av1::CodecConfiguration::from_frame(keyframe)?.sequence_header()?,
+            None => None,
+        },
+    };
+
+    if let Some(header) = sequence_header {
+        let color = header.color_config;
+        return Ok(format!(
+            "av01.{}.{:02}{}.{:02}.{}.{}{}{}.{:02}.{:02}.{:02}.{}",
+            header.profile,
+            header.level,
+            if header.tier == 0 { 'M' } else { 'H' },
+            color.bit_depth,
+            color.mono_chrome as u8,
+            color.subsampling_x as u8,
+            color.subsampling_y as u8,
+            color.chroma_sample_position,
+            color.color_primaries,
+            color.transfer_characteristics,
+            color.matrix_coefficients,
+            color.full_range as u8,
+        ));
+    }
+
+    // Without a sequence header, only the fields copied into the configuration record are known
+    let config = config.ok_or_else(|| CodecError::InvalidData(String::from("V_AV1 track has no CodecPrivate or keyframe sequence header")))?;
+    let bit_depth = if config.twelve_bit { 12 } else if config.high_bitdepth { 10 } else { 8 };
+    Ok(format!("av01.{}.{:02}{}.{bit_depth:02}", config.profile, config.level, if config.tier == 0 { 'M' } else { 'H' }))
+}
+
+fn hevc_string(codec_private: &[u8]) -> Result<String, CodecError> {
+    let config = hevc::HevcConfiguration::try_from(codec_private)?;
+    let profile_space = ["", "A", "B", "C"][config.profile_space as usize & 0x03];
+
+    let mut string = format!(
+        "hev1.{profile_space}{}.{:X}.{}{}",
+        config.profile_idc,
+        config.profile_compatibility_flags.reverse_bits(),
+        if config.tier == 0 { 'L' } else { 'H' },
+        config.level,
+    );
+
+    let constraints = &config.constraint_indicator_flags.to_be_bytes()[2..];
+    let length = constraints.iter().rposition(|byte| *byte != 0).map(|p| p + 1).unwrap_or(0);
+    for byte in &constraints[..length] {
+        string += &format!(".{byte:X}");
+    }
+    Ok(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::from_track_entry;
+    use crate::matroska_spec::{Master, MatroskaSpec};
+    use crate::WebmIterator;
+
+    fn track_entries(path: &str) -> Vec<Vec<MatroskaSpec>> {
+        let mut src = File::open(path).unwrap();
+        WebmIterator::new(&mut src, &[MatroskaSpec::TrackEntry(Master::Start)])
+            .map(|t| t.unwrap())
+            .filter_map(|t| match t {
+                MatroskaSpec::TrackEntry(Master::Full(children)) => Some(children),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn builds_strings_for_sample_files() {
+        let tracks = track_entries("media/test.webm");
+        assert_eq!("vp8", from_track_entry(&tracks[0], None).unwrap());
+        assert_eq!("vorbis", from_track_entry(&tracks[1], None).unwrap());
+
+        let tracks = track_entries("media/audiosample.webm");
+        assert_eq!("opus", from_track_entry(&tracks[1], None).unwrap());
+    }
+
+    #[test]
+    fn builds_vp9_strings() {
+        let keyframe = [0x82, 0x49, 0x83, 0x42, 0x00, 0x13, 0xf0, 0x0e, 0xf6, 0x00];
+        let mut track = vec![MatroskaSpec::CodecID(String::from("V_VP9"))];
+        assert!(from_track_entry(&track, None).is_err());
+        assert_eq!("vp09.00.20.08.01.01.01.01.00", from_track_entry(&track, Some(&keyframe)).unwrap());
+
+        // Codec features and colour elements take priority, and the frame rate can raise the level
+        track.push(MatroskaSpec::CodecPrivate(vec![0x01, 0x01, 0x02, 0x03, 0x01, 0x0a]));
+        track.push(MatroskaSpec::DefaultDuration(1_000_000_000 / 120));
+        track.push(MatroskaSpec::Video(Master::Full(vec![
+            MatroskaSpec::PixelWidth(1920),
+            MatroskaSpec::PixelHeight(1080),
+            MatroskaSpec::Colour(Master::Full(vec![
+                MatroskaSpec::MatrixCoefficients(9),
+                MatroskaSpec::TransferCharacteristics(16),
+                MatroskaSpec::Primaries(9),
+                MatroskaSpec::Range(2),
+            ])),
+        ])));
+        assert_eq!("vp09.02.50.10.01.09.16.09.01", from_track_entry(&track, None).unwrap());
+    }
+
+    #[test]
+    fn builds_av1_strings() {
+        let sequence_header = [0x0a, 0x0e, 0x00, 0x00, 0x00, 0x42, 0xab, 0xbf, 0xc3, 0x73, 0xff, 0xe6, 0x40, 0x40, 0x40, 0x41];
+        let mut codec_private = vec![0x81, 0x08, 0x0c, 0x00];
+        let mut track = vec![
+            MatroskaSpec::CodecID(String::from("V_AV1")),
+            MatroskaSpec::CodecPrivate(codec_private.clone()),
+        ];
+        assert_eq!("av01.0.08M.08", from_track_entry(&track, None).unwrap());
+
+        let mut keyframe = sequence_header.to_vec();
+        keyframe.extend_from_slice(&[0x32, 0x01, 0x10]);
+        assert_eq!("av01.0.08M.08.0.110.01.01.01.0", from_track_entry(&track, Some(&keyframe)).unwrap());
+
+        codec_private.extend_from_slice(&sequence_header);
+        track[1] = MatroskaSpec::CodecPrivate(codec_private);
+        assert_eq!("av01.0.08M.08.0.110.01.01.01.0", from_track_entry(&track, None).unwrap());
+    }
+
+    #[test]
+    fn builds_avc_hevc_and_aac_strings() {
+        let hevc = vec![
+            MatroskaSpec::CodecID(String::from("V_MPEGH/ISO/HEVC")),
+            MatroskaSpec::CodecPrivate(vec![
+                0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0xb0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5d, 0xf0, 0x00, 0xfc,
+                0xfd, 0xf8, 0xf8, 0x00, 0x00, 0x0f, 0x00,
+            ]),
+        ];
+        assert_eq!("hev1.1.6.L93.B0", from_track_entry(&hevc, None).unwrap());
+
+        let he_aac = vec![
+            MatroskaSpec::CodecID(String::from("A_AAC")),
+            MatroskaSpec::CodecPrivate(vec![0xeb, 0x11, 0x88, 0x00]),
+        ];
+        assert_eq!("mp4a.40.29", from_track_entry(&he_aac, None).unwrap());
+
+        let legacy_aac = vec![MatroskaSpec::CodecID(String::from("A_AAC/MPEG4/LC"))];
+        assert_eq!("mp4a.40.2", from_track_entry(&legacy_aac, None).unwrap());
+
+        let avc = vec![MatroskaSpec::CodecID(String::from("V_MPEG4/ISO/AVC"))];
+        assert!(from_track_entry(&avc, None).is_err());
+        assert!(from_track_entry(&[MatroskaSpec::CodecID(String::from("S_TEXT/UTF8"))], None).is_err());
+    }
+}
@@ -0,0 +1,527 @@
+//!
+//! Parses the FLAC metadata stored in the "CodecPrivate" of "A_FLAC" tracks and writes native `.flac` files.
+//!
+//! The "CodecPrivate" holds the `fLaC` marker followed by the stream's metadata blocks, starting with `STREAMINFO` - exactly the start of a `.flac` file.  Each block holds one FLAC frame, so a track can be exported by writing the metadata followed by the frames.  [`FlacWriter`] does this, and can fix the total sample count in `STREAMINFO` once every frame has been written.
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::errors::CodecError;
+use super::bits::{BitReader, BitWriter};
+use super::vorbis_comment::VorbisComment;
+
+const MARKER: &[u8; 4] = b"fLaC";
+const STREAMINFO_LENGTH: usize = 34;
+
+///
+/// The metadata block type of `STREAMINFO`, which describes the whole stream and must come first.
+///
+pub const BLOCK_TYPE_STREAMINFO: u8 = 0;
+
+///
+/// The metadata block type of `PADDING`.
+///
+pub const BLOCK_TYPE_PADDING: u8 = 1;
+
+///
+/// The metadata block type of `SEEKTABLE`.
+///
+pub const BLOCK_TYPE_SEEKTABLE: u8 = 3;
+
+///
+/// The metadata block type of `VORBIS_COMMENT`, which holds the stream's tags.
+///
+pub const BLOCK_TYPE_VORBIS_COMMENT: u8 = 4;
+
+///
+/// The metadata block type of `PICTURE`.
+///
+pub const BLOCK_TYPE_PICTURE: u8 = 6;
+
+///
+/// The `STREAMINFO` metadata block, describing the audio properties of a FLAC stream.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+
+    /// The smallest frame in bytes, or 0 if unknown.
+    pub min_frame_size: u32,
+
+    /// The largest frame in bytes, or 0 if unknown.
+    pub max_frame_size: u32,
+
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
+
+    /// The number of samples per channel in the stream, or 0 if unknown.
+    pub total_samples: u64,
+
+    /// The MD5 signature of the decoded audio, or all zeros if unknown.
+    pub md5: [u8; 16],
+}
+
+impl StreamInfo {
+    ///
+    /// Writes the block contents, without the metadata block header.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if a field is out of range for its `STREAMINFO` bits, such as a channel count outside 1-8 or a sample size outside 1-32 bits.
+    ///
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CodecError> {
+        if !(1..=8).contains(&self.channels) {
+            return Err(CodecError::InvalidData(format!("FLAC streams have 1-8 channels, not {}", self.channels)));
+        }
+        if !(1..=32).contains(&self.bits_per_sample) {
+            return Err(CodecError::InvalidData(format!("FLAC samples are 1-32 bits, not {}", self.bits_per_sample)));
+        }
+        let fields = [
+            ("min frame size", self.min_frame_size as u64, 24),
+            ("max frame size", self.max_frame_size as u64, 24),
+            ("sample rate", self.sample_rate as u64, 20),
+            ("total samples", self.total_samples, 36),
+        ];
+        if let Some((name, value, bits)) = fields.iter().find(|(_, value, bits)| *value >= 1 << bits) {
+            return Err(CodecError::InvalidData(format!("FLAC {name} {value} doesn't fit in {bits} bits")));
+        }
+
+        let mut writer = BitWriter::new();
+        writer.write_bits(self.min_block_size as u64, 16);
+        writer.write_bits(self.max_block_size as u64, 16);
+        writer.write_bits(self.min_frame_size as u64, 24);
+        writer.write_bits(self.max_frame_size as u64, 24);
+        writer.write_bits(self.sample_rate as u64, 20);
+        writer.write_bits((self.channels - 1) as u64, 3);
+        writer.write_bits((self.bits_per_sample - 1) as u64, 5);
+        writer.write_bits(self.total_samples, 36);
+
+        let mut data = writer.into_bytes();
+        data.extend_from_slice(&self.md5);
+        Ok(data)
+    }
+}
+
+impl TryFrom<&[u8]> for StreamInfo {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < STREAMINFO_LENGTH {
+            return Err(CodecError::TruncatedData(format!("FLAC STREAMINFO is {} bytes, not {STREAMINFO_LENGTH}", data.len())));
+        }
+
+        let mut reader = BitReader::new(data);
+        let mut md5 = [0u8; 16];
+        md5.copy_from_slice(&data[18..STREAMINFO_LENGTH]);
+        Ok(StreamInfo {
+            min_block_size: reader.read_bits(16)? as u16,
+            max_block_size: reader.read_bits(16)? as u16,
+            min_frame_size: reader.read_bits(24)? as u32,
+            max_frame_size: reader.read_bits(24)? as u32,
+            sample_rate: reader.read_bits(20)? as u32,
+            channels: reader.read_bits(3)? as u8 + 1,
+            bits_per_sample: reader.read_bits(5)? as u8 + 1,
+            total_samples: reader.read_bits(36)?,
+            md5,
+        })
+    }
+}
+
+///
+/// A FLAC metadata block, such as [`BLOCK_TYPE_SEEKTABLE`].
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetadataBlock {
+    pub block_type: u8,
+    pub data: Vec<u8>,
+}
+
+///
+/// The `fLaC` marker and metadata blocks stored in "CodecPrivate" and at the start of `.flac` files.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::flac::Metadata;
+///
+/// let mut data = b"fLaC".to_vec();
+/// data.extend_from_slice(&[0x80, 0x00, 0x00, 0x22, 0x10, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0xc4, 0x42, 0xf0]);
+/// data.extend_from_slice(&[0x00; 20]);
+///
+/// let metadata = Metadata::try_from(data.as_slice()).unwrap();
+/// let stream_info = metadata.stream_info().unwrap();
+/// assert_eq!(44100, stream_info.sample_rate);
+/// assert_eq!(2, stream_info.channels);
+/// assert_eq!(16, stream_info.bits_per_sample);
+/// assert_eq!(data, metadata.to_bytes().unwrap());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub blocks: Vec<MetadataBlock>,
+}
+
+impl Metadata {
+    ///
+    /// Parses the `STREAMINFO` block.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the first block isn't a valid `STREAMINFO`.
+    ///
+    pub fn stream_info(&self) -> Result<StreamInfo, CodecError> {
+        match self.blocks.first() {
+            Some(block) if block.block_type == BLOCK_TYPE_STREAMINFO => StreamInfo::try_from(block.data.as_slice()),
+            _ => Err(CodecError::InvalidData(String::from("FLAC metadata doesn't start with STREAMINFO"))),
+        }
+    }
+
+    ///
+    /// Parses the `VORBIS_COMMENT` block, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the block is invalid.
+    ///
+    pub fn vorbis_comment(&self) -> Result<Option<VorbisComment>, CodecError> {
+        self.blocks.iter()
+            .find(|block| block.block_type == BLOCK_TYPE_VORBIS_COMMENT)
+            .map(|block| VorbisComment::try_from(block.data.as_slice()))
+            .transpose()
+    }
+
+    ///
+    /// Replaces the `VORBIS_COMMENT` block, adding one after `STREAMINFO` if there isn't one already.
+    ///
+    pub fn set_vorbis_comment(&mut self, comment: &VorbisComment) {
+        let block = MetadataBlock { block_type: BLOCK_TYPE_VORBIS_COMMENT, data: comment.to_bytes() };
+        match self.blocks.iter_mut().find(|block| block.block_type == BLOCK_TYPE_VORBIS_COMMENT) {
+            Some(existing) => *existing = block,
+            None => {
+                let index = self.blocks.iter().position(|block| block.block_type == BLOCK_TYPE_STREAMINFO).map(|i| i + 1).unwrap_or(0);
+                self.blocks.insert(index, block);
+            },
+        }
+    }
+
+    ///
+    /// Writes the `fLaC` marker followed by the metadata blocks, marking the last block.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if a block is larger than the 16MiB allowed by a metadata block header.
+    ///
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CodecError> {
+        let mut data = MARKER.to_vec();
+        for (index, block) in self.blocks.iter().enumerate() {
+            if block.data.len() >= 1 << 24 {
+                return Err(CodecError::InvalidData(format!("FLAC metadata block of {} bytes is larger than 16MiB", block.data.len())));
+            }
+            let last = if index + 1 == self.blocks.len() { 0x80 } else { 0x00 };
+            data.push(last | (block.block_type & 0x7f));
+            data.extend_from_slice(&(block.data.len() as u32).to_be_bytes()[1..]);
+            data.extend_from_slice(&block.data);
+        }
+        Ok(data)
+    }
+}
+
+impl TryFrom<&[u8]> for Metadata {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if !data.starts_with(MARKER) {
+            return Err(CodecError::InvalidData(String::from("FLAC metadata doesn't start with fLaC")));
+        }
+
+        let mut blocks = Vec::new();
+        let mut position = MARKER.len();
+        loop {
+            if data.len() < position + 4 {
+                return Err(CodecError::TruncatedData(String::from("FLAC metadata ended before the last block header")));
+            }
+            let last = data[position] & 0x80 != 0;
+            let block_type = data[position] & 0x7f;
+            let length = u32::from_be_bytes([0, data[position + 1], data[position + 2], data[position + 3]]) as usize;
+            position += 4;
+            if data.len() - position < length {
+                return Err(CodecError::TruncatedData(format!("FLAC metadata block of {length} bytes is larger than the remaining data")));
+            }
+            blocks.push(MetadataBlock { block_type, data: data[position..position + length].to_vec() });
+            position += length;
+
+            if last {
+                return Ok(Metadata { blocks });
+            }
+        }
+    }
+}
+
+///
+/// Reads the number of samples (per channel) in a FLAC frame from its header.
+///
+/// # Errors
+///
+/// This function returns an error if the frame doesn't start with a valid frame header.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::flac::frame_samples;
+///
+/// // Fixed block size of 4096 samples
+/// assert_eq!(4096, frame_samples(&[0xff, 0xf8, 0xc9, 0x18, 0x00, 0xc2]).unwrap());
+/// ```
+///
+pub fn frame_samples(frame: &[u8]) -> Result<u64, CodecError> {
+    if frame.len() < 5 {
+        return Err(CodecError::TruncatedData(String::from("FLAC frame header is shorter than 5 bytes")));
+    }
+    if frame[0] != 0xff || frame[1] & 0xfe != 0xf8 {
+        return Err(CodecError::InvalidData(String::from("FLAC frame is missing its sync code")));
+    }
+
+    let block_size_code = frame[2] >> 4;
+    let coded_number_length = match frame[4].leading_ones() {
+        0 => 1,
+        ones @ 2..=7 => ones as usize,
+        _ => return Err(CodecError::InvalidData(String::from("FLAC frame has an invalid coded frame number"))),
+    };
+    let end = 4 + coded_number_length;
+
+    match block_size_code {
+        0 => Err(CodecError::InvalidData(String::from("FLAC frame uses a reserved block size"))),
+        1 => Ok(192),
+        2..=5 => Ok(576 << (block_size_code - 2)),
+        6 => frame.get(end)
+            .map(|size| *size as u64 + 1)
+            .ok_or_else(|| CodecError::TruncatedData(String::from("FLAC frame header ended before its block size"))),
+        7 => frame.get(end..end + 2)
+            .map(|size| u16::from_be_bytes([size[0], size[1]]) as u64 + 1)
+            .ok_or_else(|| CodecError::TruncatedData(String::from("FLAC frame header ended before its block size"))),
+        _ => Ok(256 << (block_size_code - 8)),
+    }
+}
+
+///
+/// Writes FLAC frames as a native `.flac` stream.
+///
+/// The metadata is written before the first frame, and the number of samples in each frame is counted.  When the destination can seek, [`Self::finish()`] rewrites `STREAMINFO` with the final sample count, since the count in "CodecPrivate" is often 0 or wrong after editing.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use std::io::Cursor;
+/// use webm_iterable::codecs::flac::{FlacWriter, Metadata};
+///
+/// let mut codec_private = b"fLaC".to_vec();
+/// codec_private.extend_from_slice(&[0x80, 0x00, 0x00, 0x22, 0x10, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0xc4, 0x42, 0xf0]);
+/// codec_private.extend_from_slice(&[0x00; 20]);
+///
+/// let metadata = Metadata::try_from(codec_private.as_slice()).unwrap();
+/// let mut writer = FlacWriter::new(Cursor::new(Vec::new()), &metadata).unwrap();
+/// writer.write_frame(&[0xff, 0xf8, 0xc9, 0x18, 0x00, 0xc2]).unwrap();
+/// let data = writer.finish().unwrap().into_inner();
+///
+/// let written = Metadata::try_from(data.as_slice()).unwrap();
+/// assert_eq!(4096, written.stream_info().unwrap().total_samples);
+/// ```
+///
+pub struct FlacWriter<W: Write> {
+    writer: W,
+    header: Vec<u8>,
+    stream_info: StreamInfo,
+    header_written: bool,
+    total_samples: u64,
+    written: u64,
+}
+
+impl<W: Write> FlacWriter<W> {
+    ///
+    /// Creates a new writer for a stream with the given metadata (usually parsed from "CodecPrivate").
+    ///
+    /// Use [`Metadata::set_vorbis_comment()`] first to store tags in the file, for example from [`VorbisComment::from_tags()`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the metadata doesn't start with a valid `STREAMINFO` block or has a block larger than 16MiB.
+    ///
+    pub fn new(writer: W, metadata: &Metadata) -> Result<Self, CodecError> {
+        Ok(FlacWriter {
+            writer,
+            stream_info: metadata.stream_info()?,
+            header: metadata.to_bytes()?,
+            header_written: false,
+            total_samples: 0,
+            written: 0,
+        })
+    }
+
+    ///
+    /// Writes a FLAC frame (such as the data of a block).
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the frame header is invalid or if there is a problem writing to the destination.
+    ///
+    pub fn write_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        let samples = frame_samples(frame).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.write_header()?;
+        self.writer.write_all(frame)?;
+        self.total_samples += samples;
+        self.written += frame.len() as u64;
+        Ok(())
+    }
+
+    ///
+    /// The number of samples (per channel) in the frames written so far.
+    ///
+    pub fn total_samples(&self) -> u64 {
+        self.total_samples
+    }
+
+    ///
+    /// Writes the metadata if no frames were written and returns the underlying destination, leaving the `STREAMINFO` sample count as it was in the metadata.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.write_header()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.header_written = true;
+            self.writer.write_all(&self.header)?;
+            self.written += self.header.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    ///
+    /// Updates the `STREAMINFO` sample count to the number of samples written and returns the underlying destination.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to or seeking in the destination, or if the sample count doesn't fit in `STREAMINFO`.
+    ///
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+
+        let stream_info = StreamInfo { total_samples: self.total_samples, ..self.stream_info };
+        let stream_info_offset = (MARKER.len() + 4) as u64;
+        self.writer.seek(SeekFrom::Current(-((self.written - stream_info_offset) as i64)))?;
+        let data = stream_info.to_bytes().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.writer.write_all(&data)?;
+        self.writer.seek(SeekFrom::Current((self.written - stream_info_offset - STREAMINFO_LENGTH as u64) as i64))?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::io::{Cursor, Write};
+
+    use super::{frame_samples, FlacWriter, Metadata, MetadataBlock, StreamInfo, BLOCK_TYPE_PADDING, BLOCK_TYPE_STREAMINFO};
+    use crate::codecs::vorbis_comment::VorbisComment;
+
+    fn metadata() -> Metadata {
+        let stream_info = StreamInfo {
+            min_block_size: 4096,
+            max_block_size: 4096,
+            min_frame_size: 14,
+            max_frame_size: 8000,
+            sample_rate: 96000,
+            channels: 6,
+            bits_per_sample: 24,
+            total_samples: 1,
+            md5: [0x5a; 16],
+        };
+        Metadata {
+            blocks: vec![
+                MetadataBlock { block_type: BLOCK_TYPE_STREAMINFO, data: stream_info.to_bytes().unwrap() },
+                MetadataBlock { block_type: BLOCK_TYPE_PADDING, data: vec![0; 10] },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trips_metadata() {
+        let metadata = metadata();
+        let stream_info = metadata.stream_info().unwrap();
+        assert_eq!(stream_info, StreamInfo::try_from(stream_info.to_bytes().unwrap().as_slice()).unwrap());
+        assert_eq!((96000, 6, 24), (stream_info.sample_rate, stream_info.channels, stream_info.bits_per_sample));
+
+        let data = metadata.to_bytes().unwrap();
+        assert_eq!(&[0x00, 0x00, 0x00, 0x22], &data[4..8]);
+        assert_eq!(0x81, data[8 + 34]);
+        assert_eq!(metadata, Metadata::try_from(data.as_slice()).unwrap());
+        assert!(Metadata::try_from(&data[..data.len() - 1]).is_err());
+        assert!(Metadata::try_from(&data[1..]).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_fields() {
+        let stream_info = metadata().stream_info().unwrap();
+        assert!(StreamInfo { channels: 0, ..stream_info }.to_bytes().is_err());
+        assert!(StreamInfo { channels: 9, ..stream_info }.to_bytes().is_err());
+        assert!(StreamInfo { bits_per_sample: 0, ..stream_info }.to_bytes().is_err());
+        assert!(StreamInfo { bits_per_sample: 33, ..stream_info }.to_bytes().is_err());
+        assert!(StreamInfo { sample_rate: 1 << 20, ..stream_info }.to_bytes().is_err());
+        assert!(StreamInfo { max_frame_size: 1 << 24, ..stream_info }.to_bytes().is_err());
+        assert!(StreamInfo { total_samples: 1 << 36, ..stream_info }.to_bytes().is_err());
+        assert!(StreamInfo { channels: 8, bits_per_sample: 32, total_samples: (1 << 36) - 1, ..stream_info }.to_bytes().is_ok());
+
+        let mut metadata = metadata();
+        metadata.blocks.push(MetadataBlock { block_type: BLOCK_TYPE_PADDING, data: vec![0; 1 << 24] });
+        assert!(metadata.to_bytes().is_err());
+        assert!(FlacWriter::new(Vec::new(), &metadata).is_err());
+    }
+
+    #[test]
+    fn reads_frame_sizes() {
+        assert_eq!(192, frame_samples(&[0xff, 0xf8, 0x19, 0x18, 0x00]).unwrap());
+        assert_eq!(1152, frame_samples(&[0xff, 0xf8, 0x39, 0x18, 0x00]).unwrap());
+        // Variable block size with a 2 byte coded sample number and an 8 bit block size
+        assert_eq!(17, frame_samples(&[0xff, 0xf9, 0x69, 0x18, 0xc2, 0x80, 0x10]).unwrap());
+        assert_eq!(1000, frame_samples(&[0xff, 0xf9, 0x79, 0x18, 0x01, 0x03, 0xe7]).unwrap());
+        assert!(frame_samples(&[0xff, 0xf8, 0x09, 0x18, 0x00]).is_err());
+        assert!(frame_samples(&[0xff, 0xf9, 0x79, 0x18, 0x01, 0x03]).is_err());
+        assert!(frame_samples(&[0x00; 6]).is_err());
+    }
+
+    #[test]
+    fn writes_flac_files() {
+        let mut metadata = metadata();
+        let mut comment = VorbisComment::new("test");
+        comment.comments.push((String::from("TITLE"), String::from("Song")));
+        metadata.set_vorbis_comment(&comment);
+
+        let mut writer = FlacWriter::new(Cursor::new(Vec::new()), &metadata).unwrap();
+        writer.write_frame(&[0xff, 0xf8, 0xc9, 0x18, 0x00, 0x01]).unwrap();
+        writer.write_frame(&[0xff, 0xf8, 0x69, 0x18, 0x01, 0x63, 0x02]).unwrap();
+        assert!(writer.write_frame(&[0x00; 8]).is_err());
+        let mut cursor = writer.finish().unwrap();
+        cursor.write_all(&[0xaa]).unwrap();
+        let data = cursor.into_inner();
+
+        let written = Metadata::try_from(data.as_slice()).unwrap();
+        assert_eq!(4096 + 100, written.stream_info().unwrap().total_samples);
+        assert_eq!(Some(comment), written.vorbis_comment().unwrap());
+        assert_eq!(&[0x02, 0xaa], &data[data.len() - 2..]);
+
+        let unseekable = FlacWriter::new(Vec::new(), &metadata).unwrap().into_inner().unwrap();
+        assert_eq!(metadata.to_bytes().unwrap(), unseekable);
+    }
+}
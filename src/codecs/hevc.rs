@@ -0,0 +1,350 @@
+//!
+//! Parses the "V_MPEGH/ISO/HEVC" "CodecPrivate" (the HEVC decoder configuration record, or `hvcC`) as defined in ISO/IEC 14496-15.
+//!
+//! Like H.264, Matroska blocks store HEVC NAL units with length prefixes and keep the parameter sets (VPS, SPS, and PPS) in "CodecPrivate".  [`to_annex_b()`] and [`from_annex_b()`] convert block frames to and from the Annex-B byte stream format, and [`is_keyframe()`] checks a frame for IRAP (random access point) pictures.
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+use super::nal::{check_length_size, split_annex_b, split_length_prefixed, write_annex_b, write_length_prefixed};
+
+///
+/// The NAL unit type of a video parameter set.
+///
+pub const NAL_TYPE_VPS: u8 = 32;
+
+///
+/// The NAL unit type of a sequence parameter set.
+///
+pub const NAL_TYPE_SPS: u8 = 33;
+
+///
+/// The NAL unit type of a picture parameter set.
+///
+pub const NAL_TYPE_PPS: u8 = 34;
+
+///
+/// Returns the type of an HEVC NAL unit.
+///
+pub fn nal_type(unit: &[u8]) -> Option<u8> {
+    unit.first().map(|header| (header >> 1) & 0x3f)
+}
+
+///
+/// Whether a NAL unit type is an IRAP (BLA, IDR, or CRA) picture, where decoding can start.
+///
+pub fn is_irap(nal_type: u8) -> bool {
+    (16..=23).contains(&nal_type)
+}
+
+///
+/// A set of NAL units of the same type stored in an `hvcC`.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NalArray {
+    /// Whether all NAL units of this type are in the array, rather than some also being in the stream.
+    pub array_completeness: bool,
+
+    pub nal_type: u8,
+    pub units: Vec<Vec<u8>>,
+}
+
+///
+/// The HEVC decoder configuration record stored in the "CodecPrivate" of "V_MPEGH/ISO/HEVC" tracks.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::hevc::HevcConfiguration;
+///
+/// let data = [
+///     0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0xb0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5d, 0xf0, 0x00, 0xfc,
+///     0xfd, 0xf8, 0xf8, 0x00, 0x00, 0x0f, 0x01, 0xa0, 0x00, 0x01, 0x00, 0x02, 0x40, 0x01,
+/// ];
+/// let config = HevcConfiguration::try_from(&data[..]).unwrap();
+/// assert_eq!(1, config.profile_idc);
+/// assert_eq!(93, config.level);
+/// assert_eq!(1, config.chroma_format);
+/// assert_eq!(8, config.bit_depth_luma);
+/// assert_eq!(4, config.nal_length_size);
+/// assert_eq!(vec![&[0x40, 0x01][..]], config.parameter_sets(32).collect::<Vec<_>>());
+/// assert_eq!(data.to_vec(), config.to_bytes().unwrap());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HevcConfiguration {
+    pub version: u8,
+    pub profile_space: u8,
+
+    /// The general tier (0 = main, 1 = high).
+    pub tier: u8,
+
+    pub profile_idc: u8,
+    pub profile_compatibility_flags: u32,
+
+    /// The 48 bit general constraint indicator flags.
+    pub constraint_indicator_flags: u64,
+
+    /// The general level, which is 30 times the level number (for example, 93 is level 3.1).
+    pub level: u8,
+
+    pub min_spatial_segmentation: u16,
+    pub parallelism_type: u8,
+
+    /// The chroma format (0 = monochrome, 1 = 4:2:0, 2 = 4:2:2, 3 = 4:4:4).
+    pub chroma_format: u8,
+
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+
+    /// The average frame rate in frames per 256 seconds, or 0 if unspecified.
+    pub avg_frame_rate: u16,
+
+    pub constant_frame_rate: u8,
+    pub num_temporal_layers: u8,
+    pub temporal_id_nested: bool,
+
+    /// The size in bytes of the length prefix before each NAL unit in blocks.
+    pub nal_length_size: u8,
+
+    pub arrays: Vec<NalArray>,
+}
+
+impl HevcConfiguration {
+    ///
+    /// Returns the NAL units of the given type stored in the configuration, such as [`NAL_TYPE_SPS`].
+    ///
+    pub fn parameter_sets(&self, nal_type: u8) -> impl Iterator<Item = &[u8]> {
+        self.arrays.iter()
+            .filter(move |array| array.nal_type == nal_type)
+            .flat_map(|array| array.units.iter().map(|unit| unit.as_slice()))
+    }
+
+    ///
+    /// Writes the configuration record in the form stored in "CodecPrivate".
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `nal_length_size` isn't 1, 2, or 4.
+    ///
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CodecError> {
+        check_length_size(self.nal_length_size)?;
+        let mut data = vec![
+            self.version,
+            (self.profile_space & 0x03) << 6 | (self.tier & 0x01) << 5 | (self.profile_idc & 0x1f),
+        ];
+        data.extend_from_slice(&self.profile_compatibility_flags.to_be_bytes());
+        data.extend_from_slice(&self.constraint_indicator_flags.to_be_bytes()[2..]);
+        data.push(self.level);
+        data.extend_from_slice(&(0xf000 | (self.min_spatial_segmentation & 0x0fff)).to_be_bytes());
+        data.push(0xfc | (self.parallelism_type & 0x03));
+        data.push(0xfc | (self.chroma_format & 0x03));
+        data.push(0xf8 | (self.bit_depth_luma.saturating_sub(8) & 0x07));
+        data.push(0xf8 | (self.bit_depth_chroma.saturating_sub(8) & 0x07));
+        data.extend_from_slice(&self.avg_frame_rate.to_be_bytes());
+        data.push(
+            (self.constant_frame_rate & 0x03) << 6
+                | (self.num_temporal_layers & 0x07) << 3
+                | (self.temporal_id_nested as u8) << 2
+                | ((self.nal_length_size - 1) & 0x03)
+        );
+
+        data.push(self.arrays.len() as u8);
+        for array in &self.arrays {
+            data.push((array.array_completeness as u8) << 7 | (array.nal_type & 0x3f));
+            data.extend_from_slice(&(array.units.len() as u16).to_be_bytes());
+            for unit in &array.units {
+                data.extend_from_slice(&(unit.len() as u16).to_be_bytes());
+                data.extend_from_slice(unit);
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl TryFrom<&[u8]> for HevcConfiguration {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 23 {
+            return Err(CodecError::TruncatedData(String::from("HEVC configuration record is shorter than 23 bytes")));
+        }
+        if data[0] != 1 {
+            return Err(CodecError::InvalidData(format!("HEVC configuration record version {} is not supported", data[0])));
+        }
+
+        let nal_length_size = (data[21] & 0x03) + 1;
+        if nal_length_size == 3 {
+            return Err(CodecError::InvalidData(String::from("HEVC configuration record has an invalid NAL length size of 3")));
+        }
+
+        let mut arrays = Vec::with_capacity(data[22] as usize);
+        let mut position = 23;
+        for _ in 0..data[22] {
+            if data.len() < position + 3 {
+                return Err(CodecError::TruncatedData(String::from("HEVC configuration record ended before a NAL unit array header")));
+            }
+            let array_completeness = data[position] & 0x80 != 0;
+            let nal_type = data[position] & 0x3f;
+            let count = u16::from_be_bytes([data[position + 1], data[position + 2]]);
+            position += 3;
+
+            let mut units = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                if data.len() < position + 2 {
+                    return Err(CodecError::TruncatedData(String::from("HEVC configuration record ended before a NAL unit length")));
+                }
+                let length = u16::from_be_bytes([data[position], data[position + 1]]) as usize;
+                position += 2;
+                if data.len() < position + length {
+                    return Err(CodecError::TruncatedData(format!("NAL unit of {length} bytes is larger than the remaining configuration record")));
+                }
+                units.push(data[position..position + length].to_vec());
+                position += length;
+            }
+            arrays.push(NalArray { array_completeness, nal_type, units });
+        }
+
+        let mut constraint_indicator_flags = [0u8; 8];
+        constraint_indicator_flags[2..].copy_from_slice(&data[6..12]);
+
+        Ok(HevcConfiguration {
+            version: data[0],
+            profile_space: data[1] >> 6,
+            tier: (data[1] >> 5) & 0x01,
+            profile_idc: data[1] & 0x1f,
+            profile_compatibility_flags: u32::from_be_bytes([data[2], data[3], data[4], data[5]]),
+            constraint_indicator_flags: u64::from_be_bytes(constraint_indicator_flags),
+            level: data[12],
+            min_spatial_segmentation: u16::from_be_bytes([data[13], data[14]]) & 0x0fff,
+            parallelism_type: data[15] & 0x03,
+            chroma_format: data[16] & 0x03,
+            bit_depth_luma: (data[17] & 0x07) + 8,
+            bit_depth_chroma: (data[18] & 0x07) + 8,
+            avg_frame_rate: u16::from_be_bytes([data[19], data[20]]),
+            constant_frame_rate: data[21] >> 6,
+            num_temporal_layers: (data[21] >> 3) & 0x07,
+            temporal_id_nested: data[21] & 0x04 != 0,
+            nal_length_size,
+            arrays,
+        })
+    }
+}
+
+///
+/// Checks whether a block frame of length-prefixed NAL units contains an IRAP picture.
+///
+/// # Errors
+///
+/// This function returns an error if the frame can't be split using `nal_length_size`.
+///
+pub fn is_keyframe(frame: &[u8], nal_length_size: u8) -> Result<bool, CodecError> {
+    Ok(split_length_prefixed(frame, nal_length_size)?.iter().any(|unit| nal_type(unit).is_some_and(is_irap)))
+}
+
+///
+/// Converts a block frame of length-prefixed NAL units into an Annex-B byte stream.
+///
+/// For keyframes, the VPS, SPS, and PPS from the configuration record are inserted before the frame's NAL units unless the frame already carries its own parameter sets, so that decoders can start decoding at any keyframe.
+///
+/// # Errors
+///
+/// This function returns an error if the frame can't be split using the configuration's NAL length size.
+///
+pub fn to_annex_b(frame: &[u8], config: &HevcConfiguration, keyframe: bool) -> Result<Vec<u8>, CodecError> {
+    let units = split_length_prefixed(frame, config.nal_length_size)?;
+    let has_parameter_sets = units.iter().any(|unit| matches!(nal_type(unit), Some(NAL_TYPE_VPS) | Some(NAL_TYPE_SPS)));
+
+    let mut all_units: Vec<&[u8]> = Vec::with_capacity(units.len() + 3);
+    if keyframe && !has_parameter_sets {
+        for parameter_set_type in [NAL_TYPE_VPS, NAL_TYPE_SPS, NAL_TYPE_PPS] {
+            all_units.extend(config.parameter_sets(parameter_set_type));
+        }
+    }
+    all_units.extend(units);
+    Ok(write_annex_b(&all_units))
+}
+
+///
+/// Converts an Annex-B access unit into a block frame of length-prefixed NAL units.
+///
+/// # Errors
+///
+/// This function returns an error if a NAL unit is too large for `nal_length_size`.
+///
+pub fn from_annex_b(data: &[u8], nal_length_size: u8) -> Result<Vec<u8>, CodecError> {
+    write_length_prefixed(&split_annex_b(data), nal_length_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{from_annex_b, is_keyframe, to_annex_b, HevcConfiguration, NalArray, NAL_TYPE_PPS, NAL_TYPE_SPS, NAL_TYPE_VPS};
+
+    fn config() -> HevcConfiguration {
+        HevcConfiguration {
+            version: 1,
+            profile_space: 0,
+            tier: 1,
+            profile_idc: 2,
+            profile_compatibility_flags: 0x2000_0000,
+            constraint_indicator_flags: 0x9000_0000_0000,
+            level: 153,
+            min_spatial_segmentation: 0,
+            parallelism_type: 0,
+            chroma_format: 1,
+            bit_depth_luma: 10,
+            bit_depth_chroma: 10,
+            avg_frame_rate: 0,
+            constant_frame_rate: 0,
+            num_temporal_layers: 1,
+            temporal_id_nested: true,
+            nal_length_size: 4,
+            arrays: vec![
+                NalArray { array_completeness: true, nal_type: NAL_TYPE_VPS, units: vec![vec![0x40, 0x01, 0x0c]] },
+                NalArray { array_completeness: true, nal_type: NAL_TYPE_SPS, units: vec![vec![0x42, 0x01, 0x01]] },
+                NalArray { array_completeness: true, nal_type: NAL_TYPE_PPS, units: vec![vec![0x44, 0x01, 0xc1]] },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trips_configuration() {
+        let config = config();
+        let data = config.to_bytes().unwrap();
+        assert_eq!(&[0x01, 0x22, 0x20, 0x00, 0x00, 0x00, 0x90], &data[..7]);
+        assert_eq!(0xfa, data[17]);
+        assert_eq!(0x0f, data[21]);
+
+        let parsed = HevcConfiguration::try_from(data.as_slice()).unwrap();
+        assert_eq!(config, parsed);
+        assert!(HevcConfiguration::try_from(&data[..data.len() - 1]).is_err());
+        assert!(HevcConfiguration { nal_length_size: 0, ..config }.to_bytes().is_err());
+    }
+
+    #[test]
+    fn converts_keyframes_to_annex_b() {
+        let config = config();
+        // An IDR_W_RADL slice (type 19) and a TRAIL_R slice (type 1)
+        let keyframe = [0x00, 0x00, 0x00, 0x02, 0x26, 0x01];
+        let inter_frame = [0x00, 0x00, 0x00, 0x02, 0x02, 0x01];
+        assert!(is_keyframe(&keyframe, 4).unwrap());
+        assert!(!is_keyframe(&inter_frame, 4).unwrap());
+
+        let annex_b = to_annex_b(&keyframe, &config, true).unwrap();
+        assert_eq!(vec![
+            0x00, 0x00, 0x00, 0x01, 0x40, 0x01, 0x0c,
+            0x00, 0x00, 0x00, 0x01, 0x42, 0x01, 0x01,
+            0x00, 0x00, 0x00, 0x01, 0x44, 0x01, 0xc1,
+            0x00, 0x00, 0x00, 0x01, 0x26, 0x01,
+        ], annex_b);
+        assert_eq!(vec![0x00, 0x00, 0x00, 0x01, 0x02, 0x01], to_annex_b(&inter_frame, &config, false).unwrap());
+
+        let mut frame = from_annex_b(&annex_b, 4).unwrap();
+        frame.drain(..21);
+        assert_eq!(keyframe.to_vec(), frame);
+    }
+}
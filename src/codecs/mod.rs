@@ -0,0 +1,21 @@
+//!
+//! Parsers for codec specific data stored in Matroska tracks.
+//!
+//! Matroska treats frame data and "CodecPrivate" contents as opaque binary data.  The modules here understand enough of each codec's format to inspect that data without decoding it - for example, to check whether a frame is really a keyframe or to find a video track's dimensions.
+//!
+
+pub(crate) mod bits;
+
+pub mod aac;
+pub mod av1;
+pub mod avc;
+pub mod codec_string;
+pub mod flac;
+pub mod hevc;
+pub mod nal;
+pub mod opus;
+pub mod vorbis;
+pub mod vorbis_comment;
+pub mod vp8;
+pub mod vp9;
+pub mod xiph;
@@ -0,0 +1,173 @@
+//!
+//! Splits and joins the NAL units used by H.264 and HEVC.
+//!
+//! Matroska stores these codecs with each NAL unit prefixed by its length (the size of the length field is given in "CodecPrivate"), while many decoders and raw `.h264`/`.h265` files use the Annex-B byte stream format, where NAL units are separated by `00 00 01` start codes.
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+
+const START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
+///
+/// Splits length-prefixed NAL units, as stored in Matroska blocks.
+///
+/// # Errors
+///
+/// This function returns an error if `length_size` isn't 1, 2, or 4 or if a NAL unit is larger than the remaining data.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::nal::split_length_prefixed;
+///
+/// let units = split_length_prefixed(&[0x00, 0x00, 0x00, 0x02, 0x65, 0x88, 0x00, 0x00, 0x00, 0x01, 0x06], 4).unwrap();
+/// assert_eq!(vec![&[0x65, 0x88][..], &[0x06][..]], units);
+/// ```
+///
+pub fn split_length_prefixed(data: &[u8], length_size: u8) -> Result<Vec<&[u8]>, CodecError> {
+    check_length_size(length_size)?;
+    let length_size = length_size as usize;
+
+    let mut units = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        if data.len() - position < length_size {
+            return Err(CodecError::TruncatedData(format!("NAL unit length at offset {position} is truncated")));
+        }
+        let length = data[position..position + length_size].iter().fold(0usize, |length, byte| (length << 8) | *byte as usize);
+        position += length_size;
+        if length > data.len() - position {
+            return Err(CodecError::TruncatedData(format!("NAL unit of {length} bytes is larger than the {} bytes remaining", data.len() - position)));
+        }
+        units.push(&data[position..position + length]);
+        position += length;
+    }
+    Ok(units)
+}
+
+///
+/// Joins NAL units with length prefixes of the given size.
+///
+/// # Errors
+///
+/// This function returns an error if `length_size` isn't 1, 2, or 4 or if a NAL unit is too large for the length prefix.
+///
+pub fn write_length_prefixed(units: &[&[u8]], length_size: u8) -> Result<Vec<u8>, CodecError> {
+    check_length_size(length_size)?;
+
+    let mut data = Vec::with_capacity(units.iter().map(|unit| unit.len() + length_size as usize).sum());
+    for unit in units {
+        if length_size < 4 && unit.len() >= 1 << (8 * length_size as usize) {
+            return Err(CodecError::InvalidData(format!("NAL unit of {} bytes is too large for a {length_size} byte length prefix", unit.len())));
+        }
+        let length = u32::try_from(unit.len())
+            .map_err(|_| CodecError::InvalidData(format!("NAL unit of {} bytes is too large for a {length_size} byte length prefix", unit.len())))?;
+        data.extend_from_slice(&length.to_be_bytes()[4 - length_size as usize..]);
+        data.extend_from_slice(unit);
+    }
+    Ok(data)
+}
+
+///
+/// Splits an Annex-B byte stream into NAL units.
+///
+/// NAL units are separated by three or four byte start codes.  Any data before the first start code and trailing zero bytes after each NAL unit are dropped.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::nal::split_annex_b;
+///
+/// let units = split_annex_b(&[0x00, 0x00, 0x00, 0x01, 0x67, 0x64, 0x00, 0x00, 0x01, 0x68, 0xee]);
+/// assert_eq!(vec![&[0x67, 0x64][..], &[0x68, 0xee][..]], units);
+/// ```
+///
+pub fn split_annex_b(data: &[u8]) -> Vec<&[u8]> {
+    let mut units = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut position = 0;
+    while position + 3 <= data.len() {
+        if data[position] == 0 && data[position + 1] == 0 && data[position + 2] == 1 {
+            if let Some(start) = start {
+                units.push(trim_trailing_zeros(&data[start..position]));
+            }
+            position += 3;
+            start = Some(position);
+        } else {
+            position += 1;
+        }
+    }
+    if let Some(start) = start {
+        units.push(trim_trailing_zeros(&data[start..]));
+    }
+    units.retain(|unit| !unit.is_empty());
+    units
+}
+
+///
+/// Joins NAL units into an Annex-B byte stream, using four byte start codes.
+///
+pub fn write_annex_b(units: &[&[u8]]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(units.iter().map(|unit| unit.len() + START_CODE.len()).sum());
+    for unit in units {
+        data.extend_from_slice(&START_CODE);
+        data.extend_from_slice(unit);
+    }
+    data
+}
+
+///
+/// Removes emulation prevention bytes (the `03` in `00 00 03`) from a NAL unit, giving the raw bits to parse.
+///
+pub(crate) fn unescape_rbsp(unit: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(unit.len());
+    let mut zeros = 0;
+    for byte in unit {
+        if zeros >= 2 && *byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if *byte == 0 { zeros + 1 } else { 0 };
+        data.push(*byte);
+    }
+    data
+}
+
+fn trim_trailing_zeros(unit: &[u8]) -> &[u8] {
+    let end = unit.iter().rposition(|byte| *byte != 0).map(|p| p + 1).unwrap_or(0);
+    &unit[..end]
+}
+
+pub(crate) fn check_length_size(length_size: u8) -> Result<(), CodecError> {
+    match length_size {
+        1 | 2 | 4 => Ok(()),
+        _ => Err(CodecError::InvalidData(format!("NAL unit length size must be 1, 2, or 4, not {length_size}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_annex_b, split_length_prefixed, unescape_rbsp, write_annex_b, write_length_prefixed};
+
+    #[test]
+    fn converts_between_formats() {
+        let units: [&[u8]; 3] = [&[0x67, 0x42, 0x1e], &[0x68, 0xce], &[0x65; 300]];
+        let annex_b = write_annex_b(&units);
+        assert_eq!(units.to_vec(), split_annex_b(&annex_b));
+
+        let length_prefixed = write_length_prefixed(&split_annex_b(&annex_b), 2).unwrap();
+        assert_eq!(&[0x00, 0x03, 0x67], &length_prefixed[..3]);
+        assert_eq!(units.to_vec(), split_length_prefixed(&length_prefixed, 2).unwrap());
+
+        assert!(write_length_prefixed(&units, 1).is_err());
+        assert!(write_length_prefixed(&units, 3).is_err());
+        assert!(split_length_prefixed(&[0x00, 0x05, 0x01], 2).is_err());
+    }
+
+    #[test]
+    fn removes_emulation_prevention_bytes() {
+        assert_eq!(vec![0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x03], unescape_rbsp(&[0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x03]));
+    }
+}
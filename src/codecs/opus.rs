@@ -0,0 +1,387 @@
+//!
+//! Parses the "A_OPUS" "CodecPrivate" (the Opus identification header, or `OpusHead`) as defined in [RFC 7845](https://www.rfc-editor.org/rfc/rfc7845.html#section-5.1) and computes Opus packet durations as defined in [RFC 6716](https://www.rfc-editor.org/rfc/rfc6716.html#section-3.1).
+//!
+//! Opus always decodes at 48kHz, so all durations here are in 48kHz samples regardless of the input sample rate stored in the header.  [`SampleCounter`] combines packet durations with the track "CodecDelay" and block "DiscardPadding" values to get the exact number of samples a track plays.
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+use crate::matroska_spec::MatroskaSpec;
+
+///
+/// The sample rate Opus always decodes at.
+///
+pub const SAMPLE_RATE: u64 = 48000;
+
+const MAX_PACKET_SAMPLES: u64 = 5760;
+
+///
+/// Converts a duration in nanoseconds into 48kHz samples, rounding to the nearest sample.
+///
+pub fn ns_to_samples(ns: u64) -> u64 {
+    ((ns as u128 * SAMPLE_RATE as u128 + 500_000_000) / 1_000_000_000) as u64
+}
+
+///
+/// Converts a number of 48kHz samples into nanoseconds, rounding to the nearest nanosecond.
+///
+pub fn samples_to_ns(samples: u64) -> u64 {
+    ((samples as u128 * 1_000_000_000 + SAMPLE_RATE as u128 / 2) / SAMPLE_RATE as u128) as u64
+}
+
+///
+/// The channel mapping table stored in an `OpusHead` when the channel mapping family isn't 0.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelMappingTable {
+    pub stream_count: u8,
+    pub coupled_count: u8,
+
+    /// The decoded stream channel for each output channel.
+    pub mapping: Vec<u8>,
+}
+
+///
+/// The Opus identification header stored in the "CodecPrivate" of "A_OPUS" tracks.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::opus::OpusHead;
+///
+/// let data = [0x4f, 0x70, 0x75, 0x73, 0x48, 0x65, 0x61, 0x64, 0x01, 0x02, 0x38, 0x01, 0x80, 0xbb, 0x00, 0x00, 0x00, 0x00, 0x00];
+/// let head = OpusHead::try_from(&data[..]).unwrap();
+/// assert_eq!(2, head.channels);
+/// assert_eq!(312, head.pre_skip);
+/// assert_eq!(48000, head.input_sample_rate);
+/// assert!(head.mapping_table.is_none());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpusHead {
+    pub version: u8,
+    pub channels: u8,
+
+    /// The number of 48kHz samples to discard from the start of the decoded output.
+    pub pre_skip: u16,
+
+    /// The sample rate of the original input, for information only.  Opus always decodes at 48kHz.
+    pub input_sample_rate: u32,
+
+    /// The gain to apply to decoded output, in Q7.8 dB.
+    pub output_gain: i16,
+
+    pub mapping_family: u8,
+    pub mapping_table: Option<ChannelMappingTable>,
+}
+
+impl OpusHead {
+    ///
+    /// Writes the header in the form stored in "CodecPrivate".
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = b"OpusHead".to_vec();
+        data.push(self.version);
+        data.push(self.channels);
+        data.extend_from_slice(&self.pre_skip.to_le_bytes());
+        data.extend_from_slice(&self.input_sample_rate.to_le_bytes());
+        data.extend_from_slice(&self.output_gain.to_le_bytes());
+        data.push(self.mapping_family);
+        if let Some(table) = &self.mapping_table {
+            data.push(table.stream_count);
+            data.push(table.coupled_count);
+            data.extend_from_slice(&table.mapping);
+        }
+        data
+    }
+}
+
+impl TryFrom<&[u8]> for OpusHead {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 19 {
+            return Err(CodecError::TruncatedData(String::from("OpusHead is shorter than 19 bytes")));
+        }
+        if &data[..8] != b"OpusHead" {
+            return Err(CodecError::InvalidData(String::from("OpusHead is missing its magic signature")));
+        }
+
+        let version = data[8];
+        if version >> 4 != 0 {
+            return Err(CodecError::InvalidData(format!("OpusHead version {version} is not supported")));
+        }
+
+        let channels = data[9];
+        if channels == 0 {
+            return Err(CodecError::InvalidData(String::from("OpusHead has 0 channels")));
+        }
+
+        let mapping_family = data[18];
+        let mapping_table = if mapping_family == 0 {
+            if channels > 2 {
+                return Err(CodecError::InvalidData(format!("OpusHead channel mapping family 0 does not support {channels} channels")));
+            }
+            None
+        } else {
+            if data.len() < 21 + channels as usize {
+                return Err(CodecError::TruncatedData(String::from("OpusHead ended before its channel mapping table")));
+            }
+            Some(ChannelMappingTable {
+                stream_count: data[19],
+                coupled_count: data[20],
+                mapping: data[21..21 + channels as usize].to_vec(),
+            })
+        };
+
+        Ok(OpusHead {
+            version,
+            channels,
+            pre_skip: u16::from_le_bytes([data[10], data[11]]),
+            input_sample_rate: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+            output_gain: i16::from_le_bytes([data[16], data[17]]),
+            mapping_family,
+            mapping_table,
+        })
+    }
+}
+
+///
+/// Computes the number of 48kHz samples in an Opus packet from its TOC byte and frame count.
+///
+/// # Errors
+///
+/// This function returns an error if the packet is empty, is missing its frame count byte, has a frame count of 0, or is longer than the 120ms Opus allows.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::opus::packet_samples;
+///
+/// // CELT-only 20ms frame, 1 frame per packet
+/// assert_eq!(960, packet_samples(&[0xfc, 0xff]).unwrap());
+/// // SILK-only 60ms frames, 2 frames per packet
+/// assert_eq!(5760, packet_samples(&[0x19, 0xff]).unwrap());
+/// ```
+///
+pub fn packet_samples(packet: &[u8]) -> Result<u64, CodecError> {
+    let toc = *packet.first().ok_or_else(|| CodecError::TruncatedData(String::from("Opus packet is empty")))?;
+    let frame_samples = frame_samples(toc);
+    let frame_count = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => {
+            let count = packet.get(1).ok_or_else(|| CodecError::TruncatedData(String::from("Opus packet is missing its frame count byte")))?;
+            if count & 0x3f == 0 {
+                return Err(CodecError::InvalidData(String::from("Opus packet has a frame count of 0")));
+            }
+            (count & 0x3f) as u64
+        },
+    };
+
+    let samples = frame_samples * frame_count;
+    if samples > MAX_PACKET_SAMPLES {
+        return Err(CodecError::InvalidData(format!("Opus packet holds {samples} samples, more than the 120ms maximum")));
+    }
+    Ok(samples)
+}
+
+///
+/// Computes the duration of an Opus packet in nanoseconds.  See [`packet_samples()`].
+///
+/// # Errors
+///
+/// This function returns an error if the packet is invalid.
+///
+pub fn packet_duration(packet: &[u8]) -> Result<u64, CodecError> {
+    Ok(samples_to_ns(packet_samples(packet)?))
+}
+
+fn frame_samples(toc: u8) -> u64 {
+    let config = toc >> 3;
+    match config {
+        0..=11 => [480, 960, 1920, 2880][(config % 4) as usize],
+        12..=15 => [480, 960][(config % 2) as usize],
+        _ => [120, 240, 480, 960][(config % 4) as usize],
+    }
+}
+
+///
+/// Counts the samples played by an Opus track.
+///
+/// Packet durations are added as blocks are read.  The total excludes the "CodecDelay" (pre-skip) at the start of the track and any "DiscardPadding" trimmed from blocks, giving the exact number of samples a player outputs.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::opus::SampleCounter;
+///
+/// // 6.5ms codec delay (312 samples)
+/// let mut counter = SampleCounter::new(6_500_000, 80_000_000);
+/// counter.add_packet(&[0xfc], None).unwrap();
+/// counter.add_packet(&[0xfc], Some(10_000_000)).unwrap();
+/// assert_eq!(960 * 2 - 312 - 480, counter.total_samples());
+/// ```
+///
+#[derive(Clone, Debug, Default)]
+pub struct SampleCounter {
+    codec_delay: u64,
+    seek_pre_roll: u64,
+    decoded: u64,
+    discarded: u64,
+}
+
+impl SampleCounter {
+    ///
+    /// Creates a new counter from the track "CodecDelay" and "SeekPreRoll" values, in nanoseconds.
+    ///
+    pub fn new(codec_delay: u64, seek_pre_roll: u64) -> Self {
+        SampleCounter {
+            codec_delay: ns_to_samples(codec_delay),
+            seek_pre_roll: ns_to_samples(seek_pre_roll),
+            decoded: 0,
+            discarded: 0,
+        }
+    }
+
+    ///
+    /// Creates a new counter from the children of an "A_OPUS" "TrackEntry".
+    ///
+    /// If the track has no "CodecDelay", the pre-skip from the `OpusHead` in "CodecPrivate" is used instead.
+    ///
+    pub fn from_track_entry(children: &[MatroskaSpec]) -> Self {
+        let codec_delay = children.iter().find_map(|c| match c {
+            MatroskaSpec::CodecDelay(val) => Some(*val),
+            _ => None,
+        }).or_else(|| children.iter().find_map(|c| match c {
+            MatroskaSpec::CodecPrivate(data) => OpusHead::try_from(data.as_slice()).ok().map(|head| samples_to_ns(head.pre_skip as u64)),
+            _ => None,
+        })).unwrap_or(0);
+        let seek_pre_roll = children.iter().find_map(|c| match c {
+            MatroskaSpec::SeekPreRoll(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(0);
+        SampleCounter::new(codec_delay, seek_pre_roll)
+    }
+
+    ///
+    /// Adds a packet to the count, along with the block "DiscardPadding" (in nanoseconds) if there is one.  Returns the number of samples in the packet.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the packet is invalid.
+    ///
+    pub fn add_packet(&mut self, packet: &[u8], discard_padding: Option<i64>) -> Result<u64, CodecError> {
+        let samples = packet_samples(packet)?;
+        self.decoded += samples;
+        if let Some(padding) = discard_padding.filter(|p| *p > 0) {
+            self.discarded += ns_to_samples(padding as u64).min(samples);
+        }
+        Ok(samples)
+    }
+
+    ///
+    /// The codec delay in 48kHz samples.
+    ///
+    pub fn codec_delay(&self) -> u64 {
+        self.codec_delay
+    }
+
+    ///
+    /// The number of 48kHz samples that must be decoded and discarded before a seek target for the output to be correct.
+    ///
+    pub fn seek_pre_roll(&self) -> u64 {
+        self.seek_pre_roll
+    }
+
+    ///
+    /// The number of samples in all packets added so far, before any trimming.
+    ///
+    pub fn decoded_samples(&self) -> u64 {
+        self.decoded
+    }
+
+    ///
+    /// The number of samples played, after removing the codec delay and discard padding.
+    ///
+    pub fn total_samples(&self) -> u64 {
+        self.decoded.saturating_sub(self.codec_delay).saturating_sub(self.discarded)
+    }
+
+    ///
+    /// The played duration in nanoseconds.  See [`Self::total_samples()`].
+    ///
+    pub fn total_duration(&self) -> u64 {
+        samples_to_ns(self.total_samples())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::fs::File;
+
+    use super::{packet_samples, OpusHead, SampleCounter};
+    use crate::matroska_spec::{Master, MatroskaSpec, SimpleBlock};
+    use crate::WebmIterator;
+
+    #[test]
+    fn counts_samples_in_test_file() {
+        let mut src = File::open("media/audiosample.webm").unwrap();
+        let mut counter = None;
+        let mut duration = 0.0;
+        for tag in WebmIterator::new(&mut src, &[MatroskaSpec::TrackEntry(Master::Start)]) {
+            match tag.unwrap() {
+                MatroskaSpec::Duration(val) => duration = val,
+                MatroskaSpec::TrackEntry(Master::Full(children)) if children.contains(&MatroskaSpec::CodecID(String::from("A_OPUS"))) => {
+                    let head = children.iter().find_map(|c| match c {
+                        MatroskaSpec::CodecPrivate(data) => Some(OpusHead::try_from(data.as_slice()).unwrap()),
+                        _ => None,
+                    }).unwrap();
+                    assert_eq!(1, head.channels);
+                    assert_eq!(312, head.pre_skip);
+                    counter = Some(SampleCounter::from_track_entry(&children));
+                },
+                MatroskaSpec::SimpleBlock(data) => {
+                    let block = SimpleBlock::try_from(data.as_slice()).unwrap();
+                    if block.track == 2 {
+                        counter.as_mut().unwrap().add_packet(block.raw_frame_data(), None).unwrap();
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        let counter = counter.unwrap();
+        assert_eq!(312, counter.codec_delay());
+        assert_eq!(3840, counter.seek_pre_roll());
+        // The segment duration is in milliseconds and includes the video track, so it can only be approximately matched
+        let played_ms = counter.total_duration() as f64 / 1_000_000.0;
+        assert!((played_ms - duration).abs() < 40.0, "{} vs {}", played_ms, duration);
+    }
+
+    #[test]
+    fn computes_packet_samples() {
+        assert_eq!(120, packet_samples(&[0x80]).unwrap());
+        assert_eq!(480, packet_samples(&[0x60]).unwrap());
+        assert_eq!(960 * 2, packet_samples(&[0x6a]).unwrap());
+        assert_eq!(120 * 48, packet_samples(&[0x83, 48]).unwrap());
+        assert!(packet_samples(&[0x83, 49]).is_err());
+        assert!(packet_samples(&[0x83]).is_err());
+        assert!(packet_samples(&[0x83, 0x80]).is_err());
+        assert!(packet_samples(&[]).is_err());
+    }
+
+    #[test]
+    fn round_trips_opus_head() {
+        let data = [0x4f, 0x70, 0x75, 0x73, 0x48, 0x65, 0x61, 0x64, 0x01, 0x03, 0x38, 0x01, 0x44, 0xac, 0x00, 0x00, 0x00, 0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01];
+        let head = OpusHead::try_from(&data[..]).unwrap();
+        assert_eq!(44100, head.input_sample_rate);
+        assert_eq!(256, head.output_gain);
+        assert_eq!(vec![0, 2, 1], head.mapping_table.as_ref().unwrap().mapping);
+        assert_eq!(data.to_vec(), head.to_bytes());
+        assert!(OpusHead::try_from(&data[..22]).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//!
+//! Parses the Vorbis headers stored in the "CodecPrivate" of "A_VORBIS" tracks and computes Vorbis packet durations, as defined in the [Vorbis I specification](https://xiph.org/vorbis/doc/Vorbis_I_spec.html).
+//!
+//! The three Vorbis headers are Xiph-laced in "CodecPrivate" - see [`super::xiph`].  The identification header holds the channel count, sample rate, and the two block sizes.  The setup header holds the modes, which say which block size each audio packet uses.  Together these give the number of samples each packet decodes to.
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+use super::xiph::split_headers;
+
+///
+/// The Vorbis identification header, the first of the three Vorbis headers.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::vorbis::IdentificationHeader;
+///
+/// let mut data = vec![0x01, b'v', b'o', b'r', b'b', b'i', b's', 0, 0, 0, 0, 2];
+/// data.extend_from_slice(&44100u32.to_le_bytes());
+/// data.extend_from_slice(&[0; 12]);
+/// data.extend_from_slice(&[0xb8, 0x01]);
+///
+/// let header = IdentificationHeader::try_from(data.as_slice()).unwrap();
+/// assert_eq!(2, header.channels);
+/// assert_eq!(44100, header.sample_rate);
+/// assert_eq!((256, 2048), (header.blocksize_0, header.blocksize_1));
+/// ```
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdentificationHeader {
+    pub version: u32,
+    pub channels: u8,
+    pub sample_rate: u32,
+    pub bitrate_maximum: i32,
+    pub bitrate_nominal: i32,
+    pub bitrate_minimum: i32,
+
+    /// The short block size in samples.
+    pub blocksize_0: u16,
+
+    /// The long block size in samples.
+    pub blocksize_1: u16,
+}
+
+impl TryFrom<&[u8]> for IdentificationHeader {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 30 {
+            return Err(CodecError::TruncatedData(String::from("Vorbis identification header is shorter than 30 bytes")));
+        }
+        if data[0] != 1 || &data[1..7] != b"vorbis" {
+            return Err(CodecError::InvalidData(String::from("Vorbis identification header is missing its packet type or signature")));
+        }
+
+        let version = u32::from_le_bytes([data[7], data[8], data[9], data[10]]);
+        if version != 0 {
+            return Err(CodecError::InvalidData(format!("Vorbis version {version} is not supported")));
+        }
+
+        let channels = data[11];
+        let sample_rate = u32::from_le_bytes([data[12], data[13], data[14], data[15]]);
+        if channels == 0 || sample_rate == 0 {
+            return Err(CodecError::InvalidData(String::from("Vorbis identification header has 0 channels or a 0 sample rate")));
+        }
+
+        let blocksize_0 = 1u16 << (data[28] & 0x0f).min(15);
+        let blocksize_1 = 1u16 << (data[28] >> 4).min(15);
+        if !(64..=8192).contains(&blocksize_0) || !(64..=8192).contains(&blocksize_1) || blocksize_0 > blocksize_1 {
+            return Err(CodecError::InvalidData(format!("Vorbis block sizes {blocksize_0} and {blocksize_1} are invalid")));
+        }
+        if data[29] & 0x01 == 0 {
+            return Err(CodecError::InvalidData(String::from("Vorbis identification header is missing its framing bit")));
+        }
+
+        Ok(IdentificationHeader {
+            version,
+            channels,
+            sample_rate,
+            bitrate_maximum: i32::from_le_bytes([data[16], data[17], data[18], data[19]]),
+            bitrate_nominal: i32::from_le_bytes([data[20], data[21], data[22], data[23]]),
+            bitrate_minimum: i32::from_le_bytes([data[24], data[25], data[26], data[27]]),
+            blocksize_0,
+            blocksize_1,
+        })
+    }
+}
+
+///
+/// Reads the block flag (`false` for short blocks, `true` for long blocks) of each mode in a Vorbis setup header.
+///
+/// The modes are the last thing in the setup header, after many variable-length fields.  Rather than parsing all of the codebooks, floors, and residues, this scans backwards from the framing bit at the end of the header.  This is the same approach used by libavcodec and liboggz.
+///
+/// # Errors
+///
+/// This function returns an error if the data isn't a setup header or no valid mode list is found.
+///
+pub fn read_mode_block_flags(setup_header: &[u8]) -> Result<Vec<bool>, CodecError> {
+    if setup_header.len() < 7 || setup_header[0] != 5 || &setup_header[1..7] != b"vorbis" {
+        return Err(CodecError::InvalidData(String::from("Vorbis setup header is missing its packet type or signature")));
+    }
+
+    let mut reader = ReverseBitReader::new(&setup_header[7..]);
+    while reader.read(1)? == 0 {}
+    let modes_end = reader;
+
+    let mut mode_count = 0;
+    let mut valid_mode_count = None;
+    while reader.remaining() >= 41 + 6 && mode_count < 64 {
+        let mapping = reader.read(8)?;
+        let transform_type = reader.read(16)?;
+        let window_type = reader.read(16)?;
+        if mapping > 63 || transform_type != 0 || window_type != 0 {
+            break;
+        }
+        reader.read(1)?;
+        mode_count += 1;
+
+        let mut mode_count_reader = reader;
+        if mode_count_reader.read(6)? + 1 == mode_count {
+            valid_mode_count = Some(mode_count);
+        }
+    }
+
+    let mode_count = valid_mode_count
+        .ok_or_else(|| CodecError::InvalidData(String::from("Unable to find the mode list in the Vorbis setup header")))? as usize;
+
+    let mut reader = modes_end;
+    let mut block_flags = vec![false; mode_count];
+    for flag in block_flags.iter_mut().rev() {
+        reader.read(40)?;
+        *flag = reader.read(1)? == 1;
+    }
+    Ok(block_flags)
+}
+
+///
+/// Computes the number of samples decoded from each Vorbis packet in a track.
+///
+/// Vorbis blocks overlap, so the samples a packet produces depend on the block size of both that packet and the one before it.  Packets must be passed in order, and the first packet produces no samples.
+///
+#[derive(Clone, Debug)]
+pub struct PacketDurations {
+    sample_rate: u32,
+    blocksizes: [u16; 2],
+    block_flags: Vec<bool>,
+    mode_bits: usize,
+    previous_blocksize: Option<u16>,
+}
+
+impl PacketDurations {
+    ///
+    /// Creates a new instance from the identification and setup headers.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the setup header modes can't be read.
+    ///
+    pub fn new(identification: &IdentificationHeader, setup_header: &[u8]) -> Result<Self, CodecError> {
+        let block_flags = read_mode_block_flags(setup_header)?;
+        let mode_bits = (usize::BITS - (block_flags.len() - 1).leading_zeros()) as usize;
+        Ok(PacketDurations {
+            sample_rate: identification.sample_rate,
+            blocksizes: [identification.blocksize_0, identification.blocksize_1],
+            block_flags,
+            mode_bits,
+            previous_blocksize: None,
+        })
+    }
+
+    ///
+    /// Creates a new instance from the Xiph-laced headers in an "A_VORBIS" "CodecPrivate".
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the "CodecPrivate" doesn't hold three valid Vorbis headers.
+    ///
+    pub fn from_codec_private(codec_private: &[u8]) -> Result<Self, CodecError> {
+        let headers = split_headers(codec_private)?;
+        if headers.len() != 3 {
+            return Err(CodecError::InvalidData(format!("Expected 3 Vorbis headers, found {}", headers.len())));
+        }
+        PacketDurations::new(&IdentificationHeader::try_from(headers[0])?, headers[2])
+    }
+
+    ///
+    /// Returns the number of samples produced by the next audio packet in the track.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the packet is empty, isn't an audio packet, or uses a mode that doesn't exist.
+    ///
+    pub fn packet_samples(&mut self, packet: &[u8]) -> Result<u64, CodecError> {
+        let first = *packet.first().ok_or_else(|| CodecError::TruncatedData(String::from("Vorbis packet is empty")))?;
+        if first & 0x01 != 0 {
+            return Err(CodecError::InvalidData(String::from("Vorbis packet is a header packet rather than an audio packet")));
+        }
+
+        let mut mode = 0usize;
+        for bit in 0..self.mode_bits {
+            let position = bit + 1;
+            let byte = *packet.get(position / 8).ok_or_else(|| CodecError::TruncatedData(String::from("Vorbis packet ended before its mode number")))?;
+            mode |= (((byte >> (position % 8)) & 0x01) as usize) << bit;
+        }
+
+        let long_block = *self.block_flags.get(mode)
+            .ok_or_else(|| CodecError::InvalidData(format!("Vorbis packet uses mode {mode}, but the setup header only has {} modes", self.block_flags.len())))?;
+        let blocksize = self.blocksizes[long_block as usize];
+        let samples = self.previous_blocksize.map(|previous| (previous / 4 + blocksize / 4) as u64).unwrap_or(0);
+        self.previous_blocksize = Some(blocksize);
+        Ok(samples)
+    }
+
+    ///
+    /// Returns the duration in nanoseconds of the next audio packet in the track.  See [`Self::packet_samples()`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the packet is invalid.
+    ///
+    pub fn packet_duration(&mut self, packet: &[u8]) -> Result<u64, CodecError> {
+        let samples = self.packet_samples(packet)?;
+        Ok((samples as u128 * 1_000_000_000 / self.sample_rate as u128) as u64)
+    }
+}
+
+///
+/// Reads Vorbis (LSB-first) bit fields backwards from the end of a byte slice.
+///
+#[derive(Clone, Copy)]
+struct ReverseBitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ReverseBitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ReverseBitReader { data, position: data.len() * 8 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.position
+    }
+
+    fn read(&mut self, count: usize) -> Result<u64, CodecError> {
+        if count > self.position {
+            return Err(CodecError::TruncatedData(String::from("Reached the start of the Vorbis setup header while searching for modes")));
+        }
+
+        let mut value = 0u64;
+        for _ in 0..count {
+            self.position -= 1;
+            let bit = (self.data[self.position / 8] >> (self.position % 8)) & 0x01;
+            value = (value << 1) | bit as u64;
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::fs::File;
+
+    use super::{IdentificationHeader, PacketDurations};
+    use crate::codecs::xiph::{build_headers, split_headers};
+    use crate::matroska_spec::{Master, MatroskaSpec, SimpleBlock};
+    use crate::WebmIterator;
+
+    #[test]
+    fn computes_durations_in_test_file() {
+        let mut src = File::open("media/test.webm").unwrap();
+        let mut durations = None;
+        let mut cluster_timestamp = 0;
+        let mut samples = 0;
+        let mut packets = 0;
+        for tag in WebmIterator::new(&mut src, &[MatroskaSpec::TrackEntry(Master::Start)]) {
+            match tag.unwrap() {
+                MatroskaSpec::TrackEntry(Master::Full(children)) if children.contains(&MatroskaSpec::CodecID(String::from("A_VORBIS"))) => {
+                    let codec_private = children.iter().find_map(|c| match c {
+                        MatroskaSpec::CodecPrivate(data) => Some(data.clone()),
+                        _ => None,
+                    }).unwrap();
+
+                    let headers = split_headers(&codec_private).unwrap();
+                    assert_eq!(3, headers.len());
+                    assert_eq!(codec_private, build_headers(&headers));
+
+                    let identification = IdentificationHeader::try_from(headers[0]).unwrap();
+                    assert_eq!(2, identification.channels);
+                    assert_eq!(44100, identification.sample_rate);
+                    durations = Some(PacketDurations::from_codec_private(&codec_private).unwrap());
+                },
+                MatroskaSpec::Timestamp(val) => cluster_timestamp = val as i64,
+                MatroskaSpec::SimpleBlock(data) => {
+                    let block = SimpleBlock::try_from(data.as_slice()).unwrap();
+                    if block.track == 2 {
+                        // Block timestamps are the end of the packet's samples, rounded to milliseconds
+                        samples += durations.as_mut().unwrap().packet_samples(block.raw_frame_data()).unwrap();
+                        let expected_ms = (samples * 1000 / 44100) as i64;
+                        assert!((cluster_timestamp + block.timestamp as i64 - expected_ms).abs() <= 1);
+                        packets += 1;
+                    }
+                },
+                _ => {},
+            }
+        }
+        assert!(packets > 100);
+    }
+}
@@ -0,0 +1,251 @@
+//!
+//! Reads and writes Vorbis comments, the `NAME=value` metadata used by Vorbis, Opus, and FLAC streams.
+//!
+//! The same structure is stored in the Vorbis comment header, the `OpusTags` packet, and the FLAC `VORBIS_COMMENT` metadata block, each with its own prefix.  [`VorbisComment`] handles the shared part, and can be built from Matroska "Tags" when exporting a track to a native file.
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+
+///
+/// A list of Vorbis comments along with the vendor string of the encoder.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::vorbis_comment::VorbisComment;
+///
+/// let mut comment = VorbisComment::new("encoder");
+/// comment.comments.push((String::from("TITLE"), String::from("Song")));
+///
+/// let data = comment.to_bytes();
+/// assert_eq!(comment, VorbisComment::try_from(data.as_slice()).unwrap());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VorbisComment {
+    pub vendor: String,
+
+    /// The comments as (name, value) pairs, in stream order.  Names are case-insensitive and may repeat.
+    pub comments: Vec<(String, String)>,
+}
+
+impl VorbisComment {
+    ///
+    /// Creates an empty comment list with the given vendor string.
+    ///
+    pub fn new(vendor: &str) -> Self {
+        VorbisComment {
+            vendor: String::from(vendor),
+            comments: Vec::new(),
+        }
+    }
+
+    ///
+    /// Builds comments from Matroska "Tag" elements (the children of a "Tags" element) that apply to a track.
+    ///
+    /// Tags apply if their "Targets" has no "TagTrackUID" or lists `track_uid`, and doesn't target chapters, editions, or attachments.  Each "SimpleTag" with a "TagString" becomes a comment, including nested ones.  Names are mostly kept as-is, since Matroska and Vorbis comments share names like `ARTIST` and `TITLE`, but names that mean something different at the album level ("TargetTypeValue" 50 and above) are renamed, such as `TITLE` to `ALBUM`.
+    ///
+    pub fn from_tags(tags: &[MatroskaSpec], track_uid: Option<u64>) -> Self {
+        let mut comment = VorbisComment::new("webm-iterable");
+        for tag in tags {
+            let children = match tag {
+                MatroskaSpec::Tag(Master::Full(children)) => children,
+                _ => continue,
+            };
+
+            let targets: &[MatroskaSpec] = children.iter().find_map(|c| match c {
+                MatroskaSpec::Targets(Master::Full(targets)) => Some(targets.as_slice()),
+                _ => None,
+            }).unwrap_or(&[]);
+            if !tag_applies(targets, track_uid) {
+                continue;
+            }
+
+            let album_level = targets.iter().find_map(|c| match c {
+                MatroskaSpec::TargetTypeValue(val) => Some(*val),
+                _ => None,
+            }).unwrap_or(50) >= 50;
+            for child in children {
+                if let MatroskaSpec::SimpleTag(Master::Full(simple_tag)) = child {
+                    add_simple_tag(&mut comment.comments, simple_tag, album_level);
+                }
+            }
+        }
+        comment
+    }
+
+    ///
+    /// Returns the values of every comment with the given name, ignoring case.
+    ///
+    pub fn get<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.comments.iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    ///
+    /// Writes the comments without any codec specific prefix or framing.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        data.extend_from_slice(self.vendor.as_bytes());
+        data.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for (name, value) in &self.comments {
+            data.extend_from_slice(&((name.len() + 1 + value.len()) as u32).to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.push(b'=');
+            data.extend_from_slice(value.as_bytes());
+        }
+        data
+    }
+}
+
+///
+/// Reads comments without any codec specific prefix.  Data after the last comment (such as the Vorbis framing bit) is ignored, as are comments without a `=`.
+///
+impl TryFrom<&[u8]> for VorbisComment {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let mut position = 0;
+        let vendor = read_string(data, &mut position)?;
+        let count = read_length(data, &mut position)?;
+
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            let comment = read_string(data, &mut position)?;
+            if let Some((name, value)) = comment.split_once('=') {
+                comments.push((String::from(name), String::from(value)));
+            }
+        }
+        Ok(VorbisComment { vendor, comments })
+    }
+}
+
+fn read_length(data: &[u8], position: &mut usize) -> Result<usize, CodecError> {
+    if data.len() < *position + 4 {
+        return Err(CodecError::TruncatedData(String::from("Vorbis comment ended before a length field")));
+    }
+    let length = u32::from_le_bytes([data[*position], data[*position + 1], data[*position + 2], data[*position + 3]]) as usize;
+    *position += 4;
+    Ok(length)
+}
+
+fn read_string(data: &[u8], position: &mut usize) -> Result<String, CodecError> {
+    let length = read_length(data, position)?;
+    if data.len() - *position < length {
+        return Err(CodecError::TruncatedData(format!("Vorbis comment string of {length} bytes is larger than the remaining data")));
+    }
+    let string = String::from_utf8(data[*position..*position + length].to_vec())
+        .map_err(|err| CodecError::InvalidData(format!("Vorbis comment string is not valid UTF-8: {err}")))?;
+    *position += length;
+    Ok(string)
+}
+
+fn tag_applies(targets: &[MatroskaSpec], track_uid: Option<u64>) -> bool {
+    let mut track_uids = targets.iter().filter_map(|c| match c {
+        MatroskaSpec::TagTrackUID(val) if *val != 0 => Some(*val),
+        _ => None,
+    }).peekable();
+    let other_targets = targets.iter().any(|c| matches!(c,
+        MatroskaSpec::TagEditionUID(val) | MatroskaSpec::TagChapterUID(val) | MatroskaSpec::TagAttachmentUID(val) if *val != 0
+    ));
+    !other_targets && (track_uids.peek().is_none() || track_uids.any(|uid| Some(uid) == track_uid))
+}
+
+fn add_simple_tag(comments: &mut Vec<(String, String)>, simple_tag: &[MatroskaSpec], album_level: bool) {
+    let name = simple_tag.iter().find_map(|c| match c {
+        MatroskaSpec::TagName(val) => Some(val.as_str()),
+        _ => None,
+    });
+    let value = simple_tag.iter().find_map(|c| match c {
+        MatroskaSpec::TagString(val) => Some(val.as_str()),
+        _ => None,
+    });
+
+    if let (Some(name), Some(value)) = (name, value) {
+        let name = match (name, album_level) {
+            ("TITLE", true) => "ALBUM",
+            ("ARTIST", true) => "ALBUMARTIST",
+            ("TOTAL_PARTS", true) => "TRACKTOTAL",
+            ("PART_NUMBER", false) => "TRACKNUMBER",
+            ("DATE_RELEASED", _) => "DATE",
+            (name, _) => name,
+        };
+        comments.push((String::from(name), String::from(value)));
+    }
+
+    for child in simple_tag {
+        if let MatroskaSpec::SimpleTag(Master::Full(nested)) = child {
+            add_simple_tag(comments, nested, album_level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::VorbisComment;
+    use crate::matroska_spec::{Master, MatroskaSpec};
+
+    fn simple_tag(name: &str, value: &str, nested: Vec<MatroskaSpec>) -> MatroskaSpec {
+        let mut children = vec![
+            MatroskaSpec::TagName(String::from(name)),
+            MatroskaSpec::TagString(String::from(value)),
+        ];
+        children.extend(nested);
+        MatroskaSpec::SimpleTag(Master::Full(children))
+    }
+
+    #[test]
+    fn builds_comments_from_tags() {
+        let tags = vec![
+            MatroskaSpec::Tag(Master::Full(vec![
+                MatroskaSpec::Targets(Master::Full(vec![MatroskaSpec::TargetTypeValue(50)])),
+                simple_tag("TITLE", "Album", vec![]),
+                simple_tag("ARTIST", "Band", vec![simple_tag("SORT_WITH", "Band, The", vec![])]),
+            ])),
+            MatroskaSpec::Tag(Master::Full(vec![
+                MatroskaSpec::Targets(Master::Full(vec![MatroskaSpec::TargetTypeValue(30), MatroskaSpec::TagTrackUID(7)])),
+                simple_tag("TITLE", "Song", vec![]),
+                simple_tag("PART_NUMBER", "3", vec![]),
+            ])),
+            MatroskaSpec::Tag(Master::Full(vec![
+                MatroskaSpec::Targets(Master::Full(vec![MatroskaSpec::TargetTypeValue(30), MatroskaSpec::TagTrackUID(8)])),
+                simple_tag("TITLE", "Other song", vec![]),
+            ])),
+        ];
+
+        let comment = VorbisComment::from_tags(&tags, Some(7));
+        let expected: Vec<(String, String)> = [("ALBUM", "Album"), ("ALBUMARTIST", "Band"), ("SORT_WITH", "Band, The"), ("TITLE", "Song"), ("TRACKNUMBER", "3")]
+            .iter()
+            .map(|(name, value)| (String::from(*name), String::from(*value)))
+            .collect();
+        assert_eq!(expected, comment.comments);
+        assert_eq!(vec!["Song"], comment.get("title").collect::<Vec<_>>());
+
+        assert_eq!(3, VorbisComment::from_tags(&tags, None).comments.len());
+    }
+
+    #[test]
+    fn reads_comments() {
+        let mut data = VorbisComment::new("vendor").to_bytes();
+        data[10] = 2;
+        data.extend_from_slice(&[0x05, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(b"A=b=c");
+        data.extend_from_slice(&[0x02, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(b"AB");
+        data.push(0x01);
+
+        let comment = VorbisComment::try_from(data.as_slice()).unwrap();
+        assert_eq!("vendor", comment.vendor);
+        assert_eq!(vec![(String::from("A"), String::from("b=c"))], comment.comments);
+        assert!(VorbisComment::try_from(&data[..data.len() - 2]).is_err());
+    }
+}
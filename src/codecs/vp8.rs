@@ -0,0 +1,141 @@
+//!
+//! Parses VP8 frame headers as defined in [RFC 6386](https://www.rfc-editor.org/rfc/rfc6386.html#section-9.1).
+//!
+//! This is used for "V_VP8" tracks to check whether a frame is a keyframe and to find the frame dimensions without decoding the frame.
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+
+const START_CODE: [u8; 3] = [0x9d, 0x01, 0x2a];
+
+///
+/// The dimensions and scaling stored in a VP8 keyframe.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dimensions {
+    pub width: u16,
+    pub height: u16,
+
+    /// The upscaling to apply horizontally (0 = none, 1 = 5/4, 2 = 5/3, 3 = 2).
+    pub horizontal_scale: u8,
+
+    /// The upscaling to apply vertically (0 = none, 1 = 5/4, 2 = 5/3, 3 = 2).
+    pub vertical_scale: u8,
+}
+
+///
+/// The uncompressed header at the start of every VP8 frame.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::vp8::FrameHeader;
+///
+/// let data = [0x50, 0x42, 0x00, 0x9d, 0x01, 0x2a, 0x40, 0x01, 0xf0, 0x00];
+/// let header = FrameHeader::try_from(&data[..]).unwrap();
+/// assert!(header.keyframe);
+/// assert_eq!(320, header.dimensions.unwrap().width);
+/// assert_eq!(240, header.dimensions.unwrap().height);
+/// ```
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub keyframe: bool,
+
+    /// The version number, which determines the reconstruction and loop filter used.
+    pub version: u8,
+
+    /// Whether the frame is meant to be displayed.
+    pub show_frame: bool,
+
+    /// The size of the first data partition in bytes, not including the frame header.
+    pub first_partition_size: u32,
+
+    /// The frame dimensions.  Only keyframes store dimensions, so this is `None` for all other frames.
+    pub dimensions: Option<Dimensions>,
+}
+
+impl TryFrom<&[u8]> for FrameHeader {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 3 {
+            return Err(CodecError::TruncatedData(String::from("VP8 frame is too short to contain a frame tag")));
+        }
+
+        let tag = data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16;
+        let keyframe = tag & 0x01 == 0;
+        let version = ((tag >> 1) & 0x07) as u8;
+        let show_frame = (tag >> 4) & 0x01 == 1;
+        let first_partition_size = tag >> 5;
+
+        let dimensions = if keyframe {
+            if data.len() < 10 {
+                return Err(CodecError::TruncatedData(String::from("VP8 keyframe is too short to contain its start code and dimensions")));
+            }
+            if data[3..6] != START_CODE {
+                return Err(CodecError::InvalidData(String::from("VP8 keyframe is missing its start code")));
+            }
+
+            let width = u16::from_le_bytes([data[6], data[7]]);
+            let height = u16::from_le_bytes([data[8], data[9]]);
+            Some(Dimensions {
+                width: width & 0x3fff,
+                height: height & 0x3fff,
+                horizontal_scale: (width >> 14) as u8,
+                vertical_scale: (height >> 14) as u8,
+            })
+        } else {
+            None
+        };
+
+        Ok(FrameHeader {
+            keyframe,
+            version,
+            show_frame,
+            first_partition_size,
+            dimensions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::fs::File;
+
+    use super::FrameHeader;
+    use crate::matroska_spec::{MatroskaSpec, SimpleBlock};
+    use crate::WebmIterator;
+
+    #[test]
+    fn parses_frames_from_test_file() {
+        let mut src = File::open("media/test.webm").unwrap();
+        let mut headers = Vec::new();
+        for tag in WebmIterator::new(&mut src, &[]) {
+            if let MatroskaSpec::SimpleBlock(data) = tag.unwrap() {
+                let block = SimpleBlock::try_from(data.as_slice()).unwrap();
+                if block.track == 1 {
+                    let header = FrameHeader::try_from(block.read_frame_data().unwrap()[0].data).unwrap();
+                    assert_eq!(block.keyframe, header.keyframe);
+                    headers.push(header);
+                }
+            }
+        }
+
+        assert!(headers.len() > 1);
+        let dimensions = headers[0].dimensions.unwrap();
+        assert_eq!((320, 240), (dimensions.width, dimensions.height));
+        assert!(headers.iter().skip(1).any(|h| !h.keyframe && h.dimensions.is_none()));
+    }
+
+    #[test]
+    fn rejects_bad_keyframes() {
+        assert!(FrameHeader::try_from(&[0x50, 0x42][..]).is_err());
+        assert!(FrameHeader::try_from(&[0x50, 0x42, 0x00, 0x9d, 0x01, 0x2b, 0x40, 0x01, 0xf0, 0x00][..]).is_err());
+        assert!(FrameHeader::try_from(&[0x51, 0x42, 0x00][..]).unwrap().dimensions.is_none());
+    }
+}
@@ -0,0 +1,452 @@
+//!
+//! Splits VP9 superframes and parses VP9 uncompressed frame headers as defined in the [VP9 bitstream specification](https://www.webmproject.org/vp9/).
+//!
+//! A single "V_VP9" block can hold a superframe - several frames packed together with an index at the end.  Usually this is a hidden alt-ref frame (`show_frame` is false) followed by a frame that is displayed.  [`split_superframe()`] separates these so that each frame header can be parsed with [`FrameHeader`].
+//!
+
+use std::convert::TryFrom;
+
+use crate::errors::CodecError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use super::bits::BitReader;
+
+const SYNC_CODE: u64 = 0x498342;
+
+///
+/// Splits a block frame into the VP9 frames it contains.
+///
+/// If the data ends with a valid superframe index, the frames listed in the index are returned.  Otherwise the data is a single frame and is returned as-is.
+///
+/// # Errors
+///
+/// This function returns an error if the superframe index lists frames that are larger than the data.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::vp9::split_superframe;
+///
+/// // Two frames of 2 and 1 bytes, followed by a 4 byte index (marker, sizes, marker)
+/// let data = [0x01, 0x02, 0x03, 0xc1, 0x02, 0x01, 0xc1];
+/// let frames = split_superframe(&data).unwrap();
+/// assert_eq!(vec![&[0x01, 0x02][..], &[0x03][..]], frames);
+/// ```
+///
+pub fn split_superframe(data: &[u8]) -> Result<Vec<&[u8]>, CodecError> {
+    let marker = match data.last() {
+        Some(marker) if marker & 0xe0 == 0xc0 => *marker,
+        _ => return Ok(vec![data]),
+    };
+
+    let frame_count = (marker & 0x07) as usize + 1;
+    let size_length = ((marker >> 3) & 0x03) as usize + 1;
+    let index_length = 2 + size_length * frame_count;
+    if data.len() < index_length || data[data.len() - index_length] != marker {
+        return Ok(vec![data]);
+    }
+
+    let index = &data[data.len() - index_length + 1..data.len() - 1];
+    let mut frames = Vec::with_capacity(frame_count);
+    let mut position = 0;
+    for size_bytes in index.chunks(size_length) {
+        let size = size_bytes.iter().rev().fold(0usize, |size, byte| (size << 8) | *byte as usize);
+        if position + size > data.len() - index_length {
+            return Err(CodecError::InvalidData(String::from("VP9 superframe index lists frames larger than the superframe")));
+        }
+        frames.push(&data[position..position + size]);
+        position += size;
+    }
+
+    Ok(frames)
+}
+
+///
+/// The color space signalled in a VP9 frame header.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Unknown,
+    Bt601,
+    Bt709,
+    Smpte170,
+    Smpte240,
+    Bt2020,
+    Reserved,
+    Srgb,
+}
+
+///
+/// The color configuration stored in VP9 keyframes and intra-only frames.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorConfig {
+    pub bit_depth: u8,
+    pub color_space: ColorSpace,
+
+    /// Whether the full range of values is used (as opposed to studio swing).
+    pub full_range: bool,
+
+    pub subsampling_x: bool,
+    pub subsampling_y: bool,
+}
+
+impl ColorConfig {
+    ///
+    /// Builds the children of a Matroska "Colour" element describing this color configuration.
+    ///
+    /// The "MatrixCoefficients" values follow ISO/IEC 23091-4/ITU-T H.273, as required by the Matroska spec.  The matrix is omitted if the color space is unknown or reserved.
+    ///
+    pub fn colour_children(&self) -> Vec<MatroskaSpec> {
+        let mut children = vec![
+            MatroskaSpec::BitsPerChannel(self.bit_depth as u64),
+            MatroskaSpec::ChromaSubsamplingHorz(self.subsampling_x as u64),
+            MatroskaSpec::ChromaSubsamplingVert(self.subsampling_y as u64),
+        ];
+
+        let matrix = match self.color_space {
+            ColorSpace::Bt601 | ColorSpace::Smpte170 => Some(6),
+            ColorSpace::Bt709 => Some(1),
+            ColorSpace::Smpte240 => Some(7),
+            ColorSpace::Bt2020 => Some(9),
+            ColorSpace::Srgb => Some(0),
+            ColorSpace::Unknown | ColorSpace::Reserved => None,
+        };
+        if let Some(matrix) = matrix {
+            children.push(MatroskaSpec::MatrixCoefficients(matrix));
+        }
+
+        children.push(MatroskaSpec::Range(if self.full_range { 2 } else { 1 }));
+        children
+    }
+
+    ///
+    /// Builds a Matroska "Colour" element describing this color configuration.  See [`Self::colour_children()`].
+    ///
+    pub fn to_colour(&self) -> MatroskaSpec {
+        MatroskaSpec::Colour(Master::Full(self.colour_children()))
+    }
+}
+
+///
+/// The frame and render sizes stored in VP9 keyframes and intra-only frames.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dimensions {
+    pub width: u16,
+    pub height: u16,
+    pub render_width: u16,
+    pub render_height: u16,
+}
+
+///
+/// The start of a VP9 uncompressed frame header.
+///
+/// Parsing stops once the frame type, color configuration, and frame size are known.  Inter frames take their size from reference frames, so only keyframes and intra-only frames report `color_config` and `dimensions`.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::vp9::{FrameHeader, ColorSpace};
+///
+/// let data = [0x82, 0x49, 0x83, 0x42, 0x00, 0x13, 0xf0, 0x0e, 0xf6, 0x00];
+/// let header = FrameHeader::try_from(&data[..]).unwrap();
+/// assert!(header.keyframe);
+/// assert_eq!(0, header.profile);
+/// assert_eq!(ColorSpace::Unknown, header.color_config.unwrap().color_space);
+/// assert_eq!(320, header.dimensions.unwrap().width);
+/// assert_eq!(240, header.dimensions.unwrap().height);
+/// ```
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub profile: u8,
+
+    /// If set, this frame doesn't contain any new data and instead re-shows the reference frame at the given index.
+    pub show_existing_frame: Option<u8>,
+
+    pub keyframe: bool,
+
+    /// Whether the frame is meant to be displayed.  Hidden frames (such as alt-ref frames) are only used as references for other frames.
+    pub show_frame: bool,
+
+    pub error_resilient: bool,
+    pub intra_only: bool,
+    pub color_config: Option<ColorConfig>,
+    pub dimensions: Option<Dimensions>,
+}
+
+impl FrameHeader {
+    ///
+    /// Whether this is a hidden frame (such as an alt-ref frame) that is decoded but never displayed.
+    ///
+    pub fn is_hidden(&self) -> bool {
+        !self.show_frame
+    }
+}
+
+impl TryFrom<&[u8]> for FrameHeader {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let mut reader = BitReader::new(data);
+        if reader.read_bits(2)? != 2 {
+            return Err(CodecError::InvalidData(String::from("VP9 frame is missing its frame marker")));
+        }
+
+        let profile_low = reader.read_bits(1)?;
+        let profile_high = reader.read_bits(1)?;
+        let profile = ((profile_high << 1) + profile_low) as u8;
+        if profile == 3 {
+            reader.skip_bits(1)?;
+        }
+
+        if reader.read_bit()? {
+            return Ok(FrameHeader {
+                profile,
+                show_existing_frame: Some(reader.read_bits(3)? as u8),
+                keyframe: false,
+                show_frame: true,
+                error_resilient: false,
+                intra_only: false,
+                color_config: None,
+                dimensions: None,
+            });
+        }
+
+        let keyframe = !reader.read_bit()?;
+        let show_frame = reader.read_bit()?;
+        let error_resilient = reader.read_bit()?;
+
+        let mut header = FrameHeader {
+            profile,
+            show_existing_frame: None,
+            keyframe,
+            show_frame,
+            error_resilient,
+            intra_only: false,
+            color_config: None,
+            dimensions: None,
+        };
+
+        if keyframe {
+            read_sync_code(&mut reader)?;
+            header.color_config = Some(read_color_config(&mut reader, profile)?);
+            header.dimensions = Some(read_dimensions(&mut reader)?);
+        } else {
+            header.intra_only = if show_frame { false } else { reader.read_bit()? };
+            if !error_resilient {
+                reader.skip_bits(2)?; // reset_frame_context
+            }
+
+            if header.intra_only {
+                read_sync_code(&mut reader)?;
+                header.color_config = Some(if profile > 0 {
+                    read_color_config(&mut reader, profile)?
+                } else {
+                    ColorConfig { bit_depth: 8, color_space: ColorSpace::Bt601, full_range: false, subsampling_x: true, subsampling_y: true }
+                });
+                reader.skip_bits(8)?; // refresh_frame_flags
+                header.dimensions = Some(read_dimensions(&mut reader)?);
+            }
+        }
+
+        Ok(header)
+    }
+}
+
+fn read_sync_code(reader: &mut BitReader) -> Result<(), CodecError> {
+    if reader.read_bits(24)? != SYNC_CODE {
+        return Err(CodecError::InvalidData(String::from("VP9 frame is missing its sync code")));
+    }
+    Ok(())
+}
+
+fn read_color_config(reader: &mut BitReader, profile: u8) -> Result<ColorConfig, CodecError> {
+    let bit_depth = if profile >= 2 {
+        if reader.read_bit()? { 12 } else { 10 }
+    } else {
+        8
+    };
+
+    let color_space = match reader.read_bits(3)? {
+        0 => ColorSpace::Unknown,
+        1 => ColorSpace::Bt601,
+        2 => ColorSpace::Bt709,
+        3 => ColorSpace::Smpte170,
+        4 => ColorSpace::Smpte240,
+        5 => ColorSpace::Bt2020,
+        6 => ColorSpace::Reserved,
+        _ => ColorSpace::Srgb,
+    };
+
+    let (full_range, subsampling_x, subsampling_y) = if color_space != ColorSpace::Srgb {
+        let full_range = reader.read_bit()?;
+        if profile == 1 || profile == 3 {
+            let subsampling = (reader.read_bit()?, reader.read_bit()?);
+            reader.skip_bits(1)?;
+            (full_range, subsampling.0, subsampling.1)
+        } else {
+            (full_range, true, true)
+        }
+    } else {
+        if profile == 1 || profile == 3 {
+            reader.skip_bits(1)?;
+        } else {
+            return Err(CodecError::InvalidData(String::from("VP9 profiles 0 and 2 do not support the sRGB color space")));
+        }
+        (true, false, false)
+    };
+
+    Ok(ColorConfig { bit_depth, color_space, full_range, subsampling_x, subsampling_y })
+}
+
+fn read_dimensions(reader: &mut BitReader) -> Result<Dimensions, CodecError> {
+    let width = reader.read_bits(16)? as u16 + 1;
+    let height = reader.read_bits(16)? as u16 + 1;
+    let (render_width, render_height) = if reader.read_bit()? {
+        (reader.read_bits(16)? as u16 + 1, reader.read_bits(16)? as u16 + 1)
+    } else {
+        (width, height)
+    };
+    Ok(Dimensions { width, height, render_width, render_height })
+}
+
+///
+/// The VP9 codec feature metadata that can be stored in the "CodecPrivate" of "V_VP9" tracks, as defined in the [WebM codec mapping](https://www.webmproject.org/docs/container/#vp9-codec-feature-metadata-codecprivate).
+///
+/// Every feature is optional.  Features with unknown IDs are skipped when reading.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::codecs::vp9::CodecFeatures;
+///
+/// let features = CodecFeatures::try_from(&[0x01, 0x01, 0x02, 0x03, 0x01, 0x0a][..]).unwrap();
+/// assert_eq!(Some(2), features.profile);
+/// assert_eq!(Some(10), features.bit_depth);
+/// assert_eq!(None, features.level);
+/// ```
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CodecFeatures {
+    pub profile: Option<u8>,
+
+    /// The level, as 10 times the level number (for example, 31 is level 3.1).
+    pub level: Option<u8>,
+
+    pub bit_depth: Option<u8>,
+
+    /// The chroma subsampling (0 = 4:2:0 vertically sited, 1 = 4:2:0 sited with luma, 2 = 4:2:2, 3 = 4:4:4).
+    pub chroma_subsampling: Option<u8>,
+}
+
+impl CodecFeatures {
+    ///
+    /// Writes the features that are set in the form stored in "CodecPrivate".
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.profile, self.level, self.bit_depth, self.chroma_subsampling].iter()
+            .zip(1u8..)
+            .filter_map(|(value, id)| value.map(|value| [id, 1, value]))
+            .flatten()
+            .collect()
+    }
+}
+
+impl TryFrom<&[u8]> for CodecFeatures {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let mut features = CodecFeatures::default();
+        let mut position = 0;
+        while position < data.len() {
+            if data.len() - position < 2 {
+                return Err(CodecError::TruncatedData(format!("VP9 codec feature at offset {position} is truncated")));
+            }
+            let id = data[position];
+            let length = data[position + 1] as usize;
+            position += 2;
+            if data.len() - position < length {
+                return Err(CodecError::TruncatedData(format!("VP9 codec feature {id} of {length} bytes is larger than the remaining data")));
+            }
+
+            let value = &data[position..position + length];
+            position += length;
+            let field = match id {
+                1 => &mut features.profile,
+                2 => &mut features.level,
+                3 => &mut features.bit_depth,
+                4 => &mut features.chroma_subsampling,
+                _ => continue,
+            };
+            if length != 1 {
+                return Err(CodecError::InvalidData(format!("VP9 codec feature {id} should be 1 byte, not {length}")));
+            }
+            *field = Some(value[0]);
+        }
+        Ok(features)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{split_superframe, ColorSpace, FrameHeader};
+    use crate::matroska_spec::MatroskaSpec;
+
+    #[test]
+    fn parses_profile_2_keyframe() {
+        // profile 2 is written low bit first, followed by show_existing_frame, frame_type (key), show_frame, and error_resilient_mode
+        let mut bits = String::from("10" /* marker */) + "01" /* profile */ + "0" + "0" + "1" + "0";
+        bits += &format!("{:024b}", 0x498342);
+        bits += "0" /* 10 bit */;
+        bits += "101" /* BT.2020 */;
+        bits += "1" /* full range */;
+        bits += &format!("{:016b}{:016b}", 1919, 1079);
+        bits += "0" /* no render size */;
+        let data: Vec<u8> = bits.as_bytes().chunks(8)
+            .map(|c| u8::from_str_radix(&format!("{:0<8}", std::str::from_utf8(c).unwrap()), 2).unwrap())
+            .collect();
+
+        let header = FrameHeader::try_from(data.as_slice()).unwrap();
+        assert_eq!(2, header.profile);
+        assert!(header.keyframe);
+        assert!(!header.is_hidden());
+        let color = header.color_config.unwrap();
+        assert_eq!(10, color.bit_depth);
+        assert_eq!(ColorSpace::Bt2020, color.color_space);
+        assert!(color.full_range);
+        assert_eq!((1920, 1080), (header.dimensions.unwrap().width, header.dimensions.unwrap().height));
+        assert!(color.colour_children().contains(&MatroskaSpec::MatrixCoefficients(9)));
+        assert!(color.colour_children().contains(&MatroskaSpec::BitsPerChannel(10)));
+    }
+
+    #[test]
+    fn parses_hidden_and_existing_frames() {
+        // profile 0 inter frame: marker 10, profile 00, show_existing 0, frame_type 1, show 0, error_res 0
+        let hidden = FrameHeader::try_from(&[0b1000_0100, 0x00][..]).unwrap();
+        assert!(!hidden.keyframe);
+        assert!(hidden.is_hidden());
+        assert!(!hidden.intra_only);
+        assert!(hidden.dimensions.is_none());
+
+        // marker 10, profile 00, show_existing 1, index 011
+        let existing = FrameHeader::try_from(&[0b1000_1011][..]).unwrap();
+        assert_eq!(Some(3), existing.show_existing_frame);
+    }
+
+    #[test]
+    fn splits_superframes() {
+        let data = [0xaa; 300].iter().chain(&[0xbb, 0xc9, 0x2c, 0x01, 0x01, 0x00, 0xc9]).copied().collect::<Vec<u8>>();
+        let frames = split_superframe(&data).unwrap();
+        assert_eq!(2, frames.len());
+        assert_eq!(300, frames[0].len());
+        assert_eq!(&[0xbb], frames[1]);
+
+        assert_eq!(1, split_superframe(&[0x01, 0x02, 0xc0]).unwrap().len());
+        assert!(split_superframe(&[0x01, 0xc1, 0x05, 0x01, 0xc1]).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//!
+//! Splits and builds the Xiph-laced header sets stored in the "CodecPrivate" of "A_VORBIS" and "V_THEORA" tracks.
+//!
+//! These codecs need three header packets (identification, comment, and setup) before any frames can be decoded.  Matroska stores them together in "CodecPrivate" using the same Xiph lacing used for laced blocks: a count byte holding the number of packets minus one, the sizes of every packet except the last, and then the packet data.
+//!
+
+use crate::errors::CodecError;
+use crate::matroska_spec::{lacing_sizes, read_frame_data, BlockLacing, Frame};
+
+///
+/// Splits Xiph-laced "CodecPrivate" data into its header packets.
+///
+/// # Errors
+///
+/// This function returns an error if the data is empty or the lacing sizes don't fit the data.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::xiph::split_headers;
+///
+/// let headers = split_headers(&[0x02, 0x01, 0x02, 0xaa, 0xbb, 0xbb, 0xcc]).unwrap();
+/// assert_eq!(vec![&[0xaa][..], &[0xbb, 0xbb][..], &[0xcc][..]], headers);
+/// ```
+///
+pub fn split_headers(data: &[u8]) -> Result<Vec<&[u8]>, CodecError> {
+    if data.is_empty() {
+        return Err(CodecError::TruncatedData(String::from("Xiph-laced header data is empty")));
+    }
+
+    let frames = read_frame_data(data, &Some(BlockLacing::Xiph))
+        .map_err(|err| CodecError::InvalidData(format!("Unable to split Xiph-laced headers: {err}")))?;
+    Ok(frames.into_iter().map(|frame| frame.data).collect())
+}
+
+///
+/// Builds Xiph-laced "CodecPrivate" data from header packets.  This is the inverse of [`split_headers()`].
+///
+/// # Panics
+///
+/// This function panics if `headers` is empty or has more than 256 packets.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::codecs::xiph::build_headers;
+///
+/// let data = build_headers(&[&[0xaa], &[0xbb; 300], &[0xcc]]);
+/// assert_eq!(&[0x02, 0x01, 0xff, 0x2d, 0xaa], &data[..5]);
+/// ```
+///
+pub fn build_headers(headers: &[&[u8]]) -> Vec<u8> {
+    assert!(!headers.is_empty() && headers.len() <= 256, "Xiph lacing requires between 1 and 256 packets");
+
+    let frames: Vec<Frame> = headers.iter().map(|data| Frame { data }).collect();
+    let sizes = lacing_sizes(&frames, BlockLacing::Xiph).expect("Xiph lacing works for any frame sizes");
+
+    let mut data = Vec::with_capacity(1 + sizes.len() + headers.iter().map(|h| h.len()).sum::<usize>());
+    data.push((headers.len() - 1) as u8);
+    data.extend_from_slice(&sizes);
+    for header in headers {
+        data.extend_from_slice(header);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_headers, split_headers};
+
+    #[test]
+    fn round_trips_headers() {
+        let headers: [&[u8]; 3] = [&[0x01; 30], &[0x03; 510], &[0x05; 4000]];
+        let data = build_headers(&headers);
+        assert_eq!(&[0x02, 30, 0xff, 0xff, 0x00], &data[..5]);
+        assert_eq!(headers.to_vec(), split_headers(&data).unwrap());
+
+        assert!(split_headers(&[]).is_err());
+        assert!(split_headers(&[0x02, 0x05]).is_err());
+    }
+}
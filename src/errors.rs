@@ -24,6 +24,11 @@ pub enum WebmCoercionError {
     /// An error when coercing raw SimpleBlock data into a [`super::matroska_spec::SimpleBlock`] struct.
     ///
     SimpleBlockCoercionError(String),
+
+    ///
+    /// An error when an absolute timestamp cannot be stored as a block timestamp relative to its cluster.
+    ///
+    TimestampCoercionError(String),
 }
 
 impl fmt::Display for WebmCoercionError {
@@ -31,8 +36,157 @@ impl fmt::Display for WebmCoercionError {
         match self {
             WebmCoercionError::BlockCoercionError(msg) => write!(f, "{msg}"),
             WebmCoercionError::SimpleBlockCoercionError(msg) => write!(f, "{msg}"),
+            WebmCoercionError::TimestampCoercionError(msg) => write!(f, "{msg}"),
         }
     }
 }
 
-impl Error for WebmCoercionError {}
\ No newline at end of file
+impl Error for WebmCoercionError {}
+
+///
+/// Errors that can occur when parsing or building codec specific data, such as frame headers or "CodecPrivate" contents.
+///
+#[derive(Debug)]
+pub enum CodecError {
+
+    ///
+    /// An error when the data ends before everything required by the codec format could be read.
+    ///
+    TruncatedData(String),
+
+    ///
+    /// An error when the data doesn't follow the format expected for the codec.
+    ///
+    InvalidData(String),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::TruncatedData(msg) => write!(f, "{msg}"),
+            CodecError::InvalidData(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl Error for CodecError {}
+
+///
+/// Errors that can occur when muxing frames into WebM data.
+///
+#[derive(Debug)]
+pub enum MuxerError {
+
+    ///
+    /// An error when a track passed to the muxer is malformed or can no longer be added.
+    ///
+    TrackError(String),
+
+    ///
+    /// An error when a frame passed to the muxer cannot be written.
+    ///
+    FrameError(String),
+
+    ///
+    /// An error that wraps a [`TagWriterError`] when writing to the underlying destination.
+    ///
+    WriteError(TagWriterError),
+}
+
+impl fmt::Display for MuxerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MuxerError::TrackError(msg) => write!(f, "{msg}"),
+            MuxerError::FrameError(msg) => write!(f, "{msg}"),
+            MuxerError::WriteError(_) => write!(f, "Error writing muxed data to destination."),
+        }
+    }
+}
+
+impl Error for MuxerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MuxerError::WriteError(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<TagWriterError> for MuxerError {
+    fn from(err: TagWriterError) -> Self {
+        MuxerError::WriteError(err)
+    }
+}
+
+///
+/// Errors that can occur when converting between WebM and other file formats.
+///
+#[derive(Debug)]
+pub enum FormatError {
+
+    ///
+    /// An error when the input doesn't follow the expected format, or holds data that can't be converted.
+    ///
+    InvalidData(String),
+
+    ///
+    /// An error that wraps a [`TagIteratorError`] when reading WebM data.
+    ///
+    ReadError(TagIteratorError),
+
+    ///
+    /// An error that wraps a [`MuxerError`] when writing WebM data.
+    ///
+    MuxError(MuxerError),
+
+    ///
+    /// An error that wraps a [`std::io::Error`] when reading or writing data in the other format.
+    ///
+    IoError(std::io::Error),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::InvalidData(msg) => write!(f, "{msg}"),
+            FormatError::ReadError(_) => write!(f, "Error reading WebM data from source."),
+            FormatError::MuxError(_) => write!(f, "Error muxing WebM data."),
+            FormatError::IoError(_) => write!(f, "Error reading or writing data."),
+        }
+    }
+}
+
+impl Error for FormatError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FormatError::ReadError(source) => Some(source),
+            FormatError::MuxError(source) => Some(source),
+            FormatError::IoError(source) => Some(source),
+            FormatError::InvalidData(_) => None,
+        }
+    }
+}
+
+impl From<TagIteratorError> for FormatError {
+    fn from(err: TagIteratorError) -> Self {
+        FormatError::ReadError(err)
+    }
+}
+
+impl From<MuxerError> for FormatError {
+    fn from(err: MuxerError) -> Self {
+        FormatError::MuxError(err)
+    }
+}
+
+impl From<std::io::Error> for FormatError {
+    fn from(err: std::io::Error) -> Self {
+        FormatError::IoError(err)
+    }
+}
+
+impl From<CodecError> for FormatError {
+    fn from(err: CodecError) -> Self {
+        FormatError::InvalidData(err.to_string())
+    }
+}
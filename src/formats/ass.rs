@@ -0,0 +1,486 @@
+//!
+//! Reads and writes Advanced SubStation Alpha (`.ass`) and SubStation Alpha (`.ssa`) scripts, and converts "S_TEXT/ASS" and "S_TEXT/SSA" tracks between Matroska and script files.
+//!
+//! Matroska stores everything before the events - the `[Script Info]` and styles sections, ending with the `[Events]` section header and its `Format:` line - in "CodecPrivate".  Each `Dialogue:` event is stored in its own "Block", with the timing moved into the block timestamp and "BlockDuration".  The block always holds the fields `ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text`, whatever the order of the script's `Format:` line, where `ReadOrder` is the event's position in the original script.  Events are rearranged into this layout when muxing and back into the `Format:` order when extracting.  SSA `Marked` values are stored as `Layer`, fields missing from the `Format:` line are stored empty, and fields outside the layout are dropped.
+//!
+//! Scripts often rely on fonts stored as Matroska attachments, which can be listed with [`attached_fonts()`].
+//!
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+
+use crate::errors::FormatError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use crate::muxer::{MuxFrame, MuxerOptions, WebmMuxer};
+use crate::WebmIterator;
+use super::{Cue, TrackReader};
+
+const EVENTS_SECTION: &str = "[Events]";
+const ASS_FORMAT: [&str; 10] = ["Layer", "Start", "End", "Style", "Name", "MarginL", "MarginR", "MarginV", "Effect", "Text"];
+const SSA_FORMAT: [&str; 10] = ["Marked", "Start", "End", "Style", "Name", "MarginL", "MarginR", "MarginV", "Effect", "Text"];
+const BLOCK_FIELDS: [&str; 8] = ["Layer", "Style", "Name", "MarginL", "MarginR", "MarginV", "Effect", "Text"];
+
+const FONT_MIME_TYPES: [&str; 9] = [
+    "application/x-truetype-font", "application/x-font-ttf", "application/x-font-otf", "application/vnd.ms-opentype",
+    "application/font-sfnt", "font/ttf", "font/otf", "font/sfnt", "font/collection",
+];
+const FONT_EXTENSIONS: [&str; 3] = [".ttf", ".otf", ".ttc"];
+
+///
+/// A single `Dialogue:` event.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssEvent {
+    /// The position of the event in the original script, which may differ from the order of start times.
+    pub read_order: u64,
+
+    /// The event start time, in milliseconds.
+    pub start: u64,
+
+    /// The event end time, in milliseconds.
+    pub end: u64,
+
+    /// The remaining fields in the Matroska block layout: `Layer`, `Style`, `Name`, `MarginL`, `MarginR`, `MarginV`, `Effect` and `Text`.
+    pub fields: Vec<String>,
+}
+
+impl AssEvent {
+    ///
+    /// Reads an event from Matroska block data, given the event timing.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the data isn't valid UTF-8 or has too few fields.
+    ///
+    pub fn from_block(data: &[u8], start: u64, end: u64) -> Result<Self, FormatError> {
+        let text = std::str::from_utf8(data)
+            .map_err(|_| FormatError::InvalidData(format!("Subtitle event at {start}ms is not valid UTF-8")))?;
+        let mut fields = text.splitn(BLOCK_FIELDS.len() + 1, ',');
+        let read_order = fields.next().unwrap_or("").trim().parse::<u64>()
+            .map_err(|_| FormatError::InvalidData(format!("Subtitle event at {start}ms has no ReadOrder field")))?;
+        let fields: Vec<String> = fields.map(String::from).collect();
+        if fields.len() != BLOCK_FIELDS.len() {
+            return Err(FormatError::InvalidData(format!("Subtitle event at {start}ms has {} fields instead of {}", fields.len(), BLOCK_FIELDS.len())));
+        }
+        Ok(AssEvent { read_order, start, end, fields })
+    }
+
+    ///
+    /// Writes the event in the Matroska block format.
+    ///
+    pub fn to_block(&self) -> Vec<u8> {
+        let mut data = self.read_order.to_string();
+        for field in &self.fields {
+            data.push(',');
+            data.push_str(field);
+        }
+        data.into_bytes()
+    }
+}
+
+///
+/// The contents of an ASS or SSA script.
+///
+/// Only `Dialogue:` events are kept; `Comment:` and other event lines are dropped when reading.  Any sections after `[Events]` (such as embedded `[Fonts]`) are kept in the header, before the `[Events]` section.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::formats::ass::AssScript;
+///
+/// let script = AssScript::try_from("[Script Info]\nScriptType: v4.00+\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.50,Default,,0,0,0,,Hello, world!\n").unwrap();
+/// assert_eq!("S_TEXT/ASS", script.codec_id());
+/// assert_eq!((1000, 2500), (script.events[0].start, script.events[0].end));
+/// assert_eq!(b"0,0,Default,,0,0,0,,Hello, world!".to_vec(), script.events[0].to_block());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssScript {
+    /// The script sections other than `[Events]`, such as `[Script Info]` and `[V4+ Styles]`.
+    pub sections: String,
+
+    /// The field names from the `Format:` line of the `[Events]` section.
+    pub format: Vec<String>,
+
+    pub events: Vec<AssEvent>,
+}
+
+impl AssScript {
+    ///
+    /// Reads the script header stored in a track's "CodecPrivate".  If the header has no `[Events]` format, the default format for the script type is used.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the data isn't valid UTF-8 or has an invalid `Format:` line.
+    ///
+    pub fn from_codec_private(data: &[u8]) -> Result<Self, FormatError> {
+        let text = std::str::from_utf8(data)
+            .map_err(|_| FormatError::InvalidData(String::from("Subtitle CodecPrivate is not valid UTF-8")))?;
+        AssScript::try_from(text)
+    }
+
+    ///
+    /// "S_TEXT/SSA" for scripts with a `[V4 Styles]` section or a `ScriptType` of `v4.00`, and "S_TEXT/ASS" otherwise.
+    ///
+    pub fn codec_id(&self) -> &'static str {
+        let ssa = self.sections.lines().any(|line| {
+            let line = line.trim();
+            line.eq_ignore_ascii_case("[V4 Styles]")
+                || line.split_once(':').is_some_and(|(name, value)| name.trim().eq_ignore_ascii_case("ScriptType") && value.trim().eq_ignore_ascii_case("v4.00"))
+        });
+        if ssa { "S_TEXT/SSA" } else { "S_TEXT/ASS" }
+    }
+
+    ///
+    /// The header to store in "CodecPrivate": the other sections, followed by the `[Events]` section header and `Format:` line.
+    ///
+    pub fn header(&self) -> String {
+        let mut header = String::from(self.sections.trim_end());
+        if !header.is_empty() {
+            header.push_str("\n\n");
+        }
+        header.push_str(EVENTS_SECTION);
+        header.push_str("\nFormat: ");
+        header.push_str(&self.format.join(", "));
+        header.push('\n');
+        header
+    }
+
+    fn set_default_format(&mut self) {
+        let format = if self.codec_id() == "S_TEXT/SSA" { SSA_FORMAT } else { ASS_FORMAT };
+        self.format = format.iter().map(|name| String::from(*name)).collect();
+    }
+
+    fn parse_dialogue(&self, line: &str, line_number: usize) -> Result<AssEvent, FormatError> {
+        let invalid = |message: &str| FormatError::InvalidData(format!("Invalid subtitle event on line {line_number}: {message}"));
+        let mut event = AssEvent { read_order: self.events.len() as u64, start: 0, end: 0, fields: vec![String::new(); BLOCK_FIELDS.len()] };
+        let values: Vec<&str> = line.splitn(self.format.len(), ',').collect();
+        if values.len() != self.format.len() {
+            return Err(invalid(&format!("expected {} fields, found {}", self.format.len(), values.len())));
+        }
+
+        for (name, value) in self.format.iter().zip(values) {
+            if name.eq_ignore_ascii_case("Start") {
+                event.start = parse_timestamp(value.trim()).ok_or_else(|| invalid(&format!("invalid start time {value:?}")))?;
+            } else if name.eq_ignore_ascii_case("End") {
+                event.end = parse_timestamp(value.trim()).ok_or_else(|| invalid(&format!("invalid end time {value:?}")))?;
+            } else if let Some(index) = block_field_index(name) {
+                event.fields[index] = String::from(value);
+            }
+        }
+        Ok(event)
+    }
+}
+
+impl TryFrom<&str> for AssScript {
+    type Error = FormatError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text).replace("\r\n", "\n").replace('\r', "\n");
+
+        let mut script = AssScript { sections: String::new(), format: Vec::new(), events: Vec::new() };
+        let mut in_events = false;
+        for (index, line) in text.split('\n').enumerate() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                in_events = trimmed.eq_ignore_ascii_case(EVENTS_SECTION);
+                if in_events {
+                    continue;
+                }
+            }
+            if !in_events {
+                script.sections.push_str(line);
+                script.sections.push('\n');
+                continue;
+            }
+
+            match trimmed.split_once(':') {
+                Some((kind, fields)) if kind.eq_ignore_ascii_case("Format") => {
+                    script.format = fields.split(',').map(|name| String::from(name.trim())).collect();
+                    let timing_fields = script.format.iter().filter(|name| is_timing_field(name)).count();
+                    if !script.format.last().is_some_and(|name| name.eq_ignore_ascii_case("Text")) || timing_fields != 2 {
+                        return Err(FormatError::InvalidData(format!("Invalid subtitle event format on line {}: {fields:?}", index + 1)));
+                    }
+                },
+                Some((kind, fields)) if kind.eq_ignore_ascii_case("Dialogue") => {
+                    if script.format.is_empty() {
+                        script.set_default_format();
+                    }
+                    let event = script.parse_dialogue(fields.trim_start(), index + 1)?;
+                    script.events.push(event);
+                },
+                _ => {},
+            }
+        }
+
+        if script.format.is_empty() {
+            script.set_default_format();
+        }
+        script.sections = String::from(script.sections.trim_end());
+        Ok(script)
+    }
+}
+
+impl fmt::Display for AssScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header())?;
+        for event in &self.events {
+            let values: Vec<String> = self.format.iter().map(|name| {
+                if name.eq_ignore_ascii_case("Start") {
+                    format_timestamp(event.start)
+                } else if name.eq_ignore_ascii_case("End") {
+                    format_timestamp(event.end)
+                } else {
+                    block_field_index(name).and_then(|index| event.fields.get(index)).cloned().unwrap_or_default()
+                }
+            }).collect();
+            writeln!(f, "Dialogue: {}", values.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// A font stored as a Matroska "AttachedFile".
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttachedFont {
+    pub file_name: String,
+    pub mime_type: String,
+    pub description: Option<String>,
+    pub uid: u64,
+    pub data: Vec<u8>,
+}
+
+///
+/// Lists the fonts attached to Matroska data, so they can be installed or passed to a renderer along with an extracted script.
+///
+/// Attachments are treated as fonts if they have a font MIME type or a `.ttf`, `.otf`, or `.ttc` file name.  The whole source is read, since "Attachments" may come after the clusters.
+///
+/// # Errors
+///
+/// This function returns an error if there is a problem reading the source.
+///
+pub fn attached_fonts<R: Read>(source: R) -> Result<Vec<AttachedFont>, FormatError> {
+    let mut fonts = Vec::new();
+    for tag in WebmIterator::new(source, &[MatroskaSpec::AttachedFile(Master::Start)]) {
+        let children = match tag? {
+            MatroskaSpec::AttachedFile(Master::Full(children)) => children,
+            _ => continue,
+        };
+
+        let mut font = AttachedFont { file_name: String::new(), mime_type: String::new(), description: None, uid: 0, data: Vec::new() };
+        for child in children {
+            match child {
+                MatroskaSpec::FileName(val) => font.file_name = val,
+                MatroskaSpec::FileMimeType(val) => font.mime_type = val,
+                MatroskaSpec::FileDescription(val) => font.description = Some(val),
+                MatroskaSpec::FileUID(val) => font.uid = val,
+                MatroskaSpec::FileData(val) => font.data = val,
+                _ => {},
+            }
+        }
+
+        let file_name = font.file_name.to_ascii_lowercase();
+        if FONT_MIME_TYPES.iter().any(|mime_type| font.mime_type.eq_ignore_ascii_case(mime_type)) || FONT_EXTENSIONS.iter().any(|ext| file_name.ends_with(ext)) {
+            fonts.push(font);
+        }
+    }
+    Ok(fonts)
+}
+
+///
+/// Parses an ASS timestamp (`h:mm:ss.cc`) into milliseconds.
+///
+pub fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (time, centis) = timestamp.split_once('.')?;
+    let parts: Vec<&str> = time.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+        _ => return None,
+    };
+
+    let number = |value: &str| -> Option<u64> {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        value.parse().ok()
+    };
+    let (hours, minutes, seconds, centis) = (number(hours)?, number(minutes)?, number(seconds)?, number(centis)?);
+    if minutes > 59 || seconds > 59 || centis > 99 {
+        return None;
+    }
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + centis * 10)
+}
+
+///
+/// Formats milliseconds as an ASS timestamp, rounding down to centiseconds.
+///
+pub fn format_timestamp(timestamp: u64) -> String {
+    format!("{}:{:02}:{:02}.{:02}", timestamp / 3_600_000, timestamp / 60_000 % 60, timestamp / 1000 % 60, timestamp % 1000 / 10)
+}
+
+fn is_timing_field(name: &str) -> bool {
+    name.eq_ignore_ascii_case("Start") || name.eq_ignore_ascii_case("End")
+}
+
+fn block_field_index(name: &str) -> Option<usize> {
+    if name.eq_ignore_ascii_case("Marked") {
+        return Some(0);
+    }
+    BLOCK_FIELDS.iter().position(|field| field.eq_ignore_ascii_case(name))
+}
+
+///
+/// Writes an "S_TEXT/ASS" or "S_TEXT/SSA" track from Matroska data to a script file.
+///
+/// The header comes from "CodecPrivate", and events are written in `ReadOrder` with their fields arranged by the header's `Format:` line and `Start` and `End` restored from the block timing.  An event without a "BlockDuration" ends when the next block starts.  Use [`attached_fonts()`] to find the fonts the script needs.
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found or isn't an ASS or SSA track, if the header or an event is invalid, or if there is a problem reading the source or writing the destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::ass;
+///
+/// let source = File::open("episode.mkv").unwrap();
+/// ass::extract(source, 3, File::create("episode.ass").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, W: Write>(source: R, track: u64, dest: W) -> Result<W, FormatError> {
+    write_track(TrackReader::new(source, track)?, dest)
+}
+
+///
+/// Rebuilds the script from "CodecPrivate" and the events read by `reader`.
+///
+pub(crate) fn write_track<R: Read, W: Write>(reader: TrackReader<R>, mut dest: W) -> Result<W, FormatError> {
+    if reader.codec_id() != "S_TEXT/ASS" && reader.codec_id() != "S_TEXT/SSA" {
+        return Err(FormatError::InvalidData(format!("{} tracks are not ASS or SSA", reader.codec_id())));
+    }
+    let codec_private = reader.track_entry().iter().find_map(|c| match c {
+        MatroskaSpec::CodecPrivate(data) => Some(data.as_slice()),
+        _ => None,
+    }).unwrap_or(&[]);
+    let mut script = AssScript::from_codec_private(codec_private)?;
+    script.events.clear();
+
+    for Cue { start, end, frame } in reader.read_cues()? {
+        script.events.push(AssEvent::from_block(&frame.data, start, end)?);
+    }
+    script.events.sort_by_key(|event| event.read_order);
+
+    dest.write_all(script.to_string().as_bytes())?;
+    Ok(dest)
+}
+
+///
+/// Muxes an ASS or SSA script into a new Matroska file with a single "S_TEXT/ASS" or "S_TEXT/SSA" subtitle track.
+///
+/// The script header is stored in "CodecPrivate", and each event is written as a "BlockGroup" with a "BlockDuration", in order of start time, with timestamps in milliseconds.  The output "DocType" is `matroska`, since WebM doesn't allow ASS or SSA tracks.
+///
+/// # Errors
+///
+/// This function returns an error if the source isn't a valid UTF-8 script, or if there is a problem reading the source or writing the destination.
+///
+pub fn import<R: Read, W: Write>(mut source: R, dest: W) -> Result<W, FormatError> {
+    let mut text = String::new();
+    source.read_to_string(&mut text)?;
+    let mut script = AssScript::try_from(text.as_str())?;
+    script.events.sort_by_key(|event| event.start);
+
+    let mut muxer = WebmMuxer::with_options(dest, MuxerOptions { doc_type: String::from("matroska"), ..MuxerOptions::default() });
+    let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+        MatroskaSpec::TrackType(0x11),
+        MatroskaSpec::CodecID(String::from(script.codec_id())),
+        MatroskaSpec::CodecPrivate(script.header().into_bytes()),
+    ])))?;
+
+    for event in &script.events {
+        let mut frame = MuxFrame::new(track, event.start as i64, event.to_block());
+        frame.duration = Some(event.end.saturating_sub(event.start));
+        muxer.write_frame(frame)?;
+    }
+    Ok(muxer.finalize()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{attached_fonts, extract, import, AssScript};
+    use crate::formats::TrackReader;
+    use crate::matroska_spec::{Master, MatroskaSpec};
+    use crate::WebmWriter;
+
+    const SCRIPT: &str = "\u{feff}[Script Info]\r\nTitle: Test\r\nScriptType: v4.00+\r\n\r\n[V4+ Styles]\r\nFormat: Name, Fontname, Fontsize\r\nStyle: Default,Open Sans,20\r\n\r\n[Events]\r\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\r\nDialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,Second, later\r\nComment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Dropped\r\nDialogue: 1,0:00:01.50,0:00:03.25,Default,Bob,0,0,0,,{\\i1}First{\\i0}\r\n";
+
+    #[test]
+    fn parses_script() {
+        let script = AssScript::try_from(SCRIPT).unwrap();
+        assert_eq!("S_TEXT/ASS", script.codec_id());
+        assert!(script.sections.starts_with("[Script Info]") && script.sections.ends_with("Style: Default,Open Sans,20"));
+        assert_eq!(2, script.events.len());
+        assert_eq!((1, 1500, 3250), (script.events[1].read_order, script.events[1].start, script.events[1].end));
+        assert_eq!(b"1,1,Default,Bob,0,0,0,,{\\i1}First{\\i0}".to_vec(), script.events[1].to_block());
+        assert_eq!(script, AssScript::try_from(script.to_string().as_str()).unwrap());
+
+        let ssa = AssScript::try_from("[Script Info]\nScriptType: v4.00\n").unwrap();
+        assert_eq!(("S_TEXT/SSA", "Marked"), (ssa.codec_id(), ssa.format[0].as_str()));
+        assert!(AssScript::try_from("[Events]\nDialogue: 0,0:00:01.00,0:00:02.00\n").is_err());
+    }
+
+    #[test]
+    fn round_trips_matroska_track() {
+        let mkv = import(SCRIPT.as_bytes(), Vec::new()).unwrap();
+        let reader = TrackReader::new(mkv.as_slice(), 1).unwrap();
+        let expected_header = AssScript::try_from(SCRIPT).unwrap().header().into_bytes();
+        assert!(reader.track_entry().contains(&MatroskaSpec::CodecPrivate(expected_header)));
+        let frames: Vec<_> = reader.map(|f| f.unwrap()).collect();
+        assert_eq!((1500, Some(1750)), (frames[0].timestamp, frames[0].duration));
+        assert!(frames[0].data.starts_with(b"1,1,Default"));
+
+        let ass = String::from_utf8(extract(mkv.as_slice(), 1, Vec::new()).unwrap()).unwrap();
+        assert_eq!(AssScript::try_from(SCRIPT).unwrap(), AssScript::try_from(ass.as_str()).unwrap());
+        assert!(ass.find("Second, later").unwrap() < ass.find("First").unwrap());
+    }
+
+    #[test]
+    fn stores_fixed_block_layout() {
+        let script = "[Script Info]\nScriptType: v4.00+\n\n[Events]\nFormat: Start, End, Style, Name, Layer, Effect, Text\nDialogue: 0:00:01.00,0:00:02.00,Default,Bob,2,Scroll up,Hi, there\n";
+        let mkv = import(script.as_bytes(), Vec::new()).unwrap();
+        let frames: Vec<_> = TrackReader::new(mkv.as_slice(), 1).unwrap().map(|f| f.unwrap()).collect();
+        assert_eq!(b"0,2,Default,Bob,,,,Scroll up,Hi, there".to_vec(), frames[0].data);
+
+        let ass = String::from_utf8(extract(mkv.as_slice(), 1, Vec::new()).unwrap()).unwrap();
+        assert!(ass.ends_with("Dialogue: 0:00:01.00,0:00:02.00,Default,Bob,2,Scroll up,Hi, there\n"));
+    }
+
+    #[test]
+    fn lists_attached_fonts() {
+        let attached_file = |name: &str, mime_type: &str, uid: u64| MatroskaSpec::AttachedFile(Master::Full(vec![
+            MatroskaSpec::FileName(String::from(name)),
+            MatroskaSpec::FileMimeType(String::from(mime_type)),
+            MatroskaSpec::FileData(vec![0x00, 0x01, 0x00, 0x00]),
+            MatroskaSpec::FileUID(uid),
+        ]));
+        let mut data = Vec::new();
+        WebmWriter::new(&mut data).write(&MatroskaSpec::Segment(Master::Full(vec![
+            MatroskaSpec::Attachments(Master::Full(vec![
+                attached_file("OpenSans.TTF", "application/octet-stream", 1),
+                attached_file("cover.jpg", "image/jpeg", 2),
+                attached_file("font", "font/otf", 3),
+            ])),
+        ]))).unwrap();
+
+        let fonts = attached_fonts(data.as_slice()).unwrap();
+        assert_eq!(vec![1, 3], fonts.iter().map(|f| f.uid).collect::<Vec<_>>());
+        assert_eq!("OpenSans.TTF", fonts[0].file_name);
+        assert_eq!(vec![0x00, 0x01, 0x00, 0x00], fonts[1].data);
+    }
+}
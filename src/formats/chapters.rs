@@ -0,0 +1,520 @@
+//!
+//! Reads and writes chapters in the mkvtoolnix XML format and in the simple OGM text format.
+//!
+//! The XML mirrors the Matroska "Chapters" element, with `<EditionEntry>` elements holding nested `<ChapterAtom>` elements.  Element names match the Matroska ones, except that the chapter display and process children are spelled out (`<ChapterString>`, `<ChapterLanguage>`, `<ChapterCountry>`, and `<ChapterProcess...>`), and "ChapterTrackUID" is `<ChapterTrackNumber>` as in mkvtoolnix's DTD.  Chapter times are written as `HH:MM:SS.nnnnnnnnn`, and are stored in "ChapterTimeStart" and "ChapterTimeEnd" in nanoseconds, independent of the "TimestampScale".
+//!
+//! The OGM format is a list of numbered chapters, each with a start time line and a name line:
+//!
+//! ```text
+//! CHAPTER01=00:00:00.000
+//! CHAPTER01NAME=Intro
+//! CHAPTER02=00:01:30.500
+//! CHAPTER02NAME=Opening
+//! ```
+//!
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Read;
+
+use crate::errors::FormatError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use crate::WebmIterator;
+use super::xml::{parse_document, write_document, XmlElement};
+
+const DTD: &str = "matroskachapters.dtd";
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+///
+/// A set of Matroska "EditionEntry" elements that can be read from and written to mkvtoolnix XML.
+///
+/// Chapters without a `<ChapterUID>` are given the lowest UIDs not used elsewhere in the file, since "ChapterUID" is mandatory.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::formats::chapters::XmlChapters;
+/// use webm_iterable::matroska_spec::{Master, MatroskaSpec};
+///
+/// let chapters = XmlChapters::try_from("<Chapters><EditionEntry><ChapterAtom><ChapterTimeStart>00:01:30.5</ChapterTimeStart><ChapterDisplay><ChapterString>Opening</ChapterString></ChapterDisplay></ChapterAtom></EditionEntry></Chapters>").unwrap();
+/// assert_eq!(vec![MatroskaSpec::EditionEntry(Master::Full(vec![
+///     MatroskaSpec::ChapterAtom(Master::Full(vec![
+///         MatroskaSpec::ChapterTimeStart(90_500_000_000),
+///         MatroskaSpec::ChapterDisplay(Master::Full(vec![MatroskaSpec::ChapString(String::from("Opening"))])),
+///         MatroskaSpec::ChapterUID(1),
+///     ])),
+/// ]))], chapters.editions);
+/// assert!(chapters.to_string().contains("<ChapterTimeStart>00:01:30.500000000</ChapterTimeStart>"));
+/// ```
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XmlChapters {
+    /// "EditionEntry" elements, each holding "ChapterAtom" children.  Other elements are left out when writing XML.
+    pub editions: Vec<MatroskaSpec>,
+}
+
+impl XmlChapters {
+    ///
+    /// Takes the "EditionEntry" children of a "Chapters" element.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `chapters` isn't a full "Chapters" element.
+    ///
+    pub fn from_element(chapters: &MatroskaSpec) -> Result<Self, FormatError> {
+        match chapters {
+            MatroskaSpec::Chapters(Master::Full(children)) => Ok(XmlChapters {
+                editions: children.iter().filter(|c| matches!(c, MatroskaSpec::EditionEntry(_))).cloned().collect(),
+            }),
+            _ => Err(FormatError::InvalidData(String::from("Expected a full Chapters element"))),
+        }
+    }
+
+    ///
+    /// Builds a "Chapters" element holding the editions.
+    ///
+    pub fn to_element(&self) -> MatroskaSpec {
+        MatroskaSpec::Chapters(Master::Full(self.editions.clone()))
+    }
+}
+
+impl TryFrom<&str> for XmlChapters {
+    type Error = FormatError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        let root = parse_document(text)?;
+        if root.name != "Chapters" {
+            return Err(root.error("expected <Chapters> as the root element"));
+        }
+
+        let mut editions = Vec::new();
+        for edition in &root.children {
+            if edition.name != "EditionEntry" {
+                return Err(root.unexpected_child(edition));
+            }
+            let children = edition.children.iter().map(|child| Ok(match child.name.as_str() {
+                "EditionUID" => MatroskaSpec::EditionUID(child.to_u64()?),
+                "EditionFlagHidden" => MatroskaSpec::EditionFlagHidden(child.to_u64()?),
+                "EditionFlagDefault" => MatroskaSpec::EditionFlagDefault(child.to_u64()?),
+                "EditionFlagOrdered" => MatroskaSpec::EditionFlagOrdered(child.to_u64()?),
+                "ChapterAtom" => parse_atom(child)?,
+                _ => return Err(edition.unexpected_child(child)),
+            })).collect::<Result<Vec<_>, FormatError>>()?;
+            editions.push(MatroskaSpec::EditionEntry(Master::Full(children)));
+        }
+
+        let mut used = Vec::new();
+        for_each_atom(&editions, &mut |atom| used.extend(atom.iter().filter_map(|c| match c {
+            MatroskaSpec::ChapterUID(val) => Some(*val),
+            _ => None,
+        })));
+        let mut next_uid = 1;
+        for edition in &mut editions {
+            if let MatroskaSpec::EditionEntry(Master::Full(children)) = edition {
+                assign_uids(children, &used, &mut next_uid);
+            }
+        }
+        Ok(XmlChapters { editions })
+    }
+}
+
+impl fmt::Display for XmlChapters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let editions = self.editions.iter().filter_map(|edition| match edition {
+            MatroskaSpec::EditionEntry(Master::Full(children)) => Some(XmlElement::new("EditionEntry", children.iter().filter_map(|child| match child {
+                MatroskaSpec::EditionUID(val) => Some(XmlElement::with_text("EditionUID", val.to_string())),
+                MatroskaSpec::EditionFlagHidden(val) => Some(XmlElement::with_text("EditionFlagHidden", val.to_string())),
+                MatroskaSpec::EditionFlagDefault(val) => Some(XmlElement::with_text("EditionFlagDefault", val.to_string())),
+                MatroskaSpec::EditionFlagOrdered(val) => Some(XmlElement::with_text("EditionFlagOrdered", val.to_string())),
+                MatroskaSpec::ChapterAtom(Master::Full(atom)) => Some(write_atom(atom)),
+                _ => None,
+            }).collect())),
+            _ => None,
+        }).collect();
+        write_document(f, DTD, &XmlElement::new("Chapters", editions))
+    }
+}
+
+///
+/// Reads the "Chapters" element from Matroska data, if there is one.
+///
+/// # Errors
+///
+/// This function returns an error if there is a problem reading the source.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::chapters;
+///
+/// let chapters = chapters::read_chapters(File::open("remux.mkv").unwrap()).unwrap();
+/// std::fs::write("chapters.xml", chapters.to_string()).unwrap();
+/// ```
+///
+pub fn read_chapters<R: Read>(source: R) -> Result<XmlChapters, FormatError> {
+    let mut editions = Vec::new();
+    for tag in WebmIterator::new(source, &[MatroskaSpec::Chapters(Master::Start)]) {
+        match tag? {
+            chapters @ MatroskaSpec::Chapters(Master::Full(_)) => editions.extend(XmlChapters::from_element(&chapters)?.editions),
+            // Chapters always come before the clusters
+            MatroskaSpec::Cluster(_) => break,
+            _ => {},
+        }
+    }
+    Ok(XmlChapters { editions })
+}
+
+///
+/// A single OGM chapter.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OgmChapter {
+    /// The chapter start time, in nanoseconds.
+    pub start: u64,
+
+    pub name: String,
+}
+
+///
+/// The chapters of an OGM chapter file.
+///
+/// Chapters are renumbered from 1 in order when writing.  When converting from Matroska chapters, the default edition (or the first, if none is marked default) is used, and nested chapters are listed after their parent.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::formats::chapters::{OgmChapters, XmlChapters};
+///
+/// let ogm = OgmChapters::try_from("CHAPTER01=00:00:00.000\nCHAPTER01NAME=Intro\nCHAPTER02=00:01:30.500\nCHAPTER02NAME=Opening\n").unwrap();
+/// assert_eq!(90_500_000_000, ogm.chapters[1].start);
+///
+/// let chapters = ogm.to_chapters(Some("eng"));
+/// assert!(chapters.to_string().contains("<ChapterLanguage>eng</ChapterLanguage>"));
+/// assert_eq!(ogm, OgmChapters::from(&chapters));
+/// ```
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OgmChapters {
+    pub chapters: Vec<OgmChapter>,
+}
+
+impl OgmChapters {
+    ///
+    /// Builds a single edition holding the chapters, numbered from 1, with their names in `language` if given.  Chapter ends aren't set, since OGM chapters don't have them.
+    ///
+    pub fn to_chapters(&self, language: Option<&str>) -> XmlChapters {
+        let atoms = self.chapters.iter().enumerate().map(|(index, chapter)| {
+            let mut display = vec![MatroskaSpec::ChapString(chapter.name.clone())];
+            if let Some(language) = language {
+                display.push(MatroskaSpec::ChapLanguage(String::from(language)));
+            }
+            MatroskaSpec::ChapterAtom(Master::Full(vec![
+                MatroskaSpec::ChapterUID(index as u64 + 1),
+                MatroskaSpec::ChapterTimeStart(chapter.start),
+                MatroskaSpec::ChapterDisplay(Master::Full(display)),
+            ]))
+        }).collect();
+        XmlChapters { editions: vec![MatroskaSpec::EditionEntry(Master::Full(atoms))] }
+    }
+}
+
+impl From<&XmlChapters> for OgmChapters {
+    fn from(chapters: &XmlChapters) -> Self {
+        let editions: Vec<&[MatroskaSpec]> = chapters.editions.iter().filter_map(|edition| match edition {
+            MatroskaSpec::EditionEntry(Master::Full(children)) => Some(children.as_slice()),
+            _ => None,
+        }).collect();
+        let edition = editions.iter()
+            .find(|children| children.iter().any(|c| matches!(c, MatroskaSpec::EditionFlagDefault(1))))
+            .or_else(|| editions.first());
+
+        let mut ogm = OgmChapters::default();
+        if let Some(edition) = edition {
+            for_each_atom_in(edition, &mut |atom| ogm.chapters.push(OgmChapter {
+                start: atom.iter().find_map(|c| match c {
+                    MatroskaSpec::ChapterTimeStart(val) => Some(*val),
+                    _ => None,
+                }).unwrap_or(0),
+                name: atom.iter().find_map(|c| match c {
+                    MatroskaSpec::ChapterDisplay(Master::Full(display)) => display.iter().find_map(|d| match d {
+                        MatroskaSpec::ChapString(val) => Some(val.clone()),
+                        _ => None,
+                    }),
+                    _ => None,
+                }).unwrap_or_default(),
+            }));
+        }
+        ogm
+    }
+}
+
+impl TryFrom<&str> for OgmChapters {
+    type Error = FormatError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text).replace("\r\n", "\n").replace('\r', "\n");
+        let error = |line_number: usize, message: &str| FormatError::InvalidData(format!("Invalid OGM chapters on line {line_number}: {message}"));
+
+        // Chapters are matched up by number, since names don't always directly follow their times
+        let mut chapters: Vec<(String, Option<u64>, Option<String>)> = Vec::new();
+        for (index, line) in text.split('\n').enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=')
+                .filter(|(key, _)| key.len() > 7 && key.get(..7).is_some_and(|prefix| prefix.eq_ignore_ascii_case("CHAPTER")))
+                .ok_or_else(|| error(index + 1, &format!("expected CHAPTERnn= or CHAPTERnnNAME=, found {line:?}")))?;
+            let key = &key[7..];
+            let (number, is_name) = match key.len().checked_sub(4).filter(|at| key.get(*at..).is_some_and(|suffix| suffix.eq_ignore_ascii_case("NAME"))) {
+                Some(at) => (&key[..at], true),
+                None => (key, false),
+            };
+            if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(error(index + 1, &format!("invalid chapter number {number:?}")));
+            }
+
+            let position = match chapters.iter().position(|(existing, _, _)| existing == number) {
+                Some(position) => position,
+                None => {
+                    chapters.push((String::from(number), None, None));
+                    chapters.len() - 1
+                },
+            };
+            if is_name {
+                chapters[position].2 = Some(String::from(value));
+            } else {
+                let start = parse_time(value).ok_or_else(|| error(index + 1, &format!("invalid chapter time {value:?}")))?;
+                chapters[position].1 = Some(start);
+            }
+        }
+
+        let chapters = chapters.into_iter().map(|(number, start, name)| match start {
+            Some(start) => Ok(OgmChapter { start, name: name.unwrap_or_default() }),
+            None => Err(FormatError::InvalidData(format!("Invalid OGM chapters: CHAPTER{number} has a name but no time"))),
+        }).collect::<Result<Vec<_>, FormatError>>()?;
+        Ok(OgmChapters { chapters })
+    }
+}
+
+impl fmt::Display for OgmChapters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, chapter) in self.chapters.iter().enumerate() {
+            let number = index + 1;
+            let milliseconds = chapter.start / 1_000_000;
+            writeln!(f, "CHAPTER{number:02}={:02}:{:02}:{:02}.{:03}", milliseconds / 3_600_000, milliseconds / 60_000 % 60, milliseconds / 1000 % 60, milliseconds % 1000)?;
+            writeln!(f, "CHAPTER{number:02}NAME={}", chapter.name)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Parses a chapter time (`HH:MM:SS`, with up to nine fractional second digits) into nanoseconds.
+///
+fn parse_time(text: &str) -> Option<u64> {
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let (seconds, fraction) = parts[2].split_once('.').unwrap_or((parts[2], ""));
+    let all_digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    if !all_digits(parts[0]) || !all_digits(parts[1]) || !all_digits(seconds) || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes, seconds) = (parts[0].parse::<u64>().ok()?, parts[1].parse::<u64>().ok()?, seconds.parse::<u64>().ok()?);
+    if minutes > 59 || seconds > 59 {
+        return None;
+    }
+    let nanoseconds = format!("{fraction:0<9}").parse::<u64>().ok()?;
+    hours.checked_mul(3600)?.checked_add(minutes * 60 + seconds)?.checked_mul(NANOSECONDS_PER_SECOND)?.checked_add(nanoseconds)
+}
+
+fn format_time(nanoseconds: u64) -> String {
+    let seconds = nanoseconds / NANOSECONDS_PER_SECOND;
+    format!("{:02}:{:02}:{:02}.{:09}", seconds / 3600, seconds / 60 % 60, seconds % 60, nanoseconds % NANOSECONDS_PER_SECOND)
+}
+
+fn parse_atom(element: &XmlElement) -> Result<MatroskaSpec, FormatError> {
+    let time = |child: &XmlElement| parse_time(&child.text).ok_or_else(|| child.error(&format!("expected a time as HH:MM:SS.nnnnnnnnn, found {:?}", child.text.trim())));
+    let children = element.children.iter().map(|child| Ok(match child.name.as_str() {
+        "ChapterUID" => MatroskaSpec::ChapterUID(child.to_u64()?),
+        "ChapterStringUID" => MatroskaSpec::ChapterStringUID(child.text.clone()),
+        "ChapterTimeStart" => MatroskaSpec::ChapterTimeStart(time(child)?),
+        "ChapterTimeEnd" => MatroskaSpec::ChapterTimeEnd(time(child)?),
+        "ChapterFlagHidden" => MatroskaSpec::ChapterFlagHidden(child.to_u64()?),
+        "ChapterFlagEnabled" => MatroskaSpec::ChapterFlagEnabled(child.to_u64()?),
+        "ChapterSegmentUID" => MatroskaSpec::ChapterSegmentUID(child.to_binary()?),
+        "ChapterSegmentEditionUID" => MatroskaSpec::ChapterSegmentEditionUID(child.to_u64()?),
+        "ChapterPhysicalEquiv" => MatroskaSpec::ChapterPhysicalEquiv(child.to_u64()?),
+        "ChapterTrack" => MatroskaSpec::ChapterTrack(Master::Full(child.children.iter().map(|track| match track.name.as_str() {
+            "ChapterTrackNumber" | "ChapterTrackUID" => Ok(MatroskaSpec::ChapterTrackUID(track.to_u64()?)),
+            _ => Err(child.unexpected_child(track)),
+        }).collect::<Result<_, FormatError>>()?)),
+        "ChapterDisplay" => MatroskaSpec::ChapterDisplay(Master::Full(child.children.iter().map(|display| Ok(match display.name.as_str() {
+            "ChapterString" => MatroskaSpec::ChapString(display.text.clone()),
+            "ChapterLanguage" => MatroskaSpec::ChapLanguage(display.text.clone()),
+            "ChapLanguageIETF" => MatroskaSpec::ChapLanguageIETF(display.text.clone()),
+            "ChapterCountry" => MatroskaSpec::ChapCountry(display.text.clone()),
+            _ => return Err(child.unexpected_child(display)),
+        })).collect::<Result<_, FormatError>>()?)),
+        "ChapterProcess" => MatroskaSpec::ChapProcess(Master::Full(child.children.iter().map(|process| Ok(match process.name.as_str() {
+            "ChapterProcessCodecID" => MatroskaSpec::ChapProcessCodecID(process.to_u64()?),
+            "ChapterProcessPrivate" => MatroskaSpec::ChapProcessPrivate(process.to_binary()?),
+            "ChapterProcessCommand" => MatroskaSpec::ChapProcessCommand(Master::Full(process.children.iter().map(|command| Ok(match command.name.as_str() {
+                "ChapterProcessTime" => MatroskaSpec::ChapProcessTime(command.to_u64()?),
+                "ChapterProcessData" => MatroskaSpec::ChapProcessData(command.to_binary()?),
+                _ => return Err(process.unexpected_child(command)),
+            })).collect::<Result<_, FormatError>>()?)),
+            _ => return Err(child.unexpected_child(process)),
+        })).collect::<Result<_, FormatError>>()?)),
+        "ChapterAtom" => parse_atom(child)?,
+        _ => return Err(element.unexpected_child(child)),
+    })).collect::<Result<Vec<_>, FormatError>>()?;
+
+    if !children.iter().any(|c| matches!(c, MatroskaSpec::ChapterTimeStart(_))) {
+        return Err(element.error("missing <ChapterTimeStart>"));
+    }
+    Ok(MatroskaSpec::ChapterAtom(Master::Full(children)))
+}
+
+fn write_atom(atom: &[MatroskaSpec]) -> XmlElement {
+    XmlElement::new("ChapterAtom", atom.iter().filter_map(|child| match child {
+        MatroskaSpec::ChapterUID(val) => Some(XmlElement::with_text("ChapterUID", val.to_string())),
+        MatroskaSpec::ChapterStringUID(val) => Some(XmlElement::with_text("ChapterStringUID", val.as_str())),
+        MatroskaSpec::ChapterTimeStart(val) => Some(XmlElement::with_text("ChapterTimeStart", format_time(*val))),
+        MatroskaSpec::ChapterTimeEnd(val) => Some(XmlElement::with_text("ChapterTimeEnd", format_time(*val))),
+        MatroskaSpec::ChapterFlagHidden(val) => Some(XmlElement::with_text("ChapterFlagHidden", val.to_string())),
+        MatroskaSpec::ChapterFlagEnabled(val) => Some(XmlElement::with_text("ChapterFlagEnabled", val.to_string())),
+        MatroskaSpec::ChapterSegmentUID(val) => Some(XmlElement::with_binary("ChapterSegmentUID", val)),
+        MatroskaSpec::ChapterSegmentEditionUID(val) => Some(XmlElement::with_text("ChapterSegmentEditionUID", val.to_string())),
+        MatroskaSpec::ChapterPhysicalEquiv(val) => Some(XmlElement::with_text("ChapterPhysicalEquiv", val.to_string())),
+        MatroskaSpec::ChapterTrack(Master::Full(tracks)) => Some(XmlElement::new("ChapterTrack", tracks.iter().filter_map(|track| match track {
+            MatroskaSpec::ChapterTrackUID(val) => Some(XmlElement::with_text("ChapterTrackNumber", val.to_string())),
+            _ => None,
+        }).collect())),
+        MatroskaSpec::ChapterDisplay(Master::Full(display)) => Some(XmlElement::new("ChapterDisplay", display.iter().filter_map(|d| match d {
+            MatroskaSpec::ChapString(val) => Some(XmlElement::with_text("ChapterString", val.as_str())),
+            MatroskaSpec::ChapLanguage(val) => Some(XmlElement::with_text("ChapterLanguage", val.as_str())),
+            MatroskaSpec::ChapLanguageIETF(val) => Some(XmlElement::with_text("ChapLanguageIETF", val.as_str())),
+            MatroskaSpec::ChapCountry(val) => Some(XmlElement::with_text("ChapterCountry", val.as_str())),
+            _ => None,
+        }).collect())),
+        MatroskaSpec::ChapProcess(Master::Full(process)) => Some(XmlElement::new("ChapterProcess", process.iter().filter_map(|p| match p {
+            MatroskaSpec::ChapProcessCodecID(val) => Some(XmlElement::with_text("ChapterProcessCodecID", val.to_string())),
+            MatroskaSpec::ChapProcessPrivate(val) => Some(XmlElement::with_binary("ChapterProcessPrivate", val)),
+            MatroskaSpec::ChapProcessCommand(Master::Full(command)) => Some(XmlElement::new("ChapterProcessCommand", command.iter().filter_map(|c| match c {
+                MatroskaSpec::ChapProcessTime(val) => Some(XmlElement::with_text("ChapterProcessTime", val.to_string())),
+                MatroskaSpec::ChapProcessData(val) => Some(XmlElement::with_binary("ChapterProcessData", val)),
+                _ => None,
+            }).collect())),
+            _ => None,
+        }).collect())),
+        MatroskaSpec::ChapterAtom(Master::Full(nested)) => Some(write_atom(nested)),
+        _ => None,
+    }).collect())
+}
+
+///
+/// Calls `f` with the children of every "ChapterAtom" in the editions, parents before their nested chapters.
+///
+fn for_each_atom(editions: &[MatroskaSpec], f: &mut impl FnMut(&[MatroskaSpec])) {
+    for edition in editions {
+        if let MatroskaSpec::EditionEntry(Master::Full(children)) = edition {
+            for_each_atom_in(children, f);
+        }
+    }
+}
+
+fn for_each_atom_in(children: &[MatroskaSpec], f: &mut impl FnMut(&[MatroskaSpec])) {
+    for child in children {
+        if let MatroskaSpec::ChapterAtom(Master::Full(atom)) = child {
+            f(atom);
+            for_each_atom_in(atom, f);
+        }
+    }
+}
+
+fn assign_uids(children: &mut [MatroskaSpec], used: &[u64], next_uid: &mut u64) {
+    for child in children {
+        if let MatroskaSpec::ChapterAtom(Master::Full(atom)) = child {
+            if !atom.iter().any(|c| matches!(c, MatroskaSpec::ChapterUID(_))) {
+                while used.contains(next_uid) {
+                    *next_uid += 1;
+                }
+                atom.push(MatroskaSpec::ChapterUID(*next_uid));
+                *next_uid += 1;
+            }
+            assign_uids(atom, used, next_uid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{parse_time, OgmChapters, XmlChapters};
+    use crate::matroska_spec::{Master, MatroskaSpec};
+
+    #[test]
+    fn round_trips_xml_chapters() {
+        let chapters = XmlChapters {
+            editions: vec![MatroskaSpec::EditionEntry(Master::Full(vec![
+                MatroskaSpec::EditionUID(77),
+                MatroskaSpec::EditionFlagDefault(1),
+                MatroskaSpec::ChapterAtom(Master::Full(vec![
+                    MatroskaSpec::ChapterUID(5),
+                    MatroskaSpec::ChapterTimeStart(3_723_000_000_001),
+                    MatroskaSpec::ChapterTimeEnd(3_800_000_000_000),
+                    MatroskaSpec::ChapterFlagHidden(0),
+                    MatroskaSpec::ChapterSegmentUID(vec![0xab; 16]),
+                    MatroskaSpec::ChapterTrack(Master::Full(vec![MatroskaSpec::ChapterTrackUID(1234)])),
+                    MatroskaSpec::ChapterDisplay(Master::Full(vec![
+                        MatroskaSpec::ChapString(String::from("Part 1")),
+                        MatroskaSpec::ChapLanguage(String::from("eng")),
+                    ])),
+                    MatroskaSpec::ChapterAtom(Master::Full(vec![
+                        MatroskaSpec::ChapterUID(6),
+                        MatroskaSpec::ChapterTimeStart(3_723_500_000_000),
+                    ])),
+                ])),
+            ]))],
+        };
+
+        let xml = chapters.to_string();
+        assert!(xml.contains("<ChapterTimeStart>01:02:03.000000001</ChapterTimeStart>"));
+        assert!(xml.contains("<ChapterString>Part 1</ChapterString>"));
+        assert!(xml.contains("<ChapterTrackNumber>1234</ChapterTrackNumber>"));
+        assert!(!xml.contains("ChapterTrackUID"));
+        assert_eq!(chapters, XmlChapters::try_from(xml.as_str()).unwrap());
+        assert_eq!(chapters, XmlChapters::from_element(&chapters.to_element()).unwrap());
+
+        let missing_uids = XmlChapters::try_from("<Chapters><EditionEntry><ChapterAtom><ChapterTimeStart>00:00:00</ChapterTimeStart></ChapterAtom><ChapterAtom><ChapterUID>1</ChapterUID><ChapterTimeStart>00:00:10</ChapterTimeStart></ChapterAtom></EditionEntry></Chapters>").unwrap();
+        let ogm = OgmChapters::from(&missing_uids);
+        assert_eq!(vec![0, 10_000_000_000], ogm.chapters.iter().map(|c| c.start).collect::<Vec<_>>());
+        assert!(missing_uids.to_string().contains("<ChapterUID>2</ChapterUID>"));
+
+        assert!(XmlChapters::try_from("<Chapters><EditionEntry><ChapterAtom><ChapterUID>1</ChapterUID></ChapterAtom></EditionEntry></Chapters>").is_err());
+        assert!(XmlChapters::try_from("<Chapters><EditionEntry><ChapterAtom><ChapterTimeStart>1:2</ChapterTimeStart></ChapterAtom></EditionEntry></Chapters>").is_err());
+    }
+
+    #[test]
+    fn round_trips_ogm_chapters() {
+        let text = "CHAPTER01=00:00:00.000\nCHAPTER01NAME=Intro\nCHAPTER02=01:00:05.250\nCHAPTER02NAME=Scene = 2\n";
+        let ogm = OgmChapters::try_from(text).unwrap();
+        assert_eq!(3_605_250_000_000, ogm.chapters[1].start);
+        assert_eq!("Scene = 2", ogm.chapters[1].name);
+        assert_eq!(text, ogm.to_string());
+
+        assert_eq!(Some(1_500_000), parse_time("00:00:00.0015"));
+        assert_eq!(None, parse_time("00:60:00.000"));
+        assert!(OgmChapters::try_from("CHAPTER01=00:00:00.000\nCHAPTER02NAME=Orphan\n").is_err());
+        assert!(OgmChapters::try_from("TITLE=Something\n").is_err());
+        assert!(OgmChapters::try_from("CHAPTE\u{e9}1=00:00:00.000\n").is_err());
+        assert!(OgmChapters::try_from("CHAPTERa\u{20ac}cd=x\n").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//!
+//! Extracts a track to the raw or simple container format its tools expect, chosen from the track's "CodecID" in the same way as mkvextract.
+//!
+//! Video tracks are written as elementary streams (Annex B for AVC and HEVC) or IVF, audio tracks as ADTS, Ogg, FLAC, WAV, or raw frames, and subtitle tracks in their text or `.sup` formats.  Output is streamed to any [`std::io::Write`], so nothing is seeked back to and headers that hold totals (IVF frame count, WAV data size) are written with unknown sizes - use the format modules directly when the destination can seek.  Tracks with an unknown "CodecID" are written as the frame data back to back, optionally with each frame preceded by its length.
+//!
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use crate::codecs::{aac, avc, flac, hevc};
+use crate::errors::FormatError;
+use crate::matroska_spec::MatroskaSpec;
+use super::{ass, audio_children, ivf, ogg, pgs, srt, wav, webvtt, TrackReader};
+
+///
+/// The output format used for a track.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// An AVC or HEVC elementary stream with start codes, with the parameter sets from "CodecPrivate" repeated before each keyframe.
+    AnnexB,
+
+    /// AAC frames, each with an ADTS header.
+    Adts,
+
+    /// Frames that form an elementary stream on their own (MPEG audio, AC-3, DTS, and so on).  For MPEG video, "CodecPrivate" is written first.
+    Raw,
+
+    /// An IVF file, for VP8, VP9, and AV1.
+    Ivf,
+
+    /// An Ogg file, for Opus and Vorbis.
+    Ogg,
+
+    /// A native FLAC file.
+    Flac,
+
+    /// A WAV file, for PCM.
+    Wav,
+
+    /// A WebVTT file.
+    WebVtt,
+
+    /// An SRT file.
+    Srt,
+
+    /// An ASS or SSA script.
+    Ass,
+
+    /// A PGS `.sup` file.
+    Pgs,
+
+    /// A VobSub `.idx` and `.sub` pair, which needs two destinations and so can only be written with [`super::vobsub::extract()`].
+    VobSub,
+
+    /// The fallback for unknown codecs: frame data back to back, with an optional length prefix.
+    Frames,
+}
+
+impl StreamFormat {
+    ///
+    /// Picks the output format for a "CodecID".
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use webm_iterable::formats::elementary::StreamFormat;
+    ///
+    /// assert_eq!(StreamFormat::AnnexB, StreamFormat::from_codec_id("V_MPEG4/ISO/AVC"));
+    /// assert_eq!(StreamFormat::Adts, StreamFormat::from_codec_id("A_AAC/MPEG4/LC/SBR"));
+    /// assert_eq!(StreamFormat::Frames, StreamFormat::from_codec_id("V_MS/VFW/FOURCC"));
+    /// ```
+    ///
+    pub fn from_codec_id(codec_id: &str) -> Self {
+        match codec_id {
+            "V_MPEG4/ISO/AVC" | "V_MPEGH/ISO/HEVC" => StreamFormat::AnnexB,
+            "V_MPEG1" | "V_MPEG2" | "V_MPEG4/ISO/SP" | "V_MPEG4/ISO/ASP" | "V_MPEG4/ISO/AP" => StreamFormat::Raw,
+            "V_VP8" | "V_VP9" | "V_AV1" => StreamFormat::Ivf,
+            "A_AAC" => StreamFormat::Adts,
+            _ if codec_id.starts_with("A_AAC/") => StreamFormat::Adts,
+            "A_MPEG/L1" | "A_MPEG/L2" | "A_MPEG/L3" | "A_AC3" | "A_AC3/BSID9" | "A_AC3/BSID10" | "A_EAC3" | "A_DTS" | "A_DTS/EXPRESS" | "A_DTS/LOSSLESS" | "A_TRUEHD" | "A_MLP" => StreamFormat::Raw,
+            "A_OPUS" | "A_VORBIS" => StreamFormat::Ogg,
+            "A_FLAC" => StreamFormat::Flac,
+            "A_PCM/INT/LIT" | "A_PCM/INT/BIG" | "A_PCM/FLOAT/IEEE" => StreamFormat::Wav,
+            "D_WEBVTT/SUBTITLES" | "D_WEBVTT/CAPTIONS" | "D_WEBVTT/DESCRIPTIONS" | "D_WEBVTT/METADATA" => StreamFormat::WebVtt,
+            "S_TEXT/UTF8" => StreamFormat::Srt,
+            "S_TEXT/ASS" | "S_TEXT/SSA" => StreamFormat::Ass,
+            "S_HDMV/PGS" => StreamFormat::Pgs,
+            "S_VOBSUB" => StreamFormat::VobSub,
+            _ => StreamFormat::Frames,
+        }
+    }
+}
+
+///
+/// Options for [`extract_with_options()`].
+///
+#[derive(Clone, Debug, Default)]
+pub struct ExtractOptions {
+    /// For [`StreamFormat::Frames`] output, the size in bytes (1 to 8) of a big-endian length written before each frame.  `None` writes the frames with nothing between them.
+    pub length_prefix: Option<u8>,
+}
+
+///
+/// Extracts a track with the default [`ExtractOptions`].  See [`extract_with_options()`].
+///
+/// # Errors
+///
+/// This function returns an error in the same cases as [`extract_with_options()`].
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::elementary;
+///
+/// let source = File::open("remux.mkv").unwrap();
+/// elementary::extract(source, 1, File::create("video.h264").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, W: Write>(source: R, track: u64, dest: W) -> Result<W, FormatError> {
+    extract_with_options(source, track, dest, &ExtractOptions::default())
+}
+
+///
+/// Extracts a track from WebM data, writing it in the format [`StreamFormat::from_codec_id()`] picks for its "CodecID".
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found, if it's a VobSub track, if "CodecPrivate" or a frame isn't valid for the output format, if a frame is too long for the length prefix, or if there is a problem reading the source or writing the destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::elementary::{self, ExtractOptions};
+///
+/// let source = File::open("remux.mkv").unwrap();
+/// let options = ExtractOptions { length_prefix: Some(4) };
+/// elementary::extract_with_options(source, 3, File::create("track3.bin").unwrap(), &options).unwrap();
+/// ```
+///
+pub fn extract_with_options<R: Read, W: Write>(source: R, track: u64, dest: W, options: &ExtractOptions) -> Result<W, FormatError> {
+    let reader = TrackReader::new(source, track)?;
+    match StreamFormat::from_codec_id(reader.codec_id()) {
+        StreamFormat::AnnexB => write_annex_b(reader, dest),
+        StreamFormat::Adts => write_adts(reader, dest),
+        StreamFormat::Raw => write_raw(reader, dest),
+        StreamFormat::Ivf => Ok(ivf::write_frames(reader, dest)?.into_inner()?),
+        StreamFormat::Ogg => ogg::write_track(reader, dest),
+        StreamFormat::Flac => write_flac(reader, dest),
+        StreamFormat::Wav => Ok(wav::write_frames(reader, dest)?.into_inner()?),
+        StreamFormat::WebVtt => webvtt::write_track(reader, dest),
+        StreamFormat::Srt => srt::write_track(reader, dest),
+        StreamFormat::Ass => ass::write_track(reader, dest),
+        StreamFormat::Pgs => pgs::write_track(reader, dest),
+        StreamFormat::VobSub => Err(FormatError::InvalidData(String::from("S_VOBSUB tracks need separate .idx and .sub destinations; use formats::vobsub::extract"))),
+        StreamFormat::Frames => write_frames(reader, dest, options.length_prefix),
+    }
+}
+
+fn codec_private<R: Read>(reader: &TrackReader<R>) -> Option<&[u8]> {
+    reader.track_entry().iter().find_map(|c| match c {
+        MatroskaSpec::CodecPrivate(data) => Some(data.as_slice()),
+        _ => None,
+    })
+}
+
+fn required_codec_private<R: Read>(reader: &TrackReader<R>) -> Result<Vec<u8>, FormatError> {
+    codec_private(reader)
+        .map(|data| data.to_vec())
+        .ok_or_else(|| FormatError::InvalidData(format!("{} track {} has no CodecPrivate", reader.codec_id(), reader.track())))
+}
+
+fn write_annex_b<R: Read, W: Write>(reader: TrackReader<R>, mut dest: W) -> Result<W, FormatError> {
+    let codec_private = required_codec_private(&reader)?;
+    if reader.codec_id() == "V_MPEGH/ISO/HEVC" {
+        let config = hevc::HevcConfiguration::try_from(codec_private.as_slice())?;
+        for frame in reader {
+            let frame = frame?;
+            dest.write_all(&hevc::to_annex_b(&frame.data, &config, frame.keyframe)?)?;
+        }
+    } else {
+        let config = avc::AvcConfiguration::try_from(codec_private.as_slice())?;
+        for frame in reader {
+            let frame = frame?;
+            dest.write_all(&avc::to_annex_b(&frame.data, &config, frame.keyframe)?)?;
+        }
+    }
+    Ok(dest)
+}
+
+fn write_adts<R: Read, W: Write>(reader: TrackReader<R>, dest: W) -> Result<W, FormatError> {
+    let config = match codec_private(&reader) {
+        Some(data) => aac::AudioSpecificConfig::try_from(data)?,
+        None => legacy_aac_config(&reader)?,
+    };
+    let mut writer = aac::AdtsWriter::new(dest, &config)?;
+    for frame in reader {
+        writer.write_frame(&frame?.data)?;
+    }
+    Ok(writer.into_inner())
+}
+
+///
+/// Builds the config for tracks using the legacy "A_AAC/MPEG2/..." and "A_AAC/MPEG4/..." IDs, which give the object type in the ID and store no "CodecPrivate".  "SamplingFrequency" is the rate of the AAC core, even when SBR is used.
+///
+fn legacy_aac_config<R: Read>(reader: &TrackReader<R>) -> Result<aac::AudioSpecificConfig, FormatError> {
+    let codec_id = reader.codec_id();
+    let profile = codec_id.split('/').nth(2).unwrap_or("");
+    let object_type = match profile {
+        "MAIN" => 1,
+        "LC" => 2,
+        "SSR" => 3,
+        "LTP" => 4,
+        _ => return Err(FormatError::InvalidData(format!("Unknown AAC CodecID {codec_id}"))),
+    };
+
+    let audio = audio_children(reader.track_entry());
+    let sampling_frequency = audio.iter().find_map(|c| match c {
+        MatroskaSpec::SamplingFrequency(val) => Some(*val as u32),
+        _ => None,
+    }).unwrap_or(8000);
+    let channels = audio.iter().find_map(|c| match c {
+        MatroskaSpec::Channels(val) => Some(*val),
+        _ => None,
+    }).unwrap_or(1);
+    let sbr = codec_id.ends_with("/SBR");
+
+    Ok(aac::AudioSpecificConfig {
+        object_type,
+        sampling_frequency,
+        channel_configuration: if channels == 8 { 7 } else { channels.min(7) as u8 },
+        frame_length: 1024,
+        sbr,
+        ps: false,
+        extension_sampling_frequency: if sbr { Some(sampling_frequency * 2) } else { None },
+    })
+}
+
+fn write_raw<R: Read, W: Write>(reader: TrackReader<R>, mut dest: W) -> Result<W, FormatError> {
+    if reader.codec_id().starts_with("V_") {
+        if let Some(data) = codec_private(&reader) {
+            dest.write_all(data)?;
+        }
+    }
+    for frame in reader {
+        dest.write_all(&frame?.data)?;
+    }
+    Ok(dest)
+}
+
+fn write_flac<R: Read, W: Write>(reader: TrackReader<R>, dest: W) -> Result<W, FormatError> {
+    let metadata = flac::Metadata::try_from(required_codec_private(&reader)?.as_slice())?;
+    let mut writer = flac::FlacWriter::new(dest, &metadata)?;
+    for frame in reader {
+        writer.write_frame(&frame?.data)?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+fn write_frames<R: Read, W: Write>(reader: TrackReader<R>, mut dest: W, length_prefix: Option<u8>) -> Result<W, FormatError> {
+    let prefix_size = match length_prefix {
+        Some(size @ 1..=8) => size as usize,
+        Some(size) => return Err(FormatError::InvalidData(format!("Length prefix of {size} bytes is not supported; use 1 to 8"))),
+        None => 0,
+    };
+    for frame in reader {
+        let frame = frame?;
+        if prefix_size > 0 {
+            let length = frame.data.len() as u64;
+            if prefix_size < 8 && length >> (prefix_size * 8) != 0 {
+                return Err(FormatError::InvalidData(format!("Frame at {} of {length} bytes is too long for a {prefix_size} byte length prefix", frame.timestamp)));
+            }
+            dest.write_all(&length.to_be_bytes()[8 - prefix_size..])?;
+        }
+        dest.write_all(&frame.data)?;
+    }
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::{extract, extract_with_options, ExtractOptions};
+    use crate::formats::mux_track;
+    use crate::matroska_spec::MatroskaSpec;
+
+    #[test]
+    fn writes_container_formats() {
+        let ivf = extract(File::open("media/test.webm").unwrap(), 1, Vec::new()).unwrap();
+        assert_eq!(b"DKIF", &ivf[..4]);
+
+        let ogg = extract(File::open("media/test.webm").unwrap(), 2, Vec::new()).unwrap();
+        assert_eq!(b"OggS", &ogg[..4]);
+    }
+
+    #[test]
+    fn writes_unknown_frames_with_length_prefix() {
+        let mkv = mux_track(vec![
+            MatroskaSpec::TrackType(0x01),
+            MatroskaSpec::CodecID(String::from("V_UNKNOWN")),
+        ], vec![(0, vec![0xaa, 0xbb, 0xcc]), (40, vec![0xdd; 300])]);
+
+        let raw = extract(mkv.as_slice(), 1, Vec::new()).unwrap();
+        assert_eq!(303, raw.len());
+        assert_eq!([0xaa, 0xbb, 0xcc, 0xdd], raw[..4]);
+
+        let prefixed = extract_with_options(mkv.as_slice(), 1, Vec::new(), &ExtractOptions { length_prefix: Some(2) }).unwrap();
+        assert_eq!([0x00, 0x03, 0xaa, 0xbb, 0xcc, 0x01, 0x2c, 0xdd], prefixed[..8]);
+
+        assert!(extract_with_options(mkv.as_slice(), 1, Vec::new(), &ExtractOptions { length_prefix: Some(1) }).is_err());
+        assert!(extract_with_options(mkv.as_slice(), 1, Vec::new(), &ExtractOptions { length_prefix: Some(9) }).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+//!
+//! Reads and writes IVF files, the simple container used by libvpx and libaom tools and by VP8, VP9, and AV1 conformance vectors.
+//!
+//! An IVF file is a 32 byte header (holding the codec FourCC, frame size, and timebase) followed by each frame with a 12 byte header of its size and timestamp.  [`extract()`] writes a "V_VP8", "V_VP9", or "V_AV1" track as IVF, and [`import()`] muxes an IVF file into WebM.
+//!
+//! AV1 frames in IVF are whole temporal units, which start with a temporal delimiter OBU.  The Matroska AV1 mapping doesn't allow temporal delimiters in blocks, so they are added on export and removed on import.
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::codecs::{av1, vp8, vp9};
+use crate::errors::FormatError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use crate::muxer::{MuxFrame, WebmMuxer};
+use super::{video_children, TrackReader};
+
+const SIGNATURE: &[u8; 4] = b"DKIF";
+const HEADER_LENGTH: u16 = 32;
+const FRAME_HEADER_LENGTH: usize = 12;
+const TEMPORAL_DELIMITER: [u8; 2] = [0x12, 0x00];
+
+const CODECS: [(&[u8; 4], &str); 3] = [(b"VP80", "V_VP8"), (b"VP90", "V_VP9"), (b"AV01", "V_AV1")];
+
+///
+/// The header at the start of an IVF file.
+///
+/// Frame timestamps are in units of `timebase_numerator / timebase_denominator` seconds.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IvfHeader {
+    /// The codec FourCC, such as `VP90`.
+    pub fourcc: [u8; 4],
+
+    pub width: u16,
+    pub height: u16,
+    pub timebase_denominator: u32,
+    pub timebase_numerator: u32,
+
+    /// The number of frames in the file.  Many tools ignore this, and it may be 0 in streamed files.
+    pub frame_count: u32,
+}
+
+impl IvfHeader {
+    ///
+    /// Builds a header for a "V_VP8", "V_VP9", or "V_AV1" track from the children of its "TrackEntry", using a timebase matching the segment "TimestampScale".
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the track uses another codec.
+    ///
+    pub fn from_track_entry(children: &[MatroskaSpec], timestamp_scale: u64) -> Result<Self, FormatError> {
+        let codec_id = children.iter().find_map(|c| match c {
+            MatroskaSpec::CodecID(val) => Some(val.as_str()),
+            _ => None,
+        }).unwrap_or("");
+        let fourcc = CODECS.iter().find(|(_, id)| *id == codec_id).map(|(fourcc, _)| **fourcc)
+            .ok_or_else(|| FormatError::InvalidData(format!("{codec_id} tracks cannot be stored in IVF")))?;
+
+        let video = video_children(children);
+        let width = video.iter().find_map(|c| match c {
+            MatroskaSpec::PixelWidth(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(0);
+        let height = video.iter().find_map(|c| match c {
+            MatroskaSpec::PixelHeight(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(0);
+
+        let divisor = gcd(timestamp_scale, 1_000_000_000);
+        Ok(IvfHeader {
+            fourcc,
+            width: u16::try_from(width).unwrap_or(u16::MAX),
+            height: u16::try_from(height).unwrap_or(u16::MAX),
+            timebase_denominator: u32::try_from(1_000_000_000 / divisor).unwrap_or(u32::MAX),
+            timebase_numerator: u32::try_from(timestamp_scale / divisor).unwrap_or(u32::MAX),
+            frame_count: 0,
+        })
+    }
+
+    ///
+    /// The Matroska "CodecID" for the header's FourCC, if it is VP8, VP9, or AV1.
+    ///
+    pub fn codec_id(&self) -> Option<&'static str> {
+        CODECS.iter().find(|(fourcc, _)| **fourcc == self.fourcc).map(|(_, id)| *id)
+    }
+
+    ///
+    /// Writes the 32 byte file header.
+    ///
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut data = [0u8; 32];
+        data[..4].copy_from_slice(SIGNATURE);
+        data[6..8].copy_from_slice(&HEADER_LENGTH.to_le_bytes());
+        data[8..12].copy_from_slice(&self.fourcc);
+        data[12..14].copy_from_slice(&self.width.to_le_bytes());
+        data[14..16].copy_from_slice(&self.height.to_le_bytes());
+        data[16..20].copy_from_slice(&self.timebase_denominator.to_le_bytes());
+        data[20..24].copy_from_slice(&self.timebase_numerator.to_le_bytes());
+        data[24..28].copy_from_slice(&self.frame_count.to_le_bytes());
+        data
+    }
+}
+
+impl TryFrom<&[u8]> for IvfHeader {
+    type Error = FormatError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < HEADER_LENGTH as usize || !data.starts_with(SIGNATURE) {
+            return Err(FormatError::InvalidData(String::from("IVF data doesn't start with a DKIF header")));
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let read_u32 = |offset: usize| u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+        let mut fourcc = [0u8; 4];
+        fourcc.copy_from_slice(&data[8..12]);
+        Ok(IvfHeader {
+            fourcc,
+            width: read_u16(12),
+            height: read_u16(14),
+            timebase_denominator: read_u32(16),
+            timebase_numerator: read_u32(20),
+            frame_count: read_u32(24),
+        })
+    }
+}
+
+///
+/// A frame read from or written to an IVF file.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IvfFrame {
+    /// The presentation timestamp, in units of the header's timebase.
+    pub timestamp: i64,
+
+    pub data: Vec<u8>,
+}
+
+///
+/// Reads the header and frames of an IVF file.
+///
+/// The reader is an [`Iterator`] over the frames in the file.
+///
+pub struct IvfReader<R: Read> {
+    reader: R,
+    header: IvfHeader,
+}
+
+impl<R: Read> IvfReader<R> {
+    ///
+    /// Creates a new reader, reading the file header from the source.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the header can't be read or is invalid.
+    ///
+    pub fn new(mut reader: R) -> Result<Self, FormatError> {
+        let mut data = [0u8; HEADER_LENGTH as usize];
+        reader.read_exact(&mut data)?;
+        let header = IvfHeader::try_from(&data[..])?;
+
+        // Skip any header fields added after the standard 32 bytes
+        let header_length = u16::from_le_bytes([data[6], data[7]]);
+        if header_length > HEADER_LENGTH {
+            io::copy(&mut (&mut reader).take((header_length - HEADER_LENGTH) as u64), &mut io::sink())?;
+        }
+        Ok(IvfReader { reader, header })
+    }
+
+    ///
+    /// The header read from the start of the file.
+    ///
+    pub fn header(&self) -> &IvfHeader {
+        &self.header
+    }
+
+    fn read_frame(&mut self) -> Result<Option<IvfFrame>, FormatError> {
+        let mut frame_header = [0u8; FRAME_HEADER_LENGTH];
+        let mut read = 0;
+        while read < FRAME_HEADER_LENGTH {
+            match self.reader.read(&mut frame_header[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(FormatError::InvalidData(String::from("IVF data ended inside a frame header"))),
+                count => read += count,
+            }
+        }
+
+        let size = u32::from_le_bytes([frame_header[0], frame_header[1], frame_header[2], frame_header[3]]) as usize;
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&frame_header[4..]);
+        // Read through `take` so a corrupt frame size can't allocate more than the data that's there
+        let mut data = Vec::new();
+        (&mut self.reader).take(size as u64).read_to_end(&mut data)?;
+        if data.len() != size {
+            return Err(FormatError::InvalidData(format!("IVF data ended inside a frame of {size} bytes")));
+        }
+        Ok(Some(IvfFrame { timestamp: i64::from_le_bytes(timestamp), data }))
+    }
+}
+
+impl<R: Read> Iterator for IvfReader<R> {
+    type Item = Result<IvfFrame, FormatError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+///
+/// Writes frames to an IVF file.
+///
+/// The header is written before the first frame.  When the destination can seek, [`Self::finish()`] rewrites it with the number of frames written.
+///
+pub struct IvfWriter<W: Write> {
+    writer: W,
+    header: IvfHeader,
+    header_written: bool,
+    written: u64,
+}
+
+impl<W: Write> IvfWriter<W> {
+    ///
+    /// Creates a new writer for frames described by the given header.  The header's frame count is ignored.
+    ///
+    pub fn new(writer: W, header: IvfHeader) -> Self {
+        IvfWriter {
+            writer,
+            header: IvfHeader { frame_count: 0, ..header },
+            header_written: false,
+            written: 0,
+        }
+    }
+
+    ///
+    /// Writes a frame with the given timestamp, in units of the header's timebase.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the frame is larger than 4GiB or if there is a problem writing to the destination.
+    ///
+    pub fn write_frame(&mut self, timestamp: i64, data: &[u8]) -> io::Result<()> {
+        let size = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("Frame of {} bytes is too large for IVF", data.len())))?;
+        self.write_header()?;
+        self.writer.write_all(&size.to_le_bytes())?;
+        self.writer.write_all(&timestamp.to_le_bytes())?;
+        self.writer.write_all(data)?;
+        self.header.frame_count += 1;
+        self.written += (FRAME_HEADER_LENGTH + data.len()) as u64;
+        Ok(())
+    }
+
+    ///
+    /// Writes the header if no frames were written and returns the underlying destination, leaving the header frame count as 0.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.write_header()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.header_written = true;
+            self.writer.write_all(&IvfHeader { frame_count: 0, ..self.header }.to_bytes())?;
+            self.written += HEADER_LENGTH as u64;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> IvfWriter<W> {
+    ///
+    /// Updates the header frame count to the number of frames written and returns the underlying destination.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to or seeking in the destination.
+    ///
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+        self.writer.seek(SeekFrom::Current(-(self.written as i64)))?;
+        self.writer.write_all(&self.header.to_bytes())?;
+        self.writer.seek(SeekFrom::Current(self.written as i64 - HEADER_LENGTH as i64))?;
+        Ok(self.writer)
+    }
+}
+
+///
+/// Writes a "V_VP8", "V_VP9", or "V_AV1" track from WebM data to an IVF file.
+///
+/// Timestamps are written in the units of the segment "TimestampScale", with the IVF timebase set to match.
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found or uses another codec, or if there is a problem reading the source or writing the destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::ivf;
+///
+/// let source = File::open("media/test.webm").unwrap();
+/// ivf::extract(source, 1, File::create("track1.ivf").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, W: Write + Seek>(source: R, track: u64, dest: W) -> Result<W, FormatError> {
+    Ok(write_frames(TrackReader::new(source, track)?, dest)?.finish()?)
+}
+
+///
+/// Writes the frames from `reader`.  The caller decides whether to seek back and fill in the header frame count.
+///
+pub(crate) fn write_frames<R: Read, W: Write>(reader: TrackReader<R>, dest: W) -> Result<IvfWriter<W>, FormatError> {
+    let header = IvfHeader::from_track_entry(reader.track_entry(), reader.timestamp_scale())?;
+    let is_av1 = reader.codec_id() == "V_AV1";
+
+    let mut writer = IvfWriter::new(dest, header);
+    for frame in reader {
+        let frame = frame?;
+        if is_av1 && !frame.data.starts_with(&TEMPORAL_DELIMITER) {
+            let mut data = TEMPORAL_DELIMITER.to_vec();
+            data.extend_from_slice(&frame.data);
+            writer.write_frame(frame.timestamp, &data)?;
+        } else {
+            writer.write_frame(frame.timestamp, &frame.data)?;
+        }
+    }
+    Ok(writer)
+}
+
+///
+/// Muxes an IVF file holding VP8, VP9, or AV1 into a new WebM with a single video track.
+///
+/// The track entry is built from the IVF header, timestamps are converted to milliseconds, and keyframes are detected from the frame data.
+///
+/// # Errors
+///
+/// This function returns an error if the IVF data is invalid or uses another codec, or if there is a problem writing the destination.
+///
+pub fn import<R: Read, W: Write>(source: R, dest: W) -> Result<W, FormatError> {
+    let reader = IvfReader::new(source)?;
+    let header = *reader.header();
+    let codec_id = header.codec_id()
+        .ok_or_else(|| FormatError::InvalidData(format!("IVF FourCC {} is not VP8, VP9, or AV1", String::from_utf8_lossy(&header.fourcc))))?;
+    if header.timebase_denominator == 0 {
+        return Err(FormatError::InvalidData(String::from("IVF timebase denominator is 0")));
+    }
+
+    let mut muxer = WebmMuxer::new(dest);
+    let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+        MatroskaSpec::TrackType(1),
+        MatroskaSpec::CodecID(String::from(codec_id)),
+        MatroskaSpec::Video(Master::Full(vec![
+            MatroskaSpec::PixelWidth(header.width as u64),
+            MatroskaSpec::PixelHeight(header.height as u64),
+        ])),
+    ])))?;
+
+    for frame in reader {
+        let frame = frame?;
+        let timestamp = frame.timestamp as i128 * header.timebase_numerator as i128 * 1000 / header.timebase_denominator as i128;
+        let mut mux_frame = MuxFrame::new(track, timestamp as i64, frame.data);
+        match codec_id {
+            "V_VP8" => {
+                let frame_header = vp8::FrameHeader::try_from(mux_frame.data.as_slice())?;
+                mux_frame.keyframe = frame_header.keyframe;
+                mux_frame.invisible = !frame_header.show_frame;
+            },
+            "V_VP9" => {
+                let frame_headers = vp9::split_superframe(&mux_frame.data)?.into_iter()
+                    .map(vp9::FrameHeader::try_from)
+                    .collect::<Result<Vec<_>, _>>()?;
+                mux_frame.keyframe = frame_headers.first().is_some_and(|h| h.keyframe);
+                mux_frame.invisible = frame_headers.iter().all(|h| h.is_hidden());
+            },
+            _ => {
+                let obus = av1::read_obus(&mux_frame.data)?;
+                let data: Vec<u8> = obus.iter()
+                    .filter(|obu| obu.obu_type != av1::ObuType::TemporalDelimiter)
+                    .flat_map(|obu| obu.to_bytes())
+                    .collect();
+                mux_frame.keyframe = av1::is_keyframe(&data)?;
+                mux_frame.data = data;
+            },
+        }
+        muxer.write_frame(mux_frame)?;
+    }
+    Ok(muxer.finalize()?)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a.max(1) } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::fs::File;
+    use std::io::Cursor;
+
+    use super::{extract, import, IvfFrame, IvfHeader, IvfReader, IvfWriter};
+    use crate::formats::TrackReader;
+    use crate::matroska_spec::MatroskaSpec;
+
+    #[test]
+    fn round_trips_vp8_track() {
+        let ivf = extract(File::open("media/test.webm").unwrap(), 1, Cursor::new(Vec::new())).unwrap().into_inner();
+        let reader = IvfReader::new(ivf.as_slice()).unwrap();
+        let header = *reader.header();
+        assert_eq!((*b"VP80", 320, 240), (header.fourcc, header.width, header.height));
+        assert_eq!((1, 1000), (header.timebase_numerator, header.timebase_denominator));
+
+        let frames: Vec<IvfFrame> = reader.map(|f| f.unwrap()).collect();
+        assert_eq!(header.frame_count as usize, frames.len());
+        let original: Vec<_> = TrackReader::new(File::open("media/test.webm").unwrap(), 1).unwrap().map(|f| f.unwrap()).collect();
+        assert_eq!(original.len(), frames.len());
+
+        let webm = import(ivf.as_slice(), Vec::new()).unwrap();
+        let imported = TrackReader::new(webm.as_slice(), 1).unwrap();
+        assert!(imported.track_entry().contains(&MatroskaSpec::CodecID(String::from("V_VP8"))));
+        let imported: Vec<_> = imported.map(|f| f.unwrap()).collect();
+        assert_eq!(original.len(), imported.len());
+        for (original, imported) in original.iter().zip(imported.iter()) {
+            assert_eq!(original.timestamp, imported.timestamp);
+            assert_eq!(original.keyframe, imported.keyframe);
+            assert_eq!(original.data, imported.data);
+        }
+
+        assert!(extract(File::open("media/test.webm").unwrap(), 2, Cursor::new(Vec::new())).is_err());
+
+        let mut corrupt = ivf[..32].to_vec();
+        corrupt.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x02]);
+        assert!(IvfReader::new(corrupt.as_slice()).unwrap().next().unwrap().is_err());
+    }
+
+    #[test]
+    fn strips_av1_temporal_delimiters() {
+        let header = IvfHeader { fourcc: *b"AV01", width: 1920, height: 1080, timebase_denominator: 30, timebase_numerator: 1, frame_count: 0 };
+        let sequence_header = [0x0a, 0x0e, 0x00, 0x00, 0x00, 0x42, 0xab, 0xbf, 0xc3, 0x73, 0xff, 0xe6, 0x40, 0x40, 0x40, 0x41];
+        let mut keyframe = vec![0x12, 0x00];
+        keyframe.extend_from_slice(&sequence_header);
+        keyframe.extend_from_slice(&[0x32, 0x01, 0x10]);
+
+        let mut writer = IvfWriter::new(Vec::new(), header);
+        writer.write_frame(3, &keyframe).unwrap();
+        let ivf = writer.into_inner().unwrap();
+        assert_eq!(header, IvfHeader::try_from(&ivf[..32]).unwrap());
+
+        let webm = import(ivf.as_slice(), Vec::new()).unwrap();
+        let reader = TrackReader::new(webm.as_slice(), 1).unwrap();
+        assert!(reader.track_entry().iter().any(|c| matches!(c, MatroskaSpec::CodecPrivate(_))));
+        let frames: Vec<_> = reader.map(|f| f.unwrap()).collect();
+        assert_eq!(100, frames[0].timestamp);
+        assert!(frames[0].keyframe);
+        assert_eq!(&keyframe[2..], frames[0].data.as_slice());
+    }
+}
@@ -0,0 +1,416 @@
+//!
+//! Converts tracks between WebM and the native file formats used by other tools.
+//!
+//! Each module handles one format, with functions to export a track from WebM data and to import a file into a new WebM.  Export reads the WebM data once from start to end, so any [`std::io::Read`] source can be used.  Import is built on the [`crate::muxer::WebmMuxer`].
+//!
+//! Export undoes "ContentEncoding" header stripping.  Tracks with zlib "ContentCompression", which mkvmerge uses for subtitles by default, can be read when the `miniz_oxide` feature is enabled.
+//!
+
+pub mod ass;
+pub mod chapters;
+pub mod elementary;
+pub mod ivf;
+pub mod ogg;
+pub mod pgs;
+pub mod srt;
+pub mod tags;
+pub mod vobsub;
+pub mod wav;
+pub mod webvtt;
+mod xml;
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::Read;
+
+use crate::errors::FormatError;
+use crate::matroska_spec::{Block, Master, MatroskaSpec, SimpleBlock};
+use crate::muxer::{BlockAddition, MuxFrame};
+use crate::WebmIterator;
+
+///
+/// Reads the frames of a single track from WebM data.
+///
+/// Frames are returned as [`MuxFrame`]s holding the absolute timestamp and block properties, so they can be passed straight back into a muxer.  Frames from laced blocks are given timestamps spaced by the track's "DefaultDuration" when it has one.
+///
+pub(crate) struct TrackReader<R: Read> {
+    tags: WebmIterator<R>,
+    track: u64,
+    track_entry: Vec<MatroskaSpec>,
+    timestamp_scale: u64,
+    file_tags: Vec<MatroskaSpec>,
+    encodings: Vec<FrameEncoding>,
+    cluster_timestamp: i64,
+    pending: VecDeque<MuxFrame>,
+}
+
+impl<R: Read> TrackReader<R> {
+    ///
+    /// Reads the start of the WebM data up to the first cluster, keeping the "TrackEntry" of the given track and any "Tag" elements.
+    ///
+    /// Frames stored with "ContentEncoding" header stripping are restored, as are frames with zlib compression when the `miniz_oxide` feature is enabled.  Tracks using other compression algorithms or encryption are rejected.
+    ///
+    pub(crate) fn new(source: R, track: u64) -> Result<Self, FormatError> {
+        let mut iter = WebmIterator::new(source, &[
+            MatroskaSpec::TrackEntry(Master::Start),
+            MatroskaSpec::BlockGroup(Master::Start),
+            MatroskaSpec::Tag(Master::Start),
+        ]);
+        let mut timestamp_scale = 1_000_000;
+        let mut track_entry = None;
+        let mut tags = Vec::new();
+        for tag in &mut iter {
+            match tag? {
+                MatroskaSpec::TimestampScale(val) => timestamp_scale = val,
+                MatroskaSpec::TrackEntry(Master::Full(children)) if children.iter().any(|c| matches!(c, MatroskaSpec::TrackNumber(val) if *val == track)) => {
+                    track_entry = Some(children);
+                },
+                tag @ MatroskaSpec::Tag(Master::Full(_)) => tags.push(tag),
+                MatroskaSpec::Cluster(_) => break,
+                _ => {},
+            }
+        }
+
+        match track_entry {
+            Some(track_entry) => Ok(TrackReader {
+                tags: iter,
+                track,
+                encodings: frame_encodings(&track_entry, track)?,
+                track_entry,
+                timestamp_scale,
+                file_tags: tags,
+                cluster_timestamp: 0,
+                pending: VecDeque::new(),
+            }),
+            None => Err(FormatError::InvalidData(format!("Track {track} was not found before the first cluster"))),
+        }
+    }
+
+    pub(crate) fn track(&self) -> u64 {
+        self.track
+    }
+
+    pub(crate) fn track_entry(&self) -> &[MatroskaSpec] {
+        &self.track_entry
+    }
+
+    pub(crate) fn codec_id(&self) -> &str {
+        self.track_entry.iter().find_map(|c| match c {
+            MatroskaSpec::CodecID(val) => Some(val.as_str()),
+            _ => None,
+        }).unwrap_or("")
+    }
+
+    ///
+    /// The "Tag" elements stored before the first cluster.  Tags written at the end of the file aren't available, since frames are read in a single pass.
+    ///
+    pub(crate) fn tags(&self) -> &[MatroskaSpec] {
+        &self.file_tags
+    }
+
+    pub(crate) fn track_uid(&self) -> Option<u64> {
+        self.track_entry.iter().find_map(|c| match c {
+            MatroskaSpec::TrackUID(val) => Some(*val),
+            _ => None,
+        })
+    }
+
+    ///
+    /// The segment "TimestampScale", which is the unit of frame timestamps and durations.
+    ///
+    pub(crate) fn timestamp_scale(&self) -> u64 {
+        self.timestamp_scale
+    }
+
+    ///
+    /// Reads the remaining frames as subtitle cues, with times converted to milliseconds.  A frame without a "BlockDuration" ends when the next frame starts.
+    ///
+    pub(crate) fn read_cues(self) -> Result<Vec<Cue>, FormatError> {
+        let timestamp_scale = self.timestamp_scale;
+        let mut cues: Vec<Cue> = Vec::new();
+        let mut missing_end = false;
+        for frame in self {
+            let frame = frame?;
+            let start = frame.timestamp.max(0) as u64 * timestamp_scale / 1_000_000;
+            if missing_end {
+                if let Some(previous) = cues.last_mut() {
+                    previous.end = start.max(previous.start);
+                }
+            }
+            missing_end = frame.duration.is_none();
+
+            let end = start + frame.duration.unwrap_or(0) * timestamp_scale / 1_000_000;
+            cues.push(Cue { start, end, frame });
+        }
+        Ok(cues)
+    }
+
+    fn default_duration(&self) -> Option<u64> {
+        self.track_entry.iter().find_map(|c| match c {
+            MatroskaSpec::DefaultDuration(val) => Some(*val),
+            _ => None,
+        })
+    }
+
+    fn queue_frames(&mut self, frames: Vec<&[u8]>, template: MuxFrame) -> Result<(), FormatError> {
+        let default_duration = self.default_duration();
+        for (index, data) in frames.into_iter().enumerate() {
+            let offset = default_duration.map(|duration| (index as u64 * duration / self.timestamp_scale) as i64).unwrap_or(0);
+            let mut frame = template.clone();
+            frame.timestamp += offset;
+            frame.data = data.to_vec();
+            for encoding in &self.encodings {
+                frame.data = match encoding {
+                    #[cfg(feature = "miniz_oxide")]
+                    FrameEncoding::Zlib => decompress_frame(&frame.data, MAX_DECOMPRESSED_FRAME_SIZE)?,
+                    FrameEncoding::HeaderStripping(header) => [header.as_slice(), &frame.data].concat(),
+                };
+            }
+            self.pending.push_back(frame);
+        }
+        Ok(())
+    }
+
+    fn read_block_group(&mut self, children: &[MatroskaSpec]) -> Result<(), FormatError> {
+        let block = match children.iter().find(|c| matches!(c, MatroskaSpec::Block(_))) {
+            Some(tag) => Block::try_from(tag).map_err(|err| FormatError::InvalidData(err.to_string()))?,
+            None => return Ok(()),
+        };
+        if block.track != self.track {
+            return Ok(());
+        }
+
+        let mut template = MuxFrame::new(self.track, self.cluster_timestamp + block.timestamp as i64, Vec::new());
+        template.invisible = block.invisible;
+        for child in children {
+            match child {
+                MatroskaSpec::BlockDuration(val) => template.duration = Some(*val),
+                MatroskaSpec::DiscardPadding(val) => template.discard_padding = Some(*val),
+                MatroskaSpec::ReferenceBlock(_) => template.keyframe = false,
+                MatroskaSpec::BlockAdditions(Master::Full(additions)) => {
+                    template.additions = additions.iter().filter_map(|more| match more {
+                        MatroskaSpec::BlockMore(Master::Full(more)) => Some(BlockAddition {
+                            id: more.iter().find_map(|c| match c {
+                                MatroskaSpec::BlockAddID(val) => Some(*val),
+                                _ => None,
+                            }).unwrap_or(1),
+                            data: more.iter().find_map(|c| match c {
+                                MatroskaSpec::BlockAdditional(data) => Some(data.clone()),
+                                _ => None,
+                            })?,
+                        }),
+                        _ => None,
+                    }).collect();
+                },
+                _ => {},
+            }
+        }
+
+        let frames = block.read_frame_data().map_err(|err| FormatError::InvalidData(err.to_string()))?;
+        self.queue_frames(frames.iter().map(|f| f.data).collect(), template)
+    }
+}
+
+impl<R: Read> Iterator for TrackReader<R> {
+    type Item = Result<MuxFrame, FormatError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let tag = match self.tags.next()? {
+                Ok(tag) => tag,
+                Err(err) => return Some(Err(err.into())),
+            };
+            let result = match &tag {
+                MatroskaSpec::Timestamp(val) => {
+                    self.cluster_timestamp = *val as i64;
+                    Ok(())
+                },
+                MatroskaSpec::SimpleBlock(_) => match SimpleBlock::try_from(&tag) {
+                    Ok(block) if block.track == self.track => {
+                        let mut template = MuxFrame::new(self.track, self.cluster_timestamp + block.timestamp as i64, Vec::new());
+                        template.keyframe = block.keyframe;
+                        template.invisible = block.invisible;
+                        template.discardable = block.discardable;
+                        match block.read_frame_data() {
+                            Ok(frames) => self.queue_frames(frames.iter().map(|f| f.data).collect(), template),
+                            Err(err) => Err(FormatError::InvalidData(err.to_string())),
+                        }
+                    },
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(FormatError::InvalidData(err.to_string())),
+                },
+                MatroskaSpec::BlockGroup(Master::Full(children)) => self.read_block_group(children),
+                _ => Ok(()),
+            };
+            if let Err(err) = result {
+                return Some(Err(err));
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+///
+/// A subtitle frame read by [`TrackReader::read_cues()`].
+///
+pub(crate) struct Cue {
+    /// The start time, in milliseconds.
+    pub(crate) start: u64,
+
+    /// The end time, in milliseconds.
+    pub(crate) end: u64,
+
+    pub(crate) frame: MuxFrame,
+}
+
+///
+/// The most a zlib compressed frame may expand to, so a small corrupt frame can't exhaust memory.
+///
+#[cfg(feature = "miniz_oxide")]
+const MAX_DECOMPRESSED_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+#[cfg(feature = "miniz_oxide")]
+fn decompress_frame(data: &[u8], max_size: usize) -> Result<Vec<u8>, FormatError> {
+    use miniz_oxide::inflate::{decompress_to_vec_zlib_with_limit, TINFLStatus};
+
+    decompress_to_vec_zlib_with_limit(data, max_size).map_err(|err| match err.status {
+        TINFLStatus::HasMoreOutput => FormatError::InvalidData(format!("Compressed frame expands to more than {max_size} bytes")),
+        _ => FormatError::InvalidData(format!("Invalid compressed frame: {err}")),
+    })
+}
+
+///
+/// A "ContentEncoding" applied to every frame of a track.
+///
+enum FrameEncoding {
+    #[cfg(feature = "miniz_oxide")]
+    Zlib,
+
+    /// Header stripping, holding the bytes removed from the start of every frame.
+    HeaderStripping(Vec<u8>),
+}
+
+///
+/// Finds the "ContentEncoding" elements that apply to frames, in the order they have to be undone.
+///
+fn frame_encodings(track_entry: &[MatroskaSpec], track: u64) -> Result<Vec<FrameEncoding>, FormatError> {
+    let encodings = track_entry.iter().find_map(|c| match c {
+        MatroskaSpec::ContentEncodings(Master::Full(children)) => Some(children.as_slice()),
+        _ => None,
+    }).unwrap_or(&[]);
+
+    let mut frame_encodings = Vec::new();
+    for encoding in encodings {
+        let children = match encoding {
+            MatroskaSpec::ContentEncoding(Master::Full(children)) => children,
+            _ => continue,
+        };
+        let scope = children.iter().find_map(|c| match c {
+            MatroskaSpec::ContentEncodingScope(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(1);
+        if scope & 0x01 == 0 {
+            continue;
+        }
+        let order = children.iter().find_map(|c| match c {
+            MatroskaSpec::ContentEncodingOrder(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(0);
+
+        let encoding_type = children.iter().find_map(|c| match c {
+            MatroskaSpec::ContentEncodingType(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(0);
+        let compression: &[MatroskaSpec] = children.iter().find_map(|c| match c {
+            MatroskaSpec::ContentCompression(Master::Full(children)) => Some(children.as_slice()),
+            _ => None,
+        }).unwrap_or(&[]);
+        let algorithm = compression.iter().find_map(|c| match c {
+            MatroskaSpec::ContentCompAlgo(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(0);
+
+        let frame_encoding = match (encoding_type, algorithm) {
+            #[cfg(feature = "miniz_oxide")]
+            (0, 0) => FrameEncoding::Zlib,
+            #[cfg(not(feature = "miniz_oxide"))]
+            (0, 0) => return Err(FormatError::InvalidData(format!("Track {track} frames are zlib compressed, which needs the `miniz_oxide` feature"))),
+            (0, 3) => FrameEncoding::HeaderStripping(compression.iter().find_map(|c| match c {
+                MatroskaSpec::ContentCompSettings(settings) => Some(settings.clone()),
+                _ => None,
+            }).unwrap_or_default()),
+            (0, algorithm) => return Err(FormatError::InvalidData(format!("Track {track} frames use ContentCompAlgo {algorithm}, which isn't supported"))),
+            _ => return Err(FormatError::InvalidData(format!("Track {track} frames are encrypted"))),
+        };
+        frame_encodings.push((order, frame_encoding));
+    }
+
+    // Encodings are undone starting from the highest "ContentEncodingOrder"
+    frame_encodings.sort_by_key(|(order, _)| std::cmp::Reverse(*order));
+    Ok(frame_encodings.into_iter().map(|(_, encoding)| encoding).collect())
+}
+
+///
+/// Finds the "Video" child of a track entry.
+///
+pub(crate) fn video_children(track_entry: &[MatroskaSpec]) -> &[MatroskaSpec] {
+    track_entry.iter().find_map(|c| match c {
+        MatroskaSpec::Video(Master::Full(children)) => Some(children.as_slice()),
+        _ => None,
+    }).unwrap_or(&[])
+}
+
+///
+/// Finds the "Audio" child of a track entry.
+///
+pub(crate) fn audio_children(track_entry: &[MatroskaSpec]) -> &[MatroskaSpec] {
+    track_entry.iter().find_map(|c| match c {
+        MatroskaSpec::Audio(Master::Full(children)) => Some(children.as_slice()),
+        _ => None,
+    }).unwrap_or(&[])
+}
+
+///
+/// Muxes a new Matroska file with a single track holding the given `(timestamp, data)` frames, for testing extraction.
+///
+#[cfg(test)]
+fn mux_track(track_entry: Vec<MatroskaSpec>, frames: Vec<(i64, Vec<u8>)>) -> Vec<u8> {
+    use crate::muxer::{MuxerOptions, WebmMuxer};
+
+    let mut muxer = WebmMuxer::with_options(Vec::new(), MuxerOptions { doc_type: String::from("matroska"), ..MuxerOptions::default() });
+    let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(track_entry))).unwrap();
+    for (timestamp, data) in frames {
+        muxer.write_frame(MuxFrame::new(track, timestamp, data)).unwrap();
+    }
+    muxer.finalize().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::TrackReader;
+
+    #[test]
+    fn reads_track_frames() {
+        let reader = TrackReader::new(File::open("media/test.webm").unwrap(), 2).unwrap();
+        assert_eq!("A_VORBIS", reader.codec_id());
+        assert_eq!(1_000_000, reader.timestamp_scale());
+
+        let frames: Vec<_> = reader.map(|f| f.unwrap()).collect();
+        assert!(!frames.is_empty());
+        assert!(frames.iter().all(|f| f.track == 2));
+        assert!(frames.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+        assert!(TrackReader::new(File::open("media/test.webm").unwrap(), 9).is_err());
+    }
+
+    #[cfg(feature = "miniz_oxide")]
+    #[test]
+    fn limits_decompressed_frames() {
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&[0x01; 4096], 6);
+        assert_eq!(vec![0x01; 4096], super::decompress_frame(&compressed, 4096).unwrap());
+        assert!(super::decompress_frame(&compressed, 4095).is_err());
+        assert!(super::decompress_frame(&compressed[..compressed.len() - 1], 4096).is_err());
+    }
+}
@@ -0,0 +1,574 @@
+//!
+//! Reads and writes Ogg pages, and converts Opus and Vorbis tracks between WebM and Ogg (`.opus` and `.ogg` files).
+//!
+//! Ogg splits a stream of packets into pages.  Each page records the granule position - for audio, the number of samples decoded by the end of the last packet completed on the page - which is how Ogg stores timing.  [`extract()`] computes granule positions from packet durations, taking the Opus pre-skip from "CodecDelay" and end trimming from "DiscardPadding".  [`import()`] does the reverse for Ogg Opus files.
+//!
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+use crate::codecs::opus::{self, OpusHead, SampleCounter};
+use crate::codecs::vorbis::{IdentificationHeader, PacketDurations};
+use crate::codecs::vorbis_comment::VorbisComment;
+use crate::codecs::xiph;
+use crate::errors::FormatError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use crate::muxer::{MuxFrame, WebmMuxer};
+use super::TrackReader;
+
+const CAPTURE_PATTERN: &[u8; 4] = b"OggS";
+const PAGE_HEADER_LENGTH: usize = 27;
+const TARGET_PAGE_SIZE: usize = 4096;
+
+const FLAG_CONTINUED: u8 = 0x01;
+const FLAG_BEGINNING_OF_STREAM: u8 = 0x02;
+const FLAG_END_OF_STREAM: u8 = 0x04;
+
+const OPUS_TAGS: &[u8; 8] = b"OpusTags";
+const VORBIS_COMMENT_HEADER: &[u8; 7] = b"\x03vorbis";
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = (index as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, byte| (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize])
+}
+
+///
+/// A single Ogg page.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OggPage {
+    /// Whether the first packet on the page continues a packet from the previous page.
+    pub continued: bool,
+
+    /// Whether this is the first page of the logical stream.
+    pub beginning_of_stream: bool,
+
+    /// Whether this is the last page of the logical stream.
+    pub end_of_stream: bool,
+
+    /// The granule position after the last packet completed on this page, or `u64::MAX` if no packet is completed.
+    pub granule_position: u64,
+
+    pub serial: u32,
+    pub sequence: u32,
+
+    /// The lacing values giving the size of each segment of data.  A value under 255 ends a packet.
+    pub segments: Vec<u8>,
+
+    pub data: Vec<u8>,
+}
+
+impl OggPage {
+    ///
+    /// Writes the page, including its checksum.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(PAGE_HEADER_LENGTH + self.segments.len() + self.data.len());
+        data.extend_from_slice(CAPTURE_PATTERN);
+        data.push(0);
+        data.push(
+            if self.continued { FLAG_CONTINUED } else { 0 }
+            | if self.beginning_of_stream { FLAG_BEGINNING_OF_STREAM } else { 0 }
+            | if self.end_of_stream { FLAG_END_OF_STREAM } else { 0 }
+        );
+        data.extend_from_slice(&self.granule_position.to_le_bytes());
+        data.extend_from_slice(&self.serial.to_le_bytes());
+        data.extend_from_slice(&self.sequence.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.push(self.segments.len() as u8);
+        data.extend_from_slice(&self.segments);
+        data.extend_from_slice(&self.data);
+
+        let crc = crc32(&data);
+        data[22..26].copy_from_slice(&crc.to_le_bytes());
+        data
+    }
+}
+
+///
+/// A packet read from an Ogg stream.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OggPacket {
+    pub data: Vec<u8>,
+
+    /// The page granule position, if this is the last packet completed on its page.
+    pub granule_position: Option<u64>,
+
+    /// Whether this is the last packet of the stream.
+    pub end_of_stream: bool,
+}
+
+///
+/// Reads the pages and packets of an Ogg stream.
+///
+/// The reader is an [`Iterator`] over the packets of the first logical stream in the file.  Pages from other multiplexed streams are skipped.
+///
+pub struct OggReader<R: Read> {
+    reader: R,
+    serial: Option<u32>,
+    partial: Vec<u8>,
+    packets: VecDeque<OggPacket>,
+}
+
+impl<R: Read> OggReader<R> {
+    pub fn new(reader: R) -> Self {
+        OggReader {
+            reader,
+            serial: None,
+            partial: Vec::new(),
+            packets: VecDeque::new(),
+        }
+    }
+
+    ///
+    /// Reads the next page, from any logical stream, and checks its checksum.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the page is invalid or truncated, or if there is a problem reading the source.
+    ///
+    pub fn read_page(&mut self) -> Result<Option<OggPage>, FormatError> {
+        let mut header = [0u8; PAGE_HEADER_LENGTH];
+        let mut read = 0;
+        while read < PAGE_HEADER_LENGTH {
+            match self.reader.read(&mut header[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(FormatError::InvalidData(String::from("Ogg data ended inside a page header"))),
+                count => read += count,
+            }
+        }
+        if &header[..4] != CAPTURE_PATTERN || header[4] != 0 {
+            return Err(FormatError::InvalidData(String::from("Ogg page doesn't start with a version 0 OggS header")));
+        }
+
+        let mut segments = vec![0u8; header[26] as usize];
+        self.reader.read_exact(&mut segments)
+            .map_err(|_| FormatError::InvalidData(String::from("Ogg data ended inside a segment table")))?;
+        let mut data = vec![0u8; segments.iter().map(|s| *s as usize).sum()];
+        self.reader.read_exact(&mut data)
+            .map_err(|_| FormatError::InvalidData(String::from("Ogg data ended inside a page")))?;
+
+        let read_u32 = |offset: usize| u32::from_le_bytes([header[offset], header[offset + 1], header[offset + 2], header[offset + 3]]);
+        let mut granule_position = [0u8; 8];
+        granule_position.copy_from_slice(&header[6..14]);
+        let page = OggPage {
+            continued: header[5] & FLAG_CONTINUED != 0,
+            beginning_of_stream: header[5] & FLAG_BEGINNING_OF_STREAM != 0,
+            end_of_stream: header[5] & FLAG_END_OF_STREAM != 0,
+            granule_position: u64::from_le_bytes(granule_position),
+            serial: read_u32(14),
+            sequence: read_u32(18),
+            segments,
+            data,
+        };
+
+        if page.to_bytes()[22..26] != header[22..26] {
+            return Err(FormatError::InvalidData(format!("Ogg page {} has an invalid checksum", page.sequence)));
+        }
+        Ok(Some(page))
+    }
+
+    fn read_packets(&mut self) -> Result<bool, FormatError> {
+        let page = loop {
+            match self.read_page()? {
+                None => return Ok(false),
+                Some(page) if *self.serial.get_or_insert(page.serial) == page.serial => break page,
+                Some(_) => continue,
+            }
+        };
+
+        if !page.continued {
+            self.partial.clear();
+        }
+        let mut position = 0;
+        for segment in &page.segments {
+            let size = *segment as usize;
+            self.partial.extend_from_slice(&page.data[position..position + size]);
+            position += size;
+            if size < 255 {
+                self.packets.push_back(OggPacket { data: std::mem::take(&mut self.partial), granule_position: None, end_of_stream: false });
+            }
+        }
+
+        // The granule position belongs to the last packet completed on the page
+        let packet_count = page.segments.iter().filter(|s| **s < 255).count();
+        if let Some(last) = self.packets.len().checked_sub(1).filter(|_| packet_count > 0) {
+            if page.granule_position != u64::MAX {
+                self.packets[last].granule_position = Some(page.granule_position);
+            }
+            self.packets[last].end_of_stream = page.end_of_stream;
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for OggReader<R> {
+    type Item = Result<OggPacket, FormatError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(packet) = self.packets.pop_front() {
+                return Some(Ok(packet));
+            }
+            match self.read_packets() {
+                Ok(true) => {},
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+///
+/// Writes packets to a single Ogg logical stream.
+///
+/// Packets are gathered into pages of about 4KiB.  Codecs that need their headers on separate pages should call [`Self::flush_page()`] after writing them.  [`Self::finish()`] must be called to mark the last page as the end of the stream.
+///
+pub struct OggWriter<W: Write> {
+    writer: W,
+    serial: u32,
+    sequence: u32,
+    segments: Vec<u8>,
+    data: Vec<u8>,
+    continued: bool,
+    page_granule: Option<u64>,
+    last_granule: u64,
+}
+
+impl<W: Write> OggWriter<W> {
+    pub fn new(writer: W, serial: u32) -> Self {
+        OggWriter {
+            writer,
+            serial,
+            sequence: 0,
+            segments: Vec::new(),
+            data: Vec::new(),
+            continued: false,
+            page_granule: None,
+            last_granule: 0,
+        }
+    }
+
+    ///
+    /// Writes a packet, along with the granule position after the packet.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub fn write_packet(&mut self, packet: &[u8], granule_position: u64) -> io::Result<()> {
+        // Full pages are only written once more data arrives, so the last packet is always left for the end of stream page
+        if self.data.len() >= TARGET_PAGE_SIZE {
+            self.write_page(false)?;
+        }
+
+        let mut remaining = packet;
+        let mut continued = false;
+        loop {
+            if self.segments.len() == 255 {
+                self.write_page(false)?;
+                self.continued = continued;
+            }
+
+            let size = remaining.len().min(255);
+            self.segments.push(size as u8);
+            self.data.extend_from_slice(&remaining[..size]);
+            remaining = &remaining[size..];
+            if size < 255 {
+                break;
+            }
+            continued = true;
+        }
+        self.page_granule = Some(granule_position);
+        self.last_granule = granule_position;
+        Ok(())
+    }
+
+    ///
+    /// Writes any buffered packets as a page, so the next packet starts a new page.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub fn flush_page(&mut self) -> io::Result<()> {
+        if !self.segments.is_empty() {
+            self.write_page(false)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Writes the last page, marked as the end of the stream, and returns the underlying destination.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.page_granule.is_none() && self.segments.is_empty() {
+            self.page_granule = Some(self.last_granule);
+        }
+        self.write_page(true)?;
+        Ok(self.writer)
+    }
+
+    fn write_page(&mut self, end_of_stream: bool) -> io::Result<()> {
+        let page = OggPage {
+            continued: self.continued,
+            beginning_of_stream: self.sequence == 0,
+            end_of_stream,
+            granule_position: self.page_granule.take().unwrap_or(u64::MAX),
+            serial: self.serial,
+            sequence: self.sequence,
+            segments: std::mem::take(&mut self.segments),
+            data: std::mem::take(&mut self.data),
+        };
+        self.writer.write_all(&page.to_bytes())?;
+        self.sequence += 1;
+        self.continued = false;
+        Ok(())
+    }
+}
+
+///
+/// Writes an "A_OPUS" or "A_VORBIS" track from WebM data as an Ogg stream.
+///
+/// Granule positions are computed from the duration of each packet.  For Opus, the `OpusHead` pre-skip is set from "CodecDelay", and "DiscardPadding" on the final block trims the last granule position.  "Tag" elements stored before the first cluster are written as the `OpusTags` packet or the Vorbis comment header (see [`VorbisComment::from_tags()`]).  A Vorbis track with no tags keeps its original comment header.
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found or uses another codec, if its headers or packets are invalid, or if there is a problem reading the source or writing the destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::ogg;
+///
+/// let source = File::open("media/audiosample.webm").unwrap();
+/// ogg::extract(source, 2, File::create("audio.opus").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, W: Write>(source: R, track: u64, dest: W) -> Result<W, FormatError> {
+    write_track(TrackReader::new(source, track)?, dest)
+}
+
+///
+/// Pages the packets from `reader`, starting with the codec headers from "CodecPrivate".
+///
+pub(crate) fn write_track<R: Read, W: Write>(reader: TrackReader<R>, dest: W) -> Result<W, FormatError> {
+    let codec_private = reader.track_entry().iter().find_map(|c| match c {
+        MatroskaSpec::CodecPrivate(data) => Some(data.clone()),
+        _ => None,
+    }).ok_or_else(|| FormatError::InvalidData(format!("Track {} has no CodecPrivate", reader.track())))?;
+    let serial = reader.track_uid().unwrap_or(reader.track()) as u32;
+    let comment = VorbisComment::from_tags(reader.tags(), reader.track_uid());
+
+    match reader.codec_id() {
+        "A_OPUS" => extract_opus(reader, &codec_private, comment, OggWriter::new(dest, serial)),
+        "A_VORBIS" => extract_vorbis(reader, &codec_private, comment, OggWriter::new(dest, serial)),
+        codec_id => Err(FormatError::InvalidData(format!("{codec_id} tracks cannot be stored in Ogg"))),
+    }
+}
+
+fn extract_opus<R: Read, W: Write>(reader: TrackReader<R>, codec_private: &[u8], comment: VorbisComment, mut writer: OggWriter<W>) -> Result<W, FormatError> {
+    let mut counter = SampleCounter::from_track_entry(reader.track_entry());
+    let mut head = OpusHead::try_from(codec_private)?;
+    head.pre_skip = u16::try_from(counter.codec_delay())
+        .map_err(|_| FormatError::InvalidData(format!("Codec delay of {} samples is too long for OpusHead", counter.codec_delay())))?;
+
+    writer.write_packet(&head.to_bytes(), 0)?;
+    writer.flush_page()?;
+    let mut tags = OPUS_TAGS.to_vec();
+    tags.extend_from_slice(&comment.to_bytes());
+    writer.write_packet(&tags, 0)?;
+    writer.flush_page()?;
+
+    for frame in reader {
+        let frame = frame?;
+        counter.add_packet(&frame.data, frame.discard_padding)?;
+        writer.write_packet(&frame.data, counter.total_samples() + counter.codec_delay())?;
+    }
+    Ok(writer.finish()?)
+}
+
+fn extract_vorbis<R: Read, W: Write>(reader: TrackReader<R>, codec_private: &[u8], comment: VorbisComment, mut writer: OggWriter<W>) -> Result<W, FormatError> {
+    let headers = xiph::split_headers(codec_private)?;
+    if headers.len() != 3 {
+        return Err(FormatError::InvalidData(format!("Vorbis CodecPrivate holds {} headers, not 3", headers.len())));
+    }
+    let identification = IdentificationHeader::try_from(headers[0])?;
+    let mut durations = PacketDurations::new(&identification, headers[2])?;
+
+    writer.write_packet(headers[0], 0)?;
+    writer.flush_page()?;
+    if comment.comments.is_empty() {
+        writer.write_packet(headers[1], 0)?;
+    } else {
+        let mut comment_header = VORBIS_COMMENT_HEADER.to_vec();
+        comment_header.extend_from_slice(&comment.to_bytes());
+        comment_header.push(0x01);
+        writer.write_packet(&comment_header, 0)?;
+    }
+    writer.write_packet(headers[2], 0)?;
+    writer.flush_page()?;
+
+    let mut decoded = 0u64;
+    let mut discarded = 0u64;
+    for frame in reader {
+        let frame = frame?;
+        let samples = durations.packet_samples(&frame.data)?;
+        decoded += samples;
+        if let Some(padding) = frame.discard_padding.filter(|p| *p > 0) {
+            discarded += (padding as u128 * identification.sample_rate as u128 / 1_000_000_000) as u64;
+        }
+        writer.write_packet(&frame.data, decoded.saturating_sub(discarded))?;
+    }
+    Ok(writer.finish()?)
+}
+
+///
+/// Muxes an Ogg Opus file into a new WebM with a single audio track.
+///
+/// The `OpusHead` is stored as "CodecPrivate" and its pre-skip as "CodecDelay".  Block timestamps are computed from packet durations, in milliseconds.  When the final granule position trims samples from the end of the stream, the trimmed duration is written as "DiscardPadding" on the last block.
+///
+/// # Errors
+///
+/// This function returns an error if the Ogg data is invalid or doesn't hold Opus, or if there is a problem writing the destination.
+///
+pub fn import<R: Read, W: Write>(source: R, dest: W) -> Result<W, FormatError> {
+    let mut reader = OggReader::new(source);
+    let head_packet = reader.next().transpose()?
+        .ok_or_else(|| FormatError::InvalidData(String::from("Ogg data holds no packets")))?;
+    let head = OpusHead::try_from(head_packet.data.as_slice())?;
+    match reader.next().transpose()? {
+        Some(packet) if packet.data.starts_with(OPUS_TAGS) => {},
+        _ => return Err(FormatError::InvalidData(String::from("Ogg Opus stream is missing its OpusTags packet"))),
+    }
+
+    let mut muxer = WebmMuxer::new(dest);
+    let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+        MatroskaSpec::TrackType(2),
+        MatroskaSpec::CodecID(String::from("A_OPUS")),
+        MatroskaSpec::CodecPrivate(head.to_bytes()),
+        MatroskaSpec::CodecDelay(opus::samples_to_ns(head.pre_skip as u64)),
+        MatroskaSpec::SeekPreRoll(80_000_000),
+        MatroskaSpec::Audio(Master::Full(vec![
+            MatroskaSpec::SamplingFrequency(opus::SAMPLE_RATE as f64),
+            MatroskaSpec::Channels(head.channels as u64),
+        ])),
+    ])))?;
+
+    let mut decoded = 0u64;
+    for packet in reader {
+        let packet = packet?;
+        let samples = opus::packet_samples(&packet.data)?;
+        let mut frame = MuxFrame::new(track, (opus::samples_to_ns(decoded) / 1_000_000) as i64, packet.data);
+        decoded += samples;
+
+        if let (true, Some(granule_position)) = (packet.end_of_stream, packet.granule_position) {
+            let padding = decoded.saturating_sub(granule_position).min(samples);
+            if padding > 0 {
+                frame.discard_padding = Some(opus::samples_to_ns(padding) as i64);
+            }
+        }
+        muxer.write_frame(frame)?;
+    }
+    Ok(muxer.finalize()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::fs::File;
+
+    use super::{extract, import, OggPacket, OggReader, OggWriter};
+    use crate::codecs::opus::OpusHead;
+    use crate::formats::TrackReader;
+    use crate::matroska_spec::MatroskaSpec;
+
+    #[test]
+    fn round_trips_packets() {
+        let packets: Vec<Vec<u8>> = vec![vec![0x01; 19], vec![], vec![0x02; 255], vec![0x03; 70_000], vec![0x04; 10]];
+        let mut writer = OggWriter::new(Vec::new(), 7);
+        writer.write_packet(&packets[0], 0).unwrap();
+        writer.flush_page().unwrap();
+        for (granule, packet) in packets.iter().enumerate().skip(1) {
+            writer.write_packet(packet, granule as u64 * 100).unwrap();
+        }
+        let data = writer.finish().unwrap();
+
+        let mut reader = OggReader::new(data.as_slice());
+        let first_page = reader.read_page().unwrap().unwrap();
+        assert!(first_page.beginning_of_stream && !first_page.end_of_stream);
+        assert_eq!((7, 0, 0), (first_page.serial, first_page.sequence, first_page.granule_position));
+
+        let read: Vec<OggPacket> = OggReader::new(data.as_slice()).map(|p| p.unwrap()).collect();
+        assert_eq!(packets, read.iter().map(|p| p.data.clone()).collect::<Vec<_>>());
+        assert_eq!(Some(400), read[4].granule_position);
+        assert!(read[4].end_of_stream);
+        assert!(!read[3].end_of_stream);
+
+        let mut corrupt = data.clone();
+        corrupt[40] ^= 0xff;
+        assert!(OggReader::new(corrupt.as_slice()).any(|p| p.is_err()));
+    }
+
+    #[test]
+    fn round_trips_opus_track() {
+        let ogg = extract(File::open("media/audiosample.webm").unwrap(), 2, Vec::new()).unwrap();
+        let packets: Vec<OggPacket> = OggReader::new(ogg.as_slice()).map(|p| p.unwrap()).collect();
+        assert_eq!(312, OpusHead::try_from(packets[0].data.as_slice()).unwrap().pre_skip);
+        assert!(packets[1].data.starts_with(b"OpusTags"));
+
+        let original: Vec<_> = TrackReader::new(File::open("media/audiosample.webm").unwrap(), 2).unwrap().map(|f| f.unwrap()).collect();
+        assert_eq!(original.len(), packets.len() - 2);
+        let last = packets.last().unwrap();
+        assert!(last.end_of_stream);
+        assert_eq!(Some(original.len() as u64 * 960 - original.last().unwrap().discard_padding.map(|p| p as u64 * 48 / 1_000_000).unwrap_or(0)), last.granule_position);
+
+        let webm = import(ogg.as_slice(), Vec::new()).unwrap();
+        let reader = TrackReader::new(webm.as_slice(), 1).unwrap();
+        assert!(reader.track_entry().contains(&MatroskaSpec::CodecDelay(6_500_000)));
+        let imported: Vec<_> = reader.map(|f| f.unwrap()).collect();
+        assert_eq!(original.len(), imported.len());
+        for (original, imported) in original.iter().zip(imported.iter()) {
+            assert_eq!(original.data, imported.data);
+            assert_eq!(original.discard_padding, imported.discard_padding);
+        }
+    }
+
+    #[test]
+    fn extracts_vorbis_track() {
+        let ogg = extract(File::open("media/test.webm").unwrap(), 2, Vec::new()).unwrap();
+        let packets: Vec<OggPacket> = OggReader::new(ogg.as_slice()).map(|p| p.unwrap()).collect();
+        assert!(packets[0].data.starts_with(b"\x01vorbis"));
+        assert!(packets[1].data.starts_with(b"\x03vorbis"));
+        assert!(packets[2].data.starts_with(b"\x05vorbis"));
+
+        // 261 audio packets of 256 and 2048 sample blocks at 44.1kHz, about 6.03 seconds
+        assert_eq!(264, packets.len());
+        assert_eq!(Some(265_792), packets.last().unwrap().granule_position);
+        assert!(packets.last().unwrap().end_of_stream);
+    }
+}
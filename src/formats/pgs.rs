@@ -0,0 +1,182 @@
+//!
+//! Writes "S_HDMV/PGS" subtitle tracks as `.sup` files, the raw Presentation Graphic Stream format used by Blu-ray authoring and OCR tools.
+//!
+//! A PGS display set is a series of segments (palette, object, presentation composition, window, and end), each with a one byte type and two byte size.  Matroska stores each display set in a single "Block".  In a `.sup` file every segment is preceded by a `PG` header holding its presentation and decoding timestamps in 90kHz units.
+//!
+
+use std::io::{self, Read, Write};
+
+use crate::errors::FormatError;
+use super::TrackReader;
+
+const MAGIC: &[u8; 2] = b"PG";
+const SEGMENT_HEADER_LENGTH: usize = 3;
+
+pub const SEGMENT_PALETTE: u8 = 0x14;
+pub const SEGMENT_OBJECT: u8 = 0x15;
+pub const SEGMENT_PRESENTATION_COMPOSITION: u8 = 0x16;
+pub const SEGMENT_WINDOW: u8 = 0x17;
+pub const SEGMENT_END: u8 = 0x80;
+
+///
+/// A single PGS segment.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgsSegment<'a> {
+    /// The segment type, such as [`SEGMENT_PALETTE`].
+    pub segment_type: u8,
+
+    pub data: &'a [u8],
+}
+
+///
+/// Splits a display set, as stored in a Matroska "Block", into its segments.
+///
+/// # Errors
+///
+/// This function returns an error if a segment header or segment data is truncated.
+///
+pub fn read_segments(data: &[u8]) -> Result<Vec<PgsSegment<'_>>, FormatError> {
+    let mut segments = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        if data.len() - position < SEGMENT_HEADER_LENGTH {
+            return Err(FormatError::InvalidData(format!("PGS segment header at offset {position} is truncated")));
+        }
+        let size = u16::from_be_bytes([data[position + 1], data[position + 2]]) as usize;
+        let start = position + SEGMENT_HEADER_LENGTH;
+        if data.len() - start < size {
+            return Err(FormatError::InvalidData(format!("PGS segment at offset {position} has {size} bytes, but only {} remain", data.len() - start)));
+        }
+        segments.push(PgsSegment { segment_type: data[position], data: &data[start..start + size] });
+        position = start + size;
+    }
+    Ok(segments)
+}
+
+///
+/// Writes PGS segments to a `.sup` file.
+///
+pub struct SupWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> SupWriter<W> {
+    pub fn new(writer: W) -> Self {
+        SupWriter { writer }
+    }
+
+    ///
+    /// Writes a segment with its `PG` header.  Timestamps are in 90kHz units, and wrap around after 2^32 ticks (about 13 hours) as in the format.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the segment is larger than 65535 bytes or if there is a problem writing to the destination.
+    ///
+    pub fn write_segment(&mut self, pts: u64, dts: u64, segment: &PgsSegment) -> io::Result<()> {
+        if segment.data.len() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("PGS segment of {} bytes is too large", segment.data.len())));
+        }
+        self.writer.write_all(MAGIC)?;
+        self.writer.write_all(&(pts as u32).to_be_bytes())?;
+        self.writer.write_all(&(dts as u32).to_be_bytes())?;
+        self.writer.write_all(&[segment.segment_type])?;
+        self.writer.write_all(&(segment.data.len() as u16).to_be_bytes())?;
+        self.writer.write_all(segment.data)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+///
+/// Writes an "S_HDMV/PGS" track from Matroska data to a `.sup` file.
+///
+/// Every segment of a block is given the block timestamp as its presentation timestamp.  Matroska doesn't keep the decoding times of a display set, so the decoding timestamp is written as 0, as mkvextract does.  Tracks compressed with zlib, as mkvmerge writes them by default, are decompressed when the `miniz_oxide` feature is enabled.
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found or isn't a PGS track, if a block holds truncated segments, or if there is a problem reading the source or writing the destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::pgs;
+///
+/// let source = File::open("remux.mkv").unwrap();
+/// pgs::extract(source, 4, File::create("subtitles.sup").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, W: Write>(source: R, track: u64, dest: W) -> Result<W, FormatError> {
+    write_track(TrackReader::new(source, track)?, dest)
+}
+
+///
+/// Splits each block from `reader` back into segments stamped with the block's PTS and a DTS of 0.
+///
+pub(crate) fn write_track<R: Read, W: Write>(reader: TrackReader<R>, dest: W) -> Result<W, FormatError> {
+    if reader.codec_id() != "S_HDMV/PGS" {
+        return Err(FormatError::InvalidData(format!("{} tracks are not PGS", reader.codec_id())));
+    }
+    let timestamp_scale = reader.timestamp_scale();
+
+    let mut writer = SupWriter::new(dest);
+    for frame in reader {
+        let frame = frame?;
+        let pts = (frame.timestamp.max(0) as u128 * timestamp_scale as u128 * 9 / 100_000) as u64;
+        for segment in read_segments(&frame.data)? {
+            writer.write_segment(pts, 0, &segment)?;
+        }
+    }
+    Ok(writer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract, read_segments, SEGMENT_END, SEGMENT_PRESENTATION_COMPOSITION};
+    use crate::formats::mux_track;
+    use crate::matroska_spec::{Master, MatroskaSpec};
+
+    #[test]
+    fn writes_sup_segments() {
+        let display_set = vec![SEGMENT_PRESENTATION_COMPOSITION, 0x00, 0x02, 0xaa, 0xbb, SEGMENT_END, 0x00, 0x00];
+        assert_eq!(2, read_segments(&display_set).unwrap().len());
+        assert!(read_segments(&display_set[..4]).is_err());
+
+        let mkv = mux_track(vec![
+            MatroskaSpec::TrackType(0x11),
+            MatroskaSpec::CodecID(String::from("S_HDMV/PGS")),
+            MatroskaSpec::ContentEncodings(Master::Full(vec![MatroskaSpec::ContentEncoding(Master::Full(vec![
+                MatroskaSpec::ContentCompression(Master::Full(vec![
+                    MatroskaSpec::ContentCompAlgo(3),
+                    MatroskaSpec::ContentCompSettings(vec![SEGMENT_PRESENTATION_COMPOSITION]),
+                ])),
+            ]))])),
+        ], vec![(2000, display_set[1..].to_vec())]);
+
+        let sup = extract(mkv.as_slice(), 1, Vec::new()).unwrap();
+        assert_eq!(28, sup.len());
+        assert_eq!(b"PG", &sup[..2]);
+        assert_eq!(180_000u32.to_be_bytes(), sup[2..6]);
+        assert_eq!([0x00; 4], sup[6..10]);
+        assert_eq!(&display_set[..5], &sup[10..15]);
+        assert_eq!(b"PG", &sup[15..17]);
+        assert_eq!(&display_set[5..], &sup[25..]);
+
+        let compressed = vec![0x78, 0xda, 0x13, 0x63, 0x60, 0x5a, 0xb5, 0xbb, 0x81, 0x81, 0x01, 0x00, 0x08, 0x82, 0x01, 0xfe];
+        let mkv = mux_track(vec![
+            MatroskaSpec::TrackType(0x11),
+            MatroskaSpec::CodecID(String::from("S_HDMV/PGS")),
+            MatroskaSpec::ContentEncodings(Master::Full(vec![MatroskaSpec::ContentEncoding(Master::Full(vec![
+                MatroskaSpec::ContentCompression(Master::Full(vec![MatroskaSpec::ContentCompAlgo(0)])),
+            ]))])),
+        ], vec![(2000, compressed)]);
+        let decompressed = extract(mkv.as_slice(), 1, Vec::new());
+        #[cfg(feature = "miniz_oxide")]
+        assert_eq!(sup, decompressed.unwrap());
+        #[cfg(not(feature = "miniz_oxide"))]
+        assert!(decompressed.is_err());
+    }
+}
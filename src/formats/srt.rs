@@ -0,0 +1,282 @@
+//!
+//! Reads and writes SubRip (`.srt`) files, and converts "S_TEXT/UTF8" subtitle tracks between Matroska and SRT.
+//!
+//! An SRT file is a list of numbered cues, each with a `hh:mm:ss,ttt --> hh:mm:ss,ttt` timing line followed by lines of text.  In Matroska each cue is a "BlockGroup" whose "Block" holds the text and whose "BlockDuration" holds the cue duration.
+//!
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+
+use crate::errors::FormatError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use crate::muxer::{MuxFrame, MuxerOptions, WebmMuxer};
+use super::{Cue, TrackReader};
+
+const CODEC_ID: &str = "S_TEXT/UTF8";
+const TIMING_SEPARATOR: &str = "-->";
+
+///
+/// A single SRT cue.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SrtCue {
+    /// The cue start time, in milliseconds.
+    pub start: u64,
+
+    /// The cue end time, in milliseconds.
+    pub end: u64,
+
+    /// The cue text, with lines separated by `\n`.
+    pub text: String,
+}
+
+///
+/// The cues of an SRT file.
+///
+/// Cue numbers are checked when reading, but not kept; cues are numbered from 1 in order when writing.  Errors in the input report the line and column where the problem was found.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::formats::srt::Srt;
+///
+/// let srt = Srt::try_from("1\n00:00:01,000 --> 00:00:02,500\nHello!\n").unwrap();
+/// assert_eq!((1000, 2500), (srt.cues[0].start, srt.cues[0].end));
+///
+/// let err = Srt::try_from("1\n00:00:01,000 --> 00:00:02.5\nHello!\n").unwrap_err();
+/// assert!(err.to_string().contains("line 2, column 18"));
+/// ```
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Srt {
+    pub cues: Vec<SrtCue>,
+}
+
+impl TryFrom<&str> for Srt {
+    type Error = FormatError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text).replace("\r\n", "\n").replace('\r', "\n");
+
+        let mut srt = Srt::default();
+        let mut lines = text.split('\n').enumerate().map(|(index, line)| (index + 1, line)).peekable();
+        loop {
+            while lines.peek().is_some_and(|(_, line)| line.trim().is_empty()) {
+                lines.next();
+            }
+            let (mut line_number, mut line) = match lines.next() {
+                Some(line) => line,
+                None => break,
+            };
+
+            // The cue number is required by the format, but some tools leave it out
+            if !line.contains(TIMING_SEPARATOR) {
+                if line.trim().parse::<u64>().is_err() {
+                    return Err(error(line_number, 1, &format!("expected a cue number, found {:?}", line.trim())));
+                }
+                match lines.next() {
+                    Some(next) => (line_number, line) = next,
+                    None => return Err(error(line_number + 1, 1, "file ended before the cue timing")),
+                }
+            }
+            let (start, end) = parse_timing(line, line_number)?;
+
+            let mut text = Vec::new();
+            while let Some((_, line)) = lines.next_if(|(_, line)| !line.trim().is_empty()) {
+                text.push(line.trim_end());
+            }
+            srt.cues.push(SrtCue { start, end, text: text.join("\n") });
+        }
+        Ok(srt)
+    }
+}
+
+impl fmt::Display for Srt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, cue) in self.cues.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}", index + 1)?;
+            writeln!(f, "{} {TIMING_SEPARATOR} {}", format_timestamp(cue.start), format_timestamp(cue.end))?;
+            if !cue.text.is_empty() {
+                writeln!(f, "{}", cue.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// Parses an SRT timestamp (`hh:mm:ss,ttt`) into milliseconds.  A `.` is also accepted before the milliseconds, as some tools write one.
+///
+pub fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (time, millis) = timestamp.split_once([',', '.'])?;
+    let parts: Vec<&str> = time.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+        _ => return None,
+    };
+
+    let number = |value: &str| -> Option<u64> {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        value.parse().ok()
+    };
+    let (hours, minutes, seconds) = (number(hours)?, number(minutes)?, number(seconds)?);
+    if minutes > 59 || seconds > 59 || millis.len() != 3 {
+        return None;
+    }
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + number(millis)?)
+}
+
+///
+/// Formats milliseconds as an SRT timestamp.
+///
+pub fn format_timestamp(timestamp: u64) -> String {
+    format!("{:02}:{:02}:{:02},{:03}", timestamp / 3_600_000, timestamp / 60_000 % 60, timestamp / 1000 % 60, timestamp % 1000)
+}
+
+fn error(line: usize, column: usize, message: &str) -> FormatError {
+    FormatError::InvalidData(format!("Invalid SRT on line {line}, column {column}: {message}"))
+}
+
+fn parse_timing(line: &str, line_number: usize) -> Result<(u64, u64), FormatError> {
+    let separator = line.find(TIMING_SEPARATOR)
+        .ok_or_else(|| error(line_number, line.len() - line.trim_start().len() + 1, &format!("expected a cue timing, found {:?}", line.trim())))?;
+
+    // Anything after the end timestamp (such as X1: coordinates) is ignored
+    let start_column = line.len() - line.trim_start().len();
+    let start = line[..separator].trim();
+    let end_offset = separator + TIMING_SEPARATOR.len();
+    let end_column = end_offset + line[end_offset..].len() - line[end_offset..].trim_start().len();
+    let end = line[end_column..].split_whitespace().next().unwrap_or("");
+
+    let start_time = parse_timestamp(start).ok_or_else(|| error(line_number, start_column + 1, &format!("invalid start timestamp {start:?}")))?;
+    let end_time = parse_timestamp(end).ok_or_else(|| error(line_number, end_column + 1, &format!("invalid end timestamp {end:?}")))?;
+    if end_time < start_time {
+        return Err(error(line_number, end_column + 1, &format!("end timestamp {end} is before the start")));
+    }
+    Ok((start_time, end_time))
+}
+
+///
+/// Writes an "S_TEXT/UTF8" track from Matroska data to an SRT file.
+///
+/// Cue times are the cluster timestamp plus the block timestamp, and end after the "BlockDuration".  A cue without a "BlockDuration" ends when the next cue starts.
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found or isn't an "S_TEXT/UTF8" track, if a cue isn't valid UTF-8, or if there is a problem reading the source or writing the destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::srt;
+///
+/// let source = File::open("movie.mkv").unwrap();
+/// srt::extract(source, 3, File::create("movie.srt").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, W: Write>(source: R, track: u64, dest: W) -> Result<W, FormatError> {
+    write_track(TrackReader::new(source, track)?, dest)
+}
+
+///
+/// Numbers the cues from `reader` and writes them out.
+///
+pub(crate) fn write_track<R: Read, W: Write>(reader: TrackReader<R>, mut dest: W) -> Result<W, FormatError> {
+    if reader.codec_id() != CODEC_ID {
+        return Err(FormatError::InvalidData(format!("{} tracks cannot be stored in SRT", reader.codec_id())));
+    }
+
+    let mut srt = Srt::default();
+    for Cue { start, end, frame } in reader.read_cues()? {
+        let text = String::from_utf8(frame.data)
+            .map_err(|_| FormatError::InvalidData(format!("Subtitle at {start}ms is not valid UTF-8")))?;
+        srt.cues.push(SrtCue { start, end, text: text.replace("\r\n", "\n") });
+    }
+
+    dest.write_all(srt.to_string().as_bytes())?;
+    Ok(dest)
+}
+
+///
+/// Muxes an SRT file into a new Matroska file with a single "S_TEXT/UTF8" subtitle track.
+///
+/// Each cue is written as a "BlockGroup" with a "BlockDuration", in order of start time, with timestamps in milliseconds.  The output "DocType" is `matroska`, since WebM doesn't allow "S_TEXT/UTF8" tracks.
+///
+/// # Errors
+///
+/// This function returns an error if the source isn't valid UTF-8 SRT, or if there is a problem reading the source or writing the destination.
+///
+pub fn import<R: Read, W: Write>(mut source: R, dest: W) -> Result<W, FormatError> {
+    let mut text = String::new();
+    source.read_to_string(&mut text)?;
+    let mut srt = Srt::try_from(text.as_str())?;
+    srt.cues.sort_by_key(|cue| cue.start);
+
+    let mut muxer = WebmMuxer::with_options(dest, MuxerOptions { doc_type: String::from("matroska"), ..MuxerOptions::default() });
+    let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+        MatroskaSpec::TrackType(0x11),
+        MatroskaSpec::CodecID(String::from(CODEC_ID)),
+    ])))?;
+
+    for cue in srt.cues {
+        let mut frame = MuxFrame::new(track, cue.start as i64, cue.text.into_bytes());
+        frame.duration = Some(cue.end - cue.start);
+        muxer.write_frame(frame)?;
+    }
+    Ok(muxer.finalize()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{extract, import, Srt};
+    use crate::formats::TrackReader;
+    use crate::matroska_spec::MatroskaSpec;
+
+    const SRT: &str = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nHello\r\n<i>world</i>\r\n\r\n2\r\n01:02:03,004 --> 01:02:05,000 X1:10 X2:20 Y1:10 Y2:20\r\nBye\r\n\r\n";
+
+    #[test]
+    fn parses_cues() {
+        let srt = Srt::try_from(SRT).unwrap();
+        assert_eq!(2, srt.cues.len());
+        assert_eq!((1000, 2500, "Hello\n<i>world</i>"), (srt.cues[0].start, srt.cues[0].end, srt.cues[0].text.as_str()));
+        assert_eq!((3_723_004, 3_725_000), (srt.cues[1].start, srt.cues[1].end));
+        assert_eq!(srt, Srt::try_from(srt.to_string().as_str()).unwrap());
+
+        let errors = [
+            ("one\n00:00:01,000 --> 00:00:02,000\nHi\n", "line 1, column 1"),
+            ("1\n\n", "line 2, column 1"),
+            ("1\n00:00:01,000 --> 00:00:02,000\nHi\n\n2\n00:00:03,000 --> 00:00:2,000\nHi\n", "line 6, column 18"),
+            ("1\n 00:00:61,000 --> 00:00:02,000\n", "line 2, column 2"),
+            ("1\n00:00:03,000 --> 00:00:02,000\n", "line 2, column 18"),
+        ];
+        for (text, position) in errors.iter() {
+            match Srt::try_from(*text) {
+                Err(err) => assert!(err.to_string().contains(position), "{} should be at {}", err, position),
+                Ok(_) => panic!("Expected an error at {}", position),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_matroska_track() {
+        let mkv = import(SRT.as_bytes(), Vec::new()).unwrap();
+        let reader = TrackReader::new(mkv.as_slice(), 1).unwrap();
+        assert!(reader.track_entry().contains(&MatroskaSpec::CodecID(String::from("S_TEXT/UTF8"))));
+        let frames: Vec<_> = reader.map(|f| f.unwrap()).collect();
+        assert_eq!((1000, Some(1500)), (frames[0].timestamp, frames[0].duration));
+        assert_eq!(b"Hello\n<i>world</i>".to_vec(), frames[0].data);
+
+        let srt = extract(mkv.as_slice(), 1, Vec::new()).unwrap();
+        assert_eq!(Srt::try_from(SRT).unwrap(), Srt::try_from(String::from_utf8(srt).unwrap().as_str()).unwrap());
+    }
+}
@@ -0,0 +1,245 @@
+//!
+//! Reads and writes tags in the mkvtoolnix XML format, as used by `mkvmerge --tags` and `mkvextract tags`.
+//!
+//! The XML mirrors the Matroska "Tags" element: each `<Tag>` has a `<Targets>` element and one or more `<Simple>` elements, and `<Simple>` elements can be nested to attach detail to a value (such as a `SORT_WITH` under a `TITLE`).  The element names are:
+//!
+//! | XML | Matroska |
+//! |-----|----------|
+//! | `<Targets>` | "Targets" |
+//! | `<TargetTypeValue>`, `<TargetType>` | "TargetTypeValue", "TargetType" |
+//! | `<TrackUID>`, `<EditionUID>`, `<ChapterUID>`, `<AttachmentUID>` | "TagTrackUID", "TagEditionUID", "TagChapterUID", "TagAttachmentUID" |
+//! | `<Simple>` | "SimpleTag" |
+//! | `<Name>`, `<String>`, `<Binary>` | "TagName", "TagString", "TagBinary" |
+//! | `<TagLanguage>`, `<TagLanguageIETF>`, `<DefaultLanguage>` | "TagLanguage", "TagLanguageIETF", "TagDefault" |
+//!
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Read;
+
+use crate::errors::FormatError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use crate::WebmIterator;
+use super::xml::{parse_document, write_document, XmlElement};
+
+const DTD: &str = "matroskatags.dtd";
+
+///
+/// A set of Matroska "Tag" elements that can be read from and written to mkvtoolnix XML.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::formats::tags::XmlTags;
+/// use webm_iterable::matroska_spec::{Master, MatroskaSpec};
+///
+/// let tags = XmlTags::try_from("<Tags><Tag><Targets><TargetTypeValue>50</TargetTypeValue></Targets><Simple><Name>TITLE</Name><String>Example</String></Simple></Tag></Tags>").unwrap();
+/// assert_eq!(vec![MatroskaSpec::Tag(Master::Full(vec![
+///     MatroskaSpec::Targets(Master::Full(vec![MatroskaSpec::TargetTypeValue(50)])),
+///     MatroskaSpec::SimpleTag(Master::Full(vec![
+///         MatroskaSpec::TagName(String::from("TITLE")),
+///         MatroskaSpec::TagString(String::from("Example")),
+///     ])),
+/// ]))], tags.tags);
+/// assert!(tags.to_string().contains("<String>Example</String>"));
+/// ```
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XmlTags {
+    /// "Tag" elements, each holding "Targets" and "SimpleTag" children.  Other elements are left out when writing XML.
+    pub tags: Vec<MatroskaSpec>,
+}
+
+impl XmlTags {
+    ///
+    /// Takes the "Tag" children of a "Tags" element.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `tags` isn't a full "Tags" element.
+    ///
+    pub fn from_element(tags: &MatroskaSpec) -> Result<Self, FormatError> {
+        match tags {
+            MatroskaSpec::Tags(Master::Full(children)) => Ok(XmlTags {
+                tags: children.iter().filter(|c| matches!(c, MatroskaSpec::Tag(_))).cloned().collect(),
+            }),
+            _ => Err(FormatError::InvalidData(String::from("Expected a full Tags element"))),
+        }
+    }
+
+    ///
+    /// Builds a "Tags" element holding the tags.
+    ///
+    pub fn to_element(&self) -> MatroskaSpec {
+        MatroskaSpec::Tags(Master::Full(self.tags.clone()))
+    }
+}
+
+impl TryFrom<&str> for XmlTags {
+    type Error = FormatError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        let root = parse_document(text)?;
+        if root.name != "Tags" {
+            return Err(root.error("expected <Tags> as the root element"));
+        }
+
+        let mut tags = Vec::new();
+        for tag in &root.children {
+            if tag.name != "Tag" {
+                return Err(root.unexpected_child(tag));
+            }
+            let mut children = Vec::new();
+            for child in &tag.children {
+                match child.name.as_str() {
+                    "Targets" => children.push(MatroskaSpec::Targets(Master::Full(parse_targets(child)?))),
+                    "Simple" => children.push(parse_simple(child)?),
+                    _ => return Err(tag.unexpected_child(child)),
+                }
+            }
+            // Targets is mandatory, and an empty one means the whole segment
+            if !children.iter().any(|c| matches!(c, MatroskaSpec::Targets(_))) {
+                children.insert(0, MatroskaSpec::Targets(Master::Full(Vec::new())));
+            }
+            tags.push(MatroskaSpec::Tag(Master::Full(children)));
+        }
+        Ok(XmlTags { tags })
+    }
+}
+
+impl fmt::Display for XmlTags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tags = self.tags.iter().filter_map(|tag| match tag {
+            MatroskaSpec::Tag(Master::Full(children)) => Some(XmlElement::new("Tag", children.iter().filter_map(|child| match child {
+                MatroskaSpec::Targets(Master::Full(targets)) => Some(XmlElement::new("Targets", targets.iter().filter_map(write_target).collect())),
+                MatroskaSpec::SimpleTag(Master::Full(simple)) => Some(write_simple(simple)),
+                _ => None,
+            }).collect())),
+            _ => None,
+        }).collect();
+        write_document(f, DTD, &XmlElement::new("Tags", tags))
+    }
+}
+
+///
+/// Reads every "Tag" from Matroska data.  The whole source is read, since "Tags" are often written after the clusters.
+///
+/// # Errors
+///
+/// This function returns an error if there is a problem reading the source.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::tags;
+///
+/// let tags = tags::read_tags(File::open("remux.mkv").unwrap()).unwrap();
+/// std::fs::write("tags.xml", tags.to_string()).unwrap();
+/// ```
+///
+pub fn read_tags<R: Read>(source: R) -> Result<XmlTags, FormatError> {
+    let mut tags = Vec::new();
+    for tag in WebmIterator::new(source, &[MatroskaSpec::Tag(Master::Start)]) {
+        if let tag @ MatroskaSpec::Tag(Master::Full(_)) = tag? {
+            tags.push(tag);
+        }
+    }
+    Ok(XmlTags { tags })
+}
+
+fn parse_targets(element: &XmlElement) -> Result<Vec<MatroskaSpec>, FormatError> {
+    element.children.iter().map(|child| Ok(match child.name.as_str() {
+        "TargetTypeValue" => MatroskaSpec::TargetTypeValue(child.to_u64()?),
+        "TargetType" => MatroskaSpec::TargetType(child.text.clone()),
+        "TrackUID" => MatroskaSpec::TagTrackUID(child.to_u64()?),
+        "EditionUID" => MatroskaSpec::TagEditionUID(child.to_u64()?),
+        "ChapterUID" => MatroskaSpec::TagChapterUID(child.to_u64()?),
+        "AttachmentUID" => MatroskaSpec::TagAttachmentUID(child.to_u64()?),
+        _ => return Err(element.unexpected_child(child)),
+    })).collect()
+}
+
+fn parse_simple(element: &XmlElement) -> Result<MatroskaSpec, FormatError> {
+    let children = element.children.iter().map(|child| Ok(match child.name.as_str() {
+        "Name" => MatroskaSpec::TagName(child.text.clone()),
+        "String" => MatroskaSpec::TagString(child.text.clone()),
+        "Binary" => MatroskaSpec::TagBinary(child.to_binary()?),
+        "TagLanguage" => MatroskaSpec::TagLanguage(child.text.clone()),
+        "TagLanguageIETF" => MatroskaSpec::TagLanguageIETF(child.text.clone()),
+        "DefaultLanguage" => MatroskaSpec::TagDefault(child.to_u64()?),
+        "Simple" => parse_simple(child)?,
+        _ => return Err(element.unexpected_child(child)),
+    })).collect::<Result<Vec<_>, FormatError>>()?;
+
+    if !children.iter().any(|c| matches!(c, MatroskaSpec::TagName(_))) {
+        return Err(element.error("missing <Name>"));
+    }
+    Ok(MatroskaSpec::SimpleTag(Master::Full(children)))
+}
+
+fn write_target(target: &MatroskaSpec) -> Option<XmlElement> {
+    match target {
+        MatroskaSpec::TargetTypeValue(val) => Some(XmlElement::with_text("TargetTypeValue", val.to_string())),
+        MatroskaSpec::TargetType(val) => Some(XmlElement::with_text("TargetType", val.as_str())),
+        MatroskaSpec::TagTrackUID(val) => Some(XmlElement::with_text("TrackUID", val.to_string())),
+        MatroskaSpec::TagEditionUID(val) => Some(XmlElement::with_text("EditionUID", val.to_string())),
+        MatroskaSpec::TagChapterUID(val) => Some(XmlElement::with_text("ChapterUID", val.to_string())),
+        MatroskaSpec::TagAttachmentUID(val) => Some(XmlElement::with_text("AttachmentUID", val.to_string())),
+        _ => None,
+    }
+}
+
+fn write_simple(simple: &[MatroskaSpec]) -> XmlElement {
+    XmlElement::new("Simple", simple.iter().filter_map(|child| match child {
+        MatroskaSpec::TagName(val) => Some(XmlElement::with_text("Name", val.as_str())),
+        MatroskaSpec::TagString(val) => Some(XmlElement::with_text("String", val.as_str())),
+        MatroskaSpec::TagBinary(val) => Some(XmlElement::with_binary("Binary", val)),
+        MatroskaSpec::TagLanguage(val) => Some(XmlElement::with_text("TagLanguage", val.as_str())),
+        MatroskaSpec::TagLanguageIETF(val) => Some(XmlElement::with_text("TagLanguageIETF", val.as_str())),
+        MatroskaSpec::TagDefault(val) => Some(XmlElement::with_text("DefaultLanguage", val.to_string())),
+        MatroskaSpec::SimpleTag(Master::Full(nested)) => Some(write_simple(nested)),
+        _ => None,
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::XmlTags;
+    use crate::matroska_spec::{Master, MatroskaSpec};
+
+    #[test]
+    fn round_trips_nested_simple_tags() {
+        let tags = XmlTags {
+            tags: vec![MatroskaSpec::Tag(Master::Full(vec![
+                MatroskaSpec::Targets(Master::Full(vec![MatroskaSpec::TargetTypeValue(30), MatroskaSpec::TagTrackUID(1234)])),
+                MatroskaSpec::SimpleTag(Master::Full(vec![
+                    MatroskaSpec::TagName(String::from("TITLE")),
+                    MatroskaSpec::TagLanguage(String::from("eng")),
+                    MatroskaSpec::TagDefault(1),
+                    MatroskaSpec::TagString(String::from("Rock & <Roll>")),
+                    MatroskaSpec::SimpleTag(Master::Full(vec![
+                        MatroskaSpec::TagName(String::from("SORT_WITH")),
+                        MatroskaSpec::TagString(String::from("Rock and Roll")),
+                    ])),
+                ])),
+                MatroskaSpec::SimpleTag(Master::Full(vec![
+                    MatroskaSpec::TagName(String::from("COVER")),
+                    MatroskaSpec::TagBinary(vec![0x00, 0xff, 0x10]),
+                ])),
+            ]))],
+        };
+
+        let xml = tags.to_string();
+        assert!(xml.contains("<String>Rock &amp; &lt;Roll&gt;</String>"));
+        assert!(xml.contains("<Binary format=\"hex\">00 ff 10</Binary>"));
+        assert_eq!(tags, XmlTags::try_from(xml.as_str()).unwrap());
+        assert_eq!(tags, XmlTags::from_element(&tags.to_element()).unwrap());
+
+        assert!(XmlTags::try_from("<Tags><Tag><Simple><String>No name</String></Simple></Tag></Tags>").is_err());
+        assert!(XmlTags::try_from("<Tags><Tag><Targets><TrackUID>abc</TrackUID></Targets></Tag></Tags>").is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//!
+//! Writes "S_VOBSUB" subtitle tracks as VobSub `.idx` and `.sub` files, the DVD subtitle format read by most players and OCR tools.
+//!
+//! Matroska stores the text of the `.idx` file header (frame size, palette, and so on) in "CodecPrivate" and each subpicture unit (SPU) in a "Block".  The `.sub` file is an MPEG program stream: each SPU is split across 2048 byte packs, as private stream 1 packets whose first packet carries the presentation timestamp.  The `.idx` file lists the timestamp and `.sub` file position of every SPU.
+//!
+
+use std::io::{self, Read, Write};
+
+use crate::codecs::bits::BitWriter;
+use crate::errors::FormatError;
+use crate::matroska_spec::MatroskaSpec;
+use super::TrackReader;
+
+const IDX_SIGNATURE: &str = "# VobSub index file, v7 (do not modify this line!)";
+
+const PACK_SIZE: usize = 2048;
+const PACK_HEADER_LENGTH: usize = 14;
+const PES_HEADER_LENGTH: usize = 9;
+const PTS_LENGTH: usize = 5;
+const PADDING_HEADER_LENGTH: usize = 6;
+
+/// The program stream mux rate written in pack headers, in units of 50 bytes per second.
+const MUX_RATE: u64 = 0x6270;
+
+const PRIVATE_STREAM_1: u8 = 0xbd;
+const PADDING_STREAM: u8 = 0xbe;
+const SUBPICTURE_STREAM: u8 = 0x20;
+
+/// ISO 639-2 codes and the matching ISO 639-1 codes used in `.idx` files, for the most common subtitle languages.
+const LANGUAGES: [(&str, &str); 30] = [
+    ("ara", "ar"), ("chi", "zh"), ("zho", "zh"), ("cze", "cs"), ("ces", "cs"), ("dan", "da"), ("dut", "nl"), ("nld", "nl"),
+    ("eng", "en"), ("fin", "fi"), ("fre", "fr"), ("fra", "fr"), ("ger", "de"), ("deu", "de"), ("gre", "el"), ("ell", "el"),
+    ("heb", "he"), ("hun", "hu"), ("ita", "it"), ("jpn", "ja"), ("kor", "ko"), ("nor", "no"), ("pol", "pl"), ("por", "pt"),
+    ("rus", "ru"), ("spa", "es"), ("swe", "sv"), ("tha", "th"), ("tur", "tr"), ("ukr", "uk"),
+];
+
+///
+/// Writes SPUs to a VobSub `.sub` file as MPEG program stream packs.
+///
+pub struct SubWriter<W: Write> {
+    writer: W,
+    stream: u8,
+    position: u64,
+}
+
+impl<W: Write> SubWriter<W> {
+    ///
+    /// Creates a new writer for the given subpicture stream index (0 to 31).
+    ///
+    pub fn new(writer: W, stream_index: u8) -> Self {
+        SubWriter {
+            writer,
+            stream: SUBPICTURE_STREAM + (stream_index & 0x1f),
+            position: 0,
+        }
+    }
+
+    ///
+    /// Writes an SPU with the given presentation timestamp, in 90kHz units.  Returns the file position of its first pack, for use in the `.idx` file.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub fn write_spu(&mut self, pts: u64, spu: &[u8]) -> io::Result<u64> {
+        let file_position = self.position;
+        let mut remaining = spu;
+        let mut first = true;
+        while first || !remaining.is_empty() {
+            let pts_length = if first { PTS_LENGTH } else { 0 };
+            let available = PACK_SIZE - PACK_HEADER_LENGTH - PES_HEADER_LENGTH - pts_length - 1;
+            let size = remaining.len().min(available);
+
+            // Space left in the last pack is filled with a padding packet, or with stuffing bytes if a padding packet won't fit
+            let padding = available - size;
+            let stuffing = if padding < PADDING_HEADER_LENGTH { padding } else { 0 };
+
+            let mut pack = Vec::with_capacity(PACK_SIZE);
+            pack.extend_from_slice(&pack_header(pts));
+            pack.extend_from_slice(&[0x00, 0x00, 0x01, PRIVATE_STREAM_1]);
+            pack.extend_from_slice(&((3 + pts_length + stuffing + 1 + size) as u16).to_be_bytes());
+            pack.push(0x81);
+            pack.push(if first { 0x80 } else { 0x00 });
+            pack.push((pts_length + stuffing) as u8);
+            if first {
+                pack.extend_from_slice(&pes_timestamp(pts));
+            }
+            pack.resize(pack.len() + stuffing, 0xff);
+            pack.push(self.stream);
+            pack.extend_from_slice(&remaining[..size]);
+            if padding >= PADDING_HEADER_LENGTH {
+                pack.extend_from_slice(&[0x00, 0x00, 0x01, PADDING_STREAM]);
+                pack.extend_from_slice(&((padding - PADDING_HEADER_LENGTH) as u16).to_be_bytes());
+                pack.resize(PACK_SIZE, 0xff);
+            }
+
+            self.writer.write_all(&pack)?;
+            self.position += PACK_SIZE as u64;
+            remaining = &remaining[size..];
+            first = false;
+        }
+        Ok(file_position)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn pack_header(scr: u64) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.write_bits(0x0000_01ba, 32);
+    writer.write_bits(0b01, 2);
+    writer.write_bits(scr >> 30, 3);
+    writer.write_bits(1, 1);
+    writer.write_bits(scr >> 15, 15);
+    writer.write_bits(1, 1);
+    writer.write_bits(scr, 15);
+    writer.write_bits(1, 1);
+    // SCR extension
+    writer.write_bits(0, 9);
+    writer.write_bits(1, 1);
+    writer.write_bits(MUX_RATE, 22);
+    writer.write_bits(0b11, 2);
+    // Reserved bits, then no stuffing
+    writer.write_bits(0b11111, 5);
+    writer.write_bits(0, 3);
+    writer.into_bytes()
+}
+
+fn pes_timestamp(pts: u64) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.write_bits(0b0010, 4);
+    writer.write_bits(pts >> 30, 3);
+    writer.write_bits(1, 1);
+    writer.write_bits(pts >> 15, 15);
+    writer.write_bits(1, 1);
+    writer.write_bits(pts, 15);
+    writer.write_bits(1, 1);
+    writer.into_bytes()
+}
+
+///
+/// Formats milliseconds as an `.idx` timestamp (`hh:mm:ss:ttt`).
+///
+pub fn format_timestamp(timestamp: u64) -> String {
+    format!("{:02}:{:02}:{:02}:{:03}", timestamp / 3_600_000, timestamp / 60_000 % 60, timestamp / 1000 % 60, timestamp % 1000)
+}
+
+///
+/// The two letter language code for an `.idx` file, from the track "LanguageIETF" or "Language".  Languages without a known code are written as `--`.
+///
+fn idx_language(track_entry: &[MatroskaSpec]) -> &str {
+    let ietf = track_entry.iter().find_map(|c| match c {
+        MatroskaSpec::LanguageIETF(val) => val.split('-').next(),
+        _ => None,
+    });
+    if let Some(language) = ietf.filter(|language| language.len() == 2) {
+        return language;
+    }
+
+    let language = track_entry.iter().find_map(|c| match c {
+        MatroskaSpec::Language(val) => Some(val.as_str()),
+        _ => None,
+    }).unwrap_or("eng");
+    LANGUAGES.iter().find(|(code, _)| *code == language).map(|(_, code)| *code).unwrap_or("--")
+}
+
+///
+/// Writes an "S_VOBSUB" track from Matroska data to a VobSub `.idx` file and `.sub` file.
+///
+/// The `.idx` file holds the "CodecPrivate" header followed by the language and one `timestamp:` line per SPU.  Each SPU is written to the `.sub` file with its block timestamp as the presentation timestamp.
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found or isn't a VobSub track, or if there is a problem reading the source or writing either destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::vobsub;
+///
+/// let source = File::open("dvd.mkv").unwrap();
+/// vobsub::extract(source, 3, File::create("subtitles.idx").unwrap(), File::create("subtitles.sub").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, I: Write, S: Write>(source: R, track: u64, mut idx: I, sub: S) -> Result<(I, S), FormatError> {
+    let reader = TrackReader::new(source, track)?;
+    if reader.codec_id() != "S_VOBSUB" {
+        return Err(FormatError::InvalidData(format!("{} tracks are not VobSub", reader.codec_id())));
+    }
+    let timestamp_scale = reader.timestamp_scale();
+
+    let header = reader.track_entry().iter().find_map(|c| match c {
+        MatroskaSpec::CodecPrivate(data) => Some(String::from_utf8_lossy(data).replace("\r\n", "\n")),
+        _ => None,
+    }).unwrap_or_default();
+    let header = header.trim_end_matches(['\n', '\0']);
+    if !header.starts_with("# VobSub index file") {
+        writeln!(idx, "{IDX_SIGNATURE}")?;
+    }
+    writeln!(idx, "{header}\n\nlangidx: 0\nid: {}, index: 0", idx_language(reader.track_entry()))?;
+
+    let mut writer = SubWriter::new(sub, 0);
+    for frame in reader {
+        let frame = frame?;
+        let timestamp = frame.timestamp.max(0) as u128 * timestamp_scale as u128;
+        let file_position = writer.write_spu((timestamp * 9 / 100_000) as u64, &frame.data)?;
+        writeln!(idx, "timestamp: {}, filepos: {file_position:09x}", format_timestamp((timestamp / 1_000_000) as u64))?;
+    }
+    Ok((idx, writer.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract, SubWriter, PACK_SIZE};
+    use crate::formats::mux_track;
+    use crate::matroska_spec::MatroskaSpec;
+
+    fn read_payload(sub: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for pack in sub.chunks(PACK_SIZE) {
+            assert_eq!([0x00, 0x00, 0x01, 0xba], pack[..4]);
+            assert_eq!([0x00, 0x00, 0x01, 0xbd], pack[14..18]);
+            let pes_length = u16::from_be_bytes([pack[18], pack[19]]) as usize;
+            let header_length = pack[22] as usize;
+            assert_eq!(0x20, pack[23 + header_length]);
+            payload.extend_from_slice(&pack[24 + header_length..20 + pes_length]);
+        }
+        payload
+    }
+
+    #[test]
+    fn writes_spus_in_packs() {
+        let mut writer = SubWriter::new(Vec::new(), 0);
+        let spu: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        assert_eq!(0, writer.write_spu(90_000, &spu).unwrap());
+        assert_eq!(3 * PACK_SIZE as u64, writer.write_spu(180_000, &[0x01; 2016]).unwrap());
+        let sub = writer.into_inner();
+        assert_eq!(4 * PACK_SIZE, sub.len());
+
+        // A PTS of 90000 in the first packet, and SCR in the pack header
+        assert_eq!([0x21, 0x00, 0x05, 0xbf, 0x21], sub[23..28]);
+        assert_eq!([0x44, 0x00, 0x16, 0xfc, 0x84, 0x01], sub[4..10]);
+        assert_eq!(spu, read_payload(&sub[..3 * PACK_SIZE]));
+        assert_eq!(vec![0x01; 2016], read_payload(&sub[3 * PACK_SIZE..]));
+    }
+
+    #[test]
+    fn writes_idx_and_sub() {
+        let mkv = mux_track(vec![
+            MatroskaSpec::TrackType(0x11),
+            MatroskaSpec::CodecID(String::from("S_VOBSUB")),
+            MatroskaSpec::Language(String::from("ger")),
+            MatroskaSpec::CodecPrivate(b"size: 720x480\npalette: 000000, ffffff\n".to_vec()),
+        ], vec![(1500, vec![0x02; 10]), (3_723_004, vec![0x03; 3000])]);
+
+        let (idx, sub) = extract(mkv.as_slice(), 1, Vec::new(), Vec::new()).unwrap();
+        let idx = String::from_utf8(idx).unwrap();
+        let expected = "# VobSub index file, v7 (do not modify this line!)\nsize: 720x480\npalette: 000000, ffffff\n\nlangidx: 0\nid: de, index: 0\ntimestamp: 00:00:01:500, filepos: 000000000\ntimestamp: 01:02:03:004, filepos: 000000800\n";
+        assert_eq!(expected, idx);
+        assert_eq!(3 * PACK_SIZE, sub.len());
+        assert_eq!(vec![0x02; 10], read_payload(&sub[..PACK_SIZE]));
+    }
+}
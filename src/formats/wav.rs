@@ -0,0 +1,575 @@
+//!
+//! Reads and writes RIFF/WAVE files, and converts uncompressed "A_PCM" tracks between Matroska and WAV.
+//!
+//! A WAV file is a "RIFF" chunk holding a "fmt " chunk that describes the samples and a "data" chunk that holds them, in little-endian order.  [`extract()`] writes "A_PCM/INT/LIT", "A_PCM/INT/BIG", and "A_PCM/FLOAT/IEEE" tracks as WAV, byte-swapping big-endian samples, and [`import()`] muxes a WAV file into a Matroska audio file.
+//!
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::errors::FormatError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use crate::muxer::{MuxFrame, MuxerOptions, WebmMuxer};
+use super::{audio_children, TrackReader};
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// The bytes following the format tag in a `WAVE_FORMAT_EXTENSIBLE` sub-format GUID.
+const SUBFORMAT_GUID_SUFFIX: [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+/// The target duration of each block written by [`import()`], in milliseconds.
+const IMPORT_BLOCK_DURATION: u64 = 40;
+
+///
+/// The sample format described by the "fmt " chunk of a WAV file.
+///
+/// `WAVE_FORMAT_EXTENSIBLE` chunks are read as the format of their sub-format GUID, and are written when there are more than two channels or when the significant bits don't fill the stored samples (such as 24 bit samples stored in 32 bits).
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::formats::wav::WavFormat;
+///
+/// let format = WavFormat { format_tag: 1, channels: 2, sample_rate: 44100, bits_per_sample: 16, valid_bits_per_sample: 16 };
+/// assert_eq!(4, format.block_align());
+/// assert_eq!(format, WavFormat::try_from(format.to_bytes().unwrap().as_slice()).unwrap());
+/// ```
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavFormat {
+    /// `1` for integer PCM or `3` for IEEE floating point.
+    pub format_tag: u16,
+
+    pub channels: u16,
+    pub sample_rate: u32,
+
+    /// The number of bits used to store each sample.  Samples are stored in whole bytes, so this is rounded up to a multiple of 8 when needed.
+    pub bits_per_sample: u16,
+
+    /// The number of significant bits in each sample, which are the most significant bits of the stored sample.
+    pub valid_bits_per_sample: u16,
+}
+
+impl WavFormat {
+    ///
+    /// Builds the format for an "A_PCM/INT/LIT", "A_PCM/INT/BIG", or "A_PCM/FLOAT/IEEE" track from the children of its "TrackEntry".
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the track uses another codec, or if its "BitDepth" is missing or not valid for the codec.
+    ///
+    pub fn from_track_entry(children: &[MatroskaSpec]) -> Result<Self, FormatError> {
+        let codec_id = children.iter().find_map(|c| match c {
+            MatroskaSpec::CodecID(val) => Some(val.as_str()),
+            _ => None,
+        }).unwrap_or("");
+        let format_tag = match codec_id {
+            "A_PCM/INT/LIT" | "A_PCM/INT/BIG" => WAVE_FORMAT_PCM,
+            "A_PCM/FLOAT/IEEE" => WAVE_FORMAT_IEEE_FLOAT,
+            _ => return Err(FormatError::InvalidData(format!("{codec_id} tracks cannot be stored in WAV"))),
+        };
+
+        let audio = audio_children(children);
+        let sample_rate = audio.iter().find_map(|c| match c {
+            MatroskaSpec::SamplingFrequency(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(8000.0);
+        let channels = audio.iter().find_map(|c| match c {
+            MatroskaSpec::Channels(val) => Some(*val),
+            _ => None,
+        }).unwrap_or(1);
+        let bit_depth = audio.iter().find_map(|c| match c {
+            MatroskaSpec::BitDepth(val) => Some(*val),
+            _ => None,
+        }).ok_or_else(|| FormatError::InvalidData(format!("{codec_id} track has no BitDepth")))?;
+
+        let valid_depth = match format_tag {
+            WAVE_FORMAT_PCM => (1..=32).contains(&bit_depth),
+            _ => bit_depth == 32 || bit_depth == 64,
+        };
+        if !valid_depth {
+            return Err(FormatError::InvalidData(format!("{codec_id} track has an unsupported BitDepth of {bit_depth}")));
+        }
+        if channels == 0 || channels > u16::MAX as u64 {
+            return Err(FormatError::InvalidData(format!("{codec_id} track has an unsupported channel count of {channels}")));
+        }
+
+        Ok(WavFormat {
+            format_tag,
+            channels: channels as u16,
+            sample_rate: sample_rate.round() as u32,
+            bits_per_sample: (bit_depth as u16).div_ceil(8) * 8,
+            valid_bits_per_sample: bit_depth as u16,
+        })
+    }
+
+    ///
+    /// The Matroska "CodecID" for this format, if it is integer or floating point PCM.
+    ///
+    pub fn codec_id(&self) -> Option<&'static str> {
+        match self.format_tag {
+            WAVE_FORMAT_PCM => Some("A_PCM/INT/LIT"),
+            WAVE_FORMAT_IEEE_FLOAT => Some("A_PCM/FLOAT/IEEE"),
+            _ => None,
+        }
+    }
+
+    ///
+    /// The number of bytes used to store each sample.
+    ///
+    pub fn sample_size(&self) -> usize {
+        (self.bits_per_sample as usize).div_ceil(8)
+    }
+
+    ///
+    /// The number of bytes used to store one sample for every channel.
+    ///
+    pub fn block_align(&self) -> usize {
+        self.sample_size() * self.channels as usize
+    }
+
+    fn is_extensible(&self) -> bool {
+        self.channels > 2 || self.valid_bits_per_sample as usize != self.sample_size() * 8
+    }
+
+    ///
+    /// Writes the contents of the "fmt " chunk.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the block alignment, sample container size, or byte rate is too large for its field.
+    ///
+    pub fn to_bytes(&self) -> Result<Vec<u8>, FormatError> {
+        let too_large = |field: &str| FormatError::InvalidData(format!("WAV {field} is too large for {} channels of {} bit samples at {}Hz", self.channels, self.bits_per_sample, self.sample_rate));
+        let block_align = u16::try_from(self.block_align()).map_err(|_| too_large("block align"))?;
+        let container_bits = u16::try_from(self.sample_size() * 8).map_err(|_| too_large("sample size"))?;
+        let byte_rate = self.sample_rate.checked_mul(block_align as u32).ok_or_else(|| too_large("byte rate"))?;
+
+        let mut data = Vec::with_capacity(40);
+        data.extend_from_slice(&if self.is_extensible() { WAVE_FORMAT_EXTENSIBLE } else { self.format_tag }.to_le_bytes());
+        data.extend_from_slice(&self.channels.to_le_bytes());
+        data.extend_from_slice(&self.sample_rate.to_le_bytes());
+        data.extend_from_slice(&byte_rate.to_le_bytes());
+        data.extend_from_slice(&block_align.to_le_bytes());
+        data.extend_from_slice(&container_bits.to_le_bytes());
+
+        if self.is_extensible() {
+            data.extend_from_slice(&22u16.to_le_bytes());
+            data.extend_from_slice(&self.valid_bits_per_sample.to_le_bytes());
+            // A channel mask of 0 leaves the speaker positions unspecified
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&self.format_tag.to_le_bytes());
+            data.extend_from_slice(&SUBFORMAT_GUID_SUFFIX);
+        }
+        Ok(data)
+    }
+}
+
+impl TryFrom<&[u8]> for WavFormat {
+    type Error = FormatError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() < 16 {
+            return Err(FormatError::InvalidData(format!("WAV fmt chunk of {} bytes is too short", data.len())));
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let mut format = WavFormat {
+            format_tag: read_u16(0),
+            channels: read_u16(2),
+            sample_rate: u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+            bits_per_sample: read_u16(14),
+            valid_bits_per_sample: read_u16(14),
+        };
+        // Some writers give the significant bits in wBitsPerSample, leaving the container size to nBlockAlign
+        let block_align = read_u16(12);
+        if format.channels != 0 && block_align.is_multiple_of(format.channels) {
+            let container_bits = (block_align / format.channels).checked_mul(8)
+                .ok_or_else(|| FormatError::InvalidData(format!("WAV fmt chunk has a block align of {block_align} for {} channels, which is too large", format.channels)))?;
+            format.bits_per_sample = format.bits_per_sample.max(container_bits);
+        }
+        if format.format_tag == WAVE_FORMAT_EXTENSIBLE {
+            if data.len() < 40 || data[26..40] != SUBFORMAT_GUID_SUFFIX {
+                return Err(FormatError::InvalidData(String::from("WAV fmt chunk has an invalid WAVE_FORMAT_EXTENSIBLE extension")));
+            }
+            let valid_bits = read_u16(18);
+            if valid_bits != 0 && valid_bits <= format.bits_per_sample {
+                format.valid_bits_per_sample = valid_bits;
+            }
+            format.format_tag = read_u16(24);
+        }
+        Ok(format)
+    }
+}
+
+///
+/// Reads the format and sample data of a WAV file.
+///
+/// The reader implements [`Read`] over the contents of the "data" chunk.  A "data" chunk size of `0xFFFFFFFF`, as written by streaming encoders, is read to the end of the source.
+///
+pub struct WavReader<R: Read> {
+    reader: R,
+    format: WavFormat,
+    remaining: Option<u64>,
+}
+
+impl<R: Read> WavReader<R> {
+    ///
+    /// Creates a new reader, reading the file up to the start of the sample data.  Chunks other than "fmt " and "data" are skipped.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the source isn't a WAV file, if there is no "fmt " chunk before the "data" chunk, or if there is a problem reading the source.
+    ///
+    pub fn new(mut reader: R) -> Result<Self, FormatError> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+            return Err(FormatError::InvalidData(String::from("WAV data doesn't start with a RIFF/WAVE header")));
+        }
+
+        let mut format = None;
+        loop {
+            let mut chunk_header = [0u8; 8];
+            reader.read_exact(&mut chunk_header)?;
+            let size = u32::from_le_bytes([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]);
+            match (&chunk_header[..4], format) {
+                (b"data", Some(format)) => {
+                    return Ok(WavReader {
+                        reader,
+                        format,
+                        remaining: if size == u32::MAX { None } else { Some(size as u64) },
+                    });
+                },
+                (b"data", None) => return Err(FormatError::InvalidData(String::from("WAV data chunk comes before the fmt chunk"))),
+                (b"fmt ", _) => {
+                    let mut data = Vec::new();
+                    (&mut reader).take(size as u64).read_to_end(&mut data)?;
+                    if data.len() != size as usize {
+                        return Err(FormatError::InvalidData(String::from("WAV fmt chunk is truncated")));
+                    }
+                    format = Some(WavFormat::try_from(data.as_slice())?);
+                    skip_bytes(&mut reader, size as u64 & 1)?;
+                },
+                // Chunks are padded to an even length
+                _ => skip_bytes(&mut reader, size as u64 + (size as u64 & 1))?,
+            }
+        }
+    }
+
+    pub fn format(&self) -> &WavFormat {
+        &self.format
+    }
+}
+
+fn skip_bytes<R: Read>(reader: &mut R, count: u64) -> Result<(), FormatError> {
+    if io::copy(&mut reader.take(count), &mut io::sink())? != count {
+        return Err(FormatError::InvalidData(String::from("WAV chunk is truncated")));
+    }
+    Ok(())
+}
+
+impl<R: Read> Read for WavReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let limit = self.remaining.map(|remaining| remaining.min(buf.len() as u64) as usize).unwrap_or(buf.len());
+        let count = self.reader.read(&mut buf[..limit])?;
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= count as u64;
+        }
+        Ok(count)
+    }
+}
+
+///
+/// Writes sample data to a WAV file.
+///
+/// The RIFF and "data" chunk sizes aren't known until all samples are written, so they are written as `0xFFFFFFFF` unless the destination supports [`Seek`] and [`Self::finish()`] is used to fill them in.
+///
+pub struct WavWriter<W: Write> {
+    writer: W,
+    format: WavFormat,
+    header_written: bool,
+    data_length: u64,
+}
+
+impl<W: Write> WavWriter<W> {
+    pub fn new(writer: W, format: WavFormat) -> Self {
+        WavWriter {
+            writer,
+            format,
+            header_written: false,
+            data_length: 0,
+        }
+    }
+
+    ///
+    /// Writes little-endian samples, interleaved by channel.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the data isn't a whole number of samples for every channel, or if there is a problem writing to the destination.
+    ///
+    pub fn write_samples(&mut self, data: &[u8]) -> io::Result<()> {
+        if !data.len().is_multiple_of(self.format.block_align()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} bytes of samples isn't a multiple of the {} byte block alignment", data.len(), self.format.block_align())));
+        }
+        self.write_header()?;
+        self.writer.write_all(data)?;
+        self.data_length += data.len() as u64;
+        Ok(())
+    }
+
+    ///
+    /// Writes the header if no samples were written and returns the underlying destination, leaving the chunk sizes unset.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.write_header()?;
+        Ok(self.writer)
+    }
+
+    fn header(&self, data_length: Option<u64>) -> io::Result<Vec<u8>> {
+        let format = self.format.to_bytes()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        let data_size = data_length.map(|length| u32::try_from(length).unwrap_or(u32::MAX)).unwrap_or(u32::MAX);
+        let riff_size = data_length
+            .map(|length| u32::try_from(length + (length & 1) + 20 + format.len() as u64).unwrap_or(u32::MAX))
+            .unwrap_or(u32::MAX);
+
+        let mut data = Vec::with_capacity(28 + format.len());
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&riff_size.to_le_bytes());
+        data.extend_from_slice(b"WAVEfmt ");
+        data.extend_from_slice(&(format.len() as u32).to_le_bytes());
+        data.extend_from_slice(&format);
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&data_size.to_le_bytes());
+        Ok(data)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.header_written = true;
+            let header = self.header(None)?;
+            self.writer.write_all(&header)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    ///
+    /// Pads the "data" chunk to an even length, fills in the chunk sizes, and returns the underlying destination.  Sizes over 4GiB are left as `0xFFFFFFFF`.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to or seeking in the destination.
+    ///
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+        if self.data_length & 1 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        let header = self.header(Some(self.data_length))?;
+        let written = (header.len() as u64 + self.data_length + (self.data_length & 1)) as i64;
+        self.writer.seek(SeekFrom::Current(-written))?;
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::Current(written - header.len() as i64))?;
+        Ok(self.writer)
+    }
+}
+
+///
+/// Writes an "A_PCM/INT/LIT", "A_PCM/INT/BIG", or "A_PCM/FLOAT/IEEE" track from Matroska data to a WAV file.
+///
+/// The format is built from the track's "SamplingFrequency", "Channels", and "BitDepth".  Samples from "A_PCM/INT/BIG" tracks are byte-swapped to little-endian.
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found or uses another codec, if a block isn't a whole number of samples, or if there is a problem reading the source or writing the destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::wav;
+///
+/// let source = File::open("recording.mka").unwrap();
+/// wav::extract(source, 1, File::create("recording.wav").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, W: Write + Seek>(source: R, track: u64, dest: W) -> Result<W, FormatError> {
+    Ok(write_frames(TrackReader::new(source, track)?, dest)?.finish()?)
+}
+
+///
+/// Writes the samples from `reader`.  The caller decides whether to seek back and fill in the chunk sizes.
+///
+pub(crate) fn write_frames<R: Read, W: Write>(reader: TrackReader<R>, dest: W) -> Result<WavWriter<W>, FormatError> {
+    let format = WavFormat::from_track_entry(reader.track_entry())?;
+    let big_endian = reader.codec_id() == "A_PCM/INT/BIG";
+
+    let mut writer = WavWriter::new(dest, format);
+    for frame in reader {
+        let mut frame = frame?;
+        if big_endian {
+            for sample in frame.data.chunks_mut(format.sample_size()) {
+                sample.reverse();
+            }
+        }
+        writer.write_samples(&frame.data)?;
+    }
+    Ok(writer)
+}
+
+///
+/// Muxes an integer or floating point PCM WAV file into a new Matroska file with a single "A_PCM/INT/LIT" or "A_PCM/FLOAT/IEEE" track.
+///
+/// Samples are split into blocks of about 40ms, with timestamps in milliseconds.  Integer samples stored with padding bits (such as 24 bit samples in 32 bits) are repacked into whole bytes of significant bits, since a Matroska "BitDepth" describes both.  The output "DocType" is `matroska`, since WebM doesn't allow PCM audio.
+///
+/// # Errors
+///
+/// This function returns an error if the WAV data is invalid or uses a compressed format, or if there is a problem writing the destination.
+///
+pub fn import<R: Read, W: Write>(source: R, dest: W) -> Result<W, FormatError> {
+    let mut reader = WavReader::new(source)?;
+    let format = *reader.format();
+    let codec_id = format.codec_id()
+        .ok_or_else(|| FormatError::InvalidData(format!("WAV format tag {:#06x} is not integer or floating point PCM", format.format_tag)))?;
+    if format.sample_rate == 0 || format.block_align() == 0 {
+        return Err(FormatError::InvalidData(String::from("WAV fmt chunk has no samples per second or no channels")));
+    }
+
+    let (bit_depth, packed_size) = match format.format_tag {
+        WAVE_FORMAT_PCM => (format.valid_bits_per_sample.min(format.bits_per_sample), (format.valid_bits_per_sample.min(format.bits_per_sample) as usize).div_ceil(8)),
+        _ => (format.bits_per_sample, format.sample_size()),
+    };
+
+    let mut muxer = WebmMuxer::with_options(dest, MuxerOptions { doc_type: String::from("matroska"), ..MuxerOptions::default() });
+    let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+        MatroskaSpec::TrackType(2),
+        MatroskaSpec::CodecID(String::from(codec_id)),
+        MatroskaSpec::Audio(Master::Full(vec![
+            MatroskaSpec::SamplingFrequency(format.sample_rate as f64),
+            MatroskaSpec::Channels(format.channels as u64),
+            MatroskaSpec::BitDepth(bit_depth as u64),
+        ])),
+    ])))?;
+
+    let block_samples = (format.sample_rate as u64 * IMPORT_BLOCK_DURATION / 1000).max(1);
+    let mut samples = 0u64;
+    loop {
+        let mut data = Vec::new();
+        (&mut reader).take(block_samples * format.block_align() as u64).read_to_end(&mut data)?;
+        // A truncated final sample is dropped
+        data.truncate(data.len() - data.len() % format.block_align());
+        if data.is_empty() {
+            break;
+        }
+
+        let timestamp = samples * 1000 / format.sample_rate as u64;
+        samples += (data.len() / format.block_align()) as u64;
+        if packed_size < format.sample_size() {
+            // The significant bits are the high bytes of each little-endian sample
+            data = data.chunks(format.sample_size()).flat_map(|sample| &sample[format.sample_size() - packed_size..]).copied().collect();
+        }
+        muxer.write_frame(MuxFrame::new(track, timestamp as i64, data))?;
+    }
+    Ok(muxer.finalize()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::io::Cursor;
+
+    use super::{extract, import, WavFormat, WavReader, WavWriter};
+    use crate::formats::{mux_track, TrackReader};
+    use crate::matroska_spec::{Master, MatroskaSpec};
+
+    fn pcm_track(codec_id: &str, bit_depth: u64, blocks: &[Vec<u8>]) -> Vec<u8> {
+        mux_track(vec![
+            MatroskaSpec::TrackType(2),
+            MatroskaSpec::CodecID(String::from(codec_id)),
+            MatroskaSpec::Audio(Master::Full(vec![
+                MatroskaSpec::SamplingFrequency(48000.0),
+                MatroskaSpec::Channels(2),
+                MatroskaSpec::BitDepth(bit_depth),
+            ])),
+        ], blocks.iter().enumerate().map(|(index, block)| (index as i64 * 10, block.clone())).collect())
+    }
+
+    #[test]
+    fn byte_swaps_big_endian_samples() {
+        let mka = pcm_track("A_PCM/INT/BIG", 24, &[vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06], vec![0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f]]);
+        let wav = extract(mka.as_slice(), 1, Cursor::new(Vec::new())).unwrap().into_inner();
+        assert_eq!(44 + 12, wav.len());
+        assert_eq!(&(wav.len() as u32 - 8).to_le_bytes(), &wav[4..8]);
+
+        let mut reader = WavReader::new(wav.as_slice()).unwrap();
+        assert_eq!(WavFormat { format_tag: 1, channels: 2, sample_rate: 48000, bits_per_sample: 24, valid_bits_per_sample: 24 }, *reader.format());
+        let mut samples = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut samples).unwrap();
+        assert_eq!(vec![0x03, 0x02, 0x01, 0x06, 0x05, 0x04, 0x0c, 0x0b, 0x0a, 0x0f, 0x0e, 0x0d], samples);
+
+        assert!(extract(pcm_track("A_PCM/INT/BIG", 16, &[vec![0x01, 0x02, 0x03]]).as_slice(), 1, Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn round_trips_float_wav() {
+        let format = WavFormat { format_tag: 3, channels: 4, sample_rate: 8000, bits_per_sample: 32, valid_bits_per_sample: 32 };
+        let samples: Vec<u8> = (0..16 * 500).map(|i| i as u8).collect();
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), format);
+        writer.write_samples(&samples).unwrap();
+        assert!(writer.write_samples(&[0; 8]).is_err());
+        let wav = writer.finish().unwrap().into_inner();
+        assert_eq!(0xfffe, u16::from_le_bytes([wav[20], wav[21]]));
+
+        let webm = import(wav.as_slice(), Vec::new()).unwrap();
+        let reader = TrackReader::new(webm.as_slice(), 1).unwrap();
+        assert!(reader.track_entry().contains(&MatroskaSpec::CodecID(String::from("A_PCM/FLOAT/IEEE"))));
+        let frames: Vec<_> = reader.map(|f| f.unwrap()).collect();
+        assert_eq!(vec![0, 40], frames.iter().map(|f| f.timestamp).collect::<Vec<_>>());
+        assert_eq!(320 * 16, frames[0].data.len());
+        assert_eq!(samples, frames.iter().flat_map(|f| f.data.clone()).collect::<Vec<_>>());
+
+        let wav = extract(webm.as_slice(), 1, Cursor::new(Vec::new())).unwrap().into_inner();
+        let mut reader = WavReader::new(wav.as_slice()).unwrap();
+        assert_eq!(format, *reader.format());
+        assert!(WavReader::new(&b"RIFF\x00\x00\x00\x00WAVEjunk\xf0\xff\xff\xff\x00\x00"[..]).is_err());
+        let mut extracted = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut extracted).unwrap();
+        assert_eq!(samples, extracted);
+    }
+
+    #[test]
+    fn repacks_padded_samples_on_import() {
+        let format = WavFormat { format_tag: 1, channels: 2, sample_rate: 8000, bits_per_sample: 32, valid_bits_per_sample: 24 };
+        assert_eq!(8, format.block_align());
+        assert_eq!(format, WavFormat::try_from(format.to_bytes().unwrap().as_slice()).unwrap());
+        assert!(WavFormat { channels: 16384, ..format }.to_bytes().is_err());
+        assert!(WavFormat { sample_rate: u32::MAX, ..format }.to_bytes().is_err());
+        let oversized = [0x01, 0x00, 0x01, 0x00, 0x40, 0x1f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x10, 0x00];
+        assert!(WavFormat::try_from(&oversized[..]).is_err());
+
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), format);
+        writer.write_samples(&[0x00, 0x01, 0x02, 0x03, 0x00, 0x04, 0x05, 0x06, 0x00, 0x07, 0x08, 0x09, 0x00, 0x0a, 0x0b, 0x0c]).unwrap();
+        let wav = writer.finish().unwrap().into_inner();
+
+        let mka = import(wav.as_slice(), Vec::new()).unwrap();
+        let reader = TrackReader::new(mka.as_slice(), 1).unwrap();
+        assert!(reader.track_entry().contains(&MatroskaSpec::Audio(Master::Full(vec![
+            MatroskaSpec::SamplingFrequency(8000.0),
+            MatroskaSpec::Channels(2),
+            MatroskaSpec::BitDepth(24),
+        ]))));
+        let frames: Vec<_> = reader.map(|f| f.unwrap()).collect();
+        assert_eq!(vec![vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c]], frames.into_iter().map(|f| f.data).collect::<Vec<_>>());
+    }
+}
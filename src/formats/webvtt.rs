@@ -0,0 +1,329 @@
+//!
+//! Reads and writes WebVTT files, and converts WebVTT text tracks between WebM and `.vtt` files.
+//!
+//! WebM stores WebVTT in "D_WEBVTT/SUBTITLES", "D_WEBVTT/CAPTIONS", "D_WEBVTT/DESCRIPTIONS", and "D_WEBVTT/METADATA" tracks, with one cue per "BlockGroup".  The "Block" holds the cue payload and "BlockDuration" its duration.  A cue's settings and identifier are stored in a "BlockAdditional" as the settings list, a line feed, and the identifier (optionally followed by another line feed and comments, which are ignored).  Cues without settings or an identifier have no "BlockAdditions".
+//!
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+
+use crate::errors::FormatError;
+use crate::matroska_spec::{Master, MatroskaSpec};
+use crate::muxer::{BlockAddition, MuxFrame, WebmMuxer};
+use super::{Cue, TrackReader};
+
+const SIGNATURE: &str = "WEBVTT";
+const TIMING_SEPARATOR: &str = "-->";
+
+const CODEC_IDS: [&str; 4] = ["D_WEBVTT/SUBTITLES", "D_WEBVTT/CAPTIONS", "D_WEBVTT/DESCRIPTIONS", "D_WEBVTT/METADATA"];
+
+///
+/// A single WebVTT cue.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WebVttCue {
+    /// The cue identifier, or an empty string if the cue has none.
+    pub identifier: String,
+
+    /// The cue start time, in milliseconds.
+    pub start: u64,
+
+    /// The cue end time, in milliseconds.
+    pub end: u64,
+
+    /// The cue settings list, such as `align:start line:0`, or an empty string if the cue has none.
+    pub settings: String,
+
+    /// The cue text, with lines separated by `\n`.
+    pub payload: String,
+}
+
+///
+/// The contents of a WebVTT file.
+///
+/// Comment blocks between cues are dropped when reading.
+///
+/// ## Example
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// use webm_iterable::formats::webvtt::WebVtt;
+///
+/// let vtt = WebVtt::try_from("WEBVTT\n\nintro\n00:01.000 --> 00:02.500 align:start\nHello!\n").unwrap();
+/// assert_eq!("intro", vtt.cues[0].identifier);
+/// assert_eq!((1000, 2500), (vtt.cues[0].start, vtt.cues[0].end));
+/// assert_eq!("align:start", vtt.cues[0].settings);
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebVtt {
+    /// Everything before the first cue, starting with the `WEBVTT` line and including any `STYLE`, `REGION`, or `NOTE` blocks.
+    pub header: String,
+
+    pub cues: Vec<WebVttCue>,
+}
+
+impl Default for WebVtt {
+    fn default() -> Self {
+        WebVtt {
+            header: String::from(SIGNATURE),
+            cues: Vec::new(),
+        }
+    }
+}
+
+impl TryFrom<&str> for WebVtt {
+    type Error = FormatError;
+
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text).replace("\r\n", "\n").replace('\r', "\n");
+
+        // Group the lines into blocks separated by blank lines, keeping the line number each block starts on
+        let mut blocks: Vec<(usize, Vec<&str>)> = Vec::new();
+        let mut in_block = false;
+        for (index, line) in text.split('\n').enumerate() {
+            if line.is_empty() {
+                in_block = false;
+            } else if in_block {
+                blocks.last_mut().unwrap().1.push(line);
+            } else {
+                in_block = true;
+                blocks.push((index + 1, vec![line]));
+            }
+        }
+
+        let first_line = blocks.first().filter(|(line, _)| *line == 1).map(|(_, lines)| lines[0]).unwrap_or("");
+        if !(first_line == SIGNATURE || first_line.starts_with("WEBVTT ") || first_line.starts_with("WEBVTT\t")) {
+            return Err(FormatError::InvalidData(String::from("WebVTT file doesn't start with a WEBVTT line")));
+        }
+
+        let mut vtt = WebVtt { header: blocks[0].1.join("\n"), cues: Vec::new() };
+        for (line_number, lines) in &blocks[1..] {
+            let timing_index = lines.iter().take(2).position(|line| line.contains(TIMING_SEPARATOR));
+            match timing_index {
+                Some(timing_index) => {
+                    let (start, end, settings) = parse_timing(lines[timing_index], line_number + timing_index)?;
+                    vtt.cues.push(WebVttCue {
+                        identifier: if timing_index == 1 { String::from(lines[0]) } else { String::new() },
+                        start,
+                        end,
+                        settings: String::from(settings),
+                        payload: lines[timing_index + 1..].join("\n"),
+                    });
+                },
+                None if vtt.cues.is_empty() => {
+                    vtt.header.push_str("\n\n");
+                    vtt.header.push_str(&lines.join("\n"));
+                },
+                None if lines[0].starts_with("NOTE") => {},
+                None => return Err(FormatError::InvalidData(format!("WebVTT block on line {line_number} is not a cue or comment"))),
+            }
+        }
+        Ok(vtt)
+    }
+}
+
+impl fmt::Display for WebVtt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.header.trim_end())?;
+        for cue in &self.cues {
+            writeln!(f)?;
+            if !cue.identifier.is_empty() {
+                writeln!(f, "{}", cue.identifier)?;
+            }
+            write!(f, "{} {TIMING_SEPARATOR} {}", format_timestamp(cue.start), format_timestamp(cue.end))?;
+            if !cue.settings.is_empty() {
+                write!(f, " {}", cue.settings)?;
+            }
+            writeln!(f)?;
+            if !cue.payload.is_empty() {
+                writeln!(f, "{}", cue.payload)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// Parses a WebVTT timestamp (`mm:ss.ttt` or `hh:mm:ss.ttt`) into milliseconds.
+///
+pub fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (time, millis) = timestamp.split_once('.')?;
+    let parts: Vec<&str> = time.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [minutes, seconds] => ("0", *minutes, *seconds),
+        [hours, minutes, seconds] if hours.len() >= 2 => (*hours, *minutes, *seconds),
+        _ => return None,
+    };
+
+    let number = |value: &str, digits: Option<usize>| -> Option<u64> {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) || digits.is_some_and(|d| d != value.len()) {
+            return None;
+        }
+        value.parse().ok()
+    };
+    let (hours, minutes, seconds, millis) = (number(hours, None)?, number(minutes, Some(2))?, number(seconds, Some(2))?, number(millis, Some(3))?);
+    if minutes > 59 || seconds > 59 {
+        return None;
+    }
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis)
+}
+
+///
+/// Formats milliseconds as a WebVTT timestamp, always including the hours.
+///
+pub fn format_timestamp(timestamp: u64) -> String {
+    format!("{:02}:{:02}:{:02}.{:03}", timestamp / 3_600_000, timestamp / 60_000 % 60, timestamp / 1000 % 60, timestamp % 1000)
+}
+
+fn parse_timing(line: &str, line_number: usize) -> Result<(u64, u64, &str), FormatError> {
+    let invalid = || FormatError::InvalidData(format!("Invalid WebVTT cue timing on line {line_number}"));
+    let (start, rest) = line.split_once(TIMING_SEPARATOR).ok_or_else(invalid)?;
+    let rest = rest.trim_start();
+    let (end, settings) = rest.split_once([' ', '\t']).unwrap_or((rest, ""));
+    let start = parse_timestamp(start.trim()).ok_or_else(invalid)?;
+    let end = parse_timestamp(end).ok_or_else(invalid)?;
+    Ok((start, end, settings.trim()))
+}
+
+///
+/// Writes a "D_WEBVTT/*" track from WebM data to a WebVTT file.
+///
+/// A cue without a "BlockDuration" ends when the next cue starts.  If the track has a "CodecPrivate" starting with `WEBVTT`, it is used as the file header.
+///
+/// # Errors
+///
+/// This function returns an error if the track isn't found or isn't a WebVTT track, if a cue isn't valid UTF-8, or if there is a problem reading the source or writing the destination.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use webm_iterable::formats::webvtt;
+///
+/// let source = File::open("captioned.webm").unwrap();
+/// webvtt::extract(source, 3, File::create("captions.vtt").unwrap()).unwrap();
+/// ```
+///
+pub fn extract<R: Read, W: Write>(source: R, track: u64, dest: W) -> Result<W, FormatError> {
+    write_track(TrackReader::new(source, track)?, dest)
+}
+
+///
+/// Writes the cues from `reader`, restoring cue settings and identifiers from "BlockAdditional".
+///
+pub(crate) fn write_track<R: Read, W: Write>(reader: TrackReader<R>, mut dest: W) -> Result<W, FormatError> {
+    if !CODEC_IDS.contains(&reader.codec_id()) {
+        return Err(FormatError::InvalidData(format!("{} tracks are not WebVTT", reader.codec_id())));
+    }
+
+    let mut vtt = WebVtt::default();
+    if let Some(header) = reader.track_entry().iter().find_map(|c| match c {
+        MatroskaSpec::CodecPrivate(data) => std::str::from_utf8(data).ok().filter(|text| text.starts_with(SIGNATURE)),
+        _ => None,
+    }) {
+        vtt.header = String::from(header);
+    }
+
+    for Cue { start, end, frame } in reader.read_cues()? {
+        let payload = String::from_utf8(frame.data)
+            .map_err(|_| FormatError::InvalidData(format!("WebVTT cue at {start}ms is not valid UTF-8")))?;
+        let mut cue = WebVttCue { start, end, payload, ..WebVttCue::default() };
+        if let Some(addition) = frame.additions.first() {
+            let addition = String::from_utf8_lossy(&addition.data);
+            let mut lines = addition.split('\n');
+            cue.settings = String::from(lines.next().unwrap_or("").trim());
+            cue.identifier = String::from(lines.next().unwrap_or(""));
+        }
+        vtt.cues.push(cue);
+    }
+
+    dest.write_all(vtt.to_string().as_bytes())?;
+    Ok(dest)
+}
+
+///
+/// Muxes a WebVTT file into a new WebM with a single text track, using the given "D_WEBVTT/*" codec ID.
+///
+/// Each cue is written as a "BlockGroup" with a "BlockDuration", in order of start time, with timestamps in milliseconds.  The file header (including any `STYLE` or `REGION` blocks) isn't stored, since WebM doesn't define a "CodecPrivate" for WebVTT tracks.
+///
+/// # Errors
+///
+/// This function returns an error if the codec ID isn't a WebVTT codec, if the source isn't valid UTF-8 WebVTT, or if there is a problem reading the source or writing the destination.
+///
+pub fn import<R: Read, W: Write>(mut source: R, codec_id: &str, dest: W) -> Result<W, FormatError> {
+    if !CODEC_IDS.contains(&codec_id) {
+        return Err(FormatError::InvalidData(format!("{codec_id} is not a WebVTT codec")));
+    }
+    let mut text = String::new();
+    source.read_to_string(&mut text)?;
+    let mut vtt = WebVtt::try_from(text.as_str())?;
+    vtt.cues.sort_by_key(|cue| cue.start);
+
+    // Subtitles and captions are subtitle tracks, while descriptions and metadata are metadata tracks
+    let track_type = if codec_id == CODEC_IDS[0] || codec_id == CODEC_IDS[1] { 0x11 } else { 0x21 };
+    let mut muxer = WebmMuxer::new(dest);
+    let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+        MatroskaSpec::TrackType(track_type),
+        MatroskaSpec::CodecID(String::from(codec_id)),
+    ])))?;
+
+    for cue in vtt.cues {
+        let mut frame = MuxFrame::new(track, cue.start as i64, cue.payload.into_bytes());
+        frame.duration = Some(cue.end.saturating_sub(cue.start));
+        if !cue.settings.is_empty() || !cue.identifier.is_empty() {
+            frame.additions.push(BlockAddition { id: 1, data: format!("{}\n{}", cue.settings, cue.identifier).into_bytes() });
+        }
+        muxer.write_frame(frame)?;
+    }
+    Ok(muxer.finalize()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{extract, import, parse_timestamp, WebVtt};
+    use crate::formats::TrackReader;
+    use crate::matroska_spec::MatroskaSpec;
+
+    const VTT: &str = "\u{feff}WEBVTT - captions\r\n\r\nSTYLE\r\n::cue { color: yellow }\r\n\r\n1\r\n00:00.500 --> 00:02.000 align:start line:0\r\nHello\r\nworld\r\n\r\nNOTE between cues\r\n\r\n01:00:03.250 --> 01:00:04.000\r\n<v Bob>Bye\r\n";
+
+    #[test]
+    fn parses_cues() {
+        let vtt = WebVtt::try_from(VTT).unwrap();
+        assert_eq!("WEBVTT - captions\n\nSTYLE\n::cue { color: yellow }", vtt.header);
+        assert_eq!(2, vtt.cues.len());
+        assert_eq!(("1", 500, 2000, "align:start line:0", "Hello\nworld"), (vtt.cues[0].identifier.as_str(), vtt.cues[0].start, vtt.cues[0].end, vtt.cues[0].settings.as_str(), vtt.cues[0].payload.as_str()));
+        assert_eq!((3_603_250, 3_604_000, ""), (vtt.cues[1].start, vtt.cues[1].end, vtt.cues[1].identifier.as_str()));
+        assert_eq!(vtt, WebVtt::try_from(vtt.to_string().as_str()).unwrap());
+
+        assert_eq!(None, parse_timestamp("0:00.500"));
+        assert_eq!(None, parse_timestamp("00:60.000"));
+        assert!(WebVtt::try_from("SRT\n\n00:00.000 --> 00:01.000\nHi").is_err());
+        match WebVtt::try_from("WEBVTT\n\n00:00.000 --> 00:01\nHi") {
+            Err(err) => assert!(err.to_string().contains("line 3")),
+            Ok(_) => panic!("Expected an invalid timing error"),
+        }
+    }
+
+    #[test]
+    fn round_trips_webm_track() {
+        let webm = import(VTT.as_bytes(), "D_WEBVTT/CAPTIONS", Vec::new()).unwrap();
+        let reader = TrackReader::new(webm.as_slice(), 1).unwrap();
+        assert!(reader.track_entry().contains(&MatroskaSpec::TrackType(0x11)));
+        let frames: Vec<_> = reader.map(|f| f.unwrap()).collect();
+        assert_eq!((500, Some(1500)), (frames[0].timestamp, frames[0].duration));
+        assert_eq!(b"Hello\nworld".to_vec(), frames[0].data);
+        assert_eq!(b"align:start line:0\n1".to_vec(), frames[0].additions[0].data);
+        assert!(frames[1].additions.is_empty());
+
+        let vtt = extract(webm.as_slice(), 1, Vec::new()).unwrap();
+        let vtt = WebVtt::try_from(String::from_utf8(vtt).unwrap().as_str()).unwrap();
+        assert_eq!(WebVtt::try_from(VTT).unwrap().cues, vtt.cues);
+        assert_eq!("WEBVTT", vtt.header);
+
+        assert!(import(VTT.as_bytes(), "S_TEXT/UTF8", Vec::new()).is_err());
+    }
+}
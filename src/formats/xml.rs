@@ -0,0 +1,365 @@
+//!
+//! A small XML reader and writer for the mkvtoolnix XML formats.
+//!
+//! Only what those formats need is supported: elements, attributes, text, CDATA, and the predefined and numeric character references.  The XML declaration, document type declaration, comments, and processing instructions are skipped when reading.
+//!
+
+use std::fmt;
+
+use crate::errors::FormatError;
+
+///
+/// An XML element with its attributes, text content, and child elements.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct XmlElement {
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<(String, String)>,
+
+    /// The text content of the element, including whitespace between child elements.
+    pub(crate) text: String,
+
+    pub(crate) children: Vec<XmlElement>,
+
+    /// The line the element starts on, for error messages.
+    pub(crate) line: usize,
+}
+
+impl XmlElement {
+    pub(crate) fn new(name: &str, children: Vec<XmlElement>) -> Self {
+        XmlElement { name: String::from(name), children, ..XmlElement::default() }
+    }
+
+    pub(crate) fn with_text(name: &str, text: impl Into<String>) -> Self {
+        XmlElement { name: String::from(name), text: text.into(), ..XmlElement::default() }
+    }
+
+    ///
+    /// An element holding binary data as hex, the format mkvtoolnix writes.
+    ///
+    pub(crate) fn with_binary(name: &str, data: &[u8]) -> Self {
+        let text = data.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(" ");
+        XmlElement { attributes: vec![(String::from("format"), String::from("hex"))], ..XmlElement::with_text(name, text) }
+    }
+
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    pub(crate) fn error(&self, message: &str) -> FormatError {
+        FormatError::InvalidData(format!("Invalid <{}> on line {}: {message}", self.name, self.line))
+    }
+
+    pub(crate) fn unexpected_child(&self, child: &XmlElement) -> FormatError {
+        FormatError::InvalidData(format!("Unexpected <{}> in <{}> on line {}", child.name, self.name, child.line))
+    }
+
+    pub(crate) fn to_u64(&self) -> Result<u64, FormatError> {
+        self.text.trim().parse::<u64>().map_err(|_| self.error(&format!("expected an unsigned integer, found {:?}", self.text.trim())))
+    }
+
+    ///
+    /// Reads binary data given as hex (the default), base64, or ascii, according to the `format` attribute.
+    ///
+    pub(crate) fn to_binary(&self) -> Result<Vec<u8>, FormatError> {
+        match self.attribute("format").unwrap_or("hex") {
+            "hex" => {
+                let digits: Vec<u8> = self.text.bytes().filter(|byte| !byte.is_ascii_whitespace()).collect();
+                if !digits.len().is_multiple_of(2) || !digits.iter().all(u8::is_ascii_hexdigit) {
+                    return Err(self.error("expected hex digits"));
+                }
+                Ok(digits.chunks(2).map(|pair| (hex_value(pair[0]) << 4) | hex_value(pair[1])).collect())
+            },
+            "base64" => decode_base64(&self.text).ok_or_else(|| self.error("expected base64 data")),
+            "ascii" => Ok(self.text.as_bytes().to_vec()),
+            format => Err(self.error(&format!("unknown binary format {format:?}"))),
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        write!(f, "{indent}<{}", self.name)?;
+        for (key, value) in &self.attributes {
+            write!(f, " {key}=\"{}\"", escape(value, true))?;
+        }
+        if self.children.is_empty() {
+            if self.text.is_empty() {
+                return writeln!(f, "/>");
+            }
+            return writeln!(f, ">{}</{}>", escape(&self.text, false), self.name);
+        }
+        writeln!(f, ">")?;
+        for child in &self.children {
+            child.write(f, depth + 1)?;
+        }
+        writeln!(f, "{indent}</{}>", self.name)
+    }
+}
+
+///
+/// Writes a complete document with an XML declaration and a document type declaration naming `dtd`, in the layout mkvextract uses.
+///
+pub(crate) fn write_document(f: &mut fmt::Formatter, dtd: &str, root: &XmlElement) -> fmt::Result {
+    writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(f)?;
+    writeln!(f, "<!-- <!DOCTYPE {} SYSTEM \"{dtd}\"> -->", root.name)?;
+    writeln!(f)?;
+    root.write(f, 0)
+}
+
+///
+/// Parses a document, returning its root element.
+///
+pub(crate) fn parse_document(text: &str) -> Result<XmlElement, FormatError> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut parser = Parser { text, position: 0, line: 1 };
+    parser.skip_misc()?;
+    if !parser.rest().starts_with('<') {
+        return Err(parser.error("expected the root element"));
+    }
+    let root = parser.parse_element()?;
+    parser.skip_misc()?;
+    if !parser.rest().is_empty() {
+        return Err(parser.error("unexpected content after the root element"));
+    }
+    Ok(root)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn error(&self, message: &str) -> FormatError {
+        FormatError::InvalidData(format!("Invalid XML on line {}: {message}", self.line))
+    }
+
+    fn advance(&mut self, length: usize) {
+        self.line += self.rest()[..length].matches('\n').count();
+        self.position += length;
+    }
+
+    fn skip_whitespace(&mut self) {
+        let length = self.rest().len() - self.rest().trim_start().len();
+        self.advance(length);
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<&'a str, FormatError> {
+        match self.rest().find(end) {
+            Some(index) => {
+                let skipped = &self.rest()[..index];
+                self.advance(index + end.len());
+                Ok(skipped)
+            },
+            None => Err(self.error(&format!("missing {end:?}"))),
+        }
+    }
+
+    ///
+    /// Skips whitespace, comments, processing instructions, and the document type declaration.
+    ///
+    fn skip_misc(&mut self) -> Result<(), FormatError> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!DOCTYPE") {
+                // The internal subset, if any, is in brackets and may itself contain '>'
+                match (rest.find('['), rest.find('>')) {
+                    (Some(open), Some(close)) if open < close => {
+                        self.skip_past("]")?;
+                        self.skip_past(">")?;
+                    },
+                    _ => {
+                        self.skip_past(">")?;
+                    },
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<&'a str, FormatError> {
+        let rest = self.rest();
+        let length = rest.find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '=')).unwrap_or(rest.len());
+        if length == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.advance(length);
+        Ok(&rest[..length])
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), FormatError> {
+        if !self.rest().starts_with(expected) {
+            return Err(self.error(&format!("expected {expected:?}")));
+        }
+        self.advance(expected.len());
+        Ok(())
+    }
+
+    fn parse_element(&mut self) -> Result<XmlElement, FormatError> {
+        let mut element = XmlElement { line: self.line, ..XmlElement::default() };
+        self.expect("<")?;
+        element.name = String::from(self.parse_name()?);
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.advance(2);
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.advance(1);
+                break;
+            }
+            let key = self.parse_name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.error(&format!("expected a quoted value for attribute {key:?}"))),
+            };
+            self.advance(1);
+            let value = self.skip_past(if quote == '"' { "\"" } else { "'" })?;
+            element.attributes.push((String::from(key), unescape(value).map_err(|message| self.error(&message))?));
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.advance(2);
+                let name = self.parse_name()?;
+                if name != element.name {
+                    return Err(self.error(&format!("expected </{}>, found </{name}>", element.name)));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.advance(9);
+                let data = self.skip_past("]]>")?;
+                element.text.push_str(data);
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') {
+                element.children.push(self.parse_element()?);
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("missing </{}>", element.name)));
+            } else {
+                let length = rest.find('<').unwrap_or(rest.len());
+                let text = unescape(&rest[..length]).map_err(|message| self.error(&message))?;
+                element.text.push_str(&text);
+                self.advance(length);
+            }
+        }
+    }
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        let end = rest[start..].find(';').ok_or_else(|| String::from("unterminated character reference"))? + start;
+        let reference = &rest[start + 1..end];
+        let c = match reference {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = reference.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(decimal) = reference.strip_prefix('#') {
+                    decimal.parse::<u32>().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32).ok_or_else(|| format!("unknown character reference &{reference};"))?
+            },
+        };
+        unescaped.push(c);
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+fn escape(text: &str, attribute: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attribute => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in text.bytes().filter(|byte| !byte.is_ascii_whitespace() && *byte != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buffer >> bits) as u8);
+        }
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_document;
+
+    #[test]
+    fn parses_elements() {
+        let root = parse_document("<?xml version=\"1.0\"?>\n<!DOCTYPE Tags SYSTEM \"matroskatags.dtd\">\n<!-- comment -->\n<Tags>\n  <Tag a='1 &amp; 2'>\n    <String>R&#x26;B &lt;live&gt;<![CDATA[ & more]]></String>\n    <Binary format=\"base64\">AQID</Binary>\n    <Empty/>\n  </Tag>\n</Tags>\n").unwrap();
+        assert_eq!("Tags", root.name);
+        let tag = &root.children[0];
+        assert_eq!((Some("1 & 2"), 5), (tag.attribute("a"), tag.line));
+        assert_eq!("R&B <live> & more", tag.children[0].text);
+        assert_eq!(vec![1, 2, 3], tag.children[1].to_binary().unwrap());
+        assert_eq!("Empty", tag.children[2].name);
+
+        assert!(parse_document("<Tags><Tag></Tags>").is_err());
+        assert!(parse_document("<Tags>&bogus;</Tags>").is_err());
+        assert!(parse_document("<Tags/><Tags/>").is_err());
+    }
+}
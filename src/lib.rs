@@ -1,7 +1,7 @@
 //!
 //! This crate was built to ease parsing files encoded in a Matroska container, such as [WebMs][webm] or [MKVs][mkv].
 //!
-//! The main content provided by this crate is the [`MatroskaSpec`] enum.  Otherwise, this crate simply provides type aliases in the form of [`WebmIterator`] and [`WebmWriter`].
+//! The main content provided by this crate is the [`MatroskaSpec`] enum.  Otherwise, this crate simply provides type aliases in the form of [`WebmIterator`] and [`WebmWriter`], along with a frame-level [`WebmMuxer`][muxer::WebmMuxer] for writing encoded frames into new files.  With the `futures` feature enabled, `WebmIteratorAsync`, `WebmWriterAsync`, and `WebmMuxerAsync` provide the same functionality on top of the [futures](https://crates.io/crates/futures) async io traits.
 //! 
 //! [webm]: https://www.webmproject.org/
 //! [mkv]: http://www.matroska.org/technical/specs/index.html
@@ -135,8 +135,16 @@ use ebml_iterable::nonblocking::TagIteratorAsync;
 
 pub use ebml_iterable::iterator;
 pub use ebml_iterable::WriteOptions;
+pub mod codecs;
 pub mod errors;
+pub mod formats;
 pub mod matroska_spec;
+pub mod muxer;
+#[cfg(feature = "futures")]
+mod nonblocking;
+
+#[cfg(feature = "futures")]
+pub use nonblocking::WebmWriterAsync;
 
 use matroska_spec::MatroskaSpec;
 
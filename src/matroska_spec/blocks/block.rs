@@ -3,7 +3,7 @@ use ebml_iterable::tools::{self as ebml_tools, Vint};
 
 use crate::errors::WebmCoercionError;
 use crate::MatroskaSpec;
-use super::block_utils::{read_frame_data, write_frame_data};
+use super::block_utils::{lacing_sizes, read_frame_data, write_frame_data};
 
 ///
 /// An enum describing different block lacing options.
@@ -17,6 +17,47 @@ pub enum BlockLacing {
     FixedSize,
 }
 
+impl BlockLacing {
+    ///
+    /// Returns the number of bytes this lacing adds to a block containing the given frames.
+    ///
+    /// This includes the frame count byte and any frame sizes stored ahead of the frame data.  Returns `None` if the lacing cannot be used for the frames: there must be between 1 and 256 frames, and [`BlockLacing::FixedSize`] requires every frame to be the same length.
+    ///
+    pub fn overhead(&self, frames: &[Frame]) -> Option<usize> {
+        if frames.is_empty() || frames.len() > 256 {
+            return None;
+        }
+        lacing_sizes(frames, *self).map(|sizes| 1 + sizes.len())
+    }
+
+    ///
+    /// Picks the lacing with the smallest overhead for the given frames.
+    ///
+    /// Returns `None` if there are fewer than 2 frames (lacing doesn't apply) or more than 256 frames (too many for a single block).
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use webm_iterable::matroska_spec::{BlockLacing, Frame};
+    ///
+    /// let same_size = vec![Frame { data: &[0x01, 0x02] }, Frame { data: &[0x03, 0x04] }];
+    /// assert_eq!(Some(BlockLacing::FixedSize), BlockLacing::smallest_for(&same_size));
+    ///
+    /// let different_size = vec![Frame { data: &[0x01, 0x02] }, Frame { data: &[0x03] }];
+    /// assert_eq!(Some(BlockLacing::Xiph), BlockLacing::smallest_for(&different_size));
+    /// ```
+    ///
+    pub fn smallest_for(frames: &[Frame]) -> Option<BlockLacing> {
+        if frames.len() < 2 {
+            return None;
+        }
+        [BlockLacing::FixedSize, BlockLacing::Xiph, BlockLacing::Ebml].iter()
+            .filter_map(|lacing| lacing.overhead(frames).map(|overhead| (*lacing, overhead)))
+            .min_by_key(|(_, overhead)| *overhead)
+            .map(|(lacing, _)| lacing)
+    }
+}
+
 ///
 /// A single frame of data within a block.
 /// 
@@ -30,7 +71,7 @@ pub struct Frame<'a> {
 ///
 /// A typed interpretation of the Matroska "Block" element.
 ///
-/// This struct has fields specific to the [Block](https://www.matroska.org/technical/basics.html#block-structure) element as defined by the [Matroska Spec](http://www.matroska.org/technical/specs/index.html).  This struct implements `TryFrom<&MatroskaSpec>` and `Into<MatroskaSpec>` to simplify coercion to and from regular variants.
+/// This struct has fields specific to the [Block](https://www.matroska.org/technical/basics.html#block-structure) element as defined by the [Matroska Spec](http://www.matroska.org/technical/specs/index.html).  This struct implements `TryFrom<&MatroskaSpec>` and `TryInto<MatroskaSpec>` to simplify coercion to and from regular variants.
 ///
 /// ## Example
 ///
@@ -77,7 +118,7 @@ impl<'a> Block<'a> {
     /// 
     /// This method can return an error if the frame data is malformed.
     /// 
-    pub fn read_frame_data(&self) -> Result<Vec<Frame>, WebmCoercionError> {
+    pub fn read_frame_data(&self) -> Result<Vec<Frame<'_>>, WebmCoercionError> {
         read_frame_data(self.owned_frame_data.as_deref().unwrap_or(self.frame_data), &self.lacing)
     }
 
@@ -86,9 +127,7 @@ impl<'a> Block<'a> {
     /// 
     /// This method writes frame data to a newly allocated vector owned by the block.  Future calls to [`Self::read_frame_data()`] and [`Self::raw_frame_data()`] will use the data set via this method.
     /// 
-    /// # Panics
-    /// 
-    /// This method can panic if the block has its lacing set as ['BlockLacing::FixedSize`] and the input frames are not all the same length.
+    /// The lacing of the block is updated to match the data that was written.  A single frame is never laced, and [`BlockLacing::Ebml`] is used if there are several frames and the block has no lacing set, or if its lacing is [`BlockLacing::FixedSize`] and the frames are not all the same length.
     /// 
     pub fn set_frame_data(&mut self, frames: &Vec<Frame>) {
         let (data, new_lacing) = write_frame_data(frames, self.lacing);
@@ -179,15 +218,17 @@ impl<'a> TryFrom<&'a MatroskaSpec> for Block<'a> {
     }
 }
 
-impl From<Block<'_>> for MatroskaSpec {
-    fn from(block: Block) -> Self {
+impl TryFrom<Block<'_>> for MatroskaSpec {
+    type Error = WebmCoercionError;
+
+    fn try_from(block: Block) -> Result<Self, Self::Error> {
         let mut flags: u8 = 0x00;
         if block.invisible {
             flags |= 0x08;
         }
 
-        if block.lacing.is_some() {
-            match block.lacing.unwrap() {
+        if let Some(lacing) = block.lacing {
+            match lacing {
                 BlockLacing::Xiph => {
                     flags |= 0x02;
                 }
@@ -202,11 +243,13 @@ impl From<Block<'_>> for MatroskaSpec {
 
         let data = block.owned_frame_data.as_deref().unwrap_or(block.frame_data);
         let mut result = Vec::with_capacity(data.len() + 11);
-        result.extend_from_slice(&block.track.as_vint().expect("Unable to convert track value to vint"));
+        let track = block.track.as_vint()
+            .map_err(|_| WebmCoercionError::BlockCoercionError(format!("Track number {} is too large to write in a Block.", block.track)))?;
+        result.extend_from_slice(&track);
         result.extend_from_slice(&block.timestamp.to_be_bytes());
         result.extend_from_slice(&flags.to_be_bytes());
         result.extend_from_slice(data);
 
-        MatroskaSpec::Block(result)
+        Ok(MatroskaSpec::Block(result))
     }
 }
\ No newline at end of file
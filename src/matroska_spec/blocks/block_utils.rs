@@ -61,7 +61,7 @@ pub fn read_frame_data<'a>(frame_data: &'a [u8], lacing: &Option<BlockLacing>) -
             },
             BlockLacing::FixedSize => {
                 let total_size = frame_data.len() - 1;
-                if total_size % frame_count == 0 {
+                if total_size.is_multiple_of(frame_count) {
                     let frame_size = total_size / frame_count;
                     Ok((1usize, vec![frame_size; frame_count - 1]))
                 } else {
@@ -87,7 +87,7 @@ pub fn read_frame_data<'a>(frame_data: &'a [u8], lacing: &Option<BlockLacing>) -
 }
 
 #[inline(always)]
-pub fn write_frame_data(frames: &Vec<Frame>, mut desired_lacing: Option<BlockLacing>) -> (Vec<u8>, Option<BlockLacing>) {
+pub fn write_frame_data(frames: &[Frame], mut desired_lacing: Option<BlockLacing>) -> (Vec<u8>, Option<BlockLacing>) {
     if frames.len() == 1 {
         // If there is only 1 frame, lacing doesn't apply
        desired_lacing = None;
@@ -97,59 +97,10 @@ pub fn write_frame_data(frames: &Vec<Frame>, mut desired_lacing: Option<BlockLac
     }
 
     if let Some(lacing) = desired_lacing {
-        let sizes = match lacing {
-            BlockLacing::Xiph => {
-                let mut sizes: Vec<u8> = Vec::new();
-                for frame in &frames[..frames.len()-1] {
-                    sizes.resize(sizes.len() + frame.data.len()/255, 0xFF);
-                    sizes.push((frame.data.len()%255) as u8);
-                }
-                sizes
-            },
-            BlockLacing::Ebml => {
-                let mut last_size: Option<usize> = None;
-                let mut sizes: Vec<u8> = Vec::new();
-                for frame in &frames[..frames.len()-1] {
-                    let size = frame.data.len();
-                    let written_size = if let Some(last_size) = last_size {
-                        // Just like the issue in parsing EBML lacing, this writes the value in two's complement notation like the spec describes
-                        // let mut diff = (size as i64) - (last_size as i64);
-                        // if diff < 0 {
-                        //     let mut length: usize = 1;
-                        //     while length <= 8 {
-                        //         if diff > -(1 << ((7 * length) - 1)) {
-                        //             break;
-                        //         }
-                        //         length += 1;
-                        //     }
-                        //     diff &= (1 << (7 * length)) - 1;
-
-                        // }
-                        // diff as u64
-
-                        // But the spec example would be to just add half the range like this
-                        let diff = (size as i64) - (last_size as i64);
-                        let mut length: usize = 1;
-                        while length <= 8 {
-                            if diff > -(1 << ((7 * length) - 1)) && diff < (1 << ((7 * length) - 1)) {
-                                break;
-                            }
-                            length += 1;
-                        }
-                        (diff + (1 << ((7 * length) - 1)) - 1) as u64
-                    } else {
-                        size as u64
-                    };
-                    sizes.append(&mut written_size.as_vint().unwrap());
-                    last_size = Some(size);
-                }
-                sizes
-            },
-            BlockLacing::FixedSize => {
-                //FixedSize block lacing *cannot* be used with frames of different sizes
-                assert!(frames.iter().skip(1).all(|f| f.data.len() == frames[0].data.len()));
-                vec![]
-            }
+        // FixedSize lacing cannot be used with frames of different sizes, so fall back to Ebml lacing
+        let (sizes, lacing) = match lacing_sizes(frames, lacing) {
+            Some(sizes) => (sizes, lacing),
+            None => (lacing_sizes(frames, BlockLacing::Ebml).expect("Ebml lacing works for any non-empty frames"), BlockLacing::Ebml),
         };
 
         let mut payload: Vec<u8> = Vec::with_capacity(1 + sizes.len() + frames.iter().fold(0, |a, c| a + c.data.len()));
@@ -160,8 +111,75 @@ pub fn write_frame_data(frames: &Vec<Frame>, mut desired_lacing: Option<BlockLac
             payload.extend_from_slice(frame.data);
         }
 
-        (payload, desired_lacing)
+        (payload, Some(lacing))
     } else {
         (frames[0].data.to_vec(), desired_lacing)
     }
-}
\ No newline at end of file
+}
+
+///
+/// Computes the frame size header written after the frame count for the given lacing.
+///
+/// Returns `None` if there are no frames, or if the lacing cannot be used for the frames ([`BlockLacing::FixedSize`] with frames of different sizes).
+///
+pub fn lacing_sizes(frames: &[Frame], lacing: BlockLacing) -> Option<Vec<u8>> {
+    if frames.is_empty() {
+        return None;
+    }
+    Some(match lacing {
+        BlockLacing::Xiph => {
+            let mut sizes: Vec<u8> = Vec::new();
+            for frame in &frames[..frames.len()-1] {
+                sizes.resize(sizes.len() + frame.data.len()/255, 0xFF);
+                sizes.push((frame.data.len()%255) as u8);
+            }
+            sizes
+        },
+        BlockLacing::Ebml => {
+            let mut last_size: Option<usize> = None;
+            let mut sizes: Vec<u8> = Vec::new();
+            for frame in &frames[..frames.len()-1] {
+                let size = frame.data.len();
+                let written_size = if let Some(last_size) = last_size {
+                    // Just like the issue in parsing EBML lacing, this writes the value in two's complement notation like the spec describes
+                    // let mut diff = (size as i64) - (last_size as i64);
+                    // if diff < 0 {
+                    //     let mut length: usize = 1;
+                    //     while length <= 8 {
+                    //         if diff > -(1 << ((7 * length) - 1)) {
+                    //             break;
+                    //         }
+                    //         length += 1;
+                    //     }
+                    //     diff &= (1 << (7 * length)) - 1;
+
+                    // }
+                    // diff as u64
+
+                    // But the spec example would be to just add half the range like this
+                    let diff = (size as i64) - (last_size as i64);
+                    let mut length: usize = 1;
+                    while length <= 8 {
+                        if diff > -(1 << ((7 * length) - 1)) && diff < (1 << ((7 * length) - 1)) {
+                            break;
+                        }
+                        length += 1;
+                    }
+                    (diff + (1 << ((7 * length) - 1)) - 1) as u64
+                } else {
+                    size as u64
+                };
+                sizes.append(&mut written_size.as_vint().unwrap());
+                last_size = Some(size);
+            }
+            sizes
+        },
+        BlockLacing::FixedSize => {
+            //FixedSize block lacing *cannot* be used with frames of different sizes
+            if !frames.iter().skip(1).all(|f| f.data.len() == frames[0].data.len()) {
+                return None;
+            }
+            vec![]
+        }
+    })
+}
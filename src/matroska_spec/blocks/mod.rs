@@ -1,4 +1,4 @@
 pub mod block;
 pub mod simple_block;
 
-mod block_utils;
\ No newline at end of file
+pub(crate) mod block_utils;
\ No newline at end of file
@@ -9,7 +9,7 @@ use super::block_utils::{read_frame_data, write_frame_data};
 ///
 /// A typed interpretation of the Matroska "SimpleBlock" element.
 /// 
-/// This struct has fields specific to the [SimpleBlock](https://www.matroska.org/technical/basics.html#simpleblock-structure) element as defined by the [Matroska Spec](http://www.matroska.org/technical/specs/index.html).  This struct implements `TryFrom<&MatroskaSpec>` and `Into<MatroskaSpec>` to simplify coercion to and from regular enum variants.
+/// This struct has fields specific to the [SimpleBlock](https://www.matroska.org/technical/basics.html#simpleblock-structure) element as defined by the [Matroska Spec](http://www.matroska.org/technical/specs/index.html).  This struct implements `TryFrom<&MatroskaSpec>` and `TryInto<MatroskaSpec>` to simplify coercion to and from regular enum variants.
 /// 
 /// ## Example
 /// 
@@ -58,7 +58,7 @@ impl<'a> SimpleBlock<'a> {
     /// 
     /// This method can return an error if the frame data is malformed.
     /// 
-    pub fn read_frame_data(&self) -> Result<Vec<Frame>, WebmCoercionError> {
+    pub fn read_frame_data(&self) -> Result<Vec<Frame<'_>>, WebmCoercionError> {
         read_frame_data(self.owned_frame_data.as_deref().unwrap_or(self.frame_data), &self.lacing)
     }
 
@@ -67,9 +67,7 @@ impl<'a> SimpleBlock<'a> {
     /// 
     /// This method writes frame data to a newly allocated vector owned by the block.  Future calls to [`Self::read_frame_data()`] and [`Self::raw_frame_data()`] will use the data set via this method.
     /// 
-    /// # Panics
-    /// 
-    /// This method can panic if the block has its lacing set as ['BlockLacing::FixedSize`] and the input frames are not all the same length.
+    /// The lacing of the block is updated to match the data that was written.  A single frame is never laced, and [`BlockLacing::Ebml`] is used if there are several frames and the block has no lacing set, or if its lacing is [`BlockLacing::FixedSize`] and the frames are not all the same length.
     /// 
     pub fn set_frame_data(&mut self, frames: &Vec<Frame>) {
         let (data, new_lacing) = write_frame_data(frames, self.lacing);
@@ -151,15 +149,17 @@ impl<'a> TryFrom<&'a MatroskaSpec> for SimpleBlock<'a> {
     }
 }
 
-impl From<SimpleBlock<'_>> for MatroskaSpec {
-    fn from(simple_block: SimpleBlock) -> Self {        
+impl TryFrom<SimpleBlock<'_>> for MatroskaSpec {
+    type Error = WebmCoercionError;
+
+    fn try_from(simple_block: SimpleBlock) -> Result<Self, Self::Error> {        
         let mut flags: u8 = 0x00;
         if simple_block.invisible {
           flags |= 0x08;
         }
         
-        if simple_block.lacing.is_some() {
-          match simple_block.lacing.unwrap() {
+        if let Some(lacing) = simple_block.lacing {
+          match lacing {
             BlockLacing::Xiph => { flags |= 0x02; },
             BlockLacing::Ebml => { flags |= 0x06; },
             BlockLacing::FixedSize => { flags |= 0x04; },
@@ -176,12 +176,14 @@ impl From<SimpleBlock<'_>> for MatroskaSpec {
 
         let data = simple_block.owned_frame_data.as_deref().unwrap_or(simple_block.frame_data);
         let mut result = Vec::with_capacity(data.len() + 11);
-        result.extend_from_slice(&simple_block.track.as_vint().expect("Unable to convert track value to vint"));
+        let track = simple_block.track.as_vint()
+            .map_err(|_| WebmCoercionError::SimpleBlockCoercionError(format!("Track number {} is too large to write in a SimpleBlock.", simple_block.track)))?;
+        result.extend_from_slice(&track);
         result.extend_from_slice(&simple_block.timestamp.to_be_bytes());
         result.extend_from_slice(&flags.to_be_bytes());
         result.extend_from_slice(data);
 
-        MatroskaSpec::SimpleBlock(result)
+        Ok(MatroskaSpec::SimpleBlock(result))
     }
 }
 
@@ -208,7 +210,7 @@ mod tests {
         assert_eq!(1, simple_block.timestamp);
         assert_eq!(2, simple_block.read_frame_data().unwrap().len());
 
-        let encoded: MatroskaSpec = simple_block.into();
+        let encoded = MatroskaSpec::try_from(simple_block).unwrap();
 
         match encoded {
             MatroskaSpec::SimpleBlock(data) => {
@@ -233,7 +235,7 @@ mod tests {
         };
         simple_block.set_frame_data(&frames);
 
-        let encoded: MatroskaSpec = simple_block.clone().into();
+        let encoded = MatroskaSpec::try_from(simple_block.clone()).unwrap();
         let redecoded = SimpleBlock::try_from(&encoded).unwrap();
 
         assert_eq!(simple_block.keyframe, redecoded.keyframe);
@@ -263,7 +265,7 @@ mod tests {
         };
         simple_block.set_frame_data(&frames);
 
-        let encoded: MatroskaSpec = simple_block.clone().into();
+        let encoded = MatroskaSpec::try_from(simple_block.clone()).unwrap();
         let redecoded = SimpleBlock::try_from(&encoded).unwrap();
 
         assert_eq!(simple_block.keyframe, redecoded.keyframe);
@@ -293,7 +295,7 @@ mod tests {
         };
         simple_block.set_frame_data(&frames);
 
-        let encoded: MatroskaSpec = simple_block.clone().into();
+        let encoded = MatroskaSpec::try_from(simple_block.clone()).unwrap();
         let redecoded = SimpleBlock::try_from(&encoded).unwrap();
 
         assert_eq!(simple_block.keyframe, redecoded.keyframe);
@@ -323,7 +325,7 @@ mod tests {
         };
         simple_block.set_frame_data(&frames);
 
-        let encoded: MatroskaSpec = simple_block.clone().into();
+        let encoded = MatroskaSpec::try_from(simple_block.clone()).unwrap();
         let redecoded = SimpleBlock::try_from(&encoded).unwrap();
 
         assert_eq!(simple_block.keyframe, redecoded.keyframe);
@@ -337,4 +339,30 @@ mod tests {
             assert_eq!(frames[i].data, redecoded_data[i].data);
         }
     }
+
+    #[test]
+    fn encode_rejects_oversized_track() {
+        let simple_block = SimpleBlock::new_uncheked(&[0x01], u64::MAX, 0, false, None, false, true);
+        assert!(MatroskaSpec::try_from(simple_block).is_err());
+    }
+
+    #[test]
+    fn fixed_lacing_falls_back_for_mismatched_frames() {
+        let frames = vec![Frame { data: &[0x01, 0x02] }, Frame { data: &[0x03] }];
+        let mut simple_block = SimpleBlock::new_uncheked(&[], 1, 0, false, Some(BlockLacing::FixedSize), false, true);
+        simple_block.set_frame_data(&frames);
+        assert_eq!(Some(BlockLacing::Ebml), simple_block.lacing);
+
+        let encoded = MatroskaSpec::try_from(simple_block).unwrap();
+        let redecoded = SimpleBlock::try_from(&encoded).unwrap();
+        let redecoded_data = redecoded.read_frame_data().unwrap();
+        assert_eq!(vec![&[0x01, 0x02][..], &[0x03][..]], redecoded_data.iter().map(|f| f.data).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn no_lacing_sizes_without_frames() {
+        for lacing in [BlockLacing::Xiph, BlockLacing::Ebml, BlockLacing::FixedSize] {
+            assert_eq!(None, super::super::block_utils::lacing_sizes(&[], lacing));
+        }
+    }
 }
\ No newline at end of file
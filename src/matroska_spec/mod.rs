@@ -1,13 +1,16 @@
 //!
 //! Provides the [`MatroskaSpec`] enum, which implements [`EbmlSpecification`] and [`EbmlTag`].
 //!
-//! This is used in conjuction with the [ebml_iterable](https://crates.io/crates/ebml_iterable) library to be able to read and write Matroska formatted files based on raw tag data. Additionally, this module provides the [`Block`] and [`SimpleBlock`] structs, which provide an easy way to work with block data.  These can easily be converted to and from the regular enum variants using `into()` and `try_from()` to make working with the iterator stream easier.
+//! This is used in conjuction with the [ebml_iterable](https://crates.io/crates/ebml_iterable) library to be able to read and write Matroska formatted files based on raw tag data. Additionally, this module provides the [`Block`] and [`SimpleBlock`] structs, which provide an easy way to work with block data.  These can easily be converted to and from the regular enum variants using [`TryFrom`](std::convert::TryFrom) and [`TryInto`](std::convert::TryInto) to make working with the iterator stream easier.  When writing blocks by hand, the [`ClusterTimestamps`] helper converts absolute timestamps into the cluster and block timestamps needed to write them, and [`relative_block_timestamp()`] checks that a block timestamp fits relative to its cluster.
 //!
 
 mod blocks;
+mod timestamps;
 
 pub use blocks::block::{Block, BlockLacing, Frame};
 pub use blocks::simple_block::SimpleBlock;
+pub use timestamps::{ClusterTimestamps, relative_block_timestamp};
+pub(crate) use blocks::block_utils::{lacing_sizes, read_frame_data};
 
 pub use ebml_iterable::specs::{EbmlSpecification, EbmlTag, Master, TagDataType};
 use ebml_iterable::specs::easy_ebml;
@@ -0,0 +1,174 @@
+use std::convert::TryFrom;
+
+use crate::errors::WebmCoercionError;
+
+///
+/// Computes the timestamp of a block relative to its cluster.
+///
+/// Both timestamps are in units of the segment "TimestampScale".  Block timestamps are stored as a signed 16 bit value, so the result may be negative (for example, when B-frames are reordered to appear before the start of their cluster) but must fit in an [`i16`].
+///
+/// # Errors
+///
+/// This function returns an error rather than wrapping if the relative timestamp doesn't fit in an [`i16`].
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::matroska_spec::relative_block_timestamp;
+///
+/// assert_eq!(-40, relative_block_timestamp(1000, 960).unwrap());
+/// assert!(relative_block_timestamp(1000, 40000).is_err());
+/// ```
+///
+pub fn relative_block_timestamp(cluster_timestamp: u64, timestamp: i64) -> Result<i16, WebmCoercionError> {
+    let relative = timestamp as i128 - cluster_timestamp as i128;
+    i16::try_from(relative).map_err(|_| WebmCoercionError::TimestampCoercionError(format!(
+        "Timestamp {timestamp} is {relative} away from cluster timestamp {cluster_timestamp}, outside the block timestamp range of {} to {}", i16::MIN, i16::MAX
+    )))
+}
+
+///
+/// Rebases absolute timestamps onto clusters.
+///
+/// Each absolute timestamp (in nanoseconds) is converted into the "TimestampScale" units used in a file and paired with the timestamp of the cluster it belongs to, along with the block timestamp relative to that cluster.  A new cluster is started whenever a timestamp is too far past the current cluster to be stored relative to it.  Timestamps slightly before the current cluster (such as reordered B-frames) are stored as negative relative timestamps.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::matroska_spec::ClusterTimestamps;
+///
+/// let mut timestamps = ClusterTimestamps::new(1_000_000);
+/// assert_eq!((0, 0), timestamps.rebase(0).unwrap());
+/// assert_eq!((0, 1000), timestamps.rebase(1_000_000_000).unwrap());
+/// assert_eq!((40000, 0), timestamps.rebase(40_000_000_000).unwrap());
+/// assert_eq!((40000, -33), timestamps.rebase(39_967_000_000).unwrap());
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct ClusterTimestamps {
+    timestamp_scale: u64,
+    cluster_timestamp: Option<u64>,
+}
+
+impl ClusterTimestamps {
+    ///
+    /// Creates a new instance for a segment using the given "TimestampScale" (in nanoseconds).
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `timestamp_scale` is 0.
+    ///
+    pub fn new(timestamp_scale: u64) -> Self {
+        assert!(timestamp_scale > 0, "TimestampScale must be greater than 0");
+        ClusterTimestamps {
+            timestamp_scale,
+            cluster_timestamp: None,
+        }
+    }
+
+    ///
+    /// The "TimestampScale" used to convert timestamps.
+    ///
+    pub fn timestamp_scale(&self) -> u64 {
+        self.timestamp_scale
+    }
+
+    ///
+    /// The timestamp of the current cluster, in "TimestampScale" units, if a cluster has been started.
+    ///
+    pub fn cluster_timestamp(&self) -> Option<u64> {
+        self.cluster_timestamp
+    }
+
+    ///
+    /// Converts an absolute timestamp in nanoseconds into "TimestampScale" units, rounding to the nearest unit.
+    ///
+    pub fn scale(&self, timestamp: i64) -> i64 {
+        let scale = self.timestamp_scale as i128;
+        let timestamp = timestamp as i128;
+        let rounded = if timestamp < 0 { (timestamp - scale / 2) / scale } else { (timestamp + scale / 2) / scale };
+        rounded as i64
+    }
+
+    ///
+    /// Starts a new cluster at the given absolute timestamp (in nanoseconds) and returns the cluster timestamp in "TimestampScale" units.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the timestamp is negative, since cluster timestamps are unsigned.
+    ///
+    pub fn start_cluster(&mut self, timestamp: i64) -> Result<u64, WebmCoercionError> {
+        let scaled = self.scale(timestamp);
+        let cluster_timestamp = u64::try_from(scaled)
+            .map_err(|_| WebmCoercionError::TimestampCoercionError(format!("Cannot start a cluster at negative timestamp {scaled}")))?;
+        self.cluster_timestamp = Some(cluster_timestamp);
+        Ok(cluster_timestamp)
+    }
+
+    ///
+    /// Computes the block timestamp for an absolute timestamp (in nanoseconds) relative to the current cluster, without starting a new cluster.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if no cluster has been started, or if the relative timestamp doesn't fit in an [`i16`].
+    ///
+    pub fn relative(&self, timestamp: i64) -> Result<i16, WebmCoercionError> {
+        let cluster_timestamp = self.cluster_timestamp
+            .ok_or_else(|| WebmCoercionError::TimestampCoercionError(String::from("No cluster has been started")))?;
+        relative_block_timestamp(cluster_timestamp, self.scale(timestamp))
+    }
+
+    ///
+    /// Rebases an absolute timestamp (in nanoseconds) onto a cluster, returning the `(cluster_timestamp, relative_timestamp)` pair to write.
+    ///
+    /// A new cluster is started at the timestamp if there is no current cluster, or if the timestamp is too far past the current cluster to be stored relative to it.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if a new cluster would be needed for a negative timestamp, or if the timestamp is too far before the current cluster to be stored relative to it.
+    ///
+    pub fn rebase(&mut self, timestamp: i64) -> Result<(u64, i16), WebmCoercionError> {
+        if let Some(cluster_timestamp) = self.cluster_timestamp {
+            let scaled = self.scale(timestamp);
+            match relative_block_timestamp(cluster_timestamp, scaled) {
+                Ok(relative) => return Ok((cluster_timestamp, relative)),
+                Err(err) if scaled < cluster_timestamp as i64 => return Err(err),
+                Err(_) => {},
+            }
+        }
+
+        Ok((self.start_cluster(timestamp)?, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClusterTimestamps, relative_block_timestamp};
+
+    #[test]
+    fn relative_timestamps_stay_in_range() {
+        assert_eq!(i16::MAX, relative_block_timestamp(0, i16::MAX as i64).unwrap());
+        assert_eq!(i16::MIN, relative_block_timestamp(40000, 40000 + i16::MIN as i64).unwrap());
+        assert!(relative_block_timestamp(0, i16::MAX as i64 + 1).is_err());
+        assert!(relative_block_timestamp(u64::MAX, 0).is_err());
+    }
+
+    #[test]
+    fn rebase_uses_timestamp_scale() {
+        let mut timestamps = ClusterTimestamps::new(100_000);
+        assert_eq!((10, 0), timestamps.rebase(1_000_000).unwrap());
+        assert_eq!((10, 32767), timestamps.rebase(3_277_700_000).unwrap());
+        assert_eq!((32778, 0), timestamps.rebase(3_277_800_000).unwrap());
+        assert_eq!(Some(32778), timestamps.cluster_timestamp());
+    }
+
+    #[test]
+    fn rebase_allows_small_negative_offsets() {
+        let mut timestamps = ClusterTimestamps::new(1_000_000);
+        assert!(timestamps.rebase(-5_000_000).is_err());
+        assert_eq!(5000, timestamps.start_cluster(5_000_000_000).unwrap());
+        assert_eq!((5000, -5000), timestamps.rebase(0).unwrap());
+        assert!(timestamps.rebase(-30_000_000_000).is_err());
+        assert_eq!(Some(5000), timestamps.cluster_timestamp());
+    }
+}
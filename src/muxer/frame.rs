@@ -0,0 +1,144 @@
+use std::convert::TryInto;
+
+use crate::errors::{MuxerError, WebmCoercionError};
+use crate::matroska_spec::{Block, BlockLacing, Frame, Master, MatroskaSpec, SimpleBlock};
+
+///
+/// A single frame of data to be written by a [`WebmMuxer`][super::WebmMuxer].
+///
+/// Rather than building [`Block`] or [`SimpleBlock`] structs by hand, callers describe the properties of a frame and the muxer decides how it needs to be stored.  Frames that only need the information available in a [SimpleBlock](https://www.matroska.org/technical/basics.html#simpleblock-structure) are written as the more compact "SimpleBlock" element.  Frames that need a duration, discard padding, block additions, or a reference to a previous frame are written as a "BlockGroup" containing a "Block".
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::muxer::MuxFrame;
+///
+/// let mut frame = MuxFrame::new(3, 1500, vec![0x01, 0x02, 0x03]);
+/// frame.duration = Some(2000);
+/// assert!(frame.keyframe);
+/// ```
+///
+#[derive(Clone, Debug, Default)]
+pub struct MuxFrame {
+    /// The number of the track this frame belongs to.
+    pub track: u64,
+
+    /// The absolute timestamp of the frame, in units of the segment "TimestampScale".
+    pub timestamp: i64,
+
+    /// The encoded frame data.
+    pub data: Vec<u8>,
+
+    /// Whether the frame can be decoded without referencing any other frame.
+    pub keyframe: bool,
+
+    /// Whether the frame should be decoded but not displayed.
+    pub invisible: bool,
+
+    /// Whether the frame can be discarded during playback if needed.  This is only stored when the frame is written as a "SimpleBlock".
+    pub discardable: bool,
+
+    /// The duration of the frame, in units of the segment "TimestampScale".  Written as "BlockDuration" (required for subtitle frames).
+    pub duration: Option<u64>,
+
+    /// The duration of trailing audio samples to discard, in nanoseconds.  Written as "DiscardPadding".
+    pub discard_padding: Option<i64>,
+
+    /// Additional data attached to the frame (for example, VP9 alpha channel data or HDR10+ metadata).  Written as "BlockAdditions".
+    pub additions: Vec<BlockAddition>,
+}
+
+impl MuxFrame {
+    ///
+    /// Creates a new keyframe with the given data.
+    ///
+    /// All other properties are left unset, which means the frame will be written as a "SimpleBlock" unless they are changed.  Set `keyframe` to `false` for frames that depend on previous frames.
+    ///
+    pub fn new(track: u64, timestamp: i64, data: Vec<u8>) -> Self {
+        MuxFrame {
+            track,
+            timestamp,
+            data,
+            keyframe: true,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Whether the frame has properties that can only be stored in a "BlockGroup".
+    ///
+    pub fn needs_block_group(&self) -> bool {
+        self.duration.is_some() || self.discard_padding.is_some() || !self.additions.is_empty()
+    }
+}
+
+///
+/// Additional data attached to a frame, stored in the "BlockMore" element of a "BlockGroup".
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockAddition {
+    /// The "BlockAddID" identifying how the data should be interpreted.  The meaning of each id is defined in the "BlockAdditionMapping" of the track.
+    pub id: u64,
+
+    /// The "BlockAdditional" data.
+    pub data: Vec<u8>,
+}
+
+///
+/// Builds the tag used to store a frame.
+///
+/// `reference` is the timestamp of the previous frame in the track relative to this frame.  It is only written for non-keyframes stored in a "BlockGroup", where the lack of a "ReferenceBlock" would otherwise mark the frame as a keyframe.  A non-keyframe with no previous frame (such as the leading frames of an open GOP) has nothing valid to reference, so it is written without a "ReferenceBlock".
+///
+pub(crate) fn build_block_tag(frame: &MuxFrame, relative_timestamp: i16, reference: Option<i64>, simple_blocks: bool) -> Result<MatroskaSpec, MuxerError> {
+    if simple_blocks && !frame.needs_block_group() {
+        return block_tag(SimpleBlock::new_uncheked(&frame.data, frame.track, relative_timestamp, frame.invisible, None, frame.discardable, frame.keyframe));
+    }
+
+    let mut children = vec![block_tag(Block::new_uncheked(frame.track, relative_timestamp, frame.invisible, None, &frame.data))?];
+
+    if !frame.additions.is_empty() {
+        let block_more = frame.additions.iter().map(|addition| MatroskaSpec::BlockMore(Master::Full(vec![
+            MatroskaSpec::BlockAddID(addition.id),
+            MatroskaSpec::BlockAdditional(addition.data.clone()),
+        ]))).collect();
+        children.push(MatroskaSpec::BlockAdditions(Master::Full(block_more)));
+    }
+
+    if let Some(duration) = frame.duration {
+        children.push(MatroskaSpec::BlockDuration(duration));
+    }
+
+    if let (false, Some(reference)) = (frame.keyframe, reference) {
+        children.push(MatroskaSpec::ReferenceBlock(reference));
+    }
+
+    if let Some(padding) = frame.discard_padding {
+        children.push(MatroskaSpec::DiscardPadding(padding));
+    }
+
+    Ok(MatroskaSpec::BlockGroup(Master::Full(children)))
+}
+
+///
+/// Builds the tag used to store several laced frames in a single block.
+///
+/// Laced frames are always keyframes with no "BlockGroup" properties, so this writes a "SimpleBlock" unless the muxer is limited to "BlockGroup" elements.
+///
+pub(crate) fn build_laced_block_tag(frames: &[MuxFrame], relative_timestamp: i16, lacing: BlockLacing, simple_blocks: bool) -> Result<MatroskaSpec, MuxerError> {
+    let laced: Vec<Frame> = frames.iter().map(|f| Frame { data: &f.data }).collect();
+
+    if simple_blocks {
+        let discardable = frames.iter().all(|f| f.discardable);
+        let mut block = SimpleBlock::new_uncheked(&[], frames[0].track, relative_timestamp, false, Some(lacing), discardable, true);
+        block.set_frame_data(&laced);
+        block_tag(block)
+    } else {
+        let mut block = Block::new_uncheked(frames[0].track, relative_timestamp, false, Some(lacing), &[]);
+        block.set_frame_data(&laced);
+        Ok(MatroskaSpec::BlockGroup(Master::Full(vec![block_tag(block)?])))
+    }
+}
+
+fn block_tag<B: TryInto<MatroskaSpec, Error = WebmCoercionError>>(block: B) -> Result<MatroskaSpec, MuxerError> {
+    block.try_into().map_err(|err| MuxerError::FrameError(err.to_string()))
+}
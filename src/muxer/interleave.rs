@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+
+use super::MuxFrame;
+
+///
+/// A report that a track stopped producing frames while the muxer was interleaving.
+///
+/// A stall is reported once when frames from other tracks had to be written without waiting for the stalled track, because the [`MuxerOptions::max_interleave_delay`][super::MuxerOptions::max_interleave_delay] was exceeded.  The track is considered stalled until it produces another frame.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackStall {
+    /// The number of the stalled track.
+    pub track: u64,
+
+    /// The timestamp of the last frame received from the track, if any.
+    pub last_timestamp: Option<i64>,
+
+    /// The timestamp of the frame that was written without waiting for the track.
+    pub skipped_to: i64,
+}
+
+struct QueuedTrack {
+    number: u64,
+    frames: VecDeque<MuxFrame>,
+    last_timestamp: Option<i64>,
+    finished: bool,
+    stalled: bool,
+}
+
+///
+/// Buffers frames from multiple tracks so they can be written in timestamp order.
+///
+/// A frame is released once every unfinished track has a frame buffered (so no earlier frame can still arrive), or once it is more than `max_delay` older than the newest frame received.
+///
+pub(crate) struct InterleaveQueue {
+    max_delay: u64,
+    tracks: Vec<QueuedTrack>,
+    latest_timestamp: Option<i64>,
+    stalls: Vec<TrackStall>,
+}
+
+impl InterleaveQueue {
+    pub(crate) fn new(max_delay: u64) -> Self {
+        InterleaveQueue {
+            max_delay,
+            tracks: Vec::new(),
+            latest_timestamp: None,
+            stalls: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_track(&mut self, number: u64) {
+        self.tracks.push(QueuedTrack {
+            number,
+            frames: VecDeque::new(),
+            last_timestamp: None,
+            finished: false,
+            stalled: false,
+        });
+    }
+
+    pub(crate) fn finish_track(&mut self, number: u64) {
+        if let Some(track) = self.tracks.iter_mut().find(|t| t.number == number) {
+            track.finished = true;
+        }
+    }
+
+    pub(crate) fn push(&mut self, frame: MuxFrame) {
+        self.latest_timestamp = Some(self.latest_timestamp.map_or(frame.timestamp, |latest| latest.max(frame.timestamp)));
+        if let Some(track) = self.tracks.iter_mut().find(|t| t.number == frame.track) {
+            track.stalled = false;
+            track.finished = false;
+            track.last_timestamp = Some(frame.timestamp);
+            track.frames.push_back(frame);
+        }
+    }
+
+    ///
+    /// Removes the next frame that can be written, if any.  When `flush` is true, frames are released without waiting on other tracks.
+    ///
+    pub(crate) fn pop(&mut self, flush: bool) -> Option<MuxFrame> {
+        let (index, timestamp) = self.tracks.iter().enumerate()
+            .filter_map(|(i, t)| t.frames.front().map(|f| (i, f.timestamp)))
+            .min_by_key(|(_, timestamp)| *timestamp)?;
+
+        let waiting: Vec<usize> = self.tracks.iter().enumerate()
+            .filter(|(_, t)| !t.finished && t.frames.is_empty())
+            .map(|(i, _)| i)
+            .collect();
+
+        if !flush && !waiting.is_empty() {
+            let expired = self.latest_timestamp.is_some_and(|latest| latest - timestamp > self.max_delay as i64);
+            if !expired {
+                return None;
+            }
+
+            for i in waiting {
+                let track = &mut self.tracks[i];
+                if !track.stalled {
+                    track.stalled = true;
+                    self.stalls.push(TrackStall { track: track.number, last_timestamp: track.last_timestamp, skipped_to: timestamp });
+                }
+            }
+        }
+
+        self.tracks[index].frames.pop_front()
+    }
+
+    pub(crate) fn take_stalls(&mut self) -> Vec<TrackStall> {
+        std::mem::take(&mut self.stalls)
+    }
+}
@@ -0,0 +1,73 @@
+use super::MuxFrame;
+
+///
+/// Limits used when the muxer groups consecutive audio frames into laced blocks.
+///
+/// A laced block stores several frames behind a single block header, which saves a measurable amount of space for streams with many small frames (such as low bitrate Opus).  The muxer picks whichever [`BlockLacing`][crate::matroska_spec::BlockLacing] has the smallest overhead for each block.
+///
+/// Only frames from audio tracks that have a "DefaultDuration" and don't disable "FlagLacing" are laced, since the timestamps of laced frames are derived from the "DefaultDuration" when reading.  Frames that need a "BlockGroup", or whose timestamps don't follow the "DefaultDuration" (within 1 unit of "TimestampScale" for rounding), are written on their own.
+///
+#[derive(Clone, Debug)]
+pub struct LacingOptions {
+    /// The maximum number of frames in a single block.  Values above 256 are treated as 256.  Defaults to `8`.
+    pub max_frames: usize,
+
+    /// The maximum combined size of the frames in a single block, in bytes.  Defaults to `4096`.
+    pub max_size: usize,
+
+    /// The maximum combined duration of the frames in a single block, in units of the segment "TimestampScale".  Defaults to `200`.
+    pub max_duration: u64,
+}
+
+impl Default for LacingOptions {
+    fn default() -> Self {
+        LacingOptions {
+            max_frames: 8,
+            max_size: 4096,
+            max_duration: 200,
+        }
+    }
+}
+
+pub(crate) struct PendingLace {
+    pub(crate) frames: Vec<MuxFrame>,
+    size: usize,
+}
+
+impl PendingLace {
+    pub(crate) fn new(frame: MuxFrame) -> Self {
+        PendingLace {
+            size: frame.data.len(),
+            frames: vec![frame],
+        }
+    }
+
+    pub(crate) fn track(&self) -> u64 {
+        self.frames[0].track
+    }
+
+    ///
+    /// Whether the frame continues this lace without exceeding any limits.  `default_duration` is in nanoseconds and `timestamp_scale` converts it to timestamp units.
+    ///
+    pub(crate) fn accepts(&self, frame: &MuxFrame, options: &LacingOptions, default_duration: u64, timestamp_scale: u64) -> bool {
+        let first = &self.frames[0];
+        let count = self.frames.len() as u64;
+        let expected = first.timestamp + ((count * default_duration + timestamp_scale / 2) / timestamp_scale) as i64;
+        let duration = (default_duration + timestamp_scale / 2) / timestamp_scale;
+
+        frame.track == first.track
+            && self.frames.len() < options.max_frames.min(256)
+            && self.size + frame.data.len() <= options.max_size
+            && (frame.timestamp - first.timestamp) as u64 + duration <= options.max_duration
+            && (frame.timestamp - expected).abs() <= 1
+    }
+
+    pub(crate) fn push(&mut self, frame: MuxFrame) {
+        self.size += frame.data.len();
+        self.frames.push(frame);
+    }
+
+    pub(crate) fn is_full(&self, options: &LacingOptions) -> bool {
+        self.frames.len() >= options.max_frames.min(256) || self.size >= options.max_size
+    }
+}
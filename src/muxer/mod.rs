@@ -0,0 +1,865 @@
+//!
+//! Provides the [`WebmMuxer`], which writes frames of encoded data into a complete WebM (or Matroska) file.
+//!
+//! With the `futures` feature enabled, [`WebmMuxerAsync`] provides the same functionality on top of [`futures::AsyncWrite`].
+//!
+//! Where the [`WebmWriter`] requires every tag to be built by hand, the muxer only needs a description of each track and each frame.  It takes care of writing the EBML header, segment information, track entries, and clusters, and chooses the element used to store each frame based on the spec rules.
+//!
+//! ## Example
+//!
+//! ```
+//! use webm_iterable::muxer::{WebmMuxer, MuxFrame};
+//! use webm_iterable::matroska_spec::{MatroskaSpec, Master};
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let mut muxer = WebmMuxer::new(Vec::new());
+//!     let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+//!         MatroskaSpec::TrackType(0x11),
+//!         MatroskaSpec::CodecID(String::from("D_WEBVTT/SUBTITLES")),
+//!     ])))?;
+//!
+//!     let mut cue = MuxFrame::new(track, 0, b"Hello!".to_vec());
+//!     cue.duration = Some(1500);
+//!     muxer.write_frame(cue)?;
+//!
+//!     let webm: Vec<u8> = muxer.finalize()?;
+//!     assert!(!webm.is_empty());
+//!     Ok(())
+//! }
+//! ```
+//!
+
+mod frame;
+mod interleave;
+mod lacing;
+#[cfg(feature = "futures")]
+mod nonblocking;
+
+use std::io::Write;
+use std::convert::TryFrom;
+
+pub use frame::{BlockAddition, MuxFrame};
+pub use interleave::TrackStall;
+pub use lacing::LacingOptions;
+#[cfg(feature = "futures")]
+pub use nonblocking::WebmMuxerAsync;
+
+use crate::codecs::{aac, av1, vp8, vp9};
+use crate::errors::MuxerError;
+use crate::matroska_spec::{relative_block_timestamp, BlockLacing, EbmlTag, Frame, Master, MatroskaSpec};
+use crate::{WebmWriter, WriteOptions};
+use frame::{build_block_tag, build_laced_block_tag};
+use interleave::InterleaveQueue;
+use lacing::PendingLace;
+
+///
+/// Options that can be used to customize the output of a [`WebmMuxer`].
+///
+#[derive(Clone, Debug)]
+pub struct MuxerOptions {
+    /// The "DocType" written in the EBML header.  Defaults to `"webm"`; use `"matroska"` for MKV or MKA output.
+    pub doc_type: String,
+
+    /// The segment "TimestampScale" in nanoseconds.  All frame timestamps and durations are in these units.  Defaults to `1_000_000` (milliseconds).
+    pub timestamp_scale: u64,
+
+    /// The maximum duration of a cluster, in units of the segment "TimestampScale".  Defaults to `5000`.
+    pub max_cluster_duration: u64,
+
+    /// Whether frames that don't need a "BlockGroup" should be written as "SimpleBlock" elements.  Defaults to `true`.  When `false`, every frame is written as a "BlockGroup" and non-keyframes reference the previous frame in their track.
+    pub simple_blocks: bool,
+
+    /// When set, frames are buffered and written in timestamp order across all tracks.  The value is the maximum time (in units of the segment "TimestampScale") a frame is held while waiting on other tracks.  Defaults to `None`, which writes frames in the order they are received.
+    pub max_interleave_delay: Option<u64>,
+
+    /// When set, consecutive frames from audio tracks are grouped into laced blocks within the given limits.  Defaults to `None`, which writes every frame in its own block.
+    pub lacing: Option<LacingOptions>,
+}
+
+impl Default for MuxerOptions {
+    fn default() -> Self {
+        MuxerOptions {
+            doc_type: String::from("webm"),
+            timestamp_scale: 1_000_000,
+            max_cluster_duration: 5000,
+            simple_blocks: true,
+            max_interleave_delay: None,
+            lacing: None,
+        }
+    }
+}
+
+struct MuxerTrack {
+    number: u64,
+    codec_id: String,
+    is_video: bool,
+    lace_duration: Option<u64>,
+    children: Vec<MatroskaSpec>,
+    last_timestamp: Option<i64>,
+}
+
+struct MuxerCluster {
+    timestamp: u64,
+    has_blocks: bool,
+}
+
+///
+/// Writes frames of encoded data into a WebM file.
+///
+/// Tracks are registered with [`Self::add_track()`] and must all be added before the first frame is written.  Frames are then passed to [`Self::write_frame()`], either in the order they should appear in the file or, if [`MuxerOptions::max_interleave_delay`] is set, in whatever order the encoders produce them.  In the latter case the muxer buffers frames and writes them in timestamp order across tracks; tracks that hold up output for longer than the allowed delay are reported by [`Self::take_stalls()`].  The segment is written with an unknown size so that output can be streamed, and clusters are started whenever a video keyframe is written, the [`MuxerOptions::max_cluster_duration`] is exceeded, or a block timestamp would no longer fit relative to the current cluster.
+///
+/// Once all frames have been written, [`Self::finalize()`] must be called to write out the last cluster.
+///
+pub struct WebmMuxer<W: Write> {
+    writer: WebmWriter<W>,
+    options: MuxerOptions,
+    tracks: Vec<MuxerTrack>,
+    started: bool,
+    header_written: bool,
+    cluster: Option<MuxerCluster>,
+    queue: Option<InterleaveQueue>,
+    lace: Option<PendingLace>,
+}
+
+impl<W: Write> WebmMuxer<W> {
+    ///
+    /// Returns a new [`WebmMuxer`] instance using the default [`MuxerOptions`].
+    ///
+    /// The `dest` parameter can be anything that implements [`std::io::Write`].
+    ///
+    pub fn new(dest: W) -> Self {
+        Self::with_options(dest, MuxerOptions::default())
+    }
+
+    ///
+    /// Returns a new [`WebmMuxer`] instance using the given [`MuxerOptions`].
+    ///
+    pub fn with_options(dest: W, options: MuxerOptions) -> Self {
+        WebmMuxer {
+            writer: WebmWriter::new(dest),
+            queue: options.max_interleave_delay.map(InterleaveQueue::new),
+            options,
+            tracks: Vec::new(),
+            started: false,
+            header_written: false,
+            cluster: None,
+            lace: None,
+        }
+    }
+
+    ///
+    /// Adds a track to the output.
+    ///
+    /// The `track_entry` must be a [`MatroskaSpec::TrackEntry`] variant using [`Master::Full`], and must contain at least a "TrackType" and "CodecID".  If the entry has no "TrackNumber", the next available number is assigned.  If it has no "TrackUID", the track number is used.  Returns the track number to use for frames in this track.
+    ///
+    /// For "V_VP8" tracks, a missing "PixelWidth" and "PixelHeight" are filled in from the first keyframe of the track that is received before the header is written.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the track entry is malformed, if its track number is already in use, or if frames have already been written.
+    ///
+    pub fn add_track(&mut self, track_entry: MatroskaSpec) -> Result<u64, MuxerError> {
+        if self.started {
+            return Err(MuxerError::TrackError(String::from("Tracks cannot be added after frames have been written")));
+        }
+
+        let mut children = match track_entry {
+            MatroskaSpec::TrackEntry(Master::Full(children)) => children,
+            _ => return Err(MuxerError::TrackError(String::from("Expected a full TrackEntry tag when adding a track"))),
+        };
+
+        let track_type = children.iter().find_map(|c| match c {
+            MatroskaSpec::TrackType(val) => Some(*val),
+            _ => None,
+        }).ok_or_else(|| MuxerError::TrackError(String::from("TrackEntry is missing a TrackType")))?;
+
+        let codec_id = children.iter().find_map(|c| match c {
+            MatroskaSpec::CodecID(val) => Some(val.clone()),
+            _ => None,
+        }).ok_or_else(|| MuxerError::TrackError(String::from("TrackEntry is missing a CodecID")))?;
+
+        let number = match children.iter().find_map(|c| match c {
+            MatroskaSpec::TrackNumber(val) => Some(*val),
+            _ => None,
+        }) {
+            Some(number) => number,
+            None => {
+                let number = self.tracks.iter().map(|t| t.number).max().unwrap_or(0) + 1;
+                children.insert(0, MatroskaSpec::TrackNumber(number));
+                number
+            }
+        };
+
+        if number == 0 || self.tracks.iter().any(|t| t.number == number) {
+            return Err(MuxerError::TrackError(format!("Track number {number} is invalid or already in use")));
+        }
+
+        if !children.iter().any(|c| matches!(c, MatroskaSpec::TrackUID(_))) {
+            children.push(MatroskaSpec::TrackUID(number));
+        }
+
+        let lacing_enabled = children.iter().find_map(|c| match c {
+            MatroskaSpec::FlagLacing(val) => Some(*val != 0),
+            _ => None,
+        }).unwrap_or(true);
+        let default_duration = children.iter().find_map(|c| match c {
+            MatroskaSpec::DefaultDuration(val) => Some(*val),
+            _ => None,
+        });
+
+        if let Some(queue) = &mut self.queue {
+            queue.add_track(number);
+        }
+
+        self.tracks.push(MuxerTrack {
+            number,
+            codec_id,
+            is_video: track_type == 1,
+            lace_duration: default_duration.filter(|_| track_type == 2 && lacing_enabled),
+            children,
+            last_timestamp: None,
+        });
+
+        Ok(number)
+    }
+
+    ///
+    /// Writes a frame to the output.
+    ///
+    /// The header is written along with the first block, so all tracks must have been added before the first frame is passed in.  Frames are stored as "SimpleBlock" elements when possible and as "BlockGroup" elements otherwise - see [`MuxFrame`] for details.  When interleaving is enabled, the frame may be buffered and written during a later call.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the frame belongs to an unknown track, if its timestamp is negative, or if there is a problem writing to the destination.
+    ///
+    pub fn write_frame(&mut self, mut frame: MuxFrame) -> Result<(), MuxerError> {
+        if frame.timestamp < 0 {
+            return Err(MuxerError::FrameError(format!("Frame timestamp {} is negative", frame.timestamp)));
+        }
+
+        let is_aac = match self.tracks.iter().find(|t| t.number == frame.track) {
+            Some(track) => track.codec_id == "A_AAC",
+            None => return Err(MuxerError::FrameError(format!("Frame references unknown track {}", frame.track))),
+        };
+
+        self.started = true;
+        if !self.header_written {
+            self.fill_track_details(&frame);
+        }
+        if is_aac {
+            strip_adts_header(&mut frame.data);
+        }
+
+        match &mut self.queue {
+            Some(queue) => {
+                queue.push(frame);
+                self.drain_queue(false)
+            },
+            None => self.write_block(frame),
+        }
+    }
+
+    ///
+    /// Marks a track as finished when interleaving.
+    ///
+    /// Frames from other tracks are no longer held back waiting on a finished track.  Writing another frame for the track resumes waiting on it.  This has no effect if interleaving is disabled.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing released frames to the destination.
+    ///
+    pub fn finish_track(&mut self, track: u64) -> Result<(), MuxerError> {
+        if let Some(queue) = &mut self.queue {
+            queue.finish_track(track);
+            if self.started {
+                return self.drain_queue(false);
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Returns any track stalls that have occurred since the last call.
+    ///
+    /// A [`TrackStall`] is reported when frames had to be written without waiting on a track because [`MuxerOptions::max_interleave_delay`] was exceeded.  This always returns an empty list if interleaving is disabled.
+    ///
+    pub fn take_stalls(&mut self) -> Vec<TrackStall> {
+        self.queue.as_mut().map(|q| q.take_stalls()).unwrap_or_default()
+    }
+
+    fn drain_queue(&mut self, flush: bool) -> Result<(), MuxerError> {
+        while let Some(frame) = self.queue.as_mut().and_then(|q| q.pop(flush)) {
+            self.write_block(frame)?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, frame: MuxFrame) -> Result<(), MuxerError> {
+        let track_index = self.tracks.iter().position(|t| t.number == frame.track)
+            .ok_or_else(|| MuxerError::FrameError(format!("Frame references unknown track {}", frame.track)))?;
+
+        let options = match &self.options.lacing {
+            Some(options) => options,
+            None => return self.write_frames(track_index, vec![frame]),
+        };
+
+        let lace_duration = self.tracks[track_index].lace_duration
+            .filter(|_| frame.keyframe && !frame.invisible && !frame.needs_block_group());
+
+        let continues_lace = match (&self.lace, lace_duration) {
+            (Some(lace), Some(duration)) => lace.accepts(&frame, options, duration, self.options.timestamp_scale),
+            _ => false,
+        };
+
+        if continues_lace {
+            let lace = self.lace.as_mut().expect("lace should be pending");
+            lace.push(frame);
+            if lace.is_full(options) {
+                self.flush_lace()?;
+            }
+            Ok(())
+        } else {
+            self.flush_lace()?;
+            if lace_duration.is_some() {
+                self.lace = Some(PendingLace::new(frame));
+                Ok(())
+            } else {
+                self.write_frames(track_index, vec![frame])
+            }
+        }
+    }
+
+    fn flush_lace(&mut self) -> Result<(), MuxerError> {
+        if let Some(lace) = self.lace.take() {
+            let track_index = self.tracks.iter().position(|t| t.number == lace.track())
+                .expect("laced frames should belong to a known track");
+            self.write_frames(track_index, lace.frames)?;
+        }
+        Ok(())
+    }
+
+    fn write_frames(&mut self, track_index: usize, frames: Vec<MuxFrame>) -> Result<(), MuxerError> {
+        self.write_header()?;
+
+        let first = &frames[0];
+        let last = &frames[frames.len() - 1];
+
+        let start_cluster = match &self.cluster {
+            None => true,
+            Some(cluster) => {
+                relative_block_timestamp(cluster.timestamp, first.timestamp).is_err()
+                    || relative_block_timestamp(cluster.timestamp, last.timestamp).is_err()
+                    || first.timestamp - cluster.timestamp as i64 >= self.options.max_cluster_duration as i64
+                    || (cluster.has_blocks && first.keyframe && self.tracks[track_index].is_video)
+            }
+        };
+
+        if start_cluster {
+            self.close_cluster()?;
+            self.writer.write(&MatroskaSpec::Cluster(Master::Start))?;
+            self.writer.write(&MatroskaSpec::Timestamp(first.timestamp as u64))?;
+            self.cluster = Some(MuxerCluster { timestamp: first.timestamp as u64, has_blocks: false });
+        }
+
+        let cluster = self.cluster.as_mut().expect("cluster should have been started");
+        let relative_timestamp = relative_block_timestamp(cluster.timestamp, first.timestamp)
+            .map_err(|err| MuxerError::FrameError(err.to_string()))?;
+        cluster.has_blocks = true;
+
+        let track = &mut self.tracks[track_index];
+        let reference = track.last_timestamp.map(|last| last - first.timestamp);
+        track.last_timestamp = Some(last.timestamp);
+
+        let laced: Vec<Frame> = frames.iter().map(|f| Frame { data: &f.data }).collect();
+        let tag = match BlockLacing::smallest_for(&laced) {
+            Some(lacing) => build_laced_block_tag(&frames, relative_timestamp, lacing, self.options.simple_blocks)?,
+            None => build_block_tag(first, relative_timestamp, reference, self.options.simple_blocks)?,
+        };
+        self.writer.write(&tag)?;
+        Ok(())
+    }
+
+    ///
+    /// Writes out any buffered frames and data and returns the underlying destination.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub fn finalize(mut self) -> Result<W, MuxerError> {
+        self.write_header()?;
+        self.drain_queue(true)?;
+        self.flush_lace()?;
+        self.close_cluster()?;
+        Ok(self.writer.into_inner()?)
+    }
+
+    ///
+    /// Fills in track details that can be derived from the bitstream, before the header is written.
+    ///
+    fn fill_track_details(&mut self, frame: &MuxFrame) {
+        let track = match self.tracks.iter_mut().find(|t| t.number == frame.track) {
+            Some(track) => track,
+            None => return,
+        };
+
+        if track.codec_id == "V_VP8" && frame.keyframe {
+            if let Ok(vp8::FrameHeader { dimensions: Some(dimensions), .. }) = vp8::FrameHeader::try_from(frame.data.as_slice()) {
+                set_missing_video_children(&mut track.children, vec![
+                    MatroskaSpec::PixelWidth(dimensions.width as u64),
+                    MatroskaSpec::PixelHeight(dimensions.height as u64),
+                ]);
+            }
+        } else if track.codec_id == "V_VP9" && frame.keyframe {
+            let headers = vp9::split_superframe(&frame.data).unwrap_or_default().into_iter()
+                .filter_map(|data| vp9::FrameHeader::try_from(data).ok());
+            for header in headers {
+                if let (true, Some(dimensions), Some(color_config)) = (header.keyframe, header.dimensions, header.color_config) {
+                    set_missing_video_children(&mut track.children, vec![
+                        MatroskaSpec::PixelWidth(dimensions.width as u64),
+                        MatroskaSpec::PixelHeight(dimensions.height as u64),
+                        color_config.to_colour(),
+                    ]);
+                    break;
+                }
+            }
+        } else if track.codec_id == "V_AV1" && frame.keyframe && !track.children.iter().any(|c| matches!(c, MatroskaSpec::CodecPrivate(_))) {
+            if let Ok(config) = av1::CodecConfiguration::from_frame(&frame.data) {
+                track.children.push(MatroskaSpec::CodecPrivate(config.to_bytes()));
+            }
+        } else if track.codec_id == "A_AAC" && !track.children.iter().any(|c| matches!(c, MatroskaSpec::CodecPrivate(_))) {
+            if let Some(Ok(config)) = read_adts_header(&frame.data).map(|header| header.audio_specific_config()) {
+                track.children.push(MatroskaSpec::CodecPrivate(config.to_bytes()));
+            }
+        }
+    }
+
+    fn write_header(&mut self) -> Result<(), MuxerError> {
+        if self.header_written {
+            return Ok(());
+        }
+        self.header_written = true;
+
+        self.writer.write(&MatroskaSpec::Ebml(Master::Full(vec![
+            MatroskaSpec::EbmlVersion(1),
+            MatroskaSpec::EbmlReadVersion(1),
+            MatroskaSpec::EbmlMaxIdLength(4),
+            MatroskaSpec::EbmlMaxSizeLength(8),
+            MatroskaSpec::DocType(self.options.doc_type.clone()),
+            MatroskaSpec::DocTypeVersion(4),
+            MatroskaSpec::DocTypeReadVersion(2),
+        ])))?;
+        self.writer.write_advanced(&MatroskaSpec::Segment(Master::Start), WriteOptions::is_unknown_sized_element())?;
+        self.writer.write(&MatroskaSpec::Info(Master::Full(vec![
+            MatroskaSpec::TimestampScale(self.options.timestamp_scale),
+            MatroskaSpec::MuxingApp(String::from("webm-iterable")),
+            MatroskaSpec::WritingApp(String::from("webm-iterable")),
+        ])))?;
+        self.writer.write(&MatroskaSpec::Tracks(Master::Full(
+            self.tracks.iter().map(|t| MatroskaSpec::TrackEntry(Master::Full(t.children.clone()))).collect()
+        )))?;
+        Ok(())
+    }
+
+    fn close_cluster(&mut self) -> Result<(), MuxerError> {
+        if self.cluster.take().is_some() {
+            self.writer.write(&MatroskaSpec::Cluster(Master::End))?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Reads the ADTS header from an AAC frame taken from a raw `.aac` stream, if it has one describing exactly this frame.
+///
+fn read_adts_header(data: &[u8]) -> Option<aac::AdtsHeader> {
+    aac::AdtsHeader::try_from(data).ok()
+        .filter(|header| header.raw_data_blocks == 1 && header.frame_length == data.len())
+}
+
+///
+/// Removes the ADTS header from an AAC frame, as Matroska stores raw frames with the configuration in "CodecPrivate".
+///
+fn strip_adts_header(data: &mut Vec<u8>) {
+    if let Some(header) = read_adts_header(data) {
+        data.drain(..header.header_length());
+    }
+}
+
+///
+/// Adds each of the given children to the "Video" element of a track entry, unless the track already has a child of the same type.
+///
+fn set_missing_video_children(track_children: &mut Vec<MatroskaSpec>, children: Vec<MatroskaSpec>) {
+    let video_index = match track_children.iter().position(|c| matches!(c, MatroskaSpec::Video(Master::Full(_)))) {
+        Some(index) => index,
+        None => {
+            track_children.push(MatroskaSpec::Video(Master::Full(Vec::new())));
+            track_children.len() - 1
+        }
+    };
+
+    if let MatroskaSpec::Video(Master::Full(video_children)) = &mut track_children[video_index] {
+        for child in children {
+            if !video_children.iter().any(|c| c.get_id() == child.get_id()) {
+                video_children.push(child);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+impl WebmMuxer<Vec<u8>> {
+    ///
+    /// Takes the data written so far, leaving the muxer's buffer empty.  Used by [`WebmMuxerAsync`] to forward completed output.
+    ///
+    pub(crate) fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(self.writer.get_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::convert::TryFrom;
+
+    use super::{WebmMuxer, MuxerOptions, MuxFrame, BlockAddition, TrackStall, LacingOptions};
+    use crate::matroska_spec::{MatroskaSpec, Master, Block, BlockLacing, SimpleBlock};
+    use crate::WebmIterator;
+
+    fn video_track() -> MatroskaSpec {
+        MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(1),
+            MatroskaSpec::CodecID(String::from("V_VP8")),
+        ]))
+    }
+
+    fn read_tags(data: Vec<u8>) -> Vec<MatroskaSpec> {
+        let mut src = Cursor::new(data);
+        WebmIterator::new(&mut src, &[MatroskaSpec::BlockGroup(Master::Start), MatroskaSpec::TrackEntry(Master::Start)])
+            .map(|t| t.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn selects_simple_block_or_block_group() {
+        let mut muxer = WebmMuxer::new(Vec::new());
+        let track = muxer.add_track(video_track()).unwrap();
+        assert_eq!(1, track);
+
+        muxer.write_frame(MuxFrame::new(track, 0, vec![0x01])).unwrap();
+
+        let mut with_duration = MuxFrame::new(track, 10, vec![0x02]);
+        with_duration.keyframe = false;
+        with_duration.duration = Some(10);
+        muxer.write_frame(with_duration).unwrap();
+
+        let mut with_additions = MuxFrame::new(track, 20, vec![0x03]);
+        with_additions.keyframe = false;
+        with_additions.additions.push(BlockAddition { id: 1, data: vec![0xaa] });
+        muxer.write_frame(with_additions).unwrap();
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        let blocks: Vec<&MatroskaSpec> = tags.iter().filter(|t| matches!(t, MatroskaSpec::SimpleBlock(_) | MatroskaSpec::BlockGroup(_))).collect();
+        assert_eq!(3, blocks.len());
+
+        let simple_block = SimpleBlock::try_from(blocks[0]).unwrap();
+        assert!(simple_block.keyframe);
+        assert_eq!(0, simple_block.timestamp);
+
+        match blocks[1] {
+            MatroskaSpec::BlockGroup(Master::Full(children)) => {
+                let block = Block::try_from(&children[0]).unwrap();
+                assert_eq!(10, block.timestamp);
+                assert!(children.contains(&MatroskaSpec::BlockDuration(10)));
+                assert!(children.contains(&MatroskaSpec::ReferenceBlock(-10)));
+            },
+            _ => panic!("expected block group"),
+        }
+
+        match blocks[2] {
+            MatroskaSpec::BlockGroup(Master::Full(children)) => {
+                assert!(children.iter().any(|c| matches!(c, MatroskaSpec::BlockAdditions(_))));
+                assert!(children.contains(&MatroskaSpec::ReferenceBlock(-10)));
+            },
+            _ => panic!("expected block group"),
+        }
+    }
+
+    #[test]
+    fn block_only_mux_references_previous_frames() {
+        let mut muxer = WebmMuxer::with_options(Vec::new(), MuxerOptions { simple_blocks: false, ..Default::default() });
+        let track = muxer.add_track(video_track()).unwrap();
+
+        let mut leading = MuxFrame::new(track, 0, vec![0x00]);
+        leading.keyframe = false;
+        muxer.write_frame(leading).unwrap();
+        muxer.write_frame(MuxFrame::new(track, 0, vec![0x01])).unwrap();
+        let mut delta = MuxFrame::new(track, 33, vec![0x02]);
+        delta.keyframe = false;
+        muxer.write_frame(delta).unwrap();
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        assert!(!tags.iter().any(|t| matches!(t, MatroskaSpec::SimpleBlock(_))));
+        let groups: Vec<&Vec<MatroskaSpec>> = tags.iter().filter_map(|t| match t {
+            MatroskaSpec::BlockGroup(Master::Full(children)) => Some(children),
+            _ => None,
+        }).collect();
+        assert_eq!(3, groups.len());
+        assert!(!groups[0].iter().any(|c| matches!(c, MatroskaSpec::ReferenceBlock(_))));
+        assert!(!groups[1].iter().any(|c| matches!(c, MatroskaSpec::ReferenceBlock(_))));
+        assert!(groups[2].contains(&MatroskaSpec::ReferenceBlock(-33)));
+    }
+
+    #[test]
+    fn starts_clusters_on_video_keyframes() {
+        let mut muxer = WebmMuxer::new(Vec::new());
+        let track = muxer.add_track(video_track()).unwrap();
+
+        muxer.write_frame(MuxFrame::new(track, 0, vec![0x01])).unwrap();
+        let mut delta = MuxFrame::new(track, 33, vec![0x02]);
+        delta.keyframe = false;
+        muxer.write_frame(delta).unwrap();
+        muxer.write_frame(MuxFrame::new(track, 66, vec![0x03])).unwrap();
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        let timestamps: Vec<&MatroskaSpec> = tags.iter().filter(|t| matches!(t, MatroskaSpec::Timestamp(_))).collect();
+        assert_eq!(vec![&MatroskaSpec::Timestamp(0), &MatroskaSpec::Timestamp(66)], timestamps);
+    }
+
+    fn audio_track() -> MatroskaSpec {
+        MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(2),
+            MatroskaSpec::CodecID(String::from("A_OPUS")),
+        ]))
+    }
+
+    fn block_order(tags: &[MatroskaSpec]) -> Vec<(u64, i64)> {
+        let mut cluster_timestamp = 0;
+        let mut order = Vec::new();
+        for tag in tags {
+            match tag {
+                MatroskaSpec::Timestamp(val) => cluster_timestamp = *val as i64,
+                MatroskaSpec::SimpleBlock(_) => {
+                    let block = SimpleBlock::try_from(tag).unwrap();
+                    order.push((block.track, cluster_timestamp + block.timestamp as i64));
+                },
+                _ => {},
+            }
+        }
+        order
+    }
+
+    #[test]
+    fn interleaves_tracks_in_timestamp_order() {
+        let mut muxer = WebmMuxer::with_options(Vec::new(), MuxerOptions { max_interleave_delay: Some(1000), ..Default::default() });
+        let video = muxer.add_track(video_track()).unwrap();
+        let audio = muxer.add_track(audio_track()).unwrap();
+
+        for ts in [0, 40, 80] {
+            muxer.write_frame(MuxFrame::new(video, ts, vec![0x01])).unwrap();
+        }
+        for ts in [0, 20, 40, 60, 80, 100] {
+            muxer.write_frame(MuxFrame::new(audio, ts, vec![0x02])).unwrap();
+        }
+        muxer.write_frame(MuxFrame::new(video, 120, vec![0x01])).unwrap();
+
+        assert!(muxer.take_stalls().is_empty());
+        let tags = read_tags(muxer.finalize().unwrap());
+        let timestamps: Vec<i64> = block_order(&tags).into_iter().map(|(_, ts)| ts).collect();
+        assert_eq!(vec![0, 0, 20, 40, 40, 60, 80, 80, 100, 120], timestamps);
+    }
+
+    #[test]
+    fn reports_stalled_tracks() {
+        let mut muxer = WebmMuxer::with_options(Vec::new(), MuxerOptions { max_interleave_delay: Some(100), ..Default::default() });
+        let video = muxer.add_track(video_track()).unwrap();
+        let audio = muxer.add_track(audio_track()).unwrap();
+
+        muxer.write_frame(MuxFrame::new(audio, 0, vec![0x02])).unwrap();
+        for ts in [0, 40, 80, 120, 160] {
+            muxer.write_frame(MuxFrame::new(video, ts, vec![0x01])).unwrap();
+        }
+
+        assert_eq!(vec![TrackStall { track: audio, last_timestamp: Some(0), skipped_to: 40 }], muxer.take_stalls());
+        assert!(muxer.take_stalls().is_empty());
+
+        muxer.finish_track(audio).unwrap();
+        let tags = read_tags(muxer.finalize().unwrap());
+        assert_eq!(6, block_order(&tags).len());
+    }
+
+    #[test]
+    fn laces_consecutive_audio_frames() {
+        let mut muxer = WebmMuxer::with_options(Vec::new(), MuxerOptions { lacing: Some(LacingOptions { max_frames: 3, ..Default::default() }), ..Default::default() });
+        let audio = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(2),
+            MatroskaSpec::CodecID(String::from("A_OPUS")),
+            MatroskaSpec::DefaultDuration(20_000_000),
+        ]))).unwrap();
+
+        for (i, ts) in [0, 20, 40, 60, 80, 120].iter().enumerate() {
+            muxer.write_frame(MuxFrame::new(audio, *ts, vec![i as u8; 10 + i % 2])).unwrap();
+        }
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        let blocks: Vec<SimpleBlock> = tags.iter().filter(|t| matches!(t, MatroskaSpec::SimpleBlock(_))).map(|t| SimpleBlock::try_from(t).unwrap()).collect();
+        assert_eq!(3, blocks.len());
+        assert_eq!(Some(BlockLacing::Xiph), blocks[0].lacing);
+        assert_eq!(3, blocks[0].read_frame_data().unwrap().len());
+        assert_eq!(60, blocks[1].timestamp);
+        assert_eq!(2, blocks[1].read_frame_data().unwrap().len());
+        assert_eq!(None, blocks[2].lacing);
+        assert_eq!(120, blocks[2].timestamp);
+    }
+
+    #[test]
+    fn limits_lace_duration() {
+        let mut muxer = WebmMuxer::with_options(Vec::new(), MuxerOptions { lacing: Some(LacingOptions { max_duration: 50, ..Default::default() }), ..Default::default() });
+        let audio = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(2),
+            MatroskaSpec::CodecID(String::from("A_OPUS")),
+            MatroskaSpec::DefaultDuration(20_000_000),
+        ]))).unwrap();
+
+        for ts in [0, 20, 40, 60] {
+            muxer.write_frame(MuxFrame::new(audio, ts, vec![0x01; 4])).unwrap();
+        }
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        let blocks: Vec<SimpleBlock> = tags.iter().filter(|t| matches!(t, MatroskaSpec::SimpleBlock(_))).map(|t| SimpleBlock::try_from(t).unwrap()).collect();
+        assert_eq!(vec![0, 40], blocks.iter().map(|b| b.timestamp).collect::<Vec<_>>());
+        assert!(blocks.iter().all(|b| b.read_frame_data().unwrap().len() == 2));
+    }
+
+    #[test]
+    fn respects_flag_lacing() {
+        let mut muxer = WebmMuxer::with_options(Vec::new(), MuxerOptions { lacing: Some(LacingOptions::default()), ..Default::default() });
+        let audio = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(2),
+            MatroskaSpec::CodecID(String::from("A_OPUS")),
+            MatroskaSpec::DefaultDuration(20_000_000),
+            MatroskaSpec::FlagLacing(0),
+        ]))).unwrap();
+
+        for ts in [0, 20, 40] {
+            muxer.write_frame(MuxFrame::new(audio, ts, vec![0x01; 4])).unwrap();
+        }
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        assert_eq!(3, tags.iter().filter(|t| matches!(t, MatroskaSpec::SimpleBlock(_))).count());
+    }
+
+    #[test]
+    fn fills_vp8_dimensions() {
+        let mut muxer = WebmMuxer::new(Vec::new());
+        let track = muxer.add_track(video_track()).unwrap();
+        muxer.write_frame(MuxFrame::new(track, 0, vec![0x50, 0x42, 0x00, 0x9d, 0x01, 0x2a, 0x40, 0x01, 0xf0, 0x00])).unwrap();
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        let track_entry = tags.iter().find(|t| matches!(t, MatroskaSpec::TrackEntry(_))).unwrap();
+        assert_eq!(&MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackNumber(1),
+            MatroskaSpec::TrackType(1),
+            MatroskaSpec::CodecID(String::from("V_VP8")),
+            MatroskaSpec::TrackUID(1),
+            MatroskaSpec::Video(Master::Full(vec![
+                MatroskaSpec::PixelWidth(320),
+                MatroskaSpec::PixelHeight(240),
+            ])),
+        ])), track_entry);
+    }
+
+    #[test]
+    fn fills_vp9_dimensions_and_colour() {
+        let mut muxer = WebmMuxer::new(Vec::new());
+        let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(1),
+            MatroskaSpec::CodecID(String::from("V_VP9")),
+        ]))).unwrap();
+
+        // A superframe holding a hidden inter frame followed by a 320x240 keyframe
+        let mut data = vec![0x84, 0x00, 0x82, 0x49, 0x83, 0x42, 0x00, 0x13, 0xf0, 0x0e, 0xf6, 0x00];
+        data.extend_from_slice(&[0xc1, 0x02, 0x0a, 0xc1]);
+        muxer.write_frame(MuxFrame::new(track, 0, data)).unwrap();
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        let track_entry = tags.iter().find(|t| matches!(t, MatroskaSpec::TrackEntry(_))).unwrap();
+        assert_eq!(&MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackNumber(1),
+            MatroskaSpec::TrackType(1),
+            MatroskaSpec::CodecID(String::from("V_VP9")),
+            MatroskaSpec::TrackUID(1),
+            MatroskaSpec::Video(Master::Full(vec![
+                MatroskaSpec::PixelWidth(320),
+                MatroskaSpec::PixelHeight(240),
+                MatroskaSpec::Colour(Master::Full(vec![
+                    MatroskaSpec::BitsPerChannel(8),
+                    MatroskaSpec::ChromaSubsamplingHorz(1),
+                    MatroskaSpec::ChromaSubsamplingVert(1),
+                    MatroskaSpec::Range(1),
+                ])),
+            ])),
+        ])), track_entry);
+    }
+
+    #[test]
+    fn builds_av1_codec_private() {
+        let mut muxer = WebmMuxer::new(Vec::new());
+        let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(1),
+            MatroskaSpec::CodecID(String::from("V_AV1")),
+        ]))).unwrap();
+
+        // A 1920x1080 profile 0 sequence header OBU followed by a keyframe OBU
+        let sequence_header = [0x0a, 0x0e, 0x00, 0x00, 0x00, 0x42, 0xab, 0xbf, 0xc3, 0x73, 0xff, 0xe6, 0x40, 0x40, 0x40, 0x41];
+        let mut data = sequence_header.to_vec();
+        data.extend_from_slice(&[0x32, 0x01, 0x10]);
+        muxer.write_frame(MuxFrame::new(track, 0, data)).unwrap();
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        let codec_private = tags.iter().find_map(|t| match t {
+            MatroskaSpec::TrackEntry(Master::Full(children)) => children.iter().find_map(|c| match c {
+                MatroskaSpec::CodecPrivate(data) => Some(data.clone()),
+                _ => None,
+            }),
+            _ => None,
+        }).unwrap();
+        assert_eq!(&[0x81, 0x08, 0x0c, 0x00], &codec_private[..4]);
+        assert_eq!(&sequence_header, &codec_private[4..]);
+    }
+
+    #[test]
+    fn strips_adts_headers() {
+        let mut muxer = WebmMuxer::new(Vec::new());
+        let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(2),
+            MatroskaSpec::CodecID(String::from("A_AAC")),
+        ]))).unwrap();
+
+        // AAC LC, 44.1kHz, stereo, with a 3 byte raw frame
+        muxer.write_frame(MuxFrame::new(track, 0, vec![0xff, 0xf1, 0x50, 0x80, 0x01, 0x5f, 0xfc, 0x21, 0x00, 0x49])).unwrap();
+
+        let tags = read_tags(muxer.finalize().unwrap());
+        let codec_private = tags.iter().find_map(|t| match t {
+            MatroskaSpec::TrackEntry(Master::Full(children)) => children.iter().find_map(|c| match c {
+                MatroskaSpec::CodecPrivate(data) => Some(data.clone()),
+                _ => None,
+            }),
+            _ => None,
+        }).unwrap();
+        assert_eq!(vec![0x12, 0x10], codec_private);
+
+        let block = tags.iter().find(|t| matches!(t, MatroskaSpec::SimpleBlock(_))).unwrap();
+        let block = SimpleBlock::try_from(block).unwrap();
+        assert_eq!(&[0x21, 0x00, 0x49], block.read_frame_data().unwrap()[0].data);
+    }
+
+    #[test]
+    fn rejects_tracks_after_frames() {
+        let mut muxer = WebmMuxer::new(Vec::new());
+        let track = muxer.add_track(video_track()).unwrap();
+        muxer.write_frame(MuxFrame::new(track, 0, vec![0x01])).unwrap();
+        assert!(muxer.add_track(video_track()).is_err());
+        assert!(muxer.write_frame(MuxFrame::new(5, 0, vec![0x01])).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use futures::{AsyncWrite, AsyncWriteExt};
+
+use crate::errors::{MuxerError, TagWriterError};
+use crate::matroska_spec::MatroskaSpec;
+use super::{MuxFrame, MuxerOptions, TrackStall, WebmMuxer};
+
+///
+/// Writes frames of encoded data into a WebM file on a destination that implements [`futures::AsyncWrite`].
+///
+/// This is the asynchronous counterpart of [`WebmMuxer`] and behaves identically - frames are stored, interleaved, and laced based on the same [`MuxerOptions`].  Data is written to the destination as soon as each cluster is complete, which allows streaming output straight to an upload or HTTP response.
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::muxer::{WebmMuxerAsync, MuxFrame};
+/// use webm_iterable::matroska_spec::{MatroskaSpec, Master};
+///
+/// # futures::executor::block_on(async {
+/// let mut muxer = WebmMuxerAsync::new(Vec::new());
+/// let track = muxer.add_track(MatroskaSpec::TrackEntry(Master::Full(vec![
+///     MatroskaSpec::TrackType(2),
+///     MatroskaSpec::CodecID(String::from("A_OPUS")),
+/// ])))?;
+/// muxer.write_frame(MuxFrame::new(track, 0, vec![0xfc, 0xff, 0xfe])).await?;
+/// let webm: Vec<u8> = muxer.finalize().await?;
+/// assert!(!webm.is_empty());
+/// # Ok::<(), webm_iterable::errors::MuxerError>(())
+/// # }).unwrap();
+/// ```
+///
+pub struct WebmMuxerAsync<W: AsyncWrite + Unpin> {
+    muxer: WebmMuxer<Vec<u8>>,
+    dest: W,
+}
+
+impl<W: AsyncWrite + Unpin> WebmMuxerAsync<W> {
+    ///
+    /// Returns a new [`WebmMuxerAsync`] instance using the default [`MuxerOptions`].
+    ///
+    /// The `dest` parameter can be anything that implements [`futures::AsyncWrite`].
+    ///
+    pub fn new(dest: W) -> Self {
+        Self::with_options(dest, MuxerOptions::default())
+    }
+
+    ///
+    /// Returns a new [`WebmMuxerAsync`] instance using the given [`MuxerOptions`].
+    ///
+    pub fn with_options(dest: W, options: MuxerOptions) -> Self {
+        WebmMuxerAsync {
+            muxer: WebmMuxer::with_options(Vec::new(), options),
+            dest,
+        }
+    }
+
+    ///
+    /// Adds a track to the output.
+    ///
+    /// See [`WebmMuxer::add_track()`] for details.
+    ///
+    pub fn add_track(&mut self, track_entry: MatroskaSpec) -> Result<u64, MuxerError> {
+        self.muxer.add_track(track_entry)
+    }
+
+    ///
+    /// Writes a frame to the output.
+    ///
+    /// See [`WebmMuxer::write_frame()`] for details.
+    ///
+    pub async fn write_frame(&mut self, frame: MuxFrame) -> Result<(), MuxerError> {
+        self.muxer.write_frame(frame)?;
+        self.write_pending().await
+    }
+
+    ///
+    /// Marks a track as finished when interleaving.
+    ///
+    /// See [`WebmMuxer::finish_track()`] for details.
+    ///
+    pub async fn finish_track(&mut self, track: u64) -> Result<(), MuxerError> {
+        self.muxer.finish_track(track)?;
+        self.write_pending().await
+    }
+
+    ///
+    /// Returns any track stalls that have occurred since the last call.
+    ///
+    /// See [`WebmMuxer::take_stalls()`] for details.
+    ///
+    pub fn take_stalls(&mut self) -> Vec<TrackStall> {
+        self.muxer.take_stalls()
+    }
+
+    ///
+    /// Writes out any buffered frames and data and returns the underlying destination.
+    ///
+    /// # Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub async fn finalize(mut self) -> Result<W, MuxerError> {
+        let remaining = self.muxer.finalize()?;
+        write_all(&mut self.dest, &remaining).await?;
+        self.dest.flush().await.map_err(|source| MuxerError::WriteError(TagWriterError::WriteError { source }))?;
+        Ok(self.dest)
+    }
+
+    async fn write_pending(&mut self) -> Result<(), MuxerError> {
+        let pending = self.muxer.take_output();
+        write_all(&mut self.dest, &pending).await
+    }
+}
+
+async fn write_all<W: AsyncWrite + Unpin>(dest: &mut W, data: &[u8]) -> Result<(), MuxerError> {
+    if !data.is_empty() {
+        dest.write_all(data).await.map_err(|source| MuxerError::WriteError(TagWriterError::WriteError { source }))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::WebmMuxerAsync;
+    use crate::matroska_spec::{MatroskaSpec, Master};
+    use crate::muxer::{MuxFrame, MuxerOptions, WebmMuxer};
+
+    #[test]
+    fn async_muxer_matches_sync_muxer() {
+        let track_entry = MatroskaSpec::TrackEntry(Master::Full(vec![
+            MatroskaSpec::TrackType(1),
+            MatroskaSpec::CodecID(String::from("V_VP8")),
+        ]));
+        let frames: Vec<MuxFrame> = (0..10).map(|i| {
+            let mut frame = MuxFrame::new(1, i * 33, vec![i as u8; 16]);
+            frame.keyframe = i % 4 == 0;
+            frame
+        }).collect();
+        let options = MuxerOptions { max_interleave_delay: Some(100), ..Default::default() };
+
+        let mut sync_muxer = WebmMuxer::with_options(Vec::new(), options.clone());
+        sync_muxer.add_track(track_entry.clone()).unwrap();
+        for frame in frames.clone() {
+            sync_muxer.write_frame(frame).unwrap();
+        }
+        let expected = sync_muxer.finalize().unwrap();
+
+        let written = block_on(async {
+            let mut muxer = WebmMuxerAsync::with_options(Vec::new(), options);
+            muxer.add_track(track_entry).unwrap();
+            for frame in frames {
+                muxer.write_frame(frame).await.unwrap();
+            }
+            muxer.finalize().await.unwrap()
+        });
+
+        assert_eq!(expected, written);
+    }
+}
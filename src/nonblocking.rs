@@ -0,0 +1,170 @@
+use futures::{AsyncWrite, AsyncWriteExt};
+
+use crate::errors::TagWriterError;
+use crate::matroska_spec::{EbmlSpecification, EbmlTag};
+use crate::{WebmWriter, WriteOptions};
+
+///
+/// Writes webm tag data to a destination that implements [`futures::AsyncWrite`].
+///
+/// This is the asynchronous counterpart of [`WebmWriter`], and is created with the [`new()`](#method.new) function.  Tags are encoded in memory exactly as [`WebmWriter`] would encode them, and bytes are written to the destination as soon as they are complete.  Just like [`WebmWriter`], the contents of a [`Master`][crate::matroska_spec::Master] tag with a known size are held in memory until the tag is closed, so large files should be streamed using unknown sized "Segment" and "Cluster" tags (see [`WriteOptions::is_unknown_sized_element()`]).
+///
+/// ## Example
+///
+/// ```
+/// use webm_iterable::WebmWriterAsync;
+/// use webm_iterable::matroska_spec::{MatroskaSpec, Master};
+///
+/// # futures::executor::block_on(async {
+/// let mut writer = WebmWriterAsync::new(Vec::new());
+/// writer.write(&MatroskaSpec::Ebml(Master::Full(vec![MatroskaSpec::DocType(String::from("webm"))]))).await?;
+/// let webm: Vec<u8> = writer.into_inner().await?;
+/// assert!(!webm.is_empty());
+/// # Ok::<(), webm_iterable::errors::TagWriterError>(())
+/// # }).unwrap();
+/// ```
+///
+pub struct WebmWriterAsync<W: AsyncWrite + Unpin> {
+    writer: WebmWriter<Vec<u8>>,
+    dest: W,
+}
+
+impl<W: AsyncWrite + Unpin> WebmWriterAsync<W> {
+    ///
+    /// Returns a new [`WebmWriterAsync`] instance.
+    ///
+    /// The `dest` parameter can be anything that implements [`futures::AsyncWrite`].
+    ///
+    pub fn new(dest: W) -> Self {
+        WebmWriterAsync {
+            writer: WebmWriter::new(Vec::new()),
+            dest,
+        }
+    }
+
+    ///
+    /// Gets a mutable reference to the underlying write stream.
+    ///
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.dest
+    }
+
+    ///
+    /// Gets a reference to the underlying write stream.
+    ///
+    pub fn get_ref(&self) -> &W {
+        &self.dest
+    }
+
+    ///
+    /// Write a tag to this instance's destination.
+    ///
+    /// See [`WebmWriter::write()`] for details.
+    ///
+    /// ## Errors
+    ///
+    /// This method can error if there is a problem encoding the input tag or writing to the destination.
+    ///
+    pub async fn write<TSpec: EbmlSpecification<TSpec> + EbmlTag<TSpec> + Clone>(&mut self, tag: &TSpec) -> Result<(), TagWriterError> {
+        self.writer.write(tag)?;
+        self.write_pending().await
+    }
+
+    ///
+    /// Write a tag to this instance's destination using advanced options.
+    ///
+    /// See [`WebmWriter::write_advanced()`] for details.
+    ///
+    /// ## Errors
+    ///
+    /// This method can error if there is a problem encoding the input tag or writing to the destination.
+    ///
+    pub async fn write_advanced<TSpec: EbmlSpecification<TSpec> + EbmlTag<TSpec> + Clone>(&mut self, tag: &TSpec, options: WriteOptions) -> Result<(), TagWriterError> {
+        self.writer.write_advanced(tag, options)?;
+        self.write_pending().await
+    }
+
+    ///
+    /// Write raw tag data to this instance's destination.
+    ///
+    /// See [`WebmWriter::write_raw()`] for details.
+    ///
+    /// ## Errors
+    ///
+    /// This method can error if there is a problem encoding the input tag or writing to the destination.
+    ///
+    pub async fn write_raw(&mut self, tag_id: u64, data: &[u8]) -> Result<(), TagWriterError> {
+        self.writer.write_raw(tag_id, data)?;
+        self.write_pending().await
+    }
+
+    ///
+    /// Closes any open tags and flushes all written data to the destination.
+    ///
+    /// ## Errors
+    ///
+    /// This method can error if there is a problem writing to the destination.
+    ///
+    pub async fn flush(&mut self) -> Result<(), TagWriterError> {
+        self.writer.flush()?;
+        self.write_pending().await?;
+        self.dest.flush().await.map_err(|source| TagWriterError::WriteError { source })
+    }
+
+    ///
+    /// Consumes self and returns the underlying write stream.
+    ///
+    /// Any incomplete tags are written out before returning the stream.
+    ///
+    pub async fn into_inner(mut self) -> Result<W, TagWriterError> {
+        self.flush().await?;
+        Ok(self.dest)
+    }
+
+    async fn write_pending(&mut self) -> Result<(), TagWriterError> {
+        let pending = std::mem::take(self.writer.get_mut());
+        if !pending.is_empty() {
+            self.dest.write_all(&pending).await.map_err(|source| TagWriterError::WriteError { source })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::WebmWriterAsync;
+    use crate::matroska_spec::{MatroskaSpec, Master};
+    use crate::{WebmWriter, WriteOptions};
+
+    #[test]
+    fn async_writer_matches_sync_writer() {
+        let tags = [
+            MatroskaSpec::Ebml(Master::Full(vec![MatroskaSpec::DocType(String::from("webm"))])),
+            MatroskaSpec::Segment(Master::Start),
+            MatroskaSpec::Cluster(Master::Full(vec![MatroskaSpec::Timestamp(0x02)])),
+            MatroskaSpec::Segment(Master::End),
+        ];
+
+        let mut sync_writer = WebmWriter::new(Vec::new());
+        sync_writer.write(&tags[0]).unwrap();
+        sync_writer.write_advanced(&tags[1], WriteOptions::is_unknown_sized_element()).unwrap();
+        for tag in &tags[2..] {
+            sync_writer.write(tag).unwrap();
+        }
+        let expected = sync_writer.into_inner().unwrap();
+
+        let written = block_on(async {
+            let mut writer = WebmWriterAsync::new(Vec::new());
+            writer.write(&tags[0]).await.unwrap();
+            writer.write_advanced(&tags[1], WriteOptions::is_unknown_sized_element()).await.unwrap();
+            for tag in &tags[2..] {
+                writer.write(tag).await.unwrap();
+            }
+            writer.into_inner().await.unwrap()
+        });
+
+        assert_eq!(expected, written);
+    }
+}